@@ -0,0 +1,290 @@
+use std::{collections::HashMap, future::Future, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use log::{info, warn};
+use serde_json::Value;
+use tokio::sync::{broadcast, oneshot, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+use crate::websocket::{types::{jobs::{JobDone, JobFailed, JobInfo, JobStatus}, protocol::Event}, utils::broadcast};
+
+/// How many jobs can run at once. The rest wait in the queue.
+const MAX_WORKERS: usize = 2;
+/// The least amount of time between a job's progress events.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+/// How long finished jobs are listed for, in seconds.
+const FINISHED_JOB_LIFETIME: i64 = 10 * 60;
+
+struct QueuedJob {
+  info: JobInfo,
+  cancel: CancellationToken,
+}
+
+/// Runs long operations on worker tasks, and reports their progress to every connection.
+#[derive(Clone)]
+pub struct JobQueue {
+  jobs: Arc<Mutex<HashMap<String, QueuedJob>>>,
+  workers: Arc<Semaphore>,
+  tx: broadcast::Sender<String>,
+}
+
+/// Lets a running job report its progress, and check if it was cancelled.
+#[derive(Clone)]
+pub struct JobContext {
+  pub id: String,
+  queue: JobQueue,
+  cancel: CancellationToken,
+  last_report: Arc<Mutex<Option<Instant>>>,
+}
+
+impl JobContext {
+  /// Reports the job's progress from 0 to 1, or None if the amount of work isn't known.
+  /// Reports are throttled, so jobs can report as often as they like.
+  pub fn report(&self, progress: Option<f64>, message: &str) {
+    let mut last_report = self.last_report.lock().expect("Failed to lock Job Report Mutex.");
+    if last_report.is_some_and(|last_report| last_report.elapsed() < PROGRESS_INTERVAL) {
+      return;
+    }
+    *last_report = Some(Instant::now());
+    drop(last_report);
+
+    self.queue.update(&self.id, |info| {
+      info.progress = progress.map(|progress| progress.clamp(0.0, 1.0));
+      info.message = message.to_string();
+    });
+  }
+
+  /// Checks if the job was cancelled. Blocking jobs should check this between steps, since they can't be stopped otherwise.
+  pub fn is_cancelled(&self) -> bool {
+    return self.cancel.is_cancelled();
+  }
+}
+
+/// A queued job, which can be waited on for its result.
+pub struct JobHandle {
+  pub id: String,
+  result: oneshot::Receiver<Result<Value, String>>,
+}
+
+impl JobHandle {
+  /// Waits for the job to finish.
+  pub async fn wait(self) -> Result<Value, String> {
+    return self.result.await.unwrap_or(Err(String::from("The job was dropped before it finished.")));
+  }
+}
+
+impl JobQueue {
+  /// Creates a new JobQueue, which sends its events on the provided channel.
+  pub fn new(tx: broadcast::Sender<String>) -> JobQueue {
+    return JobQueue {
+      jobs: Arc::new(Mutex::new(HashMap::new())),
+      workers: Arc::new(Semaphore::new(MAX_WORKERS)),
+      tx,
+    };
+  }
+
+  /// Queues a job. It runs once a worker is free, and can be cancelled at any await point.
+  pub fn spawn<F, Fut>(&self, kind: &str, target: &str, job: F) -> JobHandle
+  where
+    F: FnOnce(JobContext) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Value, String>> + Send + 'static
+  {
+    return self.queue(kind, target, true, job);
+  }
+
+  /// Queues a job that blocks, like reading archives or walking the library.
+  /// Blocking jobs can't be aborted, so they keep their worker until they notice the cancellation and return.
+  pub fn spawn_blocking<F>(&self, kind: &str, target: &str, job: F) -> JobHandle
+  where
+    F: FnOnce(JobContext) -> Result<Value, String> + Send + 'static
+  {
+    return self.queue(kind, target, false, |context| async move {
+      return tokio::task::spawn_blocking(move || job(context)).await
+        .map_err(|err| err.to_string())?;
+    });
+  }
+
+  /// Queues a job. Abortable jobs are stopped at their next await point when cancelled, while the rest are waited on.
+  fn queue<F, Fut>(&self, kind: &str, target: &str, abortable: bool, job: F) -> JobHandle
+  where
+    F: FnOnce(JobContext) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Value, String>> + Send + 'static
+  {
+    let id = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 12]>());
+    let cancel = CancellationToken::new();
+
+    let info = JobInfo {
+      id: id.clone(),
+      kind: kind.to_string(),
+      target: target.to_string(),
+      status: JobStatus::QUEUED,
+      progress: None,
+      message: String::from("Waiting for other jobs to finish..."),
+      createdAt: Utc::now().timestamp(),
+      finishedAt: None,
+    };
+
+    let mut jobs = self.jobs.lock().expect("Failed to lock Jobs Mutex.");
+
+    // * Finished jobs are only kept around for a while, so clients that missed the event can still see how they went.
+    let now = Utc::now().timestamp();
+    jobs.retain(|_, job| job.info.finishedAt.is_none_or(|finished_at| now - finished_at < FINISHED_JOB_LIFETIME));
+
+    jobs.insert(id.clone(), QueuedJob { info: info.clone(), cancel: cancel.clone() });
+    drop(jobs);
+
+    info!("Jobs: Queued {} job \"{}\" for \"{}\".", kind, id, target);
+    broadcast(self.tx.clone(), Event::JobProgress(info));
+
+    let context = JobContext {
+      id: id.clone(),
+      queue: self.clone(),
+      cancel: cancel.clone(),
+      last_report: Arc::new(Mutex::new(None)),
+    };
+
+    let (result_tx, result_rx) = oneshot::channel();
+    let queue = self.clone();
+    let job_id = id.clone();
+
+    tokio::spawn(async move {
+      let worker_queue = queue.clone();
+      let worker_job_id = job_id.clone();
+      let worker_cancel = cancel.clone();
+
+      // * Jobs run in their own task so they can be aborted, and so a panic fails the job instead of leaving it running.
+      let mut job_task = tokio::spawn(async move {
+        let _permit = tokio::select! {
+          permit = worker_queue.workers.clone().acquire_owned() => permit.expect("The job queue's workers were closed."),
+          _ = worker_cancel.cancelled() => return Err(String::from("The job was cancelled.")),
+        };
+
+        worker_queue.update(&worker_job_id, |info| {
+          info.status = JobStatus::RUNNING;
+          info.message = String::from("Starting...");
+        });
+
+        return job(context).await;
+      });
+
+      let job_res = if abortable {
+        tokio::select! {
+          _ = cancel.cancelled() => {
+            job_task.abort();
+            Err(String::from("The job was cancelled."))
+          },
+          join_res = &mut job_task => join_res.unwrap_or_else(|err| Err(format!("The job panicked: {}", err))),
+        }
+      } else {
+        // * The job's worker is only freed once it returns, so it's only reported as cancelled after that.
+        job_task.await.unwrap_or_else(|err| Err(format!("The job panicked: {}", err)))
+      };
+
+      queue.finish(&job_id, &job_res, cancel.is_cancelled());
+      let _ = result_tx.send(job_res);
+    });
+
+    return JobHandle { id, result: result_rx };
+  }
+
+  /// Updates a job's info, and sends it to every connection.
+  fn update(&self, id: &str, update: impl FnOnce(&mut JobInfo)) {
+    let mut jobs = self.jobs.lock().expect("Failed to lock Jobs Mutex.");
+
+    let job = jobs.get_mut(id);
+    if job.is_none() {
+      return;
+    }
+
+    let info = &mut job.unwrap().info;
+    update(info);
+
+    broadcast(self.tx.clone(), Event::JobProgress(info.clone()));
+  }
+
+  /// Records how a job finished, and lets every connection know.
+  fn finish(&self, id: &str, job_res: &Result<Value, String>, cancelled: bool) {
+    let mut jobs = self.jobs.lock().expect("Failed to lock Jobs Mutex.");
+
+    let job = jobs.get_mut(id);
+    if job.is_none() {
+      return;
+    }
+    let info = &mut job.unwrap().info;
+
+    info.finishedAt = Some(Utc::now().timestamp());
+
+    match job_res {
+      Ok(result) => {
+        info!("Jobs: {} job \"{}\" finished.", info.kind, id);
+
+        info.status = JobStatus::DONE;
+        info.progress = Some(1.0);
+        info.message = String::from("Done.");
+
+        broadcast(self.tx.clone(), Event::JobDone(JobDone {
+          jobId: id.to_string(),
+          kind: info.kind.clone(),
+          result: result.clone(),
+        }));
+      },
+      Err(message) => {
+        if cancelled {
+          info!("Jobs: {} job \"{}\" was cancelled.", info.kind, id);
+        } else {
+          warn!("Jobs: {} job \"{}\" failed: {}", info.kind, id, message);
+        }
+
+        info.status = if cancelled { JobStatus::CANCELLED } else { JobStatus::FAILED };
+        info.message = message.clone();
+
+        broadcast(self.tx.clone(), Event::JobFailed(JobFailed {
+          jobId: id.to_string(),
+          kind: info.kind.clone(),
+          message: message.clone(),
+          cancelled,
+        }));
+      },
+    }
+  }
+
+  /// Gets a job's info.
+  pub fn get_job(&self, id: &str) -> Option<JobInfo> {
+    let jobs = self.jobs.lock().expect("Failed to lock Jobs Mutex.");
+    return jobs.get(id).map(|job| job.info.clone());
+  }
+
+  /// Gets the id of a job that's still working on the same thing, so it isn't done twice.
+  pub fn find_active(&self, kind: &str, target: &str) -> Option<String> {
+    let jobs = self.jobs.lock().expect("Failed to lock Jobs Mutex.");
+
+    return jobs.values()
+      .find(|job| job.info.kind == kind && job.info.target == target && job.info.status.is_active())
+      .map(|job| job.info.id.clone());
+  }
+
+  /// Cancels a job. Returns false if the job doesn't exist or already finished.
+  pub fn cancel(&self, id: &str) -> bool {
+    let jobs = self.jobs.lock().expect("Failed to lock Jobs Mutex.");
+
+    let job = jobs.get(id);
+    if job.is_none_or(|job| !job.info.status.is_active()) {
+      return false;
+    }
+
+    job.unwrap().cancel.cancel();
+
+    return true;
+  }
+
+  /// Gets every job that's queued, running, or recently finished, oldest first.
+  pub fn list(&self) -> Vec<JobInfo> {
+    let jobs = self.jobs.lock().expect("Failed to lock Jobs Mutex.");
+
+    let mut infos: Vec<JobInfo> = jobs.values().map(|job| job.info.clone()).collect();
+    infos.sort_by_key(|info| info.createdAt);
+
+    return infos;
+  }
+}
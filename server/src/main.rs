@@ -1,13 +1,17 @@
-use std::{env::var, path::PathBuf};
+use std::{env::var, path::PathBuf, sync::{Arc, Mutex}};
 
 use rest::initialize_rest_api;
-use tokio::fs::create_dir_all;
+use tokio::{fs::create_dir_all, sync::broadcast};
 use warp::Filter;
-use websocket::initialize_websocket_api;
+use websocket::{auth::init_session_store, initialize_websocket_api, types::settings::get_default_settings};
 use dotenv::dotenv;
+use scheduler::Scheduler;
+use job_queue::JobQueue;
 
 mod websocket;
 mod rest;
+mod scheduler;
+mod job_queue;
 
 /// The main function
 #[tokio::main]
@@ -22,10 +26,16 @@ async fn main() {
   create_dir_all(&grids_cache_dir.join("thumb")).await.expect("Failed to create thumb capsule cache dir.");
   create_dir_all(&grids_cache_dir.join("hero")).await.expect("Failed to create hero cache dir.");
   
-  let cleanup_schedule = var("NRM_UPLOAD_CLEAN_SCHEDULE").unwrap();
+  let sessions = Arc::new(Mutex::new(init_session_store()));
+  let settings = Arc::new(Mutex::new(get_default_settings()));
+  let scheduler = Scheduler::new();
 
-  let websocket_route = initialize_websocket_api();
-  let rest_routes = initialize_rest_api(grids_cache_dir_str, cleanup_schedule);
+  // * Library events and job progress are sent to every connection.
+  let tx = broadcast::channel(100).0;
+  let job_queue = JobQueue::new(tx.clone());
+
+  let websocket_route = initialize_websocket_api(tx, job_queue.clone(), scheduler.clone(), sessions.clone(), settings.clone());
+  let rest_routes = initialize_rest_api(grids_cache_dir_str, job_queue, scheduler, sessions, settings);
   
   let routes = websocket_route.or(rest_routes);
 
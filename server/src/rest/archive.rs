@@ -0,0 +1,274 @@
+use std::{fs::{self, File}, io::{self, Read}, path::{Path, PathBuf}};
+
+use flate2::read::GzDecoder;
+use log::{info, warn};
+use sevenz_rust::{Password, SevenZReader};
+use tar::EntryType;
+
+use crate::job_queue::JobContext;
+
+use super::zip::{cancelled_error, get_root_of_archive, sanitize_file_path, unpack_zip};
+
+/// The archive formats uploads can be extracted from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum ArchiveFormat {
+  ZIP,
+  SEVEN_ZIP,
+  RAR,
+  TAR,
+  TAR_GZ,
+}
+
+/// Checks if a block of data is the start of a tar archive.
+fn is_tar_header(header: &[u8]) -> bool {
+  return header.len() >= 262 && &header[257..262] == b"ustar";
+}
+
+/// Detects an archive's format from its first bytes, so it doesn't matter what the file is named.
+fn detect_archive_format(path: &Path) -> Result<Option<ArchiveFormat>, io::Error> {
+  let mut header = Vec::with_capacity(512);
+  File::open(path)?.take(512).read_to_end(&mut header)?;
+
+  if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+    return Ok(Some(ArchiveFormat::ZIP));
+  }
+
+  if header.starts_with(b"7z\xBC\xAF\x27\x1C") {
+    return Ok(Some(ArchiveFormat::SEVEN_ZIP));
+  }
+
+  if header.starts_with(b"Rar!\x1A\x07") {
+    return Ok(Some(ArchiveFormat::RAR));
+  }
+
+  // * Gzip can wrap any single file, so check that there's a tar inside it.
+  if header.starts_with(b"\x1F\x8B") {
+    let mut inner_header = Vec::with_capacity(512);
+    let _ = GzDecoder::new(File::open(path)?).take(512).read_to_end(&mut inner_header);
+
+    return Ok(if is_tar_header(&inner_header) { Some(ArchiveFormat::TAR_GZ) } else { None });
+  }
+
+  if is_tar_header(&header) {
+    return Ok(Some(ArchiveFormat::TAR));
+  }
+
+  return Ok(None);
+}
+
+/// Creates a file for an extracted entry, along with its parent directories.
+fn create_entry_file(path: &Path) -> Result<File, io::Error> {
+  let parent = path.parent().expect("A file entry should have parent directories");
+  if !parent.is_dir() {
+    fs::create_dir_all(parent)?;
+  }
+
+  return File::options().write(true).create_new(true).open(path);
+}
+
+/// Reports which entry is being extracted.
+fn report_entry(job: &JobContext, progress: Option<f64>, path: &Path) {
+  job.report(progress, &format!("Extracting {}...", path.file_name().unwrap_or_default().to_string_lossy()));
+}
+
+/// Extracts everything from a 7z archive to the output directory.
+fn unpack_7z(archive_path: &Path, out_dir: &Path, job: &JobContext) -> Result<String, String> {
+  let mut reader = SevenZReader::open(archive_path, Password::empty()).map_err(|e| e.to_string())?;
+  let entry_count = reader.archive().files.len();
+  let mut root_folder = None;
+  let mut index = 0;
+
+  reader.for_each_entries(|entry, entry_reader| {
+    if job.is_cancelled() {
+      return Err(cancelled_error().into());
+    }
+
+    if root_folder.is_none() {
+      root_folder = get_root_of_archive(entry.name());
+    }
+
+    let path = out_dir.join(sanitize_file_path(entry.name()));
+    report_entry(job, Some(index as f64 / entry_count as f64), &path);
+    index += 1;
+
+    if entry.is_directory() {
+      fs::create_dir_all(&path)?;
+    } else if !entry.is_anti_item() {
+      let mut writer = create_entry_file(&path)?;
+      io::copy(entry_reader, &mut writer)?;
+    }
+
+    return Ok(true);
+  }).map_err(|e| e.to_string())?;
+
+  return root_folder.ok_or(String::from("The archive is empty."));
+}
+
+/// Extracts everything from a RAR archive to the output directory.
+fn unpack_rar(archive_path: &Path, out_dir: &Path, job: &JobContext) -> Result<String, String> {
+  let mut archive = unrar::Archive::new(archive_path).open_for_processing().map_err(|e| e.to_string())?;
+  let mut root_folder = None;
+
+  while let Some(header) = archive.read_header().map_err(|e| e.to_string())? {
+    if job.is_cancelled() {
+      return Err(cancelled_error().to_string());
+    }
+
+    let entry = header.entry();
+    let filename = entry.filename.to_string_lossy().to_string();
+
+    if root_folder.is_none() {
+      root_folder = get_root_of_archive(&filename);
+    }
+
+    let path = out_dir.join(sanitize_file_path(&filename));
+    report_entry(job, None, &path);
+
+    archive = if entry.is_directory() {
+      fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+      header.skip()
+    } else if entry.is_file() {
+      // * Creating the file first keeps existing files from being overwritten.
+      drop(create_entry_file(&path).map_err(|e| e.to_string())?);
+      header.extract_to(&path)
+    } else {
+      header.skip()
+    }.map_err(|e| e.to_string())?;
+  }
+
+  return root_folder.ok_or(String::from("The archive is empty."));
+}
+
+/// Extracts everything from a tar archive, which may be gzipped, to the output directory.
+fn unpack_tar(archive_path: &Path, out_dir: &Path, is_gzipped: bool, job: &JobContext) -> Result<String, String> {
+  let file = File::open(archive_path).map_err(|e| e.to_string())?;
+  let reader: Box<dyn Read> = if is_gzipped { Box::new(GzDecoder::new(file)) } else { Box::new(file) };
+
+  let mut archive = tar::Archive::new(reader);
+  let mut root_folder = None;
+
+  for entry_res in archive.entries().map_err(|e| e.to_string())? {
+    if job.is_cancelled() {
+      return Err(cancelled_error().to_string());
+    }
+
+    let mut entry = entry_res.map_err(|e| e.to_string())?;
+    let filename = entry.path().map_err(|e| e.to_string())?.to_string_lossy().to_string();
+
+    if root_folder.is_none() {
+      root_folder = get_root_of_archive(&filename);
+    }
+
+    let path = out_dir.join(sanitize_file_path(&filename));
+    report_entry(job, None, &path);
+
+    match entry.header().entry_type() {
+      EntryType::Directory => {
+        fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+      },
+      EntryType::Regular | EntryType::Continuous => {
+        let mut writer = create_entry_file(&path).map_err(|e| e.to_string())?;
+        io::copy(&mut entry, &mut writer).map_err(|e| e.to_string())?;
+      },
+      // * Links could point outside of the library, so they're skipped.
+      entry_type => {
+        warn!("Unpack Archive: Skipping \"{}\" since {:?} entries aren't supported.", filename, entry_type);
+      }
+    }
+  }
+
+  return root_folder.ok_or(String::from("The archive is empty."));
+}
+
+/// Extracts everything from a ZIP, 7z, RAR, or tar archive to the output directory.
+/// Returns the path of the archive's root folder in the output directory.
+/// Blocking formats can't be aborted, so they stop at the next entry once the job is cancelled.
+pub async fn unpack_archive(archive_path: &Path, out_dir: &Path, job: &JobContext) -> Result<PathBuf, String> {
+  let detect_path = archive_path.to_path_buf();
+  let format_res = tokio::task::spawn_blocking(move || detect_archive_format(&detect_path))
+    .await
+    .map_err(|e| e.to_string())?;
+
+  let format = format_res.map_err(|e| e.to_string())?;
+  if format.is_none() {
+    return Err(String::from("The file isn't a supported archive. Supported formats are ZIP, 7z, RAR, tar, and tar.gz."));
+  }
+  let format = format.unwrap();
+
+  info!("Unpack Archive: Extracting \"{}\" as {:?}.", archive_path.display(), format);
+
+  if format == ArchiveFormat::ZIP {
+    let file = tokio::fs::File::open(archive_path).await.map_err(|e| e.to_string())?;
+    return unpack_zip(file, out_dir, job).await.map_err(|e| e.to_string());
+  }
+
+  // * The other formats only have blocking readers.
+  let archive_path = archive_path.to_path_buf();
+  let blocking_out_dir = out_dir.to_path_buf();
+  let job = job.clone();
+
+  let root_folder = tokio::task::spawn_blocking(move || {
+    match format {
+      ArchiveFormat::SEVEN_ZIP => unpack_7z(&archive_path, &blocking_out_dir, &job),
+      ArchiveFormat::RAR => unpack_rar(&archive_path, &blocking_out_dir, &job),
+      ArchiveFormat::TAR => unpack_tar(&archive_path, &blocking_out_dir, false, &job),
+      ArchiveFormat::TAR_GZ => unpack_tar(&archive_path, &blocking_out_dir, true, &job),
+      ArchiveFormat::ZIP => unreachable!(),
+    }
+  }).await.map_err(|e| e.to_string())??;
+
+  return Ok(out_dir.join(root_folder));
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env::temp_dir, fs::{self, File}};
+
+  use serde_json::json;
+  use tokio::sync::broadcast;
+
+  use crate::job_queue::JobQueue;
+
+  use super::unpack_archive;
+
+  #[tokio::test]
+  async fn unpack_archive_skips_current_dir_entries() {
+    let test_dir = temp_dir().join(format!("nrm-archive-test-{}", std::process::id()));
+    let out_dir = test_dir.join("out");
+    fs::create_dir_all(&out_dir).unwrap();
+
+    // * Archives made with "tar -czf game.tgz ." start with a "./" entry. The names are written as is, since the tar crate would normalize them.
+    let archive_path = test_dir.join("game.tar");
+    let mut builder = tar::Builder::new(File::create(&archive_path).unwrap());
+    let entries: [(&str, tar::EntryType, &[u8]); 3] = [
+      ("./", tar::EntryType::Directory, b""),
+      ("./Game/", tar::EntryType::Directory, b""),
+      ("./Game/game.bin", tar::EntryType::Regular, b"rom"),
+    ];
+
+    for (name, entry_type, contents) in entries {
+      let mut header = tar::Header::new_gnu();
+      header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+      header.set_entry_type(entry_type);
+      header.set_size(contents.len() as u64);
+      header.set_mode(0o755);
+      header.set_cksum();
+      builder.append(&header, contents).unwrap();
+    }
+    builder.into_inner().unwrap();
+
+    let queue = JobQueue::new(broadcast::channel(16).0);
+    let job_archive_path = archive_path.clone();
+    let job_out_dir = out_dir.clone();
+    let root_res = queue.spawn("unpack_archive", "", |job| async move {
+      let root_folder = unpack_archive(&job_archive_path, &job_out_dir, &job).await?;
+      return Ok(json!(root_folder));
+    }).wait().await;
+
+    assert_eq!(root_res, Ok(json!(out_dir.join("Game"))));
+    assert_eq!(fs::read_to_string(out_dir.join("Game").join("game.bin")).unwrap(), "rom");
+
+    let _ = fs::remove_dir_all(test_dir);
+  }
+}
@@ -0,0 +1,90 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use log::warn;
+use warp::{http::StatusCode, reject::{Reject, Rejection}, reply::Reply, Filter};
+
+use crate::websocket::{auth::validate_token, types::auth::{SessionStore, UserRole}};
+
+/// Rejection for requests without a valid session token.
+#[derive(Debug)]
+pub struct Unauthorized;
+
+impl Reject for Unauthorized {}
+
+/// Rejection for requests whose user doesn't have a high enough role.
+#[derive(Debug)]
+pub struct Forbidden;
+
+impl Reject for Forbidden {}
+
+/// Gets the session token from the Authorization header, falling back to the `token` query param for plain links.
+fn get_token(authorization: Option<String>, query_params: &HashMap<String, String>) -> Option<String> {
+  if authorization.is_some() {
+    let header = authorization.unwrap();
+    return header.strip_prefix("Bearer ").map(|token| token.trim().to_string());
+  }
+
+  return query_params.get("token").cloned();
+}
+
+/// Checks that the request's session token is valid, and that its user has at least the provided role.
+async fn check_session(authorization: Option<String>, query_params: HashMap<String, String>, sessions: Arc<Mutex<SessionStore>>, role: UserRole) -> Result<(), Rejection> {
+  let token_res = get_token(authorization, &query_params);
+  if token_res.is_none() {
+    warn!("REST: Request was missing a session token.");
+    return Err(warp::reject::custom(Unauthorized));
+  }
+
+  let store = sessions.lock().expect("Failed to lock Sessions Mutex.");
+  let claims_res = validate_token(&store, &token_res.unwrap());
+  if claims_res.is_none() {
+    warn!("REST: Session token was invalid or expired!");
+    return Err(warp::reject::custom(Unauthorized));
+  }
+
+  let claims = claims_res.unwrap();
+  if claims.role < role {
+    warn!("REST: User \"{}\" does not have permission to perform this action!", claims.user);
+    return Err(warp::reject::custom(Forbidden));
+  }
+
+  return Ok(());
+}
+
+/// Requires the request to have a valid session token for a user with at least the provided role.
+pub fn authorized(sessions: Arc<Mutex<SessionStore>>, role: UserRole) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+  warp::header::optional::<String>("authorization")
+    .and(warp::query::<HashMap<String, String>>())
+    .and(warp::any().map(move || sessions.clone()))
+    .and(warp::any().map(move || role))
+    .and_then(check_session)
+    .untuple_one()
+}
+
+/// Converts authentication rejections into their HTTP responses.
+pub async fn handle_auth_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
+  if err.find::<Unauthorized>().is_some() {
+    let response = warp::http::Response::builder()
+      .status(StatusCode::UNAUTHORIZED)
+      .header("WWW-Authenticate", "Bearer")
+      .header("Content-Type", "text/plain")
+      .header("Access-Control-Allow-Origin", "*")
+      .body("Missing or invalid session token.".to_string())
+      .map_err(|_| warp::reject())?;
+
+    return Ok(response);
+  }
+
+  if err.find::<Forbidden>().is_some() {
+    let response = warp::http::Response::builder()
+      .status(StatusCode::FORBIDDEN)
+      .header("Content-Type", "text/plain")
+      .header("Access-Control-Allow-Origin", "*")
+      .body("You don't have permission to perform this action.".to_string())
+      .map_err(|_| warp::reject())?;
+
+    return Ok(response);
+  }
+
+  return Err(err);
+}
@@ -1,37 +1,39 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::collections::HashMap;
 
 use log::warn;
-use warp::{reject::Rejection, reply::Reply};
+use warp::{http::HeaderMap, reject::Rejection, reply::Reply};
 
-use super::{types::StreamStore, utils::{download::get_file_metadata}};
+use super::{types::{StreamStore, UploadChecksums}, utils::{download::get_file_metadata, paths::PathResolver, upload::{verification_failed_reply, verify_upload}}};
 
-/// Completes the Bios File upload.
-pub async fn bios_file_upload_complete(streams_store: StreamStore, upload_id: String) -> Result<impl Reply, Rejection> {
-  streams_store.streams.write().await.remove(&upload_id);
+/// Completes the Bios File upload, once it's been verified.
+pub async fn bios_file_upload_complete(streams_store: StreamStore, upload_id: String, headers: HeaderMap) -> Result<warp::reply::Response, Rejection> {
+  let verify_res = verify_upload(&streams_store, &upload_id, None, &UploadChecksums::from_headers(&headers)).await;
+  if verify_res.is_err() {
+    return verification_failed_reply(verify_res.err().unwrap());
+  }
 
-  return Ok(warp::reply::with_header("success", "Access-Control-Allow-Origin", "*"));
+  return Ok(warp::reply::with_header("success", "Access-Control-Allow-Origin", "*").into_response());
 }
 
 /// Gets the download metadata for a bios file.
-pub async fn bios_file_download_get_metadata(query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
+pub async fn bios_file_download_get_metadata(resolver: PathResolver, query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
   if !query_params.contains_key("filePath") {
     warn!("Get BIOS Metadata: Missing query param filePath");
     return Err(warp::reject::reject());
   }
 
-  let path = query_params.get("filePath").unwrap().to_owned();
-  let file_path = PathBuf::from(&path);
+  let file_path = resolver.resolve(query_params.get("filePath").unwrap()).await?;
 
   return get_file_metadata(&file_path).await;
 }
 
 /// Handles deleting a bios file.
-pub async fn delete_bios_file(query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
+pub async fn delete_bios_file(resolver: PathResolver, query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
   if !query_params.contains_key("filePath") {
     warn!("Delete BIOS: Missing query param filePath");
     return Err(warp::reject::reject());
   }
-  let path = query_params.get("filePath").unwrap().to_owned();
+  let path = resolver.resolve_descendant(query_params.get("filePath").unwrap()).await?;
   
   tokio::fs::remove_file(&path).await.map_err(|e| {
     warn!("Error deleting BIOS file: {}", e);
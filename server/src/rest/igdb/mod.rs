@@ -4,9 +4,12 @@ mod twitch_auth;
 use std::collections::HashMap;
 use log::warn;
 
+use serde_json::{Map, Value};
 use warp::{reject::Rejection, reply::Reply};
 
-use super::types::IGDBClientStore;
+use crate::job_queue::JobQueue;
+
+use super::{jobs::job_queued_reply, types::{IGDBBulkMetadata, IGDBClientStore}};
 
 
 /// Initializes the IGDB API Client, and returns an error if there were missing env variables.
@@ -47,6 +50,39 @@ pub async fn igdb_get_metadata_by_id(igdb_client_store: IGDBClientStore, igdb_id
   return Ok(response);
 }
 
+/// Queues a job that gets the IGDB metadata for each of the provided games.
+/// The job's result maps each id to its metadata, or null if it couldn't be fetched.
+pub async fn igdb_get_metadata_bulk(job_queue: JobQueue, igdb_client_store: IGDBClientStore, data: IGDBBulkMetadata) -> Result<warp::reply::Response, Rejection> {
+  if igdb_client_store.client.read().await.client_id == "".to_string() {
+    warn!("IGDB Client was not initialized before request (Get Metadata Bulk)");
+    return Err(warp::reject::reject());
+  }
+
+  let handle = job_queue.spawn("fetch_metadata", &format!("{} games", data.igdbIds.len()), move |job| async move {
+    let mut results = Map::new();
+    let game_count = data.igdbIds.len();
+
+    for (index, igdb_id) in data.igdbIds.into_iter().enumerate() {
+      job.report(Some(index as f64 / game_count as f64), &format!("Fetching metadata ({}/{})...", index + 1, game_count));
+
+      // * The client is locked for each game, so other requests aren't stuck behind the whole job.
+      let res = igdb_client_store.client.write().await.get_metadata_by_id(igdb_id.clone()).await;
+
+      if res.is_err() {
+        warn!("IGDB Get Metadata Error: {}", res.err().unwrap());
+        results.insert(igdb_id, Value::Null);
+        continue;
+      }
+
+      results.insert(igdb_id, serde_json::to_value(res.unwrap()).map_err(|e| e.to_string())?);
+    }
+
+    return Ok(Value::Object(results));
+  });
+
+  return job_queued_reply(&handle.id);
+}
+
 /// Gets the IGDB Games for the provided search query.
 pub async fn igdb_search_game(igdb_client_store: IGDBClientStore, query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
   if !query_params.contains_key("query") {
@@ -0,0 +1,22 @@
+use serde_json::json;
+use warp::{http::StatusCode, reject::Rejection, reply::Reply};
+
+use crate::scheduler::Scheduler;
+
+/// Gets the status of the scheduled jobs.
+pub async fn get_job_statuses(scheduler: Scheduler) -> Result<impl Reply, Rejection> {
+  return Ok(warp::reply::json(&scheduler.get_statuses()));
+}
+
+/// Builds the response for a request that was handed off to the job queue.
+/// Clients follow the job's progress over the websocket, and get its result from the job_done event.
+pub fn job_queued_reply(job_id: &str) -> Result<warp::reply::Response, Rejection> {
+  let response = warp::http::Response::builder()
+    .status(StatusCode::ACCEPTED)
+    .header("Content-Type", "application/json")
+    .header("Access-Control-Allow-Origin", "*")
+    .body(json!({ "jobId": job_id }).to_string().into())
+    .map_err(|_| warp::reject())?;
+
+  return Ok(response);
+}
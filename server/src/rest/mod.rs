@@ -3,28 +3,35 @@ mod rom_download;
 mod rom_upload;
 mod types;
 mod zip;
+mod zip_stream;
+mod archive;
+mod tus;
+mod jobs;
 mod sgdb;
 mod igdb;
 mod utils;
 mod bios_files;
 mod rom_extras;
+mod auth;
 
-use std::{collections::HashMap, fs::remove_file, str::FromStr, thread};
+use std::{collections::HashMap, sync::{Arc, Mutex}};
 
+use auth::{authorized, handle_auth_rejection};
 use bios_files::{bios_file_download_get_metadata, bios_file_upload_complete, delete_bios_file};
-use chrono::Utc;
 use grids::{delete_hero, delete_capsule, upload_hero, upload_capsule};
-use cron::Schedule;
-use igdb::{igdb_get_metadata_by_id, igdb_search_game, igdb_search_platform, init_igdb_client};
-use log::{info, warn};
-use rom_download::{delete_rom, rom_download_complete, rom_download_get_metadata};
+use jobs::get_job_statuses;
+use igdb::{igdb_get_metadata_bulk, igdb_get_metadata_by_id, igdb_search_game, igdb_search_platform, init_igdb_client};
+use rom_download::{delete_rom, rom_download, rom_download_get_metadata};
 use rom_upload::{rom_upload_complete};
 use sgdb::{init_sgdb_client, sgdb_get_grids_by_id, sgdb_search_game};
-use types::{HeroUpload, CapsuleUpload, IGDBClientStore, ROMDownload, ROMUploadComplete, SGDBClientStore, StreamStore};
-use utils::{download::download_file, upload::{upload_cancel, upload_file}};
+use tus::{load_tus_uploads, remove_expired_uploads, tus_create, tus_head, tus_options, tus_patch, tus_terminate};
+use types::{HeroUpload, CapsuleUpload, IGDBBulkMetadata, IGDBClientStore, ROMUploadComplete, SGDBClientStore, StreamStore, TusStore};
+use utils::{download::download_file, paths::{handle_path_rejection, PathResolver}, upload::{remove_stale_uploads, upload_cancel, upload_file}};
 use warp::{http::Method, Filter};
 
-use crate::rest::{rom_extras::{delete_rom_extra, rom_extra_download_get_metadata, rom_extra_upload_complete}, utils::upload::{prepare_file_replace, prepare_file_upload}};
+use zip::remove_stale_packed_zips;
+
+use crate::{job_queue::JobQueue, scheduler::{get_job_schedule, Scheduler}, rest::{rom_extras::{delete_rom_extra, rom_extra_download_get_metadata, rom_extra_upload_complete}, utils::upload::{prepare_file_replace, prepare_file_upload}}, websocket::types::{auth::{SessionStore, UserRole}, settings::Settings}};
 
 fn json_capsule_upload() -> impl Filter<Extract = (CapsuleUpload,), Error = warp::Rejection> + Clone {
   warp::body::content_length_limit(50 * 1024 * 1024).and(warp::body::json())
@@ -34,28 +41,39 @@ fn json_hero_upload() -> impl Filter<Extract = (HeroUpload,), Error = warp::Reje
   warp::body::content_length_limit(50 * 1024 * 1024).and(warp::body::json())
 }
 
-fn json_body_download() -> impl Filter<Extract = (ROMDownload,), Error = warp::Rejection> + Clone {
+fn json_body_upload_complete() -> impl Filter<Extract = (ROMUploadComplete,), Error = warp::Rejection> + Clone {
   warp::body::content_length_limit(50 * 1024 * 1024).and(warp::body::json())
 }
 
-fn json_body_upload_complete() -> impl Filter<Extract = (ROMUploadComplete,), Error = warp::Rejection> + Clone {
+fn json_body_igdb_bulk() -> impl Filter<Extract = (IGDBBulkMetadata,), Error = warp::Rejection> + Clone {
   warp::body::content_length_limit(50 * 1024 * 1024).and(warp::body::json())
 }
 
 /// Gets the rest api routes.
-pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+pub fn initialize_rest_api(grids_cache_dir: String, job_queue: JobQueue, scheduler: Scheduler, sessions: Arc<Mutex<SessionStore>>, settings: Arc<Mutex<Settings>>) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
   let cache_dir = grids_cache_dir.clone();
   let cache_dir_filter = warp::any().map(move || cache_dir.clone());
 
+  let path_resolver = PathResolver::new(settings, &grids_cache_dir);
+  let path_resolver_filter = warp::any().map(move || path_resolver.clone());
+
+  let job_queue_filter = warp::any().map(move || job_queue.clone());
+
+  let read_only_auth = authorized(sessions.clone(), UserRole::READONLY);
+  let uploader_auth = authorized(sessions.clone(), UserRole::UPLOADER);
+  let admin_auth = authorized(sessions, UserRole::ADMIN);
+
   let cors = warp::cors()
     .allow_any_origin()
     .allow_headers(vec![
       "Access-Control-Allow-Origin",
       "Origin",
       "Accept",
+      "Authorization",
       "X-Requested-With",
       "Content-Range",
       "Range",
+      "If-Range",
       "Content-Type",
       "Full-Capsule-Extension",
       "Thumb-Capsule-Extension",
@@ -63,19 +81,44 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
       "File-Length",
       "Upload-Id",
       "File-Size",
+      "Upload-Sha256",
+      "Upload-Crc32",
+      "Tus-Resumable",
+      "Upload-Length",
+      "Upload-Offset",
+      "Upload-Metadata",
       "SGDB-Game-Id",
       "SGDB-Results-Page",
       "SGDB-Grid-Type",
       "IGDB-Game-Id",
     ])
+    .expose_headers(vec![
+      "Accept-Ranges",
+      "Content-Range",
+      "Content-Length",
+      "ETag",
+      "Last-Modified",
+      "Upload-Offset",
+      "Upload-Length",
+      "Upload-Expires",
+      "Upload-Metadata",
+      "Tus-Resumable",
+      "Tus-Version",
+      "Tus-Extension",
+      "Location",
+    ])
     .allow_methods(&[
       Method::GET,
       Method::POST,
-      Method::DELETE
+      Method::DELETE,
+      Method::HEAD,
+      Method::PATCH,
+      Method::OPTIONS
     ]);
 
   // * GET grids (rest/grids/{image_file})
   let grids_get_route = warp::path!("rest" / "grids" / ..)
+    .and(read_only_auth.clone())
     .and(
       warp::fs::dir(grids_cache_dir).map(|reply| {
         warp::reply::with_header(
@@ -90,6 +133,7 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * POST capsule (rest/grids/capsules/{id})
   let capsule_upload_route = warp::path!("rest" / "grids" / "capsules" / String)
     .and(warp::post())
+    .and(uploader_auth.clone())
     .and(cache_dir_filter.clone())
     .and(json_capsule_upload())
     .and_then(upload_capsule)
@@ -98,6 +142,7 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * DELETE capsule (rest/grids/capsules/{id})
   let capsule_delete_route = warp::path!("rest" / "grids" / "capsules" / String)
     .and(warp::delete())
+    .and(uploader_auth.clone())
     .and(cache_dir_filter.clone())
     .and(warp::filters::header::header("Full-Capsule-Extension"))
     .and(warp::filters::header::header("Thumb-Capsule-Extension"))
@@ -107,6 +152,7 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * POST hero (rest/grids/heroes/{id})
   let hero_upload_route = warp::path!("rest" / "grids" / "heroes" / String)
     .and(warp::post())
+    .and(uploader_auth.clone())
     .and(cache_dir_filter.clone())
     .and(json_hero_upload())
     .and_then(upload_hero)
@@ -115,6 +161,7 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * DELETE hero (rest/grids/heroes/{id})
   let hero_delete_route = warp::path!("rest" / "grids" / "heroes" / String)
     .and(warp::delete())
+    .and(uploader_auth.clone())
     .and(cache_dir_filter.clone())
     .and(warp::filters::header::header("Hero-Extension"))
     .and_then(delete_hero)
@@ -124,6 +171,9 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * GET ROM Metadata (rest/roms/download/metadata)
   let rom_download_get_metadata = warp::path!("rest" / "roms" / "download" / "metadata")
     .and(warp::get())
+    .and(read_only_auth.clone())
+    .and(job_queue_filter.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(rom_download_get_metadata)
     .with(&cors);
@@ -131,15 +181,11 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * GET ROM (rest/roms/download)
   let rom_download_route = warp::path!("rest" / "roms" / "download")
     .and(warp::get())
+    .and(read_only_auth.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
-    .and_then(download_file)
-    .with(&cors);
-  
-  // * POST ROM (rest/roms/download/complete)
-  let rom_download_complete_route = warp::path!("rest" / "roms" / "download" / "complete")
-    .and(warp::post())
-    .and(json_body_download())
-    .and_then(rom_download_complete)
+    .and(warp::filters::header::headers_cloned())
+    .and_then(rom_download)
     .with(&cors);
 
 
@@ -150,7 +196,10 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * POST ROM (rest/roms/upload/complete)
   let rom_upload_complete_route = warp::path!("rest" / "roms" / "upload" / "complete")
     .and(warp::post())
+    .and(uploader_auth.clone())
+    .and(job_queue_filter.clone())
     .and(upload_store_filter.clone())
+    .and(path_resolver_filter.clone())
     .and(json_body_upload_complete())
     .and_then(rom_upload_complete)
     .with(&cors);
@@ -159,6 +208,8 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * DELETE ROM (rest/roms)
   let rom_delete_route = warp::path!("rest" / "roms" / "delete")
     .and(warp::delete())
+    .and(admin_auth.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(delete_rom)
     .with(&cors);
@@ -167,6 +218,8 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * GET BIOS Metadata (rest/bios-files/download/metadata)
   let bios_download_get_metadata = warp::path!("rest" / "bios-files" / "download" / "metadata")
     .and(warp::get())
+    .and(read_only_auth.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(bios_file_download_get_metadata)
     .with(&cors);
@@ -174,21 +227,28 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * GET BIOS (rest/bios-files/download)
   let bios_download_route = warp::path!("rest" / "bios-files" / "download")
     .and(warp::get())
+    .and(read_only_auth.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
+    .and(warp::filters::header::headers_cloned())
     .and_then(download_file)
     .with(&cors);
 
   // * POST BIOS (rest/bios-files/upload/complete)
   let bios_upload_complete_route = warp::path!("rest" / "bios-files" / "upload" / "complete")
     .and(warp::post())
+    .and(uploader_auth.clone())
     .and(upload_store_filter.clone())
     .and(warp::filters::header::header("Upload-Id"))
+    .and(warp::filters::header::headers_cloned())
     .and_then(bios_file_upload_complete)
     .with(&cors);
 
   // * DELETE BIOS (rest/bios-files)
   let bios_delete_route = warp::path!("rest" / "bios-files" / "delete")
     .and(warp::delete())
+    .and(admin_auth.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(delete_bios_file)
     .with(&cors);
@@ -197,6 +257,8 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * GET ROM Extras Metadata (rest/rom-extras/download/metadata)
   let rom_extras_download_get_metadata = warp::path!("rest" / "rom-extras" / "download" / "metadata")
     .and(warp::get())
+    .and(read_only_auth.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(rom_extra_download_get_metadata)
     .with(&cors);
@@ -204,21 +266,28 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * GET ROM Extras (rest/rom-extras/download)
   let rom_extras_download_route = warp::path!("rest" / "rom-extras" / "download")
     .and(warp::get())
+    .and(read_only_auth.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
+    .and(warp::filters::header::headers_cloned())
     .and_then(download_file)
     .with(&cors);
 
   // * POST ROM Extras (rest/rom-extras/upload/complete)
   let rom_extras_upload_complete_route = warp::path!("rest" / "rom-extras" / "upload" / "complete")
     .and(warp::post())
+    .and(uploader_auth.clone())
     .and(upload_store_filter.clone())
     .and(warp::filters::header::header("Upload-Id"))
+    .and(warp::filters::header::headers_cloned())
     .and_then(rom_extra_upload_complete)
     .with(&cors);
 
   // * DELETE ROM Extras (rest/rom-extras)
   let rom_extras_delete_route = warp::path!("rest" / "rom-extras" / "delete")
     .and(warp::delete())
+    .and(admin_auth.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(delete_rom_extra)
     .with(&cors);
@@ -227,6 +296,8 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * PREPARE FILE (rest/upload/prepare)
   let upload_prepare_route = warp::path!("rest" / "upload" / "prepare")
     .and(warp::post())
+    .and(uploader_auth.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(prepare_file_upload)
     .with(&cors);
@@ -235,6 +306,8 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * PREPARE REPLACE FILE (rest/upload/replace/prepare)
   let upload_replace_prepare_route = warp::path!("rest" / "upload" / "replace" / "prepare")
     .and(warp::post())
+    .and(uploader_auth.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(prepare_file_replace)
     .with(&cors);
@@ -242,8 +315,10 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * POST FILE (rest/upload)
   let upload_route = warp::path!("rest" / "upload")
     .and(warp::post())
+    .and(uploader_auth.clone())
     .and(warp::filters::body::stream())
     .and(upload_store_filter.clone())
+    .and(path_resolver_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and(warp::filters::header::headers_cloned())
     .and_then(upload_file)
@@ -252,23 +327,75 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   // * CANCEL FILE (rest/upload/cancel)
   let upload_cancel_route = warp::path!("rest" / "upload" / "cancel")
     .and(warp::post())
+    .and(uploader_auth.clone())
     .and(upload_store_filter.clone())
     .and(warp::filters::header::header("Upload-Id"))
     .and_then(upload_cancel)
     .with(&cors);
 
 
+  let tus_store = TusStore::new(load_tus_uploads());
+  let filter_tus_store = tus_store.clone();
+  let tus_store_filter = warp::any().map(move || filter_tus_store.clone());
+
+  // * TUS DISCOVERY (rest/tus)
+  let tus_options_route = warp::path!("rest" / "tus")
+    .and(warp::options())
+    .and_then(tus_options)
+    .with(&cors);
+
+  // * TUS CREATE (rest/tus)
+  let tus_create_route = warp::path!("rest" / "tus")
+    .and(warp::post())
+    .and(uploader_auth.clone())
+    .and(tus_store_filter.clone())
+    .and(path_resolver_filter.clone())
+    .and(warp::filters::header::headers_cloned())
+    .and_then(tus_create)
+    .with(&cors);
+
+  // * TUS OFFSET (rest/tus/{id})
+  let tus_head_route = warp::path!("rest" / "tus" / String)
+    .and(warp::head())
+    .and(uploader_auth.clone())
+    .and(tus_store_filter.clone())
+    .and(warp::filters::header::headers_cloned())
+    .and_then(tus_head)
+    .with(&cors);
+
+  // * TUS APPEND (rest/tus/{id})
+  let tus_patch_route = warp::path!("rest" / "tus" / String)
+    .and(warp::patch())
+    .and(uploader_auth.clone())
+    .and(warp::filters::body::stream())
+    .and(tus_store_filter.clone())
+    .and(warp::filters::header::headers_cloned())
+    .and_then(tus_patch)
+    .with(&cors);
+
+  // * TUS TERMINATE (rest/tus/{id})
+  let tus_terminate_route = warp::path!("rest" / "tus" / String)
+    .and(warp::delete())
+    .and(uploader_auth.clone())
+    .and(tus_store_filter.clone())
+    .and(warp::filters::header::headers_cloned())
+    .and_then(tus_terminate)
+    .with(&cors);
+
+
   let sgdb_client_store = SGDBClientStore::new();
   let sgdb_client_store_filter = warp::any().map(move || sgdb_client_store.clone());
 
   let sgdb_init_route = warp::path!("rest" / "proxy" / "sgdb" / "init")
     .and(warp::post())
+    .and(read_only_auth.clone())
     .and(sgdb_client_store_filter.clone())
     .and_then(init_sgdb_client)
     .with(&cors);
   
   let sgdb_get_grids_route = warp::path!("rest" / "proxy" / "sgdb" / "grids")
     .and(warp::get())
+    .and(read_only_auth.clone())
     .and(sgdb_client_store_filter.clone())
     .and(warp::filters::header::header("SGDB-Game-Id"))
     .and(warp::filters::header::header("SGDB-Results-Page"))
@@ -278,6 +405,7 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   
   let sgdb_search_game_route = warp::path!("rest" / "proxy" / "sgdb" / "search")
     .and(warp::get())
+    .and(read_only_auth.clone())
     .and(sgdb_client_store_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(sgdb_search_game)
@@ -290,19 +418,31 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
 
   let igdb_init_route = warp::path!("rest" / "proxy" / "igdb" / "init")
     .and(warp::post())
+    .and(read_only_auth.clone())
     .and(igdb_client_store_filter.clone())
     .and_then(init_igdb_client)
     .with(&cors);
   
   let igdb_get_metadata_route = warp::path!("rest" / "proxy" / "igdb" / "metadata")
     .and(warp::get())
+    .and(read_only_auth.clone())
     .and(igdb_client_store_filter.clone())
     .and(warp::filters::header::header("IGDB-Game-Id"))
     .and_then(igdb_get_metadata_by_id)
     .with(&cors);
+
+  let igdb_get_metadata_bulk_route = warp::path!("rest" / "proxy" / "igdb" / "metadata" / "bulk")
+    .and(warp::post())
+    .and(read_only_auth.clone())
+    .and(job_queue_filter.clone())
+    .and(igdb_client_store_filter.clone())
+    .and(json_body_igdb_bulk())
+    .and_then(igdb_get_metadata_bulk)
+    .with(&cors);
   
   let igdb_search_game_route = warp::path!("rest" / "proxy" / "igdb" / "search" / "games")
     .and(warp::get())
+    .and(read_only_auth.clone())
     .and(igdb_client_store_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(igdb_search_game)
@@ -310,6 +450,7 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   
   let igdb_search_platform_route = warp::path!("rest" / "proxy" / "igdb" / "search" / "platforms")
     .and(warp::get())
+    .and(read_only_auth.clone())
     .and(igdb_client_store_filter.clone())
     .and(warp::query::<HashMap<String, String>>())
     .and_then(igdb_search_platform)
@@ -320,6 +461,12 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
     .or(upload_route)
     .or(upload_cancel_route);
 
+  let tus_routes = tus_options_route
+    .or(tus_create_route)
+    .or(tus_head_route)
+    .or(tus_patch_route)
+    .or(tus_terminate_route);
+
   let grids_routes = grids_get_route
     .or(capsule_upload_route)
     .or(capsule_delete_route)
@@ -328,7 +475,6 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
   
   let rom_routes = rom_download_get_metadata
     .or(rom_download_route)
-    .or(rom_download_complete_route)
     .or(rom_upload_complete_route)
     .or(rom_delete_route);
 
@@ -349,48 +495,54 @@ pub fn initialize_rest_api(grids_cache_dir: String, cleanup_schedule: String) ->
 
   let igdb_routes = igdb_init_route
     .or(igdb_get_metadata_route)
+    .or(igdb_get_metadata_bulk_route)
     .or(igdb_search_game_route)
     .or(igdb_search_platform_route);
 
+  let job_scheduler = scheduler.clone();
+  let job_scheduler_filter = warp::any().map(move || job_scheduler.clone());
+
+  // * GET job statuses (rest/jobs)
+  let jobs_get_route = warp::path!("rest" / "jobs")
+    .and(warp::get())
+    .and(admin_auth.clone())
+    .and(job_scheduler_filter)
+    .and_then(get_job_statuses)
+    .with(&cors);
+
+
   let http_routes = grids_routes
     .or(upload_routes)
+    .or(tus_routes)
     .or(rom_routes)
     .or(bios_routes)
     .or(rom_extras_routes)
     .or(sgdb_routes)
-    .or(igdb_routes);
+    .or(igdb_routes)
+    .or(jobs_get_route);
 
 
   let cleanup_upload_store = upload_store.clone();
-  std::thread::spawn(move || {
-    info!("Thread: Starting failed download cleanup check...");
-
-    const DEAD_TIME_LENGTH: i64 = 60 * 60;
+  let cleanup_tus_store = tus_store.clone();
+  scheduler.add_job("clean_uploads", &get_job_schedule("NRM_UPLOAD_CLEAN_SCHEDULE", "0 0 3 * * * *"), move || {
+    let upload_store = cleanup_upload_store.clone();
+    let tus_store = cleanup_tus_store.clone();
 
-    let schedule = Schedule::from_str(&cleanup_schedule).expect("Failed to parse CRON expression");
+    async move {
+      let removed = remove_stale_uploads(&upload_store).await;
+      let expired = remove_expired_uploads(&tus_store).await;
 
-    for datetime in schedule.upcoming(Utc).take(1) {
-      let now = Utc::now();
-      let until = datetime - now;
-      thread::sleep(until.to_std().unwrap());
-
-
-      let streams = cleanup_upload_store.streams.blocking_read().clone();
-      for stream in streams.into_values() {
-        if now.timestamp() - stream.lastChunkTime > DEAD_TIME_LENGTH {
-          info!("Removing failed upload for path \"{}\"", stream.path);
-
-          let remove_res = remove_file(&stream.path);
-          if remove_res.is_err() {
-            warn!("Error deleting zipped rom folder: {}", remove_res.err().unwrap());
-          }
-
-          cleanup_upload_store.streams.blocking_write().remove(&stream.id);
-        }
-      }
+      return Ok(format!("Removed {} failed uploads and {} expired resumable uploads.", removed, expired));
     }
   });
 
+  scheduler.add_job("clean_zips", &get_job_schedule("NRM_ZIP_CLEAN_SCHEDULE", "0 30 3 * * * *"), || async {
+    let removed = remove_stale_packed_zips().await.map_err(|err| format!("Failed to clean packed zips: {}", err))?;
+    return Ok(format!("Removed {} stale packed zips.", removed));
+  });
+
 
-  return http_routes;
+  return http_routes
+    .recover(handle_auth_rejection)
+    .recover(handle_path_rejection);
 }
\ No newline at end of file
@@ -1,15 +1,42 @@
-use std::{collections::HashMap, ffi::OsStr, path::PathBuf};
+use std::{collections::HashMap, ffi::OsStr, path::{Path, PathBuf}};
 
 use log::warn;
+use serde_json::json;
 use warp::{
+  http::{HeaderMap, Response, StatusCode},
   reject::Rejection,
   reply::Reply
 };
 
-use super::{types::ROMDownload, utils::download::get_file_metadata, zip::pack_zip};
+use crate::{job_queue::JobQueue, websocket::{parsers::load_parser, types::library::ZipCompression}};
 
-/// Gets the needed metadata for downloading a rom, and zips its folder if necessary.
-pub async fn rom_download_get_metadata(query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
+use super::{jobs::job_queued_reply, utils::{download::{download_file, download_path, get_download_metadata, get_file_metadata}, paths::PathResolver}, zip::{find_packed_zip, get_packed_zip}, zip_stream::FolderZip};
+
+/// Gets the folder a rom is stored in, if it's downloaded as a folder.
+fn get_rom_dir(file_path: &Path, parent: &str) -> Option<PathBuf> {
+  let os_parent_dir = OsStr::new(parent);
+
+  for ancestor in file_path.ancestors() {
+    if ancestor.parent().and_then(|parent| parent.file_name()) == Some(os_parent_dir) {
+      return Some(ancestor.to_path_buf());
+    }
+  }
+
+  return None;
+}
+
+/// Gets how a system's folder roms should be compressed.
+fn get_zip_compression(query_params: &HashMap<String, String>) -> ZipCompression {
+  return query_params.get("romSystem")
+    .and_then(|system| load_parser(system))
+    .map(|parser| parser.zipCompression)
+    .unwrap_or_default();
+}
+
+/// Gets the needed metadata for downloading a rom. Folder roms are downloaded as a ZIP, so this gets the size of the archive.
+/// The path of folder roms is the folder itself, which is what should be passed to the download route.
+/// If a compressed folder needs to be packed first, a job is queued and its id is returned with a 202 instead.
+pub async fn rom_download_get_metadata(job_queue: JobQueue, resolver: PathResolver, query_params: HashMap<String, String>) -> Result<warp::reply::Response, Rejection> {
   if !query_params.contains_key("romPath") {
     warn!("Get ROM Metadata: Missing query param romPath");
     return Err(warp::reject::reject());
@@ -22,57 +49,105 @@ pub async fn rom_download_get_metadata(query_params: HashMap<String, String>) ->
   }
   let parent = query_params.get("romParent").unwrap().to_owned();
   
-  let mut file_path = PathBuf::from(&path);
+  let file_path = resolver.resolve(&path).await?;
 
+  if parent.is_empty() {
+    return get_file_metadata(&file_path).await;
+  }
 
-  if parent != String::from("") {
-    let os_parent_dir = OsStr::new(&parent);
+  let rom_dir = get_rom_dir(&file_path, &parent);
+  if rom_dir.as_ref().is_none_or(|rom_dir| rom_dir == &file_path && !rom_dir.is_dir()) {
+    warn!("Get ROM Metadata: The path \"{}\" does not contain \"{}\"", &path, &parent);
+    return Err(warp::reject::reject());
+  }
+  let rom_dir = rom_dir.unwrap();
 
-    let mut rom_dir = file_path.clone();
+  let compression = get_zip_compression(&query_params);
+  if compression != ZipCompression::STORED {
+    let packed_path = find_packed_zip(&rom_dir).await.map_err(|e| {
+      warn!("Get ROM Metadata: Error checking packed rom folder: {}", e);
+      warp::reject::reject()
+    })?;
 
-    for ancestor in file_path.ancestors() {
-      if ancestor.parent().unwrap().file_name().unwrap() == os_parent_dir {
-        rom_dir = ancestor.to_path_buf();
-        break;
-      }
+    if packed_path.is_some() {
+      let metadata = tokio::fs::metadata(packed_path.unwrap()).await.map_err(|_| warp::reject())?;
+      return get_download_metadata(&rom_dir, metadata.len());
     }
 
-    if rom_dir == file_path {
-      warn!("Error zipping rom folder: The path \"{}\" does not contain \"{}\"", &path, &parent);
-      return Err(warp::reject::reject());
+    // * Packing can take minutes, so it happens in the background. Clients ask again once the job is done.
+    let target = rom_dir.to_string_lossy().to_string();
+    let active_job = job_queue.find_active("pack_zip", &target);
+    if active_job.is_some() {
+      return job_queued_reply(&active_job.unwrap());
     }
 
-    file_path = pack_zip(&rom_dir)
-      .await
-      .map_err(|e| {
-        warn!("Error zipping rom folder: {}", e);
-        warp::reject::reject()
-      })?;
+    let handle = job_queue.spawn("pack_zip", &target, move |job| async move {
+      let packed_path = get_packed_zip(&rom_dir, compression, Some(&job)).await.map_err(|e| e.to_string())?;
+      let metadata = tokio::fs::metadata(&packed_path).await.map_err(|e| e.to_string())?;
+
+      return Ok(json!({ "size": metadata.len() }));
+    });
+
+    return job_queued_reply(&handle.id);
   }
 
+  let folder_zip = FolderZip::new(&rom_dir).await.map_err(|e| {
+    warn!("Get ROM Metadata: Failed to list rom folder: {}", e);
+    warp::reject::reject()
+  })?;
 
-  return get_file_metadata(&file_path).await;
+  return get_download_metadata(&rom_dir, folder_zip.size());
 }
 
-/// Handles cleanup after a rom download finished.
-pub async fn rom_download_complete(data: ROMDownload) -> Result<impl Reply, Rejection> {
-  if data.parent != String::from("") {
-    tokio::fs::remove_file(&data.path).await.map_err(|e| {
-      warn!("(Complete) Error deleting zipped rom folder: {}", e);
-      warp::reject::reject()
-    })?;
+/// Handles downloading a rom. Folders are streamed as an uncompressed ZIP without writing anything to disk, unless their system compresses them.
+pub async fn rom_download(resolver: PathResolver, query_params: HashMap<String, String>, headers: HeaderMap) -> Result<warp::reply::Response, Rejection> {
+  let path = query_params.get("filePath").cloned().unwrap_or_default();
+  let file_path = resolver.resolve(&path).await?;
+
+  if !file_path.is_dir() {
+    return download_file(resolver, query_params, headers).await;
   }
 
-  return Ok(warp::reply::with_header("success", "Access-Control-Allow-Origin", "*"));
+  // * Compressed folders are packed ahead of time, so they can be resumed like any other file.
+  let compression = get_zip_compression(&query_params);
+  if compression != ZipCompression::STORED {
+    let packed_path_res = get_packed_zip(&file_path, compression, None).await;
+    if packed_path_res.is_err() {
+      warn!("Download ROM: Error zipping rom folder: {}", packed_path_res.err().unwrap());
+      return Ok(Response::builder().status(StatusCode::NOT_FOUND).body("".into()).unwrap());
+    }
+
+    return download_path(&packed_path_res.unwrap(), &headers).await;
+  }
+
+  let folder_zip_res = FolderZip::new(&file_path).await;
+  if folder_zip_res.is_err() {
+    warn!("Download ROM: Failed to list rom folder: {}", folder_zip_res.err().unwrap());
+    return Ok(Response::builder().status(StatusCode::NOT_FOUND).body("".into()).unwrap());
+  }
+  let folder_zip = folder_zip_res.unwrap();
+
+  let filename = format!("{}.zip", file_path.file_name().unwrap().to_str().unwrap());
+
+  // * CRCs are computed while streaming, so the archive can't be resumed partway through.
+  return Response::builder()
+    .status(StatusCode::OK)
+    .header("Accept-Ranges", "none")
+    .header("Content-Length", folder_zip.size().to_string())
+    .header("Content-Type", "application/zip")
+    .header("Content-Disposition", format!("attachement; filename = \"{}\"", filename))
+    .header("Access-Control-Allow-Origin", "*")
+    .body(folder_zip.into_body())
+    .map_err(|_| warp::reject());
 }
 
 /// Handles deleting a rom.
-pub async fn delete_rom(query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
+pub async fn delete_rom(resolver: PathResolver, query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
   if !query_params.contains_key("romPath") {
     warn!("Delete ROM: Missing query param romPath");
     return Err(warp::reject::reject());
   }
-  let path = query_params.get("romPath").unwrap().to_owned();
+  let path = resolver.resolve_descendant(query_params.get("romPath").unwrap()).await?;
   
   tokio::fs::remove_file(&path).await.map_err(|e| {
     warn!("Error deleting rom file: {}", e);
@@ -1,55 +1,60 @@
-use std::{path::PathBuf};
-
 use log::{info, warn};
-use tokio::fs::File;
+use serde_json::json;
 use warp::{reject::Rejection, reply::Reply};
 
-use crate::rest::zip::unpack_zip;
+use crate::{job_queue::JobQueue, rest::archive::unpack_archive};
 
-use super::{types::{ROMUploadComplete, StreamStore}};
+use super::{jobs::job_queued_reply, types::{ROMUploadComplete, StreamStore, UploadChecksums}, utils::{paths::PathResolver, upload::{verification_failed_reply, verify_upload}}};
 
-/// Completes the ROM upload
-pub async fn rom_upload_complete(streams_store: StreamStore, data: ROMUploadComplete) -> Result<impl Reply, Rejection> {
-  streams_store.streams.write().await.remove(&data.uploadId);
-  
-  let mut final_path = data.path.clone();
+/// Completes the ROM upload, once it's been verified.
+/// Archives are extracted by a job, so its id is returned with a 202, and the extracted path is the job's result.
+pub async fn rom_upload_complete(job_queue: JobQueue, streams_store: StreamStore, resolver: PathResolver, data: ROMUploadComplete) -> Result<warp::reply::Response, Rejection> {
+  let upload_path = resolver.resolve(&data.path).await?;
 
-  if data.unzip {
-    let file = File::open(&data.path)
-      .await
-      .map_err(|e| {
-        warn!("(Complete) Error opening zip: {}", e);
-        warp::reject::reject()
-      })?;
-    
-    let folder_path = format!("{}/{}", data.libraryPath, data.system);
-    let output_path = PathBuf::from(folder_path);
+  let checksums = UploadChecksums {
+    sha256: data.sha256.clone(),
+    crc32: data.crc32.clone(),
+  };
 
-    let unzipped_path = unpack_zip(file, &output_path)
-      .await
-      .map_err(|e| {
-        warn!("(Complete) Error unpacking zip: {}", e);
-        warp::reject::reject()
+  let verify_res = verify_upload(&streams_store, &data.uploadId, Some(&upload_path), &checksums).await;
+  if verify_res.is_err() {
+    return verification_failed_reply(verify_res.err().unwrap());
+  }
+
+  if data.extract {
+    let folder_path = format!("{}/{}", data.libraryPath, data.system);
+    let output_path = resolver.resolve(&folder_path).await?;
+
+    let target = data.path.clone();
+    let handle = job_queue.spawn("extract_archive", &target, move |job| async move {
+      let extracted_path = unpack_archive(&upload_path, &output_path, &job)
+        .await
+        .map_err(|e| {
+          warn!("(Complete) Error extracting archive: {}", e);
+          e
+        })?;
+
+      info!("Extracted file: {}", data.path);
+
+      tokio::fs::remove_file(&upload_path).await.map_err(|e| {
+        warn!("(Complete) Error deleting archived rom folder: {}", e);
+        e.to_string()
       })?;
-    
-    final_path = unzipped_path.to_str().unwrap().to_string();
 
-    info!("Unzipped file: {}", data.path);
+      info!("(Complete) Deleted file: {}", data.path);
+
+      return Ok(json!({ "path": extracted_path.to_str().unwrap() }));
+    });
 
-    tokio::fs::remove_file(&data.path).await.map_err(|e| {
-      warn!("(Complete) Error deleting zipped rom folder: {}", e);
-      warp::reject::reject()
-    })?;
-    
-    info!("(Complete) Deleted file: {}", data.path);
+    return job_queued_reply(&handle.id);
   }
 
   let response = warp::http::Response::builder()
     .status(200)
     .header("Content-Type", "text/plain")
     .header("Access-Control-Allow-Origin", "*")
-    .body(final_path)
+    .body(data.path)
     .map_err(|_| warp::reject())?;
 
-  return Ok(response);
+  return Ok(response.into_response());
 }
\ No newline at end of file
@@ -0,0 +1,529 @@
+use std::{collections::HashMap, env::var, fs, io::SeekFrom, path::{Path, PathBuf}};
+
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine};
+use bytes::Buf;
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use log::{info, warn};
+use tokio::{fs::OpenOptions, io::{AsyncSeekExt, AsyncWriteExt}};
+use warp::{http::{response::Builder, HeaderMap, Response, StatusCode}, reject::Rejection};
+
+use crate::websocket::config_files::write_atomic;
+
+use super::{types::{TusStore, TusUpload, UploadChecksums}, utils::{paths::PathResolver, upload::verify_file}};
+
+/// The version of the tus protocol that's supported.
+const TUS_VERSION: &str = "1.0.0";
+/// The tus extensions that are supported.
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+/// How long an unfinished upload is kept after it last received data, in seconds.
+const UPLOAD_EXPIRATION: i64 = 24 * 60 * 60;
+
+/// Gets the folder tus upload state is saved in.
+fn get_state_dir() -> PathBuf {
+  let config_path = PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap());
+  return config_path.join("tus");
+}
+
+/// Gets the path of an upload's state file.
+fn get_state_path(id: &str) -> PathBuf {
+  return get_state_dir().join(format!("{}.json", id));
+}
+
+/// Gets the path of the file an upload's data is written to until it's complete.
+/// Partial uploads are kept with the upload state, so the library scanner never sees them.
+fn get_part_path(upload: &TusUpload) -> PathBuf {
+  return get_state_dir().join(format!("{}.part", upload.id));
+}
+
+/// Saves an upload's state, so it can be resumed after a restart.
+async fn write_state(upload: &TusUpload) {
+  let state_path = get_state_path(&upload.id);
+  let state_str = serde_json::to_string(upload).expect("Tus upload was malformatted.");
+
+  let write_res = tokio::task::spawn_blocking(move || {
+    fs::create_dir_all(get_state_dir())?;
+    return write_atomic(&state_path, &state_str);
+  }).await.unwrap_or_else(|e| Err(std::io::Error::other(e)));
+
+  if write_res.is_err() {
+    warn!("Tus: Failed to save upload \"{}\": {}", upload.id, write_res.err().unwrap());
+  }
+}
+
+/// Deletes an upload's state, along with its data if it didn't finish.
+async fn remove_upload_files(upload: &TusUpload) {
+  let _ = tokio::fs::remove_file(get_state_path(&upload.id)).await;
+  let _ = tokio::fs::remove_file(get_part_path(upload)).await;
+}
+
+/// Loads the saved uploads from the config directory.
+/// Offsets are taken from the data that was actually written, and expired uploads are removed.
+pub fn load_tus_uploads() -> HashMap<String, TusUpload> {
+  let mut uploads = HashMap::new();
+
+  let entries_res = fs::read_dir(get_state_dir());
+  if entries_res.is_err() {
+    return uploads;
+  }
+
+  let now = Utc::now().timestamp();
+
+  for entry in entries_res.unwrap().flatten() {
+    let state_path = entry.path();
+    if state_path.extension().is_none_or(|extension| extension != "json") {
+      continue;
+    }
+
+    let upload_res = fs::read_to_string(&state_path)
+      .map_err(|e| e.to_string())
+      .and_then(|state_str| serde_json::from_str::<TusUpload>(&state_str).map_err(|e| e.to_string()));
+
+    if upload_res.is_err() {
+      warn!("Tus: Failed to load \"{}\": {}", state_path.display(), upload_res.err().unwrap());
+      let _ = fs::remove_file(&state_path);
+      continue;
+    }
+    let mut upload = upload_res.unwrap();
+
+    let part_path = get_part_path(&upload);
+    let part_length = fs::metadata(&part_path).map(|metadata| metadata.len());
+
+    if upload.expires <= now || part_length.is_err() {
+      info!("Tus: Removing expired upload for \"{}\".", upload.filePath);
+      let _ = fs::remove_file(&state_path);
+      let _ = fs::remove_file(&part_path);
+      continue;
+    }
+
+    // * The server may have stopped before the state was saved.
+    upload.offset = part_length.unwrap().min(upload.length);
+    uploads.insert(upload.id.clone(), upload);
+  }
+
+  info!("Tus: Loaded {} unfinished uploads.", uploads.len());
+
+  return uploads;
+}
+
+/// Removes uploads that haven't received data before they expired. Returns the number of uploads that were removed.
+pub async fn remove_expired_uploads(tus_store: &TusStore) -> usize {
+  let now = Utc::now().timestamp();
+
+  let mut uploads = tus_store.uploads.write().await;
+  let expired: Vec<TusUpload> = uploads.values()
+    .filter(|upload| upload.expires <= now && !upload.receivingChunk)
+    .cloned()
+    .collect();
+
+  for upload in expired.iter() {
+    info!("Tus: Removing expired upload for \"{}\".", upload.filePath);
+    uploads.remove(&upload.id);
+    remove_upload_files(upload).await;
+  }
+
+  return expired.len();
+}
+
+/// Parses an Upload-Metadata header. Values are base64 encoded, and keys may not have a value.
+fn parse_metadata(metadata: &str) -> Option<HashMap<String, String>> {
+  let mut parsed = HashMap::new();
+
+  for pair in metadata.split(',').map(|pair| pair.trim()).filter(|pair| !pair.is_empty()) {
+    let (key, value) = pair.split_once(' ').unwrap_or((pair, ""));
+
+    let decoded = STANDARD.decode(value.trim()).ok()?;
+    parsed.insert(key.to_string(), String::from_utf8(decoded).ok()?);
+  }
+
+  return Some(parsed);
+}
+
+/// Formats a timestamp for the Upload-Expires header.
+fn format_expires(expires: i64) -> String {
+  let expires = DateTime::<Utc>::from_timestamp(expires, 0).unwrap_or_default();
+  return expires.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+}
+
+/// Starts a response with the headers every tus response needs.
+fn tus_response(status: StatusCode) -> Builder {
+  return Response::builder()
+    .status(status)
+    .header("Tus-Resumable", TUS_VERSION)
+    .header("Access-Control-Allow-Origin", "*")
+    .header("Cache-Control", "no-store");
+}
+
+/// Builds a response with a plain text body.
+fn tus_error(status: StatusCode, message: &str) -> Result<Response<String>, Rejection> {
+  return tus_response(status)
+    .header("Content-Type", "text/plain")
+    .body(message.to_string())
+    .map_err(|_| warp::reject());
+}
+
+/// Checks that the client is using a supported version of tus.
+fn check_tus_version(headers: &HeaderMap) -> Option<Result<Response<String>, Rejection>> {
+  let version = headers.get("tus-resumable").and_then(|version| version.to_str().ok());
+  if version == Some(TUS_VERSION) {
+    return None;
+  }
+
+  return Some(
+    tus_response(StatusCode::PRECONDITION_FAILED)
+      .header("Tus-Version", TUS_VERSION)
+      .body(String::new())
+      .map_err(|_| warp::reject())
+  );
+}
+
+/// Gets a u64 header.
+fn get_u64_header(headers: &HeaderMap, name: &str) -> Option<u64> {
+  return headers.get(name)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.trim().parse::<u64>().ok());
+}
+
+/// Moves a file, copying it if the destination is on a different filesystem.
+async fn move_file(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+  if tokio::fs::rename(from, to).await.is_err() {
+    let copy_res = tokio::fs::copy(from, to).await;
+    if copy_res.is_err() {
+      let _ = tokio::fs::remove_file(to).await;
+      return Err(copy_res.err().unwrap());
+    }
+
+    tokio::fs::remove_file(from).await?;
+  }
+
+  return Ok(());
+}
+
+/// Moves a completed upload to its destination, once it's been verified.
+/// The upload's state is kept until the file is in place, so it can be finished again if moving it fails.
+async fn finish_upload(upload: &TusUpload) -> Result<(), (StatusCode, String)> {
+  let part_path = get_part_path(upload);
+  let state_path = get_state_path(&upload.id);
+
+  let metadata = parse_metadata(&upload.metadata).unwrap_or_default();
+  let checksums = UploadChecksums {
+    sha256: metadata.get("sha256").cloned(),
+    crc32: metadata.get("crc32").cloned(),
+  };
+
+  // * Uploads that fail verification are quarantined, so there's nothing left to resume.
+  let verify_res = verify_file(&part_path, &checksums).await;
+  if verify_res.is_err() {
+    let _ = tokio::fs::remove_file(&state_path).await;
+    return Err((StatusCode::UNPROCESSABLE_ENTITY, verify_res.err().unwrap()));
+  }
+
+  let file_path = Path::new(&upload.filePath);
+  if tokio::fs::try_exists(file_path).await.unwrap_or(true) {
+    remove_upload_files(upload).await;
+    return Err((StatusCode::CONFLICT, String::from("A file was added at the upload's path while it was uploading.")));
+  }
+
+  move_file(&part_path, file_path).await.map_err(|e| {
+    warn!("Tus: Failed to move \"{}\" to its destination: {}", part_path.display(), e);
+    (StatusCode::INTERNAL_SERVER_ERROR, String::from("Failed to move the upload to its destination."))
+  })?;
+
+  let _ = tokio::fs::remove_file(&state_path).await;
+
+  info!("Tus: Finished upload for \"{}\".", upload.filePath);
+
+  return Ok(());
+}
+
+/// Describes the server's tus support.
+pub async fn tus_options() -> Result<Response<String>, Rejection> {
+  return tus_response(StatusCode::NO_CONTENT)
+    .header("Tus-Version", TUS_VERSION)
+    .header("Tus-Extension", TUS_EXTENSIONS)
+    .body(String::new())
+    .map_err(|_| warp::reject());
+}
+
+/// Creates a tus upload. The destination is set by the filePath metadata.
+pub async fn tus_create(tus_store: TusStore, resolver: PathResolver, headers: HeaderMap) -> Result<Response<String>, Rejection> {
+  let version_res = check_tus_version(&headers);
+  if version_res.is_some() {
+    return version_res.unwrap();
+  }
+
+  let length = get_u64_header(&headers, "upload-length");
+  if length.is_none() {
+    return tus_error(StatusCode::BAD_REQUEST, "Upload-Length is required.");
+  }
+  let length = length.unwrap();
+
+  let metadata_str = headers.get("upload-metadata").and_then(|metadata| metadata.to_str().ok()).unwrap_or_default().to_string();
+  let metadata = parse_metadata(&metadata_str);
+  if metadata.is_none() {
+    return tus_error(StatusCode::BAD_REQUEST, "Upload-Metadata values must be base64 encoded.");
+  }
+
+  let file_path = metadata.unwrap().get("filePath").cloned();
+  if file_path.is_none() {
+    return tus_error(StatusCode::BAD_REQUEST, "The filePath metadata is required.");
+  }
+  let file_path = resolver.resolve(&file_path.unwrap()).await?;
+  let file_path_str = file_path.to_str().unwrap().to_string();
+
+  remove_expired_uploads(&tus_store).await;
+
+  let mut uploads = tus_store.uploads.write().await;
+  let is_uploading = uploads.values().any(|upload| upload.filePath == file_path_str);
+  if is_uploading || tokio::fs::try_exists(&file_path).await.unwrap_or(true) {
+    return tus_error(StatusCode::CONFLICT, "A file with that path already exists.");
+  }
+
+  let upload = TusUpload {
+    id: URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>()),
+    filePath: file_path_str,
+    length,
+    offset: 0,
+    metadata: metadata_str,
+    expires: Utc::now().timestamp() + UPLOAD_EXPIRATION,
+    receivingChunk: false,
+  };
+
+  let parent_dir = file_path.parent();
+  if parent_dir.is_some() {
+    tokio::fs::create_dir_all(parent_dir.unwrap()).await.map_err(|e| {
+      warn!("Tus: Error creating parent directory: {}", e);
+      warp::reject::reject()
+    })?;
+  }
+
+  let _ = tokio::fs::create_dir_all(get_state_dir()).await;
+  tokio::fs::File::create(get_part_path(&upload)).await.map_err(|e| {
+    warn!("Tus: Error creating file: {}", e);
+    warp::reject::reject()
+  })?;
+
+  // * Empty files don't need any data, so they're finished right away.
+  if length == 0 {
+    drop(uploads);
+
+    let finish_res = finish_upload(&upload).await;
+    if finish_res.is_err() {
+      let (status, message) = finish_res.err().unwrap();
+      return tus_error(status, &message);
+    }
+  } else {
+    write_state(&upload).await;
+    uploads.insert(upload.id.clone(), upload.clone());
+  }
+
+  info!("Tus: Created upload \"{}\" for \"{}\".", upload.id, upload.filePath);
+
+  return tus_response(StatusCode::CREATED)
+    .header("Location", format!("/rest/tus/{}", upload.id))
+    .header("Upload-Expires", format_expires(upload.expires))
+    .body(String::new())
+    .map_err(|_| warp::reject());
+}
+
+/// Gets the offset of a tus upload, so the client knows where to resume from.
+pub async fn tus_head(id: String, tus_store: TusStore, headers: HeaderMap) -> Result<Response<String>, Rejection> {
+  let version_res = check_tus_version(&headers);
+  if version_res.is_some() {
+    return version_res.unwrap();
+  }
+
+  let uploads = tus_store.uploads.read().await;
+  let upload = uploads.get(&id);
+  if upload.is_none() || upload.is_some_and(|upload| upload.expires <= Utc::now().timestamp()) {
+    return tus_error(StatusCode::NOT_FOUND, "");
+  }
+  let upload = upload.unwrap();
+
+  let mut response = tus_response(StatusCode::OK)
+    .header("Upload-Offset", upload.offset.to_string())
+    .header("Upload-Length", upload.length.to_string())
+    .header("Upload-Expires", format_expires(upload.expires));
+
+  if !upload.metadata.is_empty() {
+    response = response.header("Upload-Metadata", &upload.metadata);
+  }
+
+  return response.body(String::new()).map_err(|_| warp::reject());
+}
+
+/// Writes a chunk of a tus upload at the upload's offset.
+/// Everything that's received is kept, even if the request is interrupted.
+async fn write_tus_chunk(
+  mut body: impl Stream<Item = Result<impl Buf, warp::Error>> + Unpin + Send + Sync,
+  part_path: &Path,
+  offset: u64,
+  max_length: u64
+) -> (u64, Result<(), (StatusCode, String)>) {
+  let file_res = OpenOptions::new().write(true).open(part_path).await;
+  if file_res.is_err() {
+    warn!("Tus: Error opening \"{}\": {}", part_path.display(), file_res.err().unwrap());
+    return (0, Err((StatusCode::INTERNAL_SERVER_ERROR, String::from("Failed to open the upload."))));
+  }
+  let mut file = file_res.unwrap();
+
+  let seek_res = file.seek(SeekFrom::Start(offset)).await;
+  if seek_res.is_err() {
+    warn!("Tus: Error seeking \"{}\": {}", part_path.display(), seek_res.err().unwrap());
+    return (0, Err((StatusCode::INTERNAL_SERVER_ERROR, String::from("Failed to open the upload."))));
+  }
+
+  let mut written = 0;
+  let mut write_res = Ok(());
+
+  while let Some(buf) = body.next().await {
+    if buf.is_err() {
+      write_res = Err((StatusCode::BAD_REQUEST, format!("Error receiving data: {}", buf.err().unwrap())));
+      break;
+    }
+    let mut buf = buf.unwrap();
+
+    if written + buf.remaining() as u64 > max_length {
+      write_res = Err((StatusCode::PAYLOAD_TOO_LARGE, String::from("The data is larger than the rest of the upload.")));
+      break;
+    }
+
+    let chunk_length = buf.remaining() as u64;
+    let buf_res = file.write_all_buf(&mut buf).await;
+    if buf_res.is_err() {
+      warn!("Tus: Error writing to \"{}\": {}", part_path.display(), buf_res.err().unwrap());
+      write_res = Err((StatusCode::INTERNAL_SERVER_ERROR, String::from("Failed to write the upload.")));
+      break;
+    }
+
+    written += chunk_length;
+  }
+
+  let _ = file.flush().await;
+
+  // * Only keep whole buffers, so the offset matches the file.
+  let _ = file.set_len(offset + written).await;
+
+  return (written, write_res);
+}
+
+/// Appends data to a tus upload. Once all of the data is received, the upload is verified and moved to its destination.
+pub async fn tus_patch(
+  id: String,
+  body: impl Stream<Item = Result<impl Buf, warp::Error>> + Unpin + Send + Sync,
+  tus_store: TusStore,
+  headers: HeaderMap
+) -> Result<Response<String>, Rejection> {
+  let version_res = check_tus_version(&headers);
+  if version_res.is_some() {
+    return version_res.unwrap();
+  }
+
+  let content_type = headers.get("content-type").and_then(|content_type| content_type.to_str().ok());
+  if content_type != Some("application/offset+octet-stream") {
+    return tus_error(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Content-Type must be application/offset+octet-stream.");
+  }
+
+  let offset = get_u64_header(&headers, "upload-offset");
+  if offset.is_none() {
+    return tus_error(StatusCode::BAD_REQUEST, "Upload-Offset is required.");
+  }
+  let offset = offset.unwrap();
+
+  // * Claim the upload while data is written, so requests can't write at the same time.
+  let mut uploads = tus_store.uploads.write().await;
+  let upload = uploads.get_mut(&id);
+  if upload.is_none() {
+    return tus_error(StatusCode::NOT_FOUND, "");
+  }
+  let upload = upload.unwrap();
+
+  if upload.expires <= Utc::now().timestamp() {
+    return tus_error(StatusCode::GONE, "The upload expired.");
+  }
+
+  if upload.receivingChunk || upload.offset != offset {
+    return tus_response(StatusCode::CONFLICT)
+      .header("Upload-Offset", upload.offset.to_string())
+      .body(String::from("Upload-Offset doesn't match the upload's offset."))
+      .map_err(|_| warp::reject());
+  }
+
+  upload.receivingChunk = true;
+  let part_path = get_part_path(upload);
+  let max_length = upload.length - upload.offset;
+  drop(uploads);
+
+  let (written, write_res) = write_tus_chunk(body, &part_path, offset, max_length).await;
+
+  let mut uploads = tus_store.uploads.write().await;
+  let upload = uploads.get_mut(&id);
+  if upload.is_none() {
+    // * The upload was terminated while data was being written.
+    return tus_error(StatusCode::NOT_FOUND, "");
+  }
+  let upload = upload.unwrap();
+
+  upload.receivingChunk = false;
+  upload.offset += written;
+  upload.expires = Utc::now().timestamp() + UPLOAD_EXPIRATION;
+
+  let upload = upload.clone();
+  let is_complete = write_res.is_ok() && upload.offset == upload.length;
+
+  if is_complete {
+    uploads.remove(&id);
+  }
+  drop(uploads);
+
+  if write_res.is_err() {
+    write_state(&upload).await;
+
+    let (status, message) = write_res.err().unwrap();
+    return tus_error(status, &message);
+  }
+
+  if is_complete {
+    write_state(&upload).await;
+
+    let finish_res = finish_upload(&upload).await;
+    if finish_res.is_err() {
+      // * If the data is still there, the client can send an empty chunk to try finishing it again.
+      if tokio::fs::try_exists(get_part_path(&upload)).await.unwrap_or(false) {
+        tus_store.uploads.write().await.insert(upload.id.clone(), upload.clone());
+      }
+
+      let (status, message) = finish_res.err().unwrap();
+      return tus_error(status, &message);
+    }
+  } else {
+    write_state(&upload).await;
+  }
+
+  return tus_response(StatusCode::NO_CONTENT)
+    .header("Upload-Offset", upload.offset.to_string())
+    .header("Upload-Expires", format_expires(upload.expires))
+    .body(String::new())
+    .map_err(|_| warp::reject());
+}
+
+/// Cancels a tus upload, and deletes its data.
+pub async fn tus_terminate(id: String, tus_store: TusStore, headers: HeaderMap) -> Result<Response<String>, Rejection> {
+  let version_res = check_tus_version(&headers);
+  if version_res.is_some() {
+    return version_res.unwrap();
+  }
+
+  let upload = tus_store.uploads.write().await.remove(&id);
+  if upload.is_none() {
+    return tus_error(StatusCode::NOT_FOUND, "");
+  }
+  let upload = upload.unwrap();
+
+  remove_upload_files(&upload).await;
+  info!("Tus: Terminated upload for \"{}\".", upload.filePath);
+
+  return tus_response(StatusCode::NO_CONTENT)
+    .body(String::new())
+    .map_err(|_| warp::reject());
+}
@@ -3,6 +3,7 @@ pub mod igdb;
 
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
+use warp::http::HeaderMap;
 
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -28,13 +29,6 @@ pub struct CapsuleUpload {
   pub timeout: u64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-#[allow(non_snake_case)]
-pub struct ROMDownload {
-  pub path: String,
-  pub parent: String,
-}
-
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct ROMUploadComplete {
@@ -42,7 +36,38 @@ pub struct ROMUploadComplete {
   pub path: String,
   pub libraryPath: String,
   pub system: String,
-  pub unzip: bool,
+  #[serde(alias = "unzip")]
+  pub extract: bool,
+  #[serde(default)]
+  pub sha256: Option<String>,
+  #[serde(default)]
+  pub crc32: Option<String>,
+}
+
+/// The games to fetch IGDB metadata for in one job.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct IGDBBulkMetadata {
+  pub igdbIds: Vec<String>,
+}
+
+/// The checksums a client can send to verify a completed upload.
+#[derive(Clone, Debug, Default)]
+pub struct UploadChecksums {
+  pub sha256: Option<String>,
+  pub crc32: Option<String>,
+}
+
+impl UploadChecksums {
+  /// Reads the checksums from the Upload-Sha256 and Upload-Crc32 headers.
+  pub fn from_headers(headers: &HeaderMap) -> Self {
+    let get_header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_string());
+
+    return UploadChecksums {
+      sha256: get_header("upload-sha256"),
+      crc32: get_header("upload-crc32"),
+    };
+  }
 }
 
 
@@ -56,6 +81,7 @@ pub struct StreamProgress {
   pub currentSize: u64,
   pub totalSize: u64,
   pub lastChunkTime: i64,
+  pub receivingChunk: bool,
 }
 
 #[derive(Clone)]
@@ -71,6 +97,33 @@ impl StreamStore {
   }
 }
 
+/// A tus upload. These are persisted in the config directory, so they can be resumed after a restart.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[allow(non_snake_case)]
+pub struct TusUpload {
+  pub id: String,
+  pub filePath: String,
+  pub length: u64,
+  pub offset: u64,
+  pub metadata: String,
+  pub expires: i64,
+  #[serde(skip)]
+  pub receivingChunk: bool,
+}
+
+#[derive(Clone)]
+pub struct TusStore {
+  pub uploads: Arc<RwLock<HashMap<String, TusUpload>>>
+}
+
+impl TusStore {
+  pub fn new(uploads: HashMap<String, TusUpload>) -> Self {
+    TusStore {
+      uploads: Arc::new(RwLock::new(uploads)),
+    }
+  }
+}
+
 #[derive(Clone)]
 pub struct SGDBClientStore {
   pub client: Arc<RwLock<SGDBClient>>
@@ -1,22 +1,27 @@
-use std::{collections::HashMap, path::Path};
+use std::{collections::HashMap, io::SeekFrom, path::Path};
 
 use log::warn;
 use serde_json::{Map, Value};
-use tokio::{fs::File, io::BufReader};
+use tokio::{fs::File, io::{AsyncReadExt, AsyncSeekExt, BufReader}};
 use tokio_util::codec::{BytesCodec, FramedRead};
 use warp::{
   reject::Rejection,
-  reply::Reply,
-  http::{StatusCode, Response},
+  http::{HeaderMap, StatusCode, Response},
   hyper::Body
 };
 
+use super::{paths::PathResolver, range::{get_range_request, ByteRange, FileValidators, RangeRequest}};
+
 /// Gets the needed metadata for downloading a file.
-pub async fn get_file_metadata(file_path: &Path) -> Result<impl Reply, Rejection> {
+pub async fn get_file_metadata(file_path: &Path) -> Result<warp::reply::Response, Rejection> {
   let file = File::open(&file_path).await.map_err(|_| warp::reject())?;
   let metadata = file.metadata().await.map_err(|_| warp::reject())?;
-  let file_size = metadata.len();
 
+  return get_download_metadata(file_path, metadata.len());
+}
+
+/// Builds the metadata response for a download of the provided size.
+pub fn get_download_metadata(file_path: &Path, file_size: u64) -> Result<warp::reply::Response, Rejection> {
   let mut map = Map::new();
   map.insert("size".to_string(), Value::Number(file_size.into()));
   map.insert("path".to_string(), Value::String(file_path.to_str().unwrap().to_string()));
@@ -26,14 +31,14 @@ pub async fn get_file_metadata(file_path: &Path) -> Result<impl Reply, Rejection
     .header("File-Length", file_size.to_string())
     .header("Content-Type", "text/plain")
     .header("Access-Control-Allow-Origin", "*")
-    .body(serde_json::to_string(&map).unwrap())
+    .body(serde_json::to_string(&map).unwrap().into())
     .map_err(|_| warp::reject())?;
 
   return Ok(response);
 }
 
-/// Handles downloading a file.
-pub async fn download_file(query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
+/// Handles downloading a file. Supports Range requests, so interrupted downloads can be resumed.
+pub async fn download_file(resolver: PathResolver, query_params: HashMap<String, String>, headers: HeaderMap) -> Result<Response<Body>, Rejection> {
   if !query_params.contains_key("filePath") {
     warn!("Download File: Missing query param filePath");
     return Ok(Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap());
@@ -41,15 +46,20 @@ pub async fn download_file(query_params: HashMap<String, String>) -> Result<impl
   let path = query_params.get("filePath").unwrap().to_owned();
 
 
-  let file_path = Path::new(&path);
+  let resolved_path = resolver.resolve(&path).await?;
+
+  return download_path(&resolved_path, &headers).await;
+}
 
+/// Streams a file that was already resolved, honoring the request's Range headers.
+pub async fn download_path(file_path: &Path, headers: &HeaderMap) -> Result<Response<Body>, Rejection> {
   let filename = file_path.file_name().unwrap().to_str().unwrap();
 
   let file_res = File::open(file_path).await;
   if file_res.is_err() {
     return Ok(Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap());
   }
-  let file = file_res.unwrap();
+  let mut file = file_res.unwrap();
 
   let metadata_res = file.metadata().await;
   if metadata_res.is_err() {
@@ -57,18 +67,47 @@ pub async fn download_file(query_params: HashMap<String, String>) -> Result<impl
   }
   let metadata = metadata_res.unwrap();
   let file_size = metadata.len();
-
-  let reader = BufReader::new(file);
-  let framed_reader = FramedRead::new(reader, BytesCodec::new());
+  let validators = FileValidators::new(&metadata);
 
   let response = Response::builder()
-    .status(StatusCode::OK)
-    .header("Content-Length", file_size.to_string())
+    .header("Accept-Ranges", "bytes")
+    .header("ETag", &validators.etag)
+    .header("Last-Modified", &validators.last_modified)
     .header("Content-Type", "application/octet-stream")
     .header("Content-Disposition", format!("attachement; filename = \"{}\"", filename))
-    .header("Access-Control-Allow-Origin", "*")
-    .body(Body::wrap_stream(framed_reader))
-    .map_err(|_| warp::reject())?;
+    .header("Access-Control-Allow-Origin", "*");
 
-  return Ok(response);
-}
\ No newline at end of file
+  let range = match get_range_request(headers, &validators, file_size) {
+    RangeRequest::FULL => ByteRange { start: 0, end: file_size.saturating_sub(1) },
+    RangeRequest::PARTIAL(range) => range,
+    RangeRequest::UNSATISFIABLE => {
+      return response
+        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+        .header("Content-Range", format!("bytes */{}", file_size))
+        .body(Body::empty())
+        .map_err(|_| warp::reject());
+    }
+  };
+  let is_partial = range.size() != file_size && file_size != 0;
+
+  if is_partial {
+    file.seek(SeekFrom::Start(range.start)).await.map_err(|_| warp::reject())?;
+  }
+
+  let content_length = if file_size == 0 { 0 } else { range.size() };
+  let reader = BufReader::new(file.take(content_length));
+  let framed_reader = FramedRead::new(reader, BytesCodec::new());
+
+  let response = if is_partial {
+    response
+      .status(StatusCode::PARTIAL_CONTENT)
+      .header("Content-Range", format!("bytes {}-{}/{}", range.start, range.end, file_size))
+  } else {
+    response.status(StatusCode::OK)
+  };
+
+  return response
+    .header("Content-Length", content_length.to_string())
+    .body(Body::wrap_stream(framed_reader))
+    .map_err(|_| warp::reject());
+}
@@ -1,2 +1,4 @@
 pub mod download;
-pub mod upload;
\ No newline at end of file
+pub mod upload;
+pub mod paths;
+pub mod range;
\ No newline at end of file
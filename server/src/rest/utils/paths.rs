@@ -0,0 +1,283 @@
+use std::{path::{Component, Path, PathBuf}, sync::{Arc, Mutex}};
+
+use log::warn;
+use warp::{http::StatusCode, reject::{Reject, Rejection}, reply::Reply};
+
+use crate::websocket::{settings::load_settings, types::{settings::Settings, ErrorSender}};
+
+/// Rejection for paths that try to escape their root with ".." or a symlink.
+#[derive(Debug)]
+pub struct PathTraversal;
+
+impl Reject for PathTraversal {}
+
+/// Rejection for paths that aren't in the library or the grids cache.
+#[derive(Debug)]
+pub struct PathNotAllowed;
+
+impl Reject for PathNotAllowed {}
+
+/// Resolves the paths sent by the frontend, and confines them to the library and the grids cache.
+#[derive(Clone)]
+pub struct PathResolver {
+  settings: Arc<Mutex<Settings>>,
+  grids_cache_dir: PathBuf,
+}
+
+impl PathResolver {
+  pub fn new(settings: Arc<Mutex<Settings>>, grids_cache_dir: &str) -> Self {
+    PathResolver {
+      settings,
+      grids_cache_dir: PathBuf::from(grids_cache_dir),
+    }
+  }
+
+  /// Gets the library path. Falls back to settings.json if no client has loaded the settings yet.
+  fn get_library_path(&self) -> String {
+    let library_path = self.settings.lock().expect("Failed to lock Settings Mutex.").library.libraryPath.clone();
+    if !library_path.is_empty() {
+      return library_path;
+    }
+
+    let send_error: ErrorSender = Box::new(|message, _fix, _error_type| {
+      warn!("Path Resolver: {}", message);
+    });
+
+    return load_settings(send_error)
+      .map(|settings| settings.library.libraryPath)
+      .unwrap_or_default();
+  }
+
+  /// Gets the paths that requests are allowed to access.
+  fn get_roots(&self) -> Vec<PathBuf> {
+    let library_path = self.get_library_path();
+    let mut roots = vec![];
+
+    if !library_path.is_empty() {
+      roots.push(PathBuf::from(library_path));
+    }
+    roots.push(self.grids_cache_dir.clone());
+
+    return roots;
+  }
+
+  /// Canonicalizes the provided path, and ensures it's in the library or the grids cache.
+  /// Paths that don't exist yet are resolved from their closest existing parent, so uploads can create them.
+  pub async fn resolve(&self, path: &str) -> Result<PathBuf, Rejection> {
+    return self.resolve_in_roots(path).await.map(|(resolved_path, _)| resolved_path);
+  }
+
+  /// Resolves the provided path like `resolve`, but rejects the library and grids cache themselves. Used for deletes.
+  pub async fn resolve_descendant(&self, path: &str) -> Result<PathBuf, Rejection> {
+    let (resolved_path, canonical_roots) = self.resolve_in_roots(path).await?;
+
+    if canonical_roots.contains(&resolved_path) {
+      warn!("Path Resolver: Rejected root path \"{}\".", path);
+      return Err(warp::reject::custom(PathNotAllowed));
+    }
+
+    return Ok(resolved_path);
+  }
+
+  /// Resolves the provided path, and returns it with the canonical roots it was checked against.
+  async fn resolve_in_roots(&self, path: &str) -> Result<(PathBuf, Vec<PathBuf>), Rejection> {
+    let requested_path = PathBuf::from(path);
+
+    if requested_path.components().any(|component| component == Component::ParentDir) {
+      warn!("Path Resolver: Rejected traversal attempt \"{}\".", path);
+      return Err(warp::reject::custom(PathTraversal));
+    }
+
+    if !requested_path.is_absolute() {
+      warn!("Path Resolver: Rejected relative path \"{}\".", path);
+      return Err(warp::reject::custom(PathNotAllowed));
+    }
+
+    let roots = self.get_roots();
+    let mut canonical_roots = vec![];
+    for root in roots.iter() {
+      match tokio::fs::canonicalize(root).await {
+        Ok(canonical) => canonical_roots.push(canonical),
+        Err(e) => warn!("Path Resolver: Failed to resolve root \"{}\": {}", root.display(), e),
+      }
+    }
+
+    let resolved_path = canonicalize_partial(&requested_path).await.map_err(|e| {
+      warn!("Path Resolver: Failed to resolve \"{}\": {}", path, e);
+      warp::reject::custom(PathNotAllowed)
+    })?;
+
+    if canonical_roots.iter().any(|root| resolved_path.starts_with(root)) {
+      return Ok((resolved_path, canonical_roots));
+    }
+
+    // * The path looked like it was in a root, but a symlink took it somewhere else.
+    if roots.iter().chain(canonical_roots.iter()).any(|root| requested_path.starts_with(root)) {
+      warn!("Path Resolver: Rejected traversal attempt \"{}\" -> \"{}\".", path, resolved_path.display());
+      return Err(warp::reject::custom(PathTraversal));
+    }
+
+    warn!("Path Resolver: Rejected path outside of the library \"{}\".", path);
+    return Err(warp::reject::custom(PathNotAllowed));
+  }
+}
+
+/// Canonicalizes the longest existing part of the path, and appends the rest to it.
+async fn canonicalize_partial(path: &Path) -> Result<PathBuf, std::io::Error> {
+  let mut existing = path.to_path_buf();
+  let mut remaining = vec![];
+
+  while !tokio::fs::try_exists(&existing).await.unwrap_or(false) {
+    let file_name = existing.file_name().map(|name| name.to_os_string());
+    if file_name.is_none() || !existing.pop() {
+      break;
+    }
+
+    remaining.push(file_name.unwrap());
+  }
+
+  let mut resolved = tokio::fs::canonicalize(&existing).await?;
+  for component in remaining.into_iter().rev() {
+    resolved.push(component);
+  }
+
+  return Ok(resolved);
+}
+
+/// Converts path rejections into their HTTP responses.
+pub async fn handle_path_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
+  if err.find::<PathTraversal>().is_some() {
+    let response = warp::http::Response::builder()
+      .status(StatusCode::BAD_REQUEST)
+      .header("Content-Type", "text/plain")
+      .header("Access-Control-Allow-Origin", "*")
+      .body("Path traversal is not allowed.".to_string())
+      .map_err(|_| warp::reject())?;
+
+    return Ok(response);
+  }
+
+  if err.find::<PathNotAllowed>().is_some() {
+    let response = warp::http::Response::builder()
+      .status(StatusCode::FORBIDDEN)
+      .header("Content-Type", "text/plain")
+      .header("Access-Control-Allow-Origin", "*")
+      .body("Path is outside of the library.".to_string())
+      .map_err(|_| warp::reject())?;
+
+    return Ok(response);
+  }
+
+  return Err(err);
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env::{set_var, temp_dir}, fs, os::unix::fs::symlink, path::PathBuf, sync::{Arc, Mutex}};
+
+  use warp::reject::Rejection;
+
+  use crate::websocket::types::settings::get_default_settings;
+
+  use super::{PathNotAllowed, PathResolver, PathTraversal};
+
+  /// Creates a library and grids cache in a temporary folder, with a symlink in the library that points outside of it.
+  fn setup(name: &str) -> (PathBuf, PathResolver) {
+    let test_dir = temp_dir().join(format!("nrm-paths-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&test_dir);
+
+    fs::create_dir_all(test_dir.join("library").join("roms").join("gba")).unwrap();
+    fs::create_dir_all(test_dir.join("grids")).unwrap();
+    fs::create_dir_all(test_dir.join("outside")).unwrap();
+    fs::write(test_dir.join("library").join("roms").join("gba").join("game.gba"), "rom").unwrap();
+    fs::write(test_dir.join("outside").join("users.json"), "secret").unwrap();
+    symlink(test_dir.join("outside"), test_dir.join("library").join("escape")).unwrap();
+
+    set_var("NRM_VERSION", "test");
+    let mut settings = get_default_settings();
+    settings.library.libraryPath = test_dir.join("library").to_string_lossy().to_string();
+
+    let resolver = PathResolver::new(Arc::new(Mutex::new(settings)), &test_dir.join("grids").to_string_lossy());
+    let test_dir = fs::canonicalize(test_dir).unwrap();
+
+    return (test_dir, resolver);
+  }
+
+  fn is_traversal(res: Result<PathBuf, Rejection>) -> bool {
+    return res.err().is_some_and(|err| err.find::<PathTraversal>().is_some());
+  }
+
+  fn is_not_allowed(res: Result<PathBuf, Rejection>) -> bool {
+    return res.err().is_some_and(|err| err.find::<PathNotAllowed>().is_some());
+  }
+
+  fn path_str(path: PathBuf) -> String {
+    return path.to_string_lossy().to_string();
+  }
+
+  #[tokio::test]
+  async fn resolves_paths_in_the_roots() {
+    let (test_dir, resolver) = setup("roots");
+    let game = test_dir.join("library").join("roms").join("gba").join("game.gba");
+
+    assert_eq!(resolver.resolve(&path_str(game.clone())).await.ok(), Some(game));
+    assert_eq!(resolver.resolve(&path_str(test_dir.join("grids"))).await.ok(), Some(test_dir.join("grids")));
+
+    // * Uploads resolve to paths that don't exist yet.
+    let upload = test_dir.join("library").join("roms").join("snes").join("new").join("game.sfc");
+    assert_eq!(resolver.resolve(&path_str(upload.clone())).await.ok(), Some(upload));
+
+    let _ = fs::remove_dir_all(test_dir);
+  }
+
+  #[tokio::test]
+  async fn rejects_traversal() {
+    let (test_dir, resolver) = setup("traversal");
+    let library = test_dir.join("library");
+
+    assert!(is_traversal(resolver.resolve(&format!("{}/../outside/users.json", library.display())).await));
+    assert!(is_traversal(resolver.resolve(&format!("{}/roms/../../outside", library.display())).await));
+
+    let _ = fs::remove_dir_all(test_dir);
+  }
+
+  #[tokio::test]
+  async fn rejects_paths_outside_the_roots() {
+    let (test_dir, resolver) = setup("outside");
+
+    assert!(is_not_allowed(resolver.resolve(&path_str(test_dir.join("outside").join("users.json"))).await));
+    assert!(is_not_allowed(resolver.resolve(&path_str(test_dir.join("outside").join("missing.bin"))).await));
+    assert!(is_not_allowed(resolver.resolve("/etc/passwd").await));
+    assert!(is_not_allowed(resolver.resolve("roms/gba/game.gba").await));
+
+    let _ = fs::remove_dir_all(test_dir);
+  }
+
+  #[tokio::test]
+  async fn rejects_symlink_escapes() {
+    let (test_dir, resolver) = setup("symlink");
+    let escape = test_dir.join("library").join("escape");
+
+    assert!(is_traversal(resolver.resolve(&path_str(escape.join("users.json"))).await));
+    assert!(is_traversal(resolver.resolve(&path_str(escape.join("new").join("upload.bin"))).await));
+    assert!(is_traversal(resolver.resolve_descendant(&path_str(escape.clone())).await));
+
+    let _ = fs::remove_dir_all(test_dir);
+  }
+
+  #[tokio::test]
+  async fn resolve_descendant_rejects_the_roots() {
+    let (test_dir, resolver) = setup("descendant");
+    let library = test_dir.join("library");
+
+    assert!(is_not_allowed(resolver.resolve_descendant(&path_str(library.clone())).await));
+    assert!(is_not_allowed(resolver.resolve_descendant(&format!("{}/", library.display())).await));
+    assert!(is_not_allowed(resolver.resolve_descendant(&format!("{}/.", library.display())).await));
+    assert!(is_not_allowed(resolver.resolve_descendant(&path_str(test_dir.join("grids"))).await));
+
+    let game = library.join("roms").join("gba").join("game.gba");
+    assert_eq!(resolver.resolve_descendant(&path_str(game.clone())).await.ok(), Some(game));
+
+    let _ = fs::remove_dir_all(test_dir);
+  }
+}
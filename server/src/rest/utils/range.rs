@@ -0,0 +1,297 @@
+use std::{fs::Metadata, time::UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use warp::http::HeaderMap;
+
+/// Requests with more ranges than this are served in full, since they're likely abusive.
+const MAX_RANGES: usize = 16;
+
+/// An inclusive byte range of a file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ByteRange {
+  pub start: u64,
+  pub end: u64,
+}
+
+impl ByteRange {
+  /// Gets the number of bytes in the range.
+  pub fn size(&self) -> u64 {
+    return self.end - self.start + 1;
+  }
+}
+
+/// How a request's Range header should be answered.
+#[derive(Debug, PartialEq)]
+pub enum RangeRequest {
+  /// Send the whole file with a 200.
+  FULL,
+  /// Send part of the file with a 206.
+  PARTIAL(ByteRange),
+  /// None of the ranges overlap the file, so reply with a 416.
+  UNSATISFIABLE,
+}
+
+/// The validators used to check if a file changed between requests.
+pub struct FileValidators {
+  pub etag: String,
+  pub last_modified: String,
+  modified: Option<DateTime<Utc>>,
+}
+
+impl FileValidators {
+  /// Gets the validators for a file from its size and modified time.
+  pub fn new(metadata: &Metadata) -> Self {
+    let modified: Option<DateTime<Utc>> = metadata.modified().ok().map(|modified| modified.into());
+    let modified_nanos = metadata.modified().ok()
+      .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+      .map(|duration| duration.as_nanos())
+      .unwrap_or(0);
+
+    return FileValidators {
+      etag: format!("\"{:x}-{:x}\"", metadata.len(), modified_nanos),
+      last_modified: modified.map(|modified| modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string()).unwrap_or_default(),
+      modified,
+    };
+  }
+
+  /// Checks an If-Range header. Ranges are only honored if the client's copy is still current.
+  /// ETags have to match exactly, and dates have to be the file's exact modified time.
+  pub fn is_if_range_fresh(&self, if_range: &str) -> bool {
+    let if_range = if_range.trim();
+
+    if if_range.starts_with('"') {
+      return if_range == self.etag;
+    }
+
+    // * Weak ETags can't be used with If-Range.
+    if if_range.starts_with("W/") {
+      return false;
+    }
+
+    let date_res = DateTime::parse_from_rfc2822(if_range);
+    if date_res.is_err() || self.modified.is_none() {
+      return false;
+    }
+
+    return date_res.unwrap().timestamp() == self.modified.unwrap().timestamp();
+  }
+}
+
+/// Parses a single range spec, like "0-499", "500-" or "-500".
+fn parse_range_spec(spec: &str, file_size: u64) -> Result<Option<ByteRange>, ()> {
+  let (start_str, end_str) = spec.trim().split_once('-').ok_or(())?;
+  let start_str = start_str.trim();
+  let end_str = end_str.trim();
+
+  // * Suffix ranges ask for the last N bytes.
+  if start_str.is_empty() {
+    let suffix = end_str.parse::<u64>().map_err(|_| ())?;
+    if suffix == 0 || file_size == 0 {
+      return Ok(None);
+    }
+
+    return Ok(Some(ByteRange {
+      start: file_size.saturating_sub(suffix),
+      end: file_size - 1,
+    }));
+  }
+
+  let start = start_str.parse::<u64>().map_err(|_| ())?;
+  let end = if end_str.is_empty() {
+    u64::MAX
+  } else {
+    end_str.parse::<u64>().map_err(|_| ())?
+  };
+
+  if end < start {
+    return Err(());
+  }
+
+  if start >= file_size {
+    return Ok(None);
+  }
+
+  return Ok(Some(ByteRange {
+    start,
+    end: end.min(file_size - 1),
+  }));
+}
+
+/// Parses a Range header against a file's size.
+/// Overlapping and adjacent ranges are merged. If that still leaves several ranges, the whole file is sent instead.
+pub fn parse_range_header(range: &str, file_size: u64) -> RangeRequest {
+  let specs = range.trim().strip_prefix("bytes=");
+  if specs.is_none() {
+    return RangeRequest::FULL;
+  }
+
+  let specs: Vec<&str> = specs.unwrap().split(',').filter(|spec| !spec.trim().is_empty()).collect();
+  if specs.is_empty() || specs.len() > MAX_RANGES {
+    return RangeRequest::FULL;
+  }
+
+  let mut ranges = vec![];
+  for spec in specs {
+    match parse_range_spec(spec, file_size) {
+      Ok(Some(range)) => ranges.push(range),
+      Ok(None) => {},
+      // * Invalid Range headers are ignored.
+      Err(_) => return RangeRequest::FULL,
+    }
+  }
+
+  if ranges.is_empty() {
+    return RangeRequest::UNSATISFIABLE;
+  }
+
+  ranges.sort_by_key(|range| range.start);
+
+  let mut merged: Vec<ByteRange> = vec![];
+  for range in ranges {
+    let last = merged.last_mut();
+    if last.as_ref().is_some_and(|last| range.start <= last.end.saturating_add(1)) {
+      let last = last.unwrap();
+      last.end = last.end.max(range.end);
+    } else {
+      merged.push(range);
+    }
+  }
+
+  if merged.len() > 1 {
+    return RangeRequest::FULL;
+  }
+
+  return RangeRequest::PARTIAL(merged[0]);
+}
+
+/// Gets how a request should be answered based on its Range and If-Range headers.
+pub fn get_range_request(headers: &HeaderMap, validators: &FileValidators, file_size: u64) -> RangeRequest {
+  let range = headers.get("range").and_then(|range| range.to_str().ok());
+  if range.is_none() {
+    return RangeRequest::FULL;
+  }
+
+  // * The file changed since the client started downloading it, so it needs to start over.
+  let if_range = headers.get("if-range").and_then(|if_range| if_range.to_str().ok());
+  if if_range.is_some_and(|if_range| !validators.is_if_range_fresh(if_range)) {
+    return RangeRequest::FULL;
+  }
+
+  return parse_range_header(range.unwrap(), file_size);
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{DateTime, TimeZone, Utc};
+  use warp::http::HeaderMap;
+
+  use super::{get_range_request, parse_range_header, ByteRange, FileValidators, RangeRequest};
+
+  fn partial(start: u64, end: u64) -> RangeRequest {
+    return RangeRequest::PARTIAL(ByteRange { start, end });
+  }
+
+  fn get_validators() -> FileValidators {
+    let modified: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap();
+
+    return FileValidators {
+      etag: String::from("\"3e8-1\""),
+      last_modified: modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+      modified: Some(modified),
+    };
+  }
+
+  #[test]
+  fn parses_single_ranges() {
+    assert_eq!(parse_range_header("bytes=0-499", 1000), partial(0, 499));
+    assert_eq!(parse_range_header("bytes=500-", 1000), partial(500, 999));
+    assert_eq!(parse_range_header("bytes=900-2000", 1000), partial(900, 999));
+    assert_eq!(parse_range_header("bytes= 10 - 20 ", 1000), partial(10, 20));
+  }
+
+  #[test]
+  fn parses_suffix_ranges() {
+    assert_eq!(parse_range_header("bytes=-200", 1000), partial(800, 999));
+    assert_eq!(parse_range_header("bytes=-5000", 1000), partial(0, 999));
+    assert_eq!(parse_range_header("bytes=-0", 1000), RangeRequest::UNSATISFIABLE);
+    assert_eq!(parse_range_header("bytes=-10", 0), RangeRequest::UNSATISFIABLE);
+  }
+
+  #[test]
+  fn rejects_ranges_past_the_end() {
+    assert_eq!(parse_range_header("bytes=1000-", 1000), RangeRequest::UNSATISFIABLE);
+    assert_eq!(parse_range_header("bytes=1500-1600", 1000), RangeRequest::UNSATISFIABLE);
+    assert_eq!(parse_range_header("bytes=0-", 0), RangeRequest::UNSATISFIABLE);
+
+    // * Only the ranges that overlap the file are used.
+    assert_eq!(parse_range_header("bytes=0-10,2000-3000", 1000), partial(0, 10));
+  }
+
+  #[test]
+  fn merges_overlapping_ranges() {
+    assert_eq!(parse_range_header("bytes=0-100,50-200", 1000), partial(0, 200));
+    assert_eq!(parse_range_header("bytes=100-199,0-99", 1000), partial(0, 199));
+    assert_eq!(parse_range_header("bytes=0-500,-600", 1000), partial(0, 999));
+  }
+
+  #[test]
+  fn serves_multiple_ranges_in_full() {
+    assert_eq!(parse_range_header("bytes=0-10,20-30", 1000), RangeRequest::FULL);
+
+    let too_many = (0..20).map(|index| format!("{}-{}", index * 10, index * 10 + 5)).collect::<Vec<String>>().join(",");
+    assert_eq!(parse_range_header(&format!("bytes={}", too_many), 1000), RangeRequest::FULL);
+  }
+
+  #[test]
+  fn ignores_invalid_headers() {
+    assert_eq!(parse_range_header("items=0-10", 1000), RangeRequest::FULL);
+    assert_eq!(parse_range_header("bytes=abc", 1000), RangeRequest::FULL);
+    assert_eq!(parse_range_header("bytes=500-100", 1000), RangeRequest::FULL);
+    assert_eq!(parse_range_header("bytes=0-10,x-y", 1000), RangeRequest::FULL);
+    assert_eq!(parse_range_header("bytes=", 1000), RangeRequest::FULL);
+  }
+
+  #[test]
+  fn checks_if_range_etags() {
+    let validators = get_validators();
+
+    assert!(validators.is_if_range_fresh("\"3e8-1\""));
+    assert!(!validators.is_if_range_fresh("\"3e8-2\""));
+
+    // * Weak ETags never match, even if the tag is the same.
+    assert!(!validators.is_if_range_fresh("W/\"3e8-1\""));
+  }
+
+  #[test]
+  fn checks_if_range_dates() {
+    let validators = get_validators();
+
+    assert!(validators.is_if_range_fresh("Wed, 01 May 2024 12:30:00 GMT"));
+    assert!(!validators.is_if_range_fresh("Wed, 01 May 2024 12:29:59 GMT"));
+    assert!(!validators.is_if_range_fresh("yesterday"));
+  }
+
+  #[test]
+  fn only_honors_ranges_for_current_files() {
+    let validators = get_validators();
+
+    let mut headers = HeaderMap::new();
+    assert_eq!(get_range_request(&headers, &validators, 1000), RangeRequest::FULL);
+
+    headers.insert("range", "bytes=100-".parse().unwrap());
+    assert_eq!(get_range_request(&headers, &validators, 1000), partial(100, 999));
+
+    headers.insert("if-range", "\"3e8-1\"".parse().unwrap());
+    assert_eq!(get_range_request(&headers, &validators, 1000), partial(100, 999));
+
+    headers.insert("if-range", "\"3e8-2\"".parse().unwrap());
+    assert_eq!(get_range_request(&headers, &validators, 1000), RangeRequest::FULL);
+
+    headers.insert("if-range", "W/\"3e8-1\"".parse().unwrap());
+    assert_eq!(get_range_request(&headers, &validators, 1000), RangeRequest::FULL);
+
+    headers.insert("range", "bytes=2000-".parse().unwrap());
+    headers.insert("if-range", "\"3e8-1\"".parse().unwrap());
+    assert_eq!(get_range_request(&headers, &validators, 1000), RangeRequest::UNSATISFIABLE);
+  }
+}
@@ -1,22 +1,25 @@
-use std::{collections::HashMap, path::{PathBuf}};
+use std::{collections::HashMap, env::var, io::{Read, SeekFrom}, path::{Path, PathBuf}};
 
 use bytes::Buf;
+use crypto::{digest::Digest, sha2::Sha256};
 use futures::{Stream, StreamExt};
 use log::{info, warn};
-use tokio::{fs::OpenOptions, io::AsyncWriteExt};
-use warp::{http::HeaderMap, reject::Rejection, reply::Reply};
+use tokio::{fs::OpenOptions, io::{AsyncSeekExt, AsyncWriteExt}};
+use warp::{http::{HeaderMap, Response, StatusCode}, reject::Rejection, reply::Reply};
 use chrono::Utc;
 
-use crate::rest::types::{StreamProgress, StreamStore};
+use crate::rest::types::{StreamProgress, StreamStore, UploadChecksums};
+
+use super::paths::PathResolver;
 
 /// Prepares a file upload
-pub async fn prepare_file_upload(query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
+pub async fn prepare_file_upload(resolver: PathResolver, query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
   if !query_params.contains_key("filePath") {
     warn!("Prepare Upload: Missing query param filePath");
     return Err(warp::reject::reject());
   }
 
-  let file_path = PathBuf::from(query_params.get("filePath").unwrap().to_owned());
+  let file_path = resolver.resolve(query_params.get("filePath").unwrap()).await?;
 
   let exists_res = tokio::fs::try_exists(&file_path).await;
 
@@ -58,13 +61,13 @@ pub async fn prepare_file_upload(query_params: HashMap<String, String>) -> Resul
 }
 
 /// Prepares a file replace
-pub async fn prepare_file_replace(query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
+pub async fn prepare_file_replace(resolver: PathResolver, query_params: HashMap<String, String>) -> Result<impl Reply, Rejection> {
   if !query_params.contains_key("filePath") {
     warn!("Prepare Upload: Missing query param filePath");
     return Err(warp::reject::reject());
   }
 
-  let file_path = PathBuf::from(query_params.get("filePath").unwrap().to_owned());
+  let file_path = resolver.resolve(query_params.get("filePath").unwrap()).await?;
   
   let parent_dir = file_path.parent();
 
@@ -83,86 +86,317 @@ pub async fn prepare_file_replace(query_params: HashMap<String, String>) -> Resu
   return Ok(warp::reply::with_header("success", "Access-Control-Allow-Origin", "*"));
 }
 
-/// Handles an upload stream
-pub async fn upload_file(
+/// Parses the byte range of an upload chunk. Chunks have to be inside the file.
+fn parse_chunk_range(headers: &HeaderMap, file_size: u64) -> Option<(u64, u64)> {
+  let range = headers.get("range").and_then(|range| range.to_str().ok())?;
+  let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+
+  let start = start.trim().parse::<u64>().ok()?;
+  let end = end.trim().parse::<u64>().ok()?;
+
+  if end < start || end >= file_size {
+    return None;
+  }
+
+  return Some((start, end));
+}
+
+/// Builds the reply for a chunk that doesn't start where the upload left off.
+/// The Upload-Offset header tells the client where the next chunk should start.
+fn chunk_conflict_reply(offset: u64) -> Result<Response<String>, Rejection> {
+  return Response::builder()
+    .status(StatusCode::CONFLICT)
+    .header("Content-Type", "text/plain")
+    .header("Access-Control-Allow-Origin", "*")
+    .header("Upload-Offset", offset.to_string())
+    .body(offset.to_string())
+    .map_err(|_| warp::reject());
+}
+
+/// Appends a chunk to the file. If the chunk doesn't arrive in full, the file is truncated back to where it started.
+async fn write_chunk(
   mut body: impl Stream<Item = Result<impl Buf, warp::Error>> + Unpin + Send + Sync,
+  file_path: &Path,
+  start: u64,
+  length: u64
+) -> Result<(), String> {
+  let mut file = OpenOptions::new()
+    .write(true)
+    .open(file_path)
+    .await
+    .map_err(|e| format!("Error opening file: {}", e))?;
+
+  let file_length = file.metadata().await.map_err(|e| format!("Error reading file metadata: {}", e))?.len();
+  if file_length != start {
+    return Err(format!("Expected the file to be {} bytes, but it was {}", start, file_length));
+  }
+
+  file.seek(SeekFrom::Start(start)).await.map_err(|e| format!("Error seeking file: {}", e))?;
+
+  let mut written = 0;
+  let mut write_res = Ok(());
+
+  while let Some(buf) = body.next().await {
+    if buf.is_err() {
+      write_res = Err(format!("Error receiving chunk: {}", buf.err().unwrap()));
+      break;
+    }
+    let mut buf = buf.unwrap();
+
+    written += buf.remaining() as u64;
+    if written > length {
+      write_res = Err(format!("Chunk was larger than its range of {} bytes", length));
+      break;
+    }
+
+    let buf_res = file.write_all_buf(&mut buf).await;
+    if buf_res.is_err() {
+      write_res = Err(format!("Error writing data to file: {}", buf_res.err().unwrap()));
+      break;
+    }
+  }
+
+  if write_res.is_ok() && written != length {
+    write_res = Err(format!("Chunk was {} bytes, but its range was {}", written, length));
+  }
+
+  if write_res.is_ok() {
+    write_res = file.flush().await.map_err(|e| format!("Error flushing data to file: {}", e));
+  }
+
+  // * Partial chunks are removed, so the client can send the chunk again.
+  if write_res.is_err() {
+    let _ = file.set_len(start).await;
+  }
+
+  return write_res;
+}
+
+/// Handles an upload stream.
+/// Chunks have to start exactly where the last one ended, so duplicate and out of order chunks are rejected.
+pub async fn upload_file(
+  body: impl Stream<Item = Result<impl Buf, warp::Error>> + Unpin + Send + Sync,
   streams_store: StreamStore,
+  resolver: PathResolver,
   query_params: HashMap<String, String>,
   headers: HeaderMap
-) -> Result<impl Reply, Rejection> {
-  let content_type = headers.get("content-type").unwrap().to_str().unwrap();
-  if content_type != "application/octet-stream" {
+) -> Result<Response<String>, Rejection> {
+  let content_type = headers.get("content-type").and_then(|content_type| content_type.to_str().ok());
+  if content_type != Some("application/octet-stream") {
     return Err(warp::reject::reject());
   }
 
-  let file_size = headers.get("file-size").unwrap().to_str().unwrap().to_string().parse::<u64>().map_err(|e| {
-    warn!("error parsing Content-Length: {}", e);
-    warp::reject::reject()
-  })?;
+  let file_size = headers.get("file-size")
+    .and_then(|file_size| file_size.to_str().ok())
+    .and_then(|file_size| file_size.parse::<u64>().ok());
+  if file_size.is_none() {
+    warn!("Upload File: Missing or invalid File-Size header");
+    return Err(warp::reject::reject());
+  }
+  let file_size = file_size.unwrap();
+
+  let range = parse_chunk_range(&headers, file_size);
+  if range.is_none() {
+    warn!("Upload File: Missing or invalid Range header");
+    return Err(warp::reject::reject());
+  }
+  let (start, end) = range.unwrap();
 
-  let range_header = headers.get("range").unwrap().to_str();
-  let (start, end) = match range_header {
-    Ok(range) => {
-      if range.starts_with("bytes=") {
-        let range_parts: Vec<&str> = range[6..].split('-').collect();
-        let start = range_parts[0].parse::<u64>().unwrap_or(0);
-        let end = range_parts[1].parse::<u64>().unwrap_or(file_size - 1);
-        (start, end)
-      } else {
-        (0, file_size - 1) // Default to the entire file if no valid range is provided
-      }
-    },
-    Err(_) => (0, file_size - 1), // No Range header provided
-  };
-  let chunk_size = end - start;
-
-  let upload_id = headers.get("upload-id").unwrap().to_str().unwrap().to_string();
+  let upload_id = headers.get("upload-id").and_then(|upload_id| upload_id.to_str().ok());
+  if upload_id.is_none() {
+    warn!("Upload File: Missing Upload-Id header");
+    return Err(warp::reject::reject());
+  }
+  let upload_id = upload_id.unwrap().to_string();
 
   if !query_params.contains_key("filePath") {
     warn!("Upload File: Missing query param filePath");
     return Err(warp::reject::reject());
   }
-  let file_path = query_params.get("filePath").unwrap().to_owned();
-  
-  let mut file = OpenOptions::new()
-    .write(true)
-    .append(true)
-    .open(&file_path)
-    .await.map_err(|e| {
-      warn!("Error opening file: {}", e);
-      warp::reject::reject()
-    })?;
+  let file_path = resolver.resolve(query_params.get("filePath").unwrap()).await?;
+  let file_path_str = file_path.to_string_lossy().to_string();
 
-  while let Some(buf) = body.next().await {
-    let mut buf = buf.unwrap();
-    
-    file.write_buf(&mut buf).await.map_err(|e| {
-      warn!("Error writing data to file: {}", e);
-      warp::reject::reject()
-    })?;
+  // * Claim the upload while the chunk is written, so chunks can't be written at the same time.
+  let mut streams = streams_store.streams.write().await;
+  let stream = streams.entry(upload_id.clone()).or_insert_with(|| StreamProgress {
+    id: upload_id.clone(),
+    path: file_path_str.clone(),
+    currentSize: 0,
+    totalSize: file_size,
+    lastChunkTime: Utc::now().timestamp(),
+    receivingChunk: false,
+  });
+
+  // * Uploads can only write to the file they were started for.
+  if stream.path != file_path_str {
+    warn!("Upload File: Rejected chunk for \"{}\", upload \"{}\" is for \"{}\"", file_path_str, upload_id, stream.path);
+    return Response::builder()
+      .status(StatusCode::BAD_REQUEST)
+      .header("Content-Type", "text/plain")
+      .header("Access-Control-Allow-Origin", "*")
+      .body(String::from("The upload is for a different file."))
+      .map_err(|_| warp::reject());
   }
 
-  file.flush().await.map_err(|e| {
-    warn!("Error flushing data to file: {}", e);
-    warp::reject::reject()
-  })?;
+  if stream.receivingChunk || stream.totalSize != file_size || stream.currentSize != start {
+    warn!("Upload File: Rejected chunk {}-{} for \"{}\", expected it to start at {}", start, end, stream.path, stream.currentSize);
+    return chunk_conflict_reply(stream.currentSize);
+  }
+  stream.receivingChunk = true;
+  drop(streams);
 
-  
-  if !streams_store.streams.read().await.contains_key(&upload_id) {
-    streams_store.streams.write().await.insert(upload_id.clone(), StreamProgress {
-      id: upload_id.clone(),
-      path: file_path.clone(),
-      currentSize: 0,
-      totalSize: file_size,
-      lastChunkTime: Utc::now().timestamp(),
-    });
+  let write_res = write_chunk(body, &file_path, start, end - start + 1).await;
+
+  let mut streams = streams_store.streams.write().await;
+  let stream = streams.get_mut(&upload_id);
+  if stream.is_none() {
+    // * The upload was canceled while the chunk was being written.
+    return Err(warp::reject::reject());
   }
-  
-  let mut stream_progress = streams_store.streams.read().await.get(&upload_id).unwrap().clone();
-  stream_progress.currentSize += chunk_size;
-  stream_progress.lastChunkTime = Utc::now().timestamp();
+  let stream = stream.unwrap();
 
-  return Ok(warp::reply::with_header("success", "Access-Control-Allow-Origin", "*"));
+  stream.receivingChunk = false;
+  stream.lastChunkTime = Utc::now().timestamp();
+
+  if write_res.is_err() {
+    warn!("Upload File: Failed to write chunk {}-{} for \"{}\": {}", start, end, stream.path, write_res.err().unwrap());
+    return chunk_conflict_reply(stream.currentSize);
+  }
+
+  stream.currentSize = end + 1;
+
+  return Response::builder()
+    .status(200)
+    .header("Content-Type", "text/plain")
+    .header("Access-Control-Allow-Origin", "*")
+    .header("Upload-Offset", stream.currentSize.to_string())
+    .body(String::from("success"))
+    .map_err(|_| warp::reject());
+}
+
+/// Gets the quarantine folder, where uploads that fail verification are moved.
+fn get_quarantine_dir() -> PathBuf {
+  let config_path = PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap());
+  return config_path.join("quarantine");
+}
+
+/// Moves a file to the quarantine folder, so a corrupted upload is never left in the library.
+async fn quarantine_upload(path: &Path) -> Result<PathBuf, std::io::Error> {
+  let quarantine_dir = get_quarantine_dir();
+  tokio::fs::create_dir_all(&quarantine_dir).await?;
+
+  let filename = path.file_name().and_then(|name| name.to_str()).unwrap_or("upload");
+  let quarantine_path = quarantine_dir.join(format!("{}-{}", Utc::now().format("%Y%m%d%H%M%S"), filename));
+
+  // * The config directory may be on a different filesystem than the library.
+  if tokio::fs::rename(path, &quarantine_path).await.is_err() {
+    tokio::fs::copy(path, &quarantine_path).await?;
+    tokio::fs::remove_file(path).await?;
+  }
+
+  return Ok(quarantine_path);
+}
+
+/// Computes the SHA-256 and CRC32 of a file.
+fn hash_upload(path: &Path) -> Result<(String, String), std::io::Error> {
+  let mut file = std::fs::File::open(path)?;
+  let mut buffer = vec![0; 1024 * 1024];
+
+  let mut sha256 = Sha256::new();
+  let mut crc32 = crc32fast::Hasher::new();
+
+  loop {
+    let read = file.read(&mut buffer)?;
+    if read == 0 {
+      break;
+    }
+
+    sha256.input(&buffer[..read]);
+    crc32.update(&buffer[..read]);
+  }
+
+  return Ok((sha256.result_str(), format!("{:08x}", crc32.finalize())));
+}
+
+/// Checks that a file matches the client's checksums.
+async fn check_checksums(path: &Path, checksums: &UploadChecksums) -> Result<(), String> {
+  if checksums.sha256.is_none() && checksums.crc32.is_none() {
+    return Ok(());
+  }
+
+  let hash_path = path.to_path_buf();
+  let (sha256, crc32) = tokio::task::spawn_blocking(move || hash_upload(&hash_path))
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("Failed to hash the upload: {}", e))?;
+
+  if checksums.sha256.as_ref().is_some_and(|expected| !expected.eq_ignore_ascii_case(&sha256)) {
+    return Err(format!("Expected a SHA-256 of {}, but the upload's was {}.", checksums.sha256.as_ref().unwrap(), sha256));
+  }
+
+  if checksums.crc32.as_ref().is_some_and(|expected| !expected.eq_ignore_ascii_case(&crc32)) {
+    return Err(format!("Expected a CRC32 of {}, but the upload's was {}.", checksums.crc32.as_ref().unwrap(), crc32));
+  }
+
+  return Ok(());
+}
+
+/// Quarantines an upload that failed verification.
+async fn reject_upload(path: &Path, reason: &str) {
+  warn!("Verify Upload: \"{}\" failed verification: {}", path.display(), reason);
+
+  let quarantine_res = quarantine_upload(path).await;
+  if quarantine_res.is_err() {
+    warn!("Verify Upload: Failed to quarantine \"{}\": {}", path.display(), quarantine_res.err().unwrap());
+  } else {
+    info!("Verify Upload: Quarantined \"{}\" to \"{}\"", path.display(), quarantine_res.unwrap().display());
+  }
+}
+
+/// Verifies an uploaded file against the client's checksums.
+/// Files that fail verification are quarantined, and the reason is returned.
+pub async fn verify_file(path: &Path, checksums: &UploadChecksums) -> Result<(), String> {
+  let check_res = check_checksums(path, checksums).await;
+  if check_res.is_err() {
+    reject_upload(path, check_res.as_ref().err().unwrap()).await;
+  }
+
+  return check_res;
+}
+
+/// Verifies a completed upload, and removes it from the stream store.
+/// Uploads that fail verification are quarantined, and the reason is returned.
+pub async fn verify_upload(streams_store: &StreamStore, upload_id: &str, path: Option<&Path>, checksums: &UploadChecksums) -> Result<(), String> {
+  let stream = streams_store.streams.write().await.remove(upload_id);
+
+  let path = path.map(|path| path.to_path_buf()).or(stream.as_ref().map(|stream| PathBuf::from(&stream.path)));
+  if path.is_none() {
+    // * Empty files never send any chunks, so there's nothing to verify.
+    return Ok(());
+  }
+  let path = path.unwrap();
+
+  if stream.as_ref().is_some_and(|stream| stream.currentSize != stream.totalSize) {
+    let stream = stream.unwrap();
+    let reason = format!("Only {} of {} bytes were received.", stream.currentSize, stream.totalSize);
+
+    reject_upload(&path, &reason).await;
+    return Err(reason);
+  }
+
+  return verify_file(&path, checksums).await;
+}
+
+/// Builds the reply for an upload that failed verification.
+pub fn verification_failed_reply(reason: String) -> Result<warp::reply::Response, Rejection> {
+  let response = Response::builder()
+    .status(StatusCode::UNPROCESSABLE_ENTITY)
+    .header("Content-Type", "text/plain")
+    .header("Access-Control-Allow-Origin", "*")
+    .body(reason)
+    .map_err(|_| warp::reject())?;
+
+  return Ok(response.into_response());
 }
 
 /// Cancels a file upload
@@ -190,4 +424,31 @@ pub async fn upload_cancel(streams_store: StreamStore, upload_id: String) -> Res
     .map_err(|_| warp::reject())?;
 
   return Ok(response);
-}
\ No newline at end of file
+}
+
+/// Removes uploads that haven't received a chunk in over an hour, along with their partial files.
+/// Returns the number of uploads that were removed.
+pub async fn remove_stale_uploads(streams_store: &StreamStore) -> usize {
+  const DEAD_TIME_LENGTH: i64 = 60 * 60;
+
+  let now = Utc::now().timestamp();
+
+  let mut streams = streams_store.streams.write().await;
+  let stale: Vec<StreamProgress> = streams.values()
+    .filter(|stream| now - stream.lastChunkTime > DEAD_TIME_LENGTH && !stream.receivingChunk)
+    .cloned()
+    .collect();
+
+  for stream in stale.iter() {
+    info!("Removing failed upload for path \"{}\"", stream.path);
+
+    let remove_res = tokio::fs::remove_file(&stream.path).await;
+    if remove_res.is_err() {
+      warn!("Error deleting failed upload: {}", remove_res.err().unwrap());
+    }
+
+    streams.remove(&stream.id);
+  }
+
+  return stale.len();
+}
@@ -1,68 +1,85 @@
-use std::path::{Path, PathBuf};
+use std::{path::{Path, PathBuf}, time::{Duration, SystemTime}};
 use log::warn;
-use tokio::{fs::{create_dir_all, File, OpenOptions}, io::{AsyncReadExt, BufReader}};
-use async_zip::{base::read::seek::ZipFileReader, error::ZipError, tokio::write::ZipFileWriter, Compression, StoredZipEntry, ZipEntryBuilder};
+use chrono::{DateTime, Utc};
+use tokio::{fs::{create_dir_all, File, OpenOptions}, io::BufReader};
+use async_zip::{base::read::seek::ZipFileReader, error::ZipError, tokio::write::ZipFileWriter, Compression, StoredZipEntry, ZipDateTime, ZipEntryBuilder};
 use sanitize_filename::sanitize;
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
+use crate::{job_queue::JobContext, websocket::types::library::ZipCompression};
+
 /// Returns a relative path without reserved names, redundant separators, ".", or "..".
-fn sanitize_file_path(path: &str) -> PathBuf {
+pub fn sanitize_file_path(path: &str) -> PathBuf {
   path.replace('\\', "/")
     .split('/')
     .map(sanitize)
     .collect()
 }
 
-fn get_root_of_zip(file: &StoredZipEntry) -> String {
-  let file_path = sanitize_file_path(file.filename().as_str().unwrap());
+/// Gets the root folder of an archive from the name of an entry. Entries like "./" that are empty once sanitized don't have one.
+pub fn get_root_of_archive(filename: &str) -> Option<String> {
+  let file_path = sanitize_file_path(filename);
 
-  let mut components = file_path.components();
+  return file_path.components().next().map(|first| first.as_os_str().to_string_lossy().to_string());
+}
 
-  let first = components.next();
-  if first == Some(std::path::Component::CurDir) {
-    // Its relative, need to grab the next path.
-    return components.next().unwrap().as_os_str().to_str().unwrap().to_string();
-  } else {
-    // Its an absolute path.
-    return first.unwrap().as_os_str().to_str().unwrap().to_string();
+/// Gets the root folder of a ZIP archive from the first entry that has one.
+fn get_root_of_zip(entries: &[StoredZipEntry]) -> Result<String, ZipError> {
+  for entry in entries {
+    let root_folder = get_root_of_archive(entry.filename().as_str()?);
+    if root_folder.is_some() {
+      return Ok(root_folder.unwrap());
+    }
   }
+
+  return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "The archive is empty.").into());
+}
+
+/// Gets the error jobs stop with when they're cancelled partway through an archive.
+pub fn cancelled_error() -> std::io::Error {
+  return std::io::Error::new(std::io::ErrorKind::Interrupted, "The job was cancelled.");
 }
 
 /// Extracts everything from the ZIP archive to the output directory.
-pub async fn unpack_zip(archive: File, out_dir: &Path) -> Result<PathBuf, ZipError> {
+pub async fn unpack_zip(archive: File, out_dir: &Path, job: &JobContext) -> Result<PathBuf, ZipError> {
   let archive = BufReader::new(archive).compat();
   let mut reader = ZipFileReader::new(archive).await?;
 
-  let first_file: &StoredZipEntry = reader.file().entries().get(0).unwrap();
-  let zip_root_folder = get_root_of_zip(first_file);
+  let zip_root_folder = get_root_of_zip(reader.file().entries())?;
+
+  let entry_count = reader.file().entries().len();
+
+  for index in 0..entry_count {
+    if job.is_cancelled() {
+      return Err(cancelled_error().into());
+    }
 
-  for index in 0..reader.file().entries().len() {
     let entry = reader.file().entries().get(index).unwrap();
-    let path = out_dir.join(sanitize_file_path(entry.filename().as_str().unwrap()));
-    let entry_is_dir = entry.dir().unwrap();
+    let path = out_dir.join(sanitize_file_path(entry.filename().as_str()?));
+    job.report(Some(index as f64 / entry_count as f64), &format!("Extracting {}...", path.file_name().unwrap_or_default().to_string_lossy()));
+    let entry_is_dir = entry.dir()?;
 
     let mut entry_reader = reader.reader_without_entry(index).await?;
 
     if entry_is_dir {
       if !path.exists() {
-        create_dir_all(&path).await.expect("Failed to create extracted directory");
+        create_dir_all(&path).await?;
       }
     } else {
-      let parent = path.parent().expect("A file entry should have parent directories");
+      let parent = path.parent().unwrap_or(out_dir);
       if !parent.is_dir() {
-        create_dir_all(parent).await.expect("Failed to create parent directories");
+        create_dir_all(parent).await?;
       }
 
+      // * Existing files are never overwritten.
       let writer = OpenOptions::new()
         .write(true)
         .create_new(true)
         .open(&path)
         .await
-        .expect("Failed to create extracted file");
+        .map_err(|e| std::io::Error::new(e.kind(), format!("Failed to create \"{}\": {}", path.display(), e)))?;
 
-      futures_util::io::copy(&mut entry_reader, &mut writer.compat_write())
-        .await
-        .expect("Failed to copy to extracted file");
+      futures_util::io::copy(&mut entry_reader, &mut writer.compat_write()).await?;
     }
   }
 
@@ -70,16 +87,20 @@ pub async fn unpack_zip(archive: File, out_dir: &Path) -> Result<PathBuf, ZipErr
 }
 
 
-/// Writes an entry to a zip.
-async fn write_entry(filename: &str, input_path: &Path, writer: &mut ZipFileWriter<File>) -> Result<(), ZipError> {
-  let mut input_file = File::open(input_path).await?;
-  let input_file_size = input_file.metadata().await?.len() as usize;
+/// Streams a file into a zip entry, so it never has to fit in memory.
+async fn write_entry(filename: &str, input_path: &Path, compression: Compression, writer: &mut ZipFileWriter<File>) -> Result<(), ZipError> {
+  let input_file = File::open(input_path).await?;
+  let metadata = input_file.metadata().await?;
 
-  let mut buffer = Vec::with_capacity(input_file_size);
-  input_file.read_to_end(&mut buffer).await?;
+  let mut builder = ZipEntryBuilder::new(filename.into(), compression).unix_permissions(0o644);
+  if let Ok(modified) = metadata.modified() {
+    builder = builder.last_modification_date(ZipDateTime::from_chrono(&DateTime::<Utc>::from(modified)));
+  }
 
-  let builder = ZipEntryBuilder::new(filename.into(), Compression::Deflate);
-  writer.write_entry_whole(builder, &buffer).await?;
+  // * Streamed entries always have ZIP64 fields, so files over 4GB are supported.
+  let mut entry_writer = writer.write_entry_stream(builder).await?;
+  futures_util::io::copy(&mut input_file.compat(), &mut entry_writer).await?;
+  entry_writer.close().await?;
 
   return Ok(());
 }
@@ -103,34 +124,181 @@ async fn walk_dir(dir: PathBuf) -> Result<Vec<PathBuf>, tokio::io::Error> {
     }
   }
 
+  files.sort();
+
   return Ok(files);
 }
 
 
-/// Compresses the provided directory into a ZIP archive.
-pub async fn pack_zip(dir: &Path) -> Result<PathBuf, ZipError> {
-  let parent = dir.parent().unwrap().to_path_buf();
-  let output_path = parent.join(format!("{}.zip", dir.file_name().unwrap().to_str().unwrap()));
-  let output_file = File::create(&output_path).await?;
-  let mut output_writer = ZipFileWriter::with_tokio(output_file);
+/// Compresses the provided directory into a ZIP archive at the output path.
+/// Entries are named relative to the directory's parent, so the archive extracts to the directory.
+pub async fn pack_zip(dir: &Path, output_path: &Path, compression: ZipCompression, job: Option<&JobContext>) -> Result<(), ZipError> {
+  let compression = match compression {
+    ZipCompression::STORED => Compression::Stored,
+    ZipCompression::DEFLATE => Compression::Deflate,
+  };
 
+  let output_file = File::create(output_path).await?;
+  let mut output_writer = ZipFileWriter::with_tokio(output_file);
 
   let entries = walk_dir(dir.into()).await?;
-  let input_dir_str = dir.as_os_str().to_str().unwrap();
+  let base_dir = dir.parent().unwrap_or(dir);
+  let entry_count = entries.len();
 
-  for entry_path_buf in entries {
+  for (index, entry_path_buf) in entries.into_iter().enumerate() {
     let entry_path = entry_path_buf.as_path();
-    let entry_str = entry_path.as_os_str().to_str().unwrap();
 
-    if !entry_str.starts_with(input_dir_str) {
+    if let Some(job) = job {
+      if job.is_cancelled() {
+        return Err(cancelled_error().into());
+      }
+
+      job.report(Some(index as f64 / entry_count as f64), &format!("Compressing {}...", entry_path.file_name().unwrap_or_default().to_string_lossy()));
+    }
+
+    let relative_path_res = entry_path.strip_prefix(base_dir);
+    if relative_path_res.is_err() {
       warn!("Directory file path does not start with base input directory path.");
+      continue;
     }
 
-    write_entry(entry_str, entry_path, &mut output_writer).await?;
+    // * ZIPs always use forward slashes.
+    let entry_name = relative_path_res.unwrap().components()
+      .map(|component| component.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/");
+
+    write_entry(&entry_name, entry_path, compression, &mut output_writer).await?;
   }
 
 
   output_writer.close().await?;
 
+  return Ok(());
+}
+
+/// Gets the folder packed archives are cached in.
+fn get_packed_zips_dir() -> PathBuf {
+  return std::env::temp_dir().join("nrm-zips");
+}
+
+/// Gets where a directory's packed archive is cached. Archives are kept out of the library, so they never show up as roms.
+fn get_packed_zip_path(dir: &Path) -> PathBuf {
+  let mut path_hasher = crc32fast::Hasher::new();
+  path_hasher.update(dir.as_os_str().as_encoded_bytes());
+
+  let dir_name = dir.file_name().and_then(|name| name.to_str()).unwrap_or("rom");
+
+  return get_packed_zips_dir()
+    .join(format!("{:08x}", path_hasher.finalize()))
+    .join(format!("{}.zip", dir_name));
+}
+
+/// Checks if anything in a directory changed after the provided time.
+/// Directories are checked along with files, since removing or renaming a file only changes its directory.
+async fn is_dir_modified_since(dir: PathBuf, time: SystemTime) -> Result<bool, tokio::io::Error> {
+  let mut dirs = vec![dir];
+
+  while !dirs.is_empty() {
+    let dir = dirs.remove(0);
+
+    let modified = tokio::fs::metadata(&dir).await.and_then(|metadata| metadata.modified()).ok();
+    if modified.is_none_or(|modified| modified > time) {
+      return Ok(true);
+    }
+
+    let mut dir_iter = tokio::fs::read_dir(&dir).await?;
+    while let Some(entry) = dir_iter.next_entry().await? {
+      let entry_path_buf = entry.path();
+
+      if entry_path_buf.is_dir() {
+        dirs.push(entry_path_buf);
+        continue;
+      }
+
+      let modified = tokio::fs::metadata(&entry_path_buf).await.and_then(|metadata| metadata.modified()).ok();
+      if modified.is_none_or(|modified| modified > time) {
+        return Ok(true);
+      }
+    }
+  }
+
+  return Ok(false);
+}
+
+/// Gets a directory's packed archive, if it was packed after the directory and all of its files last changed.
+pub async fn find_packed_zip(dir: &Path) -> Result<Option<PathBuf>, ZipError> {
+  let output_path = get_packed_zip_path(dir);
+
+  let packed_modified = tokio::fs::metadata(&output_path).await.and_then(|metadata| metadata.modified()).ok();
+  if packed_modified.is_none() {
+    return Ok(None);
+  }
+
+  if is_dir_modified_since(dir.into(), packed_modified.unwrap()).await? {
+    return Ok(None);
+  }
+
+  return Ok(Some(output_path));
+}
+
+/// Gets a directory's packed archive, packing it again if any of its files changed since it was last packed.
+pub async fn get_packed_zip(dir: &Path, compression: ZipCompression, job: Option<&JobContext>) -> Result<PathBuf, ZipError> {
+  if let Some(packed_path) = find_packed_zip(dir).await? {
+    return Ok(packed_path);
+  }
+
+  let output_path = get_packed_zip_path(dir);
+
+  create_dir_all(output_path.parent().unwrap()).await?;
+
+  // * Packed to a temporary file first, so a download never sees a partial archive.
+  // * Each pack gets its own file, so packs of the same folder can't write over each other.
+  let partial_path = output_path.with_extension(format!("zip.{:08x}.part", rand::random::<u32>()));
+  let pack_res = pack_zip(dir, &partial_path, compression, job).await;
+  if pack_res.is_err() {
+    let _ = tokio::fs::remove_file(&partial_path).await;
+    return Err(pack_res.err().unwrap());
+  }
+
+  tokio::fs::rename(&partial_path, &output_path).await?;
+
   return Ok(output_path);
 }
+
+/// Removes packed archives that haven't been packed again in over a day, along with any partial archives left by failed packs.
+/// Returns the number of archives that were removed.
+pub async fn remove_stale_packed_zips() -> Result<usize, tokio::io::Error> {
+  const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+  let zips_dir = get_packed_zips_dir();
+  if !tokio::fs::try_exists(&zips_dir).await? {
+    return Ok(0);
+  }
+
+  let now = SystemTime::now();
+  let mut removed = 0;
+
+  for file in walk_dir(zips_dir.clone()).await? {
+    let modified = tokio::fs::metadata(&file).await.and_then(|metadata| metadata.modified());
+    if modified.is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() < MAX_AGE) {
+      continue;
+    }
+
+    let remove_res = tokio::fs::remove_file(&file).await;
+    if remove_res.is_err() {
+      warn!("Failed to remove packed zip \"{}\": {}", file.display(), remove_res.err().unwrap());
+      continue;
+    }
+
+    removed += 1;
+  }
+
+  // * Each archive has its own folder, which is only removed once it's empty.
+  let mut entries = tokio::fs::read_dir(&zips_dir).await?;
+  while let Some(entry) = entries.next_entry().await? {
+    let _ = tokio::fs::remove_dir(entry.path()).await;
+  }
+
+  return Ok(removed);
+}
@@ -0,0 +1,386 @@
+use std::{io, path::{Path, PathBuf}};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use log::warn;
+use tokio::{fs::File, io::AsyncReadExt};
+use warp::hyper::{body::Sender, Body};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const ZIP64_END_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const END_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+
+/// Sizes and offsets at or above this are stored in the ZIP64 extra field.
+const ZIP64_LIMIT: u64 = 0xFFFFFFFF;
+
+/// Entry counts at or above this need the ZIP64 end of central directory record.
+const ZIP64_ENTRIES_LIMIT: usize = 0xFFFF;
+
+/// Names are UTF-8.
+const FLAG_UTF8: u16 = 1 << 11;
+
+/// The CRC and sizes follow the entry's data, since the CRC is only known once the file has been streamed.
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+
+/// Made by Unix, so the external attributes are read as permissions.
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION_ZIP64;
+
+const READ_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// A file or directory in a streamed ZIP.
+struct FolderZipEntry {
+  path: PathBuf,
+  name: String,
+  size: u64,
+  is_dir: bool,
+  dos_time: u16,
+  dos_date: u16,
+  offset: u64,
+}
+
+impl FolderZipEntry {
+  fn is_zip64(&self) -> bool {
+    return self.size >= ZIP64_LIMIT;
+  }
+
+  fn flags(&self) -> u16 {
+    return if self.is_dir { FLAG_UTF8 } else { FLAG_UTF8 | FLAG_DATA_DESCRIPTOR };
+  }
+
+  fn version_needed(&self) -> u16 {
+    return if self.is_zip64() || self.offset >= ZIP64_LIMIT { VERSION_ZIP64 } else { VERSION_DEFAULT };
+  }
+
+  fn local_header_size(&self) -> u64 {
+    return 30 + self.name.len() as u64 + if self.is_zip64() { 20 } else { 0 };
+  }
+
+  fn data_descriptor_size(&self) -> u64 {
+    if self.is_dir {
+      return 0;
+    }
+
+    return if self.is_zip64() { 24 } else { 16 };
+  }
+
+  /// Gets the values that don't fit in the central directory header, in the order the ZIP64 extra field expects them.
+  fn central_zip64_fields(&self) -> Vec<u64> {
+    let mut fields = vec![];
+
+    if self.is_zip64() {
+      fields.push(self.size);
+      fields.push(self.size);
+    }
+    if self.offset >= ZIP64_LIMIT {
+      fields.push(self.offset);
+    }
+
+    return fields;
+  }
+
+  fn central_header_size(&self) -> u64 {
+    let fields = self.central_zip64_fields();
+    let extra_size = if fields.is_empty() { 0 } else { 4 + 8 * fields.len() as u64 };
+
+    return 46 + self.name.len() as u64 + extra_size;
+  }
+
+  fn local_header(&self) -> Bytes {
+    let mut header = BytesMut::with_capacity(self.local_header_size() as usize);
+
+    header.put_u32_le(LOCAL_HEADER_SIGNATURE);
+    header.put_u16_le(self.version_needed());
+    header.put_u16_le(self.flags());
+    header.put_u16_le(0);
+    header.put_u16_le(self.dos_time);
+    header.put_u16_le(self.dos_date);
+    header.put_u32_le(0);
+
+    // * With a data descriptor, the sizes here are zero and filled in afterwards.
+    let size_placeholder = if self.is_zip64() { ZIP64_LIMIT as u32 } else { 0 };
+    header.put_u32_le(size_placeholder);
+    header.put_u32_le(size_placeholder);
+
+    header.put_u16_le(self.name.len() as u16);
+    header.put_u16_le(if self.is_zip64() { 20 } else { 0 });
+    header.put_slice(self.name.as_bytes());
+
+    if self.is_zip64() {
+      header.put_u16_le(ZIP64_EXTRA_ID);
+      header.put_u16_le(16);
+      header.put_u64_le(0);
+      header.put_u64_le(0);
+    }
+
+    return header.freeze();
+  }
+
+  fn data_descriptor(&self, crc: u32) -> Bytes {
+    let mut descriptor = BytesMut::with_capacity(self.data_descriptor_size() as usize);
+
+    descriptor.put_u32_le(DATA_DESCRIPTOR_SIGNATURE);
+    descriptor.put_u32_le(crc);
+
+    if self.is_zip64() {
+      descriptor.put_u64_le(self.size);
+      descriptor.put_u64_le(self.size);
+    } else {
+      descriptor.put_u32_le(self.size as u32);
+      descriptor.put_u32_le(self.size as u32);
+    }
+
+    return descriptor.freeze();
+  }
+
+  fn central_header(&self, crc: u32) -> Bytes {
+    let fields = self.central_zip64_fields();
+    let mut header = BytesMut::with_capacity(self.central_header_size() as usize);
+
+    header.put_u32_le(CENTRAL_HEADER_SIGNATURE);
+    header.put_u16_le(VERSION_MADE_BY);
+    header.put_u16_le(self.version_needed());
+    header.put_u16_le(self.flags());
+    header.put_u16_le(0);
+    header.put_u16_le(self.dos_time);
+    header.put_u16_le(self.dos_date);
+    header.put_u32_le(crc);
+    header.put_u32_le(self.size.min(ZIP64_LIMIT) as u32);
+    header.put_u32_le(self.size.min(ZIP64_LIMIT) as u32);
+    header.put_u16_le(self.name.len() as u16);
+    header.put_u16_le(if fields.is_empty() { 0 } else { 4 + 8 * fields.len() as u16 });
+    header.put_u16_le(0);
+    header.put_u16_le(0);
+    header.put_u16_le(0);
+
+    // * Unix permissions, plus the MS-DOS directory flag.
+    header.put_u32_le(if self.is_dir { (0o40755 << 16) | 0x10 } else { 0o100644 << 16 });
+
+    header.put_u32_le(self.offset.min(ZIP64_LIMIT) as u32);
+    header.put_slice(self.name.as_bytes());
+
+    if !fields.is_empty() {
+      header.put_u16_le(ZIP64_EXTRA_ID);
+      header.put_u16_le(8 * fields.len() as u16);
+      for field in fields {
+        header.put_u64_le(field);
+      }
+    }
+
+    return header.freeze();
+  }
+}
+
+/// Converts a modified time to the MS-DOS time and date used by ZIPs.
+fn get_dos_date_time(metadata: &std::fs::Metadata) -> (u16, u16) {
+  let modified: DateTime<Local> = metadata.modified().map(|modified| modified.into()).unwrap_or(Local::now());
+
+  // * MS-DOS dates can only represent 1980 through 2107.
+  let year = modified.year().clamp(1980, 2107) as u16;
+
+  let time = ((modified.hour() as u16) << 11) | ((modified.minute() as u16) << 5) | (modified.second() as u16 / 2);
+  let date = ((year - 1980) << 9) | ((modified.month() as u16) << 5) | modified.day() as u16;
+
+  return (time, date);
+}
+
+/// A folder that's streamed as an uncompressed ZIP.
+/// Since nothing is compressed, the archive's exact size is known before anything is read.
+pub struct FolderZip {
+  entries: Vec<FolderZipEntry>,
+  central_offset: u64,
+  central_size: u64,
+}
+
+impl FolderZip {
+  /// Lists a folder's contents. Entries are named relative to the folder's parent, so the archive extracts to the folder.
+  pub async fn new(dir: &Path) -> Result<FolderZip, io::Error> {
+    let root_name = dir.file_name().and_then(|name| name.to_str()).unwrap_or("rom").to_string();
+    let mut entries = vec![];
+
+    let mut pending = vec![(dir.to_path_buf(), root_name)];
+    while let Some((dir_path, dir_name)) = pending.pop() {
+      let metadata = tokio::fs::metadata(&dir_path).await?;
+      let (dos_time, dos_date) = get_dos_date_time(&metadata);
+
+      entries.push(FolderZipEntry {
+        path: dir_path.clone(),
+        name: format!("{}/", dir_name),
+        size: 0,
+        is_dir: true,
+        dos_time,
+        dos_date,
+        offset: 0,
+      });
+
+      let mut children = vec![];
+      let mut dir_iter = tokio::fs::read_dir(&dir_path).await?;
+      while let Some(entry) = dir_iter.next_entry().await? {
+        children.push(entry);
+      }
+      children.sort_by_key(|entry| entry.file_name());
+
+      let mut child_dirs = vec![];
+      for child in children {
+        let name_res = child.file_name().into_string();
+        if name_res.is_err() {
+          warn!("Folder Zip: Skipping \"{}\" since its name isn't valid UTF-8.", child.path().display());
+          continue;
+        }
+        let name = format!("{}/{}", dir_name, name_res.unwrap());
+
+        // * Symlinks are skipped so they can't escape the folder or loop.
+        let metadata = tokio::fs::symlink_metadata(child.path()).await?;
+        if metadata.is_dir() {
+          child_dirs.push((child.path(), name));
+        } else if metadata.is_file() {
+          let (dos_time, dos_date) = get_dos_date_time(&metadata);
+
+          entries.push(FolderZipEntry {
+            path: child.path(),
+            name,
+            size: metadata.len(),
+            is_dir: false,
+            dos_time,
+            dos_date,
+            offset: 0,
+          });
+        }
+      }
+
+      // * Reversed so subfolders are popped in order.
+      child_dirs.reverse();
+      pending.append(&mut child_dirs);
+    }
+
+    let mut offset = 0;
+    for entry in entries.iter_mut() {
+      entry.offset = offset;
+      offset += entry.local_header_size() + entry.size + entry.data_descriptor_size();
+    }
+
+    let central_size = entries.iter().map(|entry| entry.central_header_size()).sum();
+
+    return Ok(FolderZip {
+      entries,
+      central_offset: offset,
+      central_size,
+    });
+  }
+
+  fn needs_zip64_end(&self) -> bool {
+    return self.entries.len() >= ZIP64_ENTRIES_LIMIT || self.central_offset >= ZIP64_LIMIT || self.central_size >= ZIP64_LIMIT;
+  }
+
+  /// Gets the size of the archive in bytes.
+  pub fn size(&self) -> u64 {
+    let end_size = if self.needs_zip64_end() { 56 + 20 + 22 } else { 22 };
+    return self.central_offset + self.central_size + end_size;
+  }
+
+  fn end_records(&self) -> Bytes {
+    let mut end = BytesMut::with_capacity(98);
+    let entry_count = self.entries.len() as u64;
+
+    if self.needs_zip64_end() {
+      let zip64_end_offset = self.central_offset + self.central_size;
+
+      end.put_u32_le(ZIP64_END_SIGNATURE);
+      end.put_u64_le(44);
+      end.put_u16_le(VERSION_MADE_BY);
+      end.put_u16_le(VERSION_ZIP64);
+      end.put_u32_le(0);
+      end.put_u32_le(0);
+      end.put_u64_le(entry_count);
+      end.put_u64_le(entry_count);
+      end.put_u64_le(self.central_size);
+      end.put_u64_le(self.central_offset);
+
+      end.put_u32_le(ZIP64_LOCATOR_SIGNATURE);
+      end.put_u32_le(0);
+      end.put_u64_le(zip64_end_offset);
+      end.put_u32_le(1);
+    }
+
+    end.put_u32_le(END_SIGNATURE);
+    end.put_u16_le(0);
+    end.put_u16_le(0);
+    end.put_u16_le(entry_count.min(ZIP64_ENTRIES_LIMIT as u64) as u16);
+    end.put_u16_le(entry_count.min(ZIP64_ENTRIES_LIMIT as u64) as u16);
+    end.put_u32_le(self.central_size.min(ZIP64_LIMIT) as u32);
+    end.put_u32_le(self.central_offset.min(ZIP64_LIMIT) as u32);
+    end.put_u16_le(0);
+
+    return end.freeze();
+  }
+
+  /// Writes the archive to a response body.
+  async fn write_to(&self, sender: &mut Sender) -> Result<(), io::Error> {
+    let disconnected = |_| io::Error::new(io::ErrorKind::BrokenPipe, "The client disconnected.");
+    let mut crcs = Vec::with_capacity(self.entries.len());
+
+    for entry in self.entries.iter() {
+      sender.send_data(entry.local_header()).await.map_err(disconnected)?;
+
+      if entry.is_dir {
+        crcs.push(0);
+        continue;
+      }
+
+      let mut file = File::open(&entry.path).await?;
+      let mut crc = crc32fast::Hasher::new();
+      let mut remaining = entry.size;
+
+      while remaining > 0 {
+        let mut buffer = vec![0; READ_CHUNK_SIZE.min(remaining as usize)];
+        let read = file.read(&mut buffer).await?;
+
+        // * The length was already sent, so a file that shrank can't be recovered from.
+        if read == 0 {
+          return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("\"{}\" changed while it was being zipped.", entry.path.display())));
+        }
+
+        buffer.truncate(read);
+        crc.update(&buffer);
+        remaining -= read as u64;
+
+        sender.send_data(Bytes::from(buffer)).await.map_err(disconnected)?;
+      }
+
+      let crc = crc.finalize();
+      sender.send_data(entry.data_descriptor(crc)).await.map_err(disconnected)?;
+      crcs.push(crc);
+    }
+
+    let mut central_directory = BytesMut::with_capacity(self.central_size as usize);
+    for (entry, crc) in self.entries.iter().zip(crcs) {
+      central_directory.put(entry.central_header(crc));
+    }
+    sender.send_data(central_directory.freeze()).await.map_err(disconnected)?;
+
+    sender.send_data(self.end_records()).await.map_err(disconnected)?;
+
+    return Ok(());
+  }
+
+  /// Streams the archive as a response body.
+  pub fn into_body(self) -> Body {
+    let (mut sender, body) = Body::channel();
+
+    tokio::spawn(async move {
+      let write_res = self.write_to(&mut sender).await;
+      if write_res.is_err() {
+        warn!("Folder Zip: Failed to stream archive: {}", write_res.err().unwrap());
+        sender.abort();
+      }
+    });
+
+    return body;
+  }
+}
@@ -0,0 +1,171 @@
+use std::{collections::BTreeMap, env::var, future::Future, str::FromStr, sync::{Arc, Mutex}, time::Instant};
+
+use chrono::Utc;
+use cron::Schedule;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// The outcome of a job's run.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum JobResult {
+  SUCCESS,
+  FAILED,
+}
+
+/// A finished run of a scheduled job.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct JobRun {
+  pub startedAt: i64,
+  // ? How long the job took, in milliseconds.
+  pub duration: u64,
+  pub result: JobResult,
+  pub message: String,
+}
+
+/// The status of a recurring job.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct ScheduledJobStatus {
+  pub name: String,
+  pub schedule: String,
+  pub enabled: bool,
+  pub running: bool,
+  pub nextRun: Option<i64>,
+  pub lastRun: Option<JobRun>,
+}
+
+/// Gets a job's cron schedule from an env variable. An empty schedule disables the job.
+pub fn get_job_schedule(variable: &str, default: &str) -> String {
+  let schedule = var(variable).unwrap_or(default.to_string());
+
+  // * Compose files often quote the schedule, which leaves the quotes in the value.
+  return schedule.trim().trim_matches('"').trim().to_string();
+}
+
+/// Runs recurring jobs on cron schedules, and keeps track of how their last run went.
+#[derive(Clone)]
+pub struct Scheduler {
+  jobs: Arc<Mutex<BTreeMap<String, ScheduledJobStatus>>>,
+}
+
+impl Scheduler {
+  /// Creates a new Scheduler.
+  pub fn new() -> Scheduler {
+    return Scheduler {
+      jobs: Arc::new(Mutex::new(BTreeMap::new())),
+    };
+  }
+
+  /// Adds a recurring job. Jobs without a valid schedule are listed, but never run.
+  pub fn add_job<F, Fut>(&self, name: &str, schedule_str: &str, job: F)
+  where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<String, String>> + Send + 'static
+  {
+    let mut status = ScheduledJobStatus {
+      name: name.to_string(),
+      schedule: schedule_str.to_string(),
+      enabled: false,
+      running: false,
+      nextRun: None,
+      lastRun: None,
+    };
+
+    if schedule_str.is_empty() {
+      info!("Scheduler: Job \"{}\" has no schedule, so it won't run.", name);
+      self.jobs.lock().expect("Failed to lock Scheduler Jobs Mutex.").insert(name.to_string(), status);
+      return;
+    }
+
+    let schedule_res = Schedule::from_str(schedule_str);
+    if schedule_res.is_err() {
+      warn!("Scheduler: Failed to parse the schedule \"{}\" for job \"{}\", so it won't run: {}", schedule_str, name, schedule_res.err().unwrap());
+      self.jobs.lock().expect("Failed to lock Scheduler Jobs Mutex.").insert(name.to_string(), status);
+      return;
+    }
+    let schedule = schedule_res.unwrap();
+
+    status.enabled = true;
+    self.jobs.lock().expect("Failed to lock Scheduler Jobs Mutex.").insert(name.to_string(), status);
+
+    let scheduler = self.clone();
+    let name = name.to_string();
+    let schedule_str = schedule_str.to_string();
+
+    tokio::spawn(async move {
+      info!("Scheduler: Scheduled job \"{}\" for \"{}\".", name, schedule_str);
+
+      loop {
+        // * Upcoming times are taken from now, so runs that were missed while the job was running are skipped.
+        let next_run = schedule.upcoming(Utc).next();
+        scheduler.update(&name, |status| status.nextRun = next_run.map(|next_run| next_run.timestamp()));
+
+        if next_run.is_none() {
+          info!("Scheduler: Job \"{}\" has no more runs.", name);
+          break;
+        }
+
+        let until = (next_run.unwrap() - Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(until).await;
+
+        scheduler.run(&name, job()).await;
+      }
+    });
+  }
+
+  /// Runs a job, and records the result.
+  async fn run<Fut>(&self, name: &str, job: Fut)
+  where
+    Fut: Future<Output = Result<String, String>> + Send + 'static
+  {
+    let started_at = Utc::now().timestamp();
+    let start = Instant::now();
+    self.update(name, |status| status.running = true);
+
+    info!("Scheduler: Running job \"{}\"...", name);
+
+    // * Jobs run in their own task so a panic is recorded as a failure instead of stopping the schedule.
+    let job_res = tokio::spawn(job).await
+      .unwrap_or_else(|err| Err(format!("The job panicked: {}", err)));
+
+    let (result, message) = match job_res {
+      Ok(message) => (JobResult::SUCCESS, message),
+      Err(message) => (JobResult::FAILED, message),
+    };
+
+    if result == JobResult::FAILED {
+      warn!("Scheduler: Job \"{}\" failed: {}", name, message);
+    } else {
+      info!("Scheduler: Job \"{}\" finished: {}", name, message);
+    }
+
+    let run = JobRun {
+      startedAt: started_at,
+      duration: start.elapsed().as_millis() as u64,
+      result,
+      message,
+    };
+
+    self.update(name, |status| {
+      status.running = false;
+      status.lastRun = Some(run);
+    });
+  }
+
+  /// Updates a job's status.
+  fn update(&self, name: &str, update: impl FnOnce(&mut ScheduledJobStatus)) {
+    let mut jobs = self.jobs.lock().expect("Failed to lock Scheduler Jobs Mutex.");
+
+    if let Some(status) = jobs.get_mut(name) {
+      update(status);
+    }
+  }
+
+  /// Gets the status of every job.
+  pub fn get_statuses(&self) -> Vec<ScheduledJobStatus> {
+    let jobs = self.jobs.lock().expect("Failed to lock Scheduler Jobs Mutex.");
+    return jobs.values().cloned().collect();
+  }
+}
@@ -1,8 +1,19 @@
-use std::env::var;
+use std::{collections::HashMap, env::var, fs, path::PathBuf};
 
-use crypto::{digest::Digest, sha2::Sha256};
-use log::warn;
-use tokio::sync::broadcast;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use crypto::{digest::Digest, hmac::Hmac, mac::{Mac, MacResult}, pbkdf2::pbkdf2, sha2::Sha256, util::fixed_time_eq};
+use log::{info, warn};
+use super::{config_files::write_atomic, types::{auth::{RevokedSessions, SessionClaims, SessionStore, SessionToken, User, UserRole, UserStore}, protocol::Event, Responder}, users::get_env_username, utils::send_event};
+
+/// The default session length in minutes.
+const DEFAULT_SESSION_LENGTH: i64 = 60;
+
+/// The longest a session can last in minutes, which is a year.
+const MAX_SESSION_LENGTH: i64 = 60 * 24 * 365;
+
+/// The number of PBKDF2 iterations used when hashing stored passwords.
+const PASSWORD_ITERATIONS: u32 = 100_000;
 
 /// Hashes a string using SHA256.
 fn hash(text: String) -> String {
@@ -15,40 +26,289 @@ fn hash(text: String) -> String {
   return result;
 }
 
-pub fn validate_hash(password_hash: String, tx: broadcast::Sender<String>) -> bool {
-  let env_password_res = var("NRM_PASSWORD");
-  if env_password_res.is_err() {
-    warn!("No password variable \"NRM_PASSWORD\" was found!");
-    tx.send(format!("missing_env_variable NRM_PASSWORD")).expect("Failed to broadcast message");
+/// Hashes the password hash sent by the frontend with a user's salt using PBKDF2. The iteration count is stored with the hash so it can be raised later.
+pub fn hash_password(salt: &str, password_hash: &str) -> String {
+  return format!("pbkdf2${}${}", PASSWORD_ITERATIONS, derive_password(salt, password_hash, PASSWORD_ITERATIONS));
+}
+
+/// Derives the PBKDF2 key for a password hash.
+fn derive_password(salt: &str, password_hash: &str, iterations: u32) -> String {
+  let mut mac = Hmac::new(Sha256::new(), password_hash.as_bytes());
+  let mut output = [0u8; 32];
+
+  pbkdf2(&mut mac, salt.as_bytes(), iterations, &mut output);
+
+  return URL_SAFE_NO_PAD.encode(output);
+}
+
+/// Checks the password hash sent by the frontend against a user's stored hash, in constant time.
+fn verify_password(user: &User, password_hash: &str) -> bool {
+  let stored_parts: Vec<&str> = user.passwordHash.split("$").collect();
+  if stored_parts.len() != 3 || stored_parts[0] != "pbkdf2" {
+    warn!("Auth: Stored password hash for \"{}\" was malformatted.", user.username);
     return false;
   }
 
-  let env_password = env_password_res.unwrap();
-  let env_password_hash = hash(env_password);
+  let iterations_res = stored_parts[1].parse::<u32>();
+  if iterations_res.is_err() {
+    warn!("Auth: Stored password hash for \"{}\" was malformatted.", user.username);
+    return false;
+  }
+
+  let derived = derive_password(&user.salt, password_hash, iterations_res.unwrap());
+  return fixed_time_eq(derived.as_bytes(), stored_parts[2].as_bytes());
+}
+
+/// Signs the provided payload with the session secret.
+fn sign(secret: &[u8], payload: &str) -> MacResult {
+  let mut hmac = Hmac::new(Sha256::new(), secret);
+  hmac.input(payload.as_bytes());
 
-  return password_hash == env_password_hash;
+  return hmac.result();
 }
 
+/// Gets the path of the sessions.json file.
+fn get_revoked_path() -> PathBuf {
+  let config_path = PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap());
+  return config_path.join("sessions.json");
+}
+
+/// Loads the sessions that were ended before the server restarted.
+fn load_revoked_sessions() -> RevokedSessions {
+  let revoked_path = get_revoked_path();
+  if !fs::exists(&revoked_path).unwrap_or(false) {
+    return RevokedSessions::default();
+  }
+
+  let revoked_res = fs::read_to_string(&revoked_path).map_err(|e| e.to_string())
+    .and_then(|revoked_str| serde_json::from_str::<RevokedSessions>(&revoked_str).map_err(|e| e.to_string()));
+  if revoked_res.is_err() {
+    warn!("Auth: Failed to read sessions.json: {}", revoked_res.err().unwrap());
+    return RevokedSessions::default();
+  }
+
+  return revoked_res.unwrap();
+}
+
+/// Removes revocations that no longer matter because the tokens they cover have expired.
+fn prune_revoked(store: &mut SessionStore) {
+  let now = Utc::now();
+
+  store.revoked.retain(|_, expires| *expires > now.timestamp());
+
+  // * Tokens issued before a user's sessions were ended expire within a session length of it. The longest length is used, since it can change between restarts.
+  let oldest_valid = now.timestamp_millis() - MAX_SESSION_LENGTH * 60 * 1000;
+  store.revoked_users.retain(|_, revoked_at| *revoked_at > oldest_valid);
+}
+
+/// Saves the ended sessions, so they stay ended after a restart.
+fn write_revoked_sessions(store: &SessionStore) {
+  let revoked = RevokedSessions {
+    sessions: store.revoked.clone(),
+    users: store.revoked_users.clone(),
+  };
+
+  let revoked_str = serde_json::to_string_pretty(&revoked).expect("Revoked sessions were malformatted.");
+
+  let write_res = write_atomic(&get_revoked_path(), &revoked_str);
+  if write_res.is_err() {
+    warn!("Auth: Failed to write sessions.json: {}", write_res.err().unwrap());
+  }
+}
+
+/// Creates the session store. Uses `NRM_SESSION_SECRET` as the signing key if it is set, otherwise a random key is generated.
+/// Sessions ended before a restart are only loaded with `NRM_SESSION_SECRET`, since a new key ends every session anyways.
+pub fn init_session_store() -> SessionStore {
+  let (secret, revoked) = match var("NRM_SESSION_SECRET") {
+    Ok(secret) if !secret.is_empty() => (secret.into_bytes(), load_revoked_sessions()),
+    _ => {
+      info!("No \"NRM_SESSION_SECRET\" was provided, generating one. Sessions will end when the server restarts.");
+      (rand::random::<[u8; 32]>().to_vec(), RevokedSessions::default())
+    }
+  };
+
+  let mut store = SessionStore {
+    secret,
+    length: parse_session_length(var("NRM_SESSION_LENGTH").ok()) * 60,
+    user_roles: HashMap::new(),
+    revoked: revoked.sessions,
+    revoked_users: revoked.users,
+  };
+
+  prune_revoked(&mut store);
+  return store;
+}
+
+/// Parses the session length in minutes. Lengths that aren't positive or are over a year fall back to the default.
+fn parse_session_length(length: Option<String>) -> i64 {
+  let length = length.filter(|length| !length.trim().is_empty());
+  if length.is_none() {
+    return DEFAULT_SESSION_LENGTH;
+  }
+  let length = length.unwrap();
+
+  let parsed = length.trim().parse::<i64>().ok().filter(|parsed| *parsed > 0 && *parsed <= MAX_SESSION_LENGTH);
+  if parsed.is_none() {
+    warn!("\"NRM_SESSION_LENGTH\" must be between 1 and {} minutes, but was \"{}\". Using {} minutes instead.", MAX_SESSION_LENGTH, length, DEFAULT_SESSION_LENGTH);
+    return DEFAULT_SESSION_LENGTH;
+  }
+
+  return parsed.unwrap();
+}
+
+/// Creates a new signed session token for the provided user.
+pub fn create_session(store: &SessionStore, user: String, role: UserRole) -> SessionToken {
+  let now = Utc::now();
+
+  let claims = SessionClaims {
+    user: user.clone(),
+    role,
+    sessionId: URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>()),
+    issued: now.timestamp_millis(),
+    expires: now.timestamp().checked_add(store.length).unwrap_or(i64::MAX),
+  };
+
+  let payload = URL_SAFE_NO_PAD.encode(serde_json::to_string(&claims).expect("Session claims were malformatted."));
+  let signature = URL_SAFE_NO_PAD.encode(sign(&store.secret, &payload).code());
+
+  return SessionToken {
+    token: format!("{}.{}", payload, signature),
+    user,
+    role,
+    expires: claims.expires,
+  };
+}
+
+/// Updates the roles the session store checks tokens against. Should be called whenever the users change.
+pub fn sync_user_roles(store: &mut SessionStore, users: &UserStore) {
+  store.user_roles = users.users.values().map(|user| (user.username.clone(), user.role)).collect();
+}
+
+/// Gets a user's current role, or None if they no longer exist.
+fn get_current_role(store: &SessionStore, username: &str) -> Option<UserRole> {
+  if get_env_username().as_deref() == Some(username) {
+    return Some(UserRole::ADMIN);
+  }
+
+  return store.user_roles.get(username).copied();
+}
+
+/// Validates a session token, returning its claims if it is valid, unexpired, and hasn't been revoked.
+/// The claims' role is replaced with the user's current role, since tokens can outlive a restart when `NRM_SESSION_SECRET` is set.
+pub fn validate_token(store: &SessionStore, token: &str) -> Option<SessionClaims> {
+  let (payload, signature) = token.split_once(".")?;
+
+  let signature_bytes = URL_SAFE_NO_PAD.decode(signature).ok()?;
+  if sign(&store.secret, payload) != MacResult::new(&signature_bytes) {
+    warn!("Session token signature did not match!");
+    return None;
+  }
+
+  let payload_bytes = URL_SAFE_NO_PAD.decode(payload).ok()?;
+  let mut claims: SessionClaims = serde_json::from_slice(&payload_bytes).ok()?;
+
+  if claims.expires <= Utc::now().timestamp() {
+    return None;
+  }
+
+  if store.revoked.contains_key(&claims.sessionId) {
+    return None;
+  }
+
+  let user_revoked = store.revoked_users.get(&claims.user);
+  if user_revoked.is_some() && claims.issued <= *user_revoked.unwrap() {
+    return None;
+  }
+
+  claims.role = get_current_role(store, &claims.user)?;
+
+  return Some(claims);
+}
+
+/// Ends the session with the provided claims.
+pub fn revoke_session(store: &mut SessionStore, claims: &SessionClaims) {
+  // * Expired sessions are rejected anyways, so there's no need to keep them around.
+  prune_revoked(store);
+  store.revoked.insert(claims.sessionId.clone(), claims.expires);
+
+  write_revoked_sessions(store);
+}
+
+/// Ends all of a user's current sessions. Used when their account is changed or deleted.
+pub fn revoke_user_sessions(store: &mut SessionStore, username: &str) {
+  prune_revoked(store);
+  store.revoked_users.insert(username.to_string(), Utc::now().timestamp_millis());
+
+  write_revoked_sessions(store);
+}
+
+/// Issues a new session token for a valid token, and ends the old session. The new token uses the user's current role.
+pub fn refresh_session(store: &mut SessionStore, token: &str) -> Option<SessionToken> {
+  let claims = validate_token(store, token)?;
+
+  revoke_session(store, &claims);
+
+  return Some(create_session(store, claims.user, claims.role));
+}
+
+/// Checks the credentials against the users in users.json.
+fn authenticate_stored_user(username: &str, password_hash: &str, users: &UserStore) -> Option<UserRole> {
+  let user = users.users.get(username)?;
+  if !verify_password(user, password_hash) {
+    return None;
+  }
+
+  return Some(user.role);
+}
+
+/// Authenticates a user, returning their role if the credentials were valid.
 /// password is already hashed with SHA2
-pub fn authenticate_user(username: String, password_hash: String, tx: broadcast::Sender<String>) -> bool {
-  let env_username_res = var("NRM_USERNAME");
-  if env_username_res.is_err() {
-    warn!("No environment variable \"NRM_USERNAME\" was found!");
-    tx.send(format!("missing_env_variable NRM_USERNAME")).expect("Failed to broadcast message");
-    return false;
+pub fn authenticate_user(username: String, password_hash: String, users: &UserStore, tx: Responder) -> Option<UserRole> {
+  let env_username_res = get_env_username();
+  if env_username_res.is_none() {
+    if users.users.is_empty() {
+      warn!("No environment variable \"NRM_USERNAME\" was found!");
+      send_event(tx, Event::MissingEnvVariable(String::from("NRM_USERNAME")));
+      return None;
+    }
+
+    return authenticate_stored_user(&username, &password_hash, users);
   }
 
   let env_username = env_username_res.unwrap();
-  
+  if username != env_username {
+    return authenticate_stored_user(&username, &password_hash, users);
+  }
+
   let env_password_res = var("NRM_PASSWORD");
   if env_password_res.is_err() {
     warn!("No password variable \"NRM_PASSWORD\" was found!");
-    tx.send(format!("missing_env_variable NRM_PASSWORD")).expect("Failed to broadcast message");
-    return false;
+    send_event(tx, Event::MissingEnvVariable(String::from("NRM_PASSWORD")));
+    return None;
   }
 
   let env_password = env_password_res.unwrap();
   let env_password_hash = hash(env_password);
 
-  return username == env_username && password_hash == env_password_hash;
-}
\ No newline at end of file
+  if !fixed_time_eq(password_hash.as_bytes(), env_password_hash.as_bytes()) {
+    return None;
+  }
+
+  return Some(UserRole::ADMIN);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{parse_session_length, DEFAULT_SESSION_LENGTH, MAX_SESSION_LENGTH};
+
+  #[test]
+  fn parse_session_length_rejects_invalid_lengths() {
+    assert_eq!(parse_session_length(None), DEFAULT_SESSION_LENGTH);
+    assert_eq!(parse_session_length(Some(String::from("90"))), 90);
+    assert_eq!(parse_session_length(Some(MAX_SESSION_LENGTH.to_string())), MAX_SESSION_LENGTH);
+
+    for invalid in ["0", "-5", "abc", "", "9223372036854775807", "525601"] {
+      assert_eq!(parse_session_length(Some(invalid.to_string())), DEFAULT_SESSION_LENGTH, "\"{}\" should fall back to the default.", invalid);
+    }
+  }
+}
@@ -0,0 +1,482 @@
+use std::{collections::{HashMap, HashSet}, env::var, fs, path::PathBuf};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Local, NaiveDate};
+use log::{info, warn};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use super::{
+  config_files::{get_backup_dir, read_with_recovery, write_with_backup},
+  types::{library::{Collection, CollectionRule, CollectionRules, ROMMetadata, RuleCombinator, RuleField, RuleOperator, StateStore, ROM}, protocol::Event, BackendErrorType, ErrorSender},
+  utils::{broadcast, get_igdb_list, hash64}
+};
+
+/// The value of a ROM's field, in the shape rules compare against.
+enum FieldValue {
+  Text(String),
+  Number(f64),
+  Bool(bool),
+  List(Vec<String>),
+  Date(NaiveDate),
+}
+
+fn get_collections_path() -> PathBuf {
+  return PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap()).join("collections.json");
+}
+
+/// Loads the saved collections. Smart collections are filled in once the library is loaded.
+pub fn load_collections() -> HashMap<String, Collection> {
+  let collections_path = get_collections_path();
+
+  if !fs::exists(&collections_path).unwrap_or(false) {
+    info!("Load Collections: No collections.json was found, starting with no collections.");
+    return HashMap::new();
+  }
+
+  let collections_res = read_with_recovery(&collections_path, &get_backup_dir(""));
+  if collections_res.is_err() {
+    warn!("Load Collections: Failed to load collections.json: {}", collections_res.err().unwrap());
+    return HashMap::new();
+  }
+
+  let (collections, _) = collections_res.unwrap();
+  return collections;
+}
+
+/// Writes the collections to the file system.
+pub fn write_collections(collections: &HashMap<String, Collection>, send_error: &ErrorSender) -> bool {
+  let collections_str = serde_json::to_string_pretty(collections).expect("Collections were malformatted.");
+
+  let write_res = write_with_backup(&get_collections_path(), &get_backup_dir(""), &collections_str);
+  if write_res.is_err() {
+    let err = write_res.err().unwrap();
+
+    send_error(
+      format!("Failed to write collections: {}", err.to_string()),
+      "Please ensure NRM has write access to the mounted \"/config\" directory.".to_string(),
+      BackendErrorType::PANIC
+    );
+
+    return false;
+  }
+
+  return true;
+}
+
+/// Gets the strings in a list from a ROM's IGDB metadata, ex: its genres.
+fn get_metadata_list(metadata: Option<&ROMMetadata>, key: &str) -> Vec<String> {
+  return metadata
+    .and_then(|metadata| metadata.metadata.as_ref())
+    .map(|game| get_igdb_list(game, key))
+    .unwrap_or_default();
+}
+
+/// Gets the value of the field a rule checks.
+fn get_field_value(field: RuleField, rom: &ROM, metadata: Option<&ROMMetadata>) -> Option<FieldValue> {
+  return match field {
+    // * The title in the metadata is the one users see and can edit.
+    RuleField::TITLE => Some(FieldValue::Text(metadata.map(|metadata| metadata.title.clone()).filter(|title| !title.is_empty()).unwrap_or(rom.title.clone()))),
+    RuleField::SYSTEM => Some(FieldValue::Text(rom.system.clone())),
+    RuleField::FORMAT => Some(FieldValue::Text(rom.format.clone())),
+    RuleField::SIZE => Some(FieldValue::Number(rom.size as f64)),
+    RuleField::ADDED => DateTime::from_timestamp(rom.addedAt, 0).map(|added_at| FieldValue::Date(added_at.with_timezone(&Local).date_naive())),
+    RuleField::FAVORITE => Some(FieldValue::Bool(metadata.is_some_and(|metadata| metadata.isFavorite))),
+    RuleField::GENRE => Some(FieldValue::List(get_metadata_list(metadata, "genres"))),
+    RuleField::DEVELOPER => Some(FieldValue::List(get_metadata_list(metadata, "developers"))),
+    RuleField::PUBLISHER => Some(FieldValue::List(get_metadata_list(metadata, "publishers"))),
+  };
+}
+
+/// Checks if an operator compares text.
+fn is_text_operator(operator: RuleOperator) -> bool {
+  return operator == RuleOperator::EQUALS || operator == RuleOperator::CONTAINS;
+}
+
+/// Compares text the way users expect, ignoring case.
+fn compare_text(text: &str, operator: RuleOperator, value: &str) -> bool {
+  let text = text.to_lowercase();
+  let value = value.to_lowercase();
+
+  return match operator {
+    RuleOperator::EQUALS => text == value,
+    RuleOperator::CONTAINS => text.contains(&value),
+    _ => false,
+  };
+}
+
+/// Checks if a ROM matches a rule. Rules that don't make sense for their field never match.
+fn matches_rule(rule: &CollectionRule, rom: &ROM, metadata: Option<&ROMMetadata>, today: NaiveDate) -> bool {
+  // * Negated operators are the opposite of their positive versions.
+  let (operator, negated) = match rule.operator {
+    RuleOperator::NOT_EQUALS => (RuleOperator::EQUALS, true),
+    RuleOperator::NOT_CONTAINS => (RuleOperator::CONTAINS, true),
+    operator => (operator, false),
+  };
+
+  let field_value = get_field_value(rule.field, rom, metadata);
+  if field_value.is_none() {
+    return false;
+  }
+
+  // * Rules with the wrong kind of value or operator are None, so negating them doesn't make them match.
+  let matches = match (field_value.unwrap(), &rule.value) {
+    (FieldValue::Text(text), Value::String(value)) if is_text_operator(operator) => Some(compare_text(&text, operator, value)),
+    (FieldValue::List(list), Value::String(value)) if is_text_operator(operator) => Some(list.iter().any(|entry| compare_text(entry, operator, value))),
+    (FieldValue::Bool(flag), Value::Bool(value)) if operator == RuleOperator::EQUALS => Some(flag == *value),
+    (FieldValue::Number(number), Value::Number(value)) => {
+      let value = value.as_f64().unwrap_or_default();
+
+      match operator {
+        RuleOperator::EQUALS => Some(number == value),
+        RuleOperator::GREATER_THAN => Some(number > value),
+        RuleOperator::LESS_THAN => Some(number < value),
+        _ => None,
+      }
+    },
+    (FieldValue::Date(date), Value::Number(days)) if operator == RuleOperator::WITHIN_DAYS => Some((today - date).num_days() <= days.as_i64().unwrap_or_default()),
+    _ => None,
+  };
+
+  return matches.is_some_and(|matches| matches != negated);
+}
+
+/// Gets the ids of the ROMs that match a smart collection's rules.
+pub fn get_smart_collection_roms(rules: &CollectionRules, roms: &[ROM], metadata: &HashMap<String, ROMMetadata>) -> Vec<String> {
+  let today = Local::now().date_naive();
+  let mut rom_ids = vec![];
+
+  // ? A smart collection without rules would otherwise hold the whole library.
+  if rules.rules.is_empty() {
+    return rom_ids;
+  }
+
+  for rom in roms {
+    let rom_id = hash64(&rom.path);
+    let rom_metadata = metadata.get(&rom_id);
+
+    let matches = match rules.combinator {
+      RuleCombinator::ALL => rules.rules.iter().all(|rule| matches_rule(rule, rom, rom_metadata, today)),
+      RuleCombinator::ANY => rules.rules.iter().any(|rule| matches_rule(rule, rom, rom_metadata, today)),
+    };
+
+    if matches {
+      rom_ids.push(rom_id);
+    }
+  }
+
+  rom_ids.sort();
+  return rom_ids;
+}
+
+/// Recomputes the ROMs in every smart collection. Returns whether any of them changed.
+fn update_smart_collections(state: &mut StateStore) -> bool {
+  let mut changed = false;
+
+  for collection in state.collections.values_mut() {
+    if collection.rules.is_none() {
+      continue;
+    }
+
+    let rom_ids = get_smart_collection_roms(collection.rules.as_ref().unwrap(), &state.roms, &state.metadata);
+    if rom_ids != collection.romIds {
+      collection.romIds = rom_ids;
+      changed = true;
+    }
+  }
+
+  return changed;
+}
+
+/// Removes ROMs that are no longer in the library from the regular collections. Returns whether any of them changed.
+fn prune_manual_collections(state: &mut StateStore) -> bool {
+  // * The library hasn't been loaded yet, so there's no way to tell which ROMs were removed.
+  if state.parsers.is_empty() {
+    return false;
+  }
+
+  let loaded_ids: HashSet<String> = state.roms.iter().map(|rom| hash64(&rom.path)).collect();
+  let mut changed = false;
+
+  for collection in state.collections.values_mut().filter(|collection| collection.rules.is_none()) {
+    let rom_count = collection.romIds.len();
+    collection.romIds.retain(|rom_id| loaded_ids.contains(rom_id));
+
+    if collection.romIds.len() != rom_count {
+      info!("Collections: Removed {} ROMs that are no longer in the library from \"{}\".", rom_count - collection.romIds.len(), collection.name);
+      changed = true;
+    }
+  }
+
+  return changed;
+}
+
+/// Updates the collections after the library or metadata changed, and lets every connection know if any did.
+pub fn refresh_collections(state: &mut StateStore, tx: &broadcast::Sender<String>) {
+  let pruned = prune_manual_collections(state);
+  if pruned {
+    let send_error: ErrorSender = Box::new(|message, _, _| warn!("Collections: {}", message));
+    write_collections(&state.collections, &send_error);
+  }
+
+  if update_smart_collections(state) || pruned {
+    broadcast(tx.clone(), Event::CollectionsChanged(state.collections.clone()));
+  }
+}
+
+/// Gets a collection by id, for commands that change it.
+fn get_collection<'a>(state: &'a mut StateStore, collection_id: &str) -> Result<&'a mut Collection, String> {
+  return state.collections.get_mut(collection_id).ok_or(format!("No collection with id \"{}\" exists.", collection_id));
+}
+
+/// Creates a collection. Smart collections are filled in right away.
+pub fn create_collection(state: &mut StateStore, name: &str, rules: Option<CollectionRules>) -> Result<Collection, String> {
+  let name = name.trim();
+  if name.is_empty() {
+    return Err("Collection names can't be empty.".to_string());
+  }
+
+  let rom_ids = rules.as_ref()
+    .map(|rules| get_smart_collection_roms(rules, &state.roms, &state.metadata))
+    .unwrap_or_default();
+
+  let collection = Collection {
+    id: URL_SAFE_NO_PAD.encode(rand::random::<[u8; 12]>()),
+    name: name.to_string(),
+    romIds: rom_ids,
+    rules,
+  };
+
+  state.collections.insert(collection.id.clone(), collection.clone());
+  return Ok(collection);
+}
+
+/// Renames a collection.
+pub fn rename_collection(state: &mut StateStore, collection_id: &str, name: &str) -> Result<Collection, String> {
+  let name = name.trim();
+  if name.is_empty() {
+    return Err("Collection names can't be empty.".to_string());
+  }
+
+  let collection = get_collection(state, collection_id)?;
+  collection.name = name.to_string();
+
+  return Ok(collection.clone());
+}
+
+/// Deletes a collection. Returns the deleted collection.
+pub fn delete_collection(state: &mut StateStore, collection_id: &str) -> Result<Collection, String> {
+  return state.collections.remove(collection_id).ok_or(format!("No collection with id \"{}\" exists.", collection_id));
+}
+
+/// Adds ROMs to a collection, skipping any that are already in it.
+pub fn add_to_collection(state: &mut StateStore, collection_id: &str, rom_ids: Vec<String>) -> Result<Collection, String> {
+  let collection = get_collection(state, collection_id)?;
+  if collection.rules.is_some() {
+    return Err(format!("\"{}\" is a smart collection, so its ROMs come from its rules.", collection.name));
+  }
+
+  for rom_id in rom_ids {
+    if !collection.romIds.contains(&rom_id) {
+      collection.romIds.push(rom_id);
+    }
+  }
+
+  return Ok(collection.clone());
+}
+
+/// Removes ROMs from a collection.
+pub fn remove_from_collection(state: &mut StateStore, collection_id: &str, rom_ids: Vec<String>) -> Result<Collection, String> {
+  let collection = get_collection(state, collection_id)?;
+  if collection.rules.is_some() {
+    return Err(format!("\"{}\" is a smart collection, so its ROMs come from its rules.", collection.name));
+  }
+
+  collection.romIds.retain(|rom_id| !rom_ids.contains(rom_id));
+
+  return Ok(collection.clone());
+}
+
+/// Changes a collection's rules. Removing the rules turns it into a regular collection that keeps its current ROMs.
+pub fn set_collection_rules(state: &mut StateStore, collection_id: &str, rules: Option<CollectionRules>) -> Result<Collection, String> {
+  let rom_ids = rules.as_ref().map(|rules| get_smart_collection_roms(rules, &state.roms, &state.metadata));
+
+  let collection = get_collection(state, collection_id)?;
+  if rom_ids.is_some() {
+    collection.romIds = rom_ids.unwrap();
+  }
+  collection.rules = rules;
+
+  return Ok(collection.clone());
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use chrono::{Duration, Local, NaiveDate};
+  use serde_json::{json, Value};
+
+  use crate::websocket::{
+    test_utils::{parser, rom},
+    types::library::{Collection, CollectionRule, CollectionRules, Library, LibraryIndex, ROMMetadata, RuleCombinator, RuleField, RuleOperator, StateStore, ROM},
+    utils::hash64
+  };
+
+  use super::{get_smart_collection_roms, matches_rule, prune_manual_collections};
+
+  fn metadata(title: &str, is_favorite: bool, genres: Vec<&str>) -> ROMMetadata {
+    return ROMMetadata {
+      title: title.to_string(),
+      fullCapsulePath: String::new(),
+      thumbCapsulePath: String::new(),
+      heroPath: String::new(),
+      sgdbId: String::new(),
+      igdbId: String::new(),
+      metadata: Some(json!({ "metadata": { "genres": genres } })),
+      isFavorite: is_favorite,
+    };
+  }
+
+  fn rule(field: RuleField, operator: RuleOperator, value: Value) -> CollectionRule {
+    return CollectionRule { field, operator, value };
+  }
+
+  fn today() -> NaiveDate {
+    return Local::now().date_naive();
+  }
+
+  #[test]
+  fn text_rules_ignore_case() {
+    let rom = rom("/roms/gba/Metroid Fusion.gba", "GBA");
+
+    assert!(matches_rule(&rule(RuleField::SYSTEM, RuleOperator::EQUALS, json!("gba")), &rom, None, today()));
+    assert!(matches_rule(&rule(RuleField::TITLE, RuleOperator::CONTAINS, json!("METROID")), &rom, None, today()));
+    assert!(!matches_rule(&rule(RuleField::TITLE, RuleOperator::NOT_CONTAINS, json!("metroid")), &rom, None, today()));
+    assert!(matches_rule(&rule(RuleField::SYSTEM, RuleOperator::NOT_EQUALS, json!("SNES")), &rom, None, today()));
+  }
+
+  #[test]
+  fn title_rules_prefer_the_metadata_title() {
+    let rom = rom("/roms/gba/mf.gba", "GBA");
+    let rom_metadata = metadata("Metroid Fusion", false, vec![]);
+
+    assert!(matches_rule(&rule(RuleField::TITLE, RuleOperator::EQUALS, json!("metroid fusion")), &rom, Some(&rom_metadata), today()));
+    assert!(!matches_rule(&rule(RuleField::TITLE, RuleOperator::CONTAINS, json!("mf")), &rom, Some(&rom_metadata), today()));
+  }
+
+  #[test]
+  fn number_rules_compare_sizes() {
+    let rom = ROM { size: 1024, ..rom("/roms/gba/a.gba", "GBA") };
+
+    assert!(matches_rule(&rule(RuleField::SIZE, RuleOperator::GREATER_THAN, json!(1000)), &rom, None, today()));
+    assert!(!matches_rule(&rule(RuleField::SIZE, RuleOperator::LESS_THAN, json!(1000)), &rom, None, today()));
+    assert!(matches_rule(&rule(RuleField::SIZE, RuleOperator::EQUALS, json!(1024)), &rom, None, today()));
+    assert!(!matches_rule(&rule(RuleField::SIZE, RuleOperator::CONTAINS, json!(1024)), &rom, None, today()));
+  }
+
+  #[test]
+  fn favorite_and_list_rules_use_the_metadata() {
+    let rom = rom("/roms/gba/a.gba", "GBA");
+    let rom_metadata = metadata("A", true, vec!["Platform", "Adventure"]);
+
+    assert!(matches_rule(&rule(RuleField::FAVORITE, RuleOperator::EQUALS, json!(true)), &rom, Some(&rom_metadata), today()));
+    assert!(!matches_rule(&rule(RuleField::FAVORITE, RuleOperator::EQUALS, json!(true)), &rom, None, today()));
+    assert!(matches_rule(&rule(RuleField::GENRE, RuleOperator::EQUALS, json!("adventure")), &rom, Some(&rom_metadata), today()));
+    assert!(!matches_rule(&rule(RuleField::GENRE, RuleOperator::EQUALS, json!("Racing")), &rom, Some(&rom_metadata), today()));
+  }
+
+  #[test]
+  fn added_rules_use_the_timestamp() {
+    let added_at = (Local::now() - Duration::days(3)).timestamp();
+    let rom = ROM { addedAt: added_at, ..rom("/roms/gba/a.gba", "GBA") };
+
+    assert!(matches_rule(&rule(RuleField::ADDED, RuleOperator::WITHIN_DAYS, json!(7)), &rom, None, today()));
+    assert!(!matches_rule(&rule(RuleField::ADDED, RuleOperator::WITHIN_DAYS, json!(1)), &rom, None, today()));
+    assert!(!matches_rule(&rule(RuleField::ADDED, RuleOperator::EQUALS, json!(7)), &rom, None, today()));
+  }
+
+  #[test]
+  fn mismatched_values_never_match() {
+    let rom = rom("/roms/gba/a.gba", "GBA");
+
+    assert!(!matches_rule(&rule(RuleField::SIZE, RuleOperator::GREATER_THAN, json!("big")), &rom, None, today()));
+    assert!(!matches_rule(&rule(RuleField::SYSTEM, RuleOperator::NOT_EQUALS, json!(5)), &rom, None, today()));
+    assert!(!matches_rule(&rule(RuleField::TITLE, RuleOperator::GREATER_THAN, json!("a")), &rom, None, today()));
+  }
+
+  #[test]
+  fn combinators_pick_matching_roms() {
+    let roms = vec![rom("/roms/gba/a.gba", "GBA"), rom("/roms/snes/b.sfc", "SNES")];
+    let metadata = HashMap::from([(hash64("/roms/snes/b.sfc"), metadata("B", true, vec![]))]);
+    let rules = vec![
+      rule(RuleField::SYSTEM, RuleOperator::EQUALS, json!("GBA")),
+      rule(RuleField::FAVORITE, RuleOperator::EQUALS, json!(true)),
+    ];
+
+    let all = get_smart_collection_roms(&CollectionRules { combinator: RuleCombinator::ALL, rules: rules.clone() }, &roms, &metadata);
+    assert!(all.is_empty());
+
+    let mut expected = vec![hash64("/roms/gba/a.gba"), hash64("/roms/snes/b.sfc")];
+    expected.sort();
+
+    let any = get_smart_collection_roms(&CollectionRules { combinator: RuleCombinator::ANY, rules }, &roms, &metadata);
+    assert_eq!(any, expected);
+
+    let empty = get_smart_collection_roms(&CollectionRules { combinator: RuleCombinator::ANY, rules: vec![] }, &roms, &metadata);
+    assert!(empty.is_empty());
+  }
+
+  fn state(roms: Vec<ROM>, collections: Vec<Collection>, loaded: bool) -> StateStore {
+    return StateStore {
+      library: Library {
+        libraryPath: String::from("/library"),
+        romDir: String::from("roms"),
+        emulatorDir: String::from("emulators"),
+        biosDir: String::from("bios"),
+        dlcDir: String::from("dlcs"),
+        updateDir: String::from("updates"),
+      },
+      roms,
+      parsers: if loaded { HashMap::from([(String::from("GBA"), parser("Game Boy Advance", "GBA", "gba"))]) } else { HashMap::new() },
+      metadata: HashMap::new(),
+      updates: HashMap::new(),
+      dlcs: HashMap::new(),
+      bios: HashMap::new(),
+      dats: HashMap::new(),
+      index: LibraryIndex::default(),
+      collections: collections.into_iter().map(|collection| (collection.id.clone(), collection)).collect(),
+    };
+  }
+
+  fn collection(id: &str, rom_ids: Vec<String>, rules: Option<CollectionRules>) -> Collection {
+    return Collection { id: id.to_string(), name: id.to_string(), romIds: rom_ids, rules };
+  }
+
+  #[test]
+  fn pruning_removes_missing_roms_from_regular_collections() {
+    let kept = hash64("/roms/gba/a.gba");
+    let removed = hash64("/roms/gba/gone.gba");
+    let smart_rules = Some(CollectionRules { combinator: RuleCombinator::ALL, rules: vec![] });
+
+    let mut state = state(
+      vec![rom("/roms/gba/a.gba", "GBA")],
+      vec![collection("manual", vec![kept.clone(), removed.clone()], None), collection("smart", vec![removed.clone()], smart_rules)],
+      true
+    );
+
+    assert!(prune_manual_collections(&mut state));
+    assert_eq!(state.collections["manual"].romIds, vec![kept]);
+    assert_eq!(state.collections["smart"].romIds, vec![removed]);
+    assert!(!prune_manual_collections(&mut state));
+  }
+
+  #[test]
+  fn pruning_waits_for_the_library_to_load() {
+    let rom_id = hash64("/roms/gba/a.gba");
+    let mut state = state(vec![], vec![collection("manual", vec![rom_id.clone()], None)], false);
+
+    assert!(!prune_manual_collections(&mut state));
+    assert_eq!(state.collections["manual"].romIds, vec![rom_id]);
+  }
+}
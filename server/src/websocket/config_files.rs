@@ -0,0 +1,160 @@
+use std::{env::var, fs::{self, File}, io::{self, Write}, path::{Path, PathBuf}};
+
+use chrono::Local;
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+
+const DEFAULT_BACKUP_COUNT: usize = 5;
+
+/// Gets how many backups to keep of each config file.
+fn get_backup_count() -> usize {
+  return var("NRM_CONFIG_BACKUP_COUNT").ok()
+    .and_then(|count| count.trim().parse::<usize>().ok())
+    .unwrap_or(DEFAULT_BACKUP_COUNT);
+}
+
+/// Gets the folder backups of config files are kept in. Subfolders keep files with the same name apart, ex: parsers.
+pub fn get_backup_dir(subfolder: &str) -> PathBuf {
+  let backup_dir = PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap()).join("backups");
+
+  if subfolder.is_empty() {
+    return backup_dir;
+  }
+
+  return backup_dir.join(subfolder);
+}
+
+/// Gets the backups of a file, newest first.
+fn list_backups(path: &Path, backup_dir: &Path) -> Vec<PathBuf> {
+  let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+  let prefix = format!("{}.", stem);
+
+  let entries_res = fs::read_dir(backup_dir);
+  if entries_res.is_err() {
+    return vec![];
+  }
+
+  let mut backups: Vec<PathBuf> = entries_res.unwrap()
+    .flatten()
+    .map(|entry| entry.path())
+    .filter(|backup| backup.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+      // * ex: "settings.20250101-120000000.json", the timestamps sort in the order they were made.
+      return name.starts_with(&prefix) && name[prefix.len()..].split('.').next().is_some_and(|timestamp| timestamp.len() == 18);
+    }))
+    .collect();
+
+  backups.sort();
+  backups.reverse();
+
+  return backups;
+}
+
+/// Writes a file by writing a temporary file next to it and renaming it over the original, so the original is never left half written.
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+  let file_name = path.file_name().ok_or(io::Error::other("The path has no file name."))?.to_string_lossy().to_string();
+  let temp_path = path.with_file_name(format!("{}.tmp", file_name));
+
+  let write_res = File::create(&temp_path).and_then(|mut file| {
+    file.write_all(contents.as_bytes())?;
+    return file.sync_all();
+  });
+
+  if write_res.is_err() {
+    let _ = fs::remove_file(&temp_path);
+    return write_res;
+  }
+
+  let rename_res = fs::rename(&temp_path, path);
+  if rename_res.is_err() {
+    let _ = fs::remove_file(&temp_path);
+    return rename_res;
+  }
+
+  // * Syncing the folder makes sure the rename itself survives a crash. Not every platform supports it, so it's best effort.
+  if let Some(parent) = path.parent() {
+    let _ = File::open(parent).and_then(|dir| dir.sync_all());
+  }
+
+  return Ok(());
+}
+
+/// Copies the current version of a file to its backup folder, and removes the oldest backups past the limit.
+/// Files that don't parse aren't backed up, so a corrupt file never pushes out a good backup.
+fn backup_file(path: &Path, backup_dir: &Path) -> io::Result<()> {
+  let backup_count = get_backup_count();
+  if backup_count == 0 || !path.is_file() {
+    return Ok(());
+  }
+
+  let contents = fs::read_to_string(path)?;
+  if serde_json::from_str::<serde_json::Value>(&contents).is_err() {
+    warn!("Config Files: Not backing up \"{}\" since it isn't valid JSON.", path.display());
+    return Ok(());
+  }
+
+  fs::create_dir_all(backup_dir)?;
+
+  let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+  let timestamp = Local::now().format("%Y%m%d-%H%M%S%3f");
+  write_atomic(&backup_dir.join(format!("{}.{}.json", stem, timestamp)), &contents)?;
+
+  for old_backup in list_backups(path, backup_dir).into_iter().skip(backup_count) {
+    let remove_res = fs::remove_file(&old_backup);
+    if remove_res.is_err() {
+      warn!("Config Files: Failed to remove old backup \"{}\": {}", old_backup.display(), remove_res.err().unwrap());
+    }
+  }
+
+  return Ok(());
+}
+
+/// Backs up a file, then replaces it atomically with the new contents.
+pub fn write_with_backup(path: &Path, backup_dir: &Path, contents: &str) -> io::Result<()> {
+  // ? A failed backup shouldn't stop the user's changes from being saved.
+  let backup_res = backup_file(path, backup_dir);
+  if backup_res.is_err() {
+    warn!("Config Files: Failed to back up \"{}\": {}", path.display(), backup_res.err().unwrap());
+  }
+
+  return write_atomic(path, contents);
+}
+
+/// Reads and parses a JSON file. If it can't be parsed, the newest backup that can be replaces it.
+/// Returns the parsed file, and the backup it was recovered from, if any.
+pub fn read_with_recovery<T: DeserializeOwned>(path: &Path, backup_dir: &Path) -> Result<(T, Option<PathBuf>), String> {
+  let contents_res = fs::read_to_string(path);
+  if contents_res.is_err() {
+    return Err(contents_res.err().unwrap().to_string());
+  }
+
+  let parsed_res = serde_json::from_str::<T>(&contents_res.unwrap());
+  if parsed_res.is_ok() {
+    return Ok((parsed_res.unwrap(), None));
+  }
+  let parse_err = parsed_res.err().unwrap();
+
+  warn!("Config Files: Failed to parse \"{}\": {}. Checking for a backup...", path.display(), parse_err);
+
+  for backup in list_backups(path, backup_dir) {
+    let backup_contents = fs::read_to_string(&backup).ok();
+    let backup_parsed = backup_contents.as_ref().and_then(|contents| serde_json::from_str::<T>(contents).ok());
+    if backup_parsed.is_none() {
+      warn!("Config Files: Backup \"{}\" couldn't be parsed either.", backup.display());
+      continue;
+    }
+
+    // * The corrupt file is kept next to the original in case it has anything worth saving by hand.
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let _ = fs::rename(path, path.with_file_name(format!("{}.corrupt", file_name)));
+
+    let restore_res = write_atomic(path, backup_contents.as_ref().unwrap());
+    if restore_res.is_err() {
+      warn!("Config Files: Failed to restore \"{}\" from backup: {}", path.display(), restore_res.err().unwrap());
+    }
+
+    info!("Config Files: Recovered \"{}\" from \"{}\".", path.display(), backup.display());
+    return Ok((backup_parsed.unwrap(), Some(backup)));
+  }
+
+  return Err(parse_err.to_string());
+}
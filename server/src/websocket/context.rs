@@ -0,0 +1,42 @@
+use std::sync::{Arc, Mutex};
+
+use sysinfo::Disks;
+use tokio::sync::broadcast;
+
+use crate::job_queue::JobQueue;
+
+use super::{
+  exporter::Exporter,
+  hasher::Hasher,
+  types::{auth::{SessionStore, UserStore}, library::StateStore, settings::Settings},
+  watcher::Watcher
+};
+
+/// The shared state that commands and background jobs work with.
+/// When more than one lock is needed, take them in the order the fields are listed here.
+/// Don't hold any of them while parsing or exporting the library, snapshot what's needed instead.
+#[derive(Clone)]
+pub struct ServerContext {
+  pub settings: Arc<Mutex<Settings>>,
+  pub watcher: Arc<Mutex<Watcher>>,
+  pub hasher: Arc<Mutex<Hasher>>,
+  pub state_store: Arc<Mutex<StateStore>>,
+  pub users: Arc<Mutex<UserStore>>,
+  pub sessions: Arc<Mutex<SessionStore>>,
+  pub disks: Arc<Mutex<Disks>>,
+  pub job_queue: JobQueue,
+  pub broadcast_tx: broadcast::Sender<String>,
+  pub exporter: Exporter,
+}
+
+impl ServerContext {
+  /// Gets a handle to the watcher. Handles share the watcher thread, so the lock isn't held while it's used.
+  pub fn get_watcher(&self) -> Watcher {
+    return self.watcher.lock().expect("Failed to lock Watcher Mutex.").clone();
+  }
+
+  /// Gets a handle to the hasher. Handles share the hash cache, so the lock isn't held while it's used.
+  pub fn get_hasher(&self) -> Hasher {
+    return self.hasher.lock().expect("Failed to lock Hasher Mutex.").clone();
+  }
+}
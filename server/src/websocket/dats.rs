@@ -0,0 +1,371 @@
+use std::{collections::HashMap, env::var, fs, path::{Path, PathBuf}};
+
+use log::info;
+
+use super::types::{
+  dat::{DatEntry, DatFile, DatIndex, DatRom, ROMVerification, VerificationStatus},
+  library::{ArchiveMember, Parser, ROMHashes},
+  ErrorSender
+};
+
+/// Gets the path of a parser's DAT. Relative paths are resolved from the "dats" folder in the config directory.
+fn get_dat_path(dat_file: &str) -> PathBuf {
+  let dat_path = PathBuf::from(dat_file);
+  if dat_path.is_absolute() {
+    return dat_path;
+  }
+
+  let config_path = PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap());
+  return config_path.join("dats").join(dat_path);
+}
+
+/// Indexes a DAT's roms by their hashes and names.
+fn index_dat(dat: DatFile) -> DatIndex {
+  let mut index = DatIndex {
+    name: dat.header.map(|header| header.name).unwrap_or_default(),
+    path: PathBuf::new(),
+    modified: None,
+    entries: vec![],
+    by_game: HashMap::new(),
+    by_sha1: HashMap::new(),
+    by_md5: HashMap::new(),
+    by_crc: HashMap::new(),
+    by_name: HashMap::new(),
+  };
+
+  for game in dat.games {
+    for rom in game.roms {
+      let position = index.entries.len();
+
+      if rom.sha1.is_some() {
+        index.by_sha1.insert(rom.sha1.as_ref().unwrap().to_lowercase(), position);
+      }
+      if rom.md5.is_some() {
+        index.by_md5.insert(rom.md5.as_ref().unwrap().to_lowercase(), position);
+      }
+      if rom.crc.is_some() && rom.size.is_some() {
+        index.by_crc.insert((rom.crc.as_ref().unwrap().to_lowercase(), rom.size.unwrap()), position);
+      }
+      index.by_name.insert(rom.name.clone(), position);
+      index.by_game.entry(game.name.clone()).or_default().push(position);
+
+      index.entries.push(DatEntry {
+        game: game.name.clone(),
+        rom,
+      });
+    }
+  }
+
+  return index;
+}
+
+/// Loads a Logiqx XML DAT. If the previously loaded DAT is from the same file and it hasn't changed, it's reused.
+pub fn load_dat(dat_file: &str, previous: Option<&DatIndex>) -> Result<DatIndex, String> {
+  let dat_path = get_dat_path(dat_file);
+  let modified = fs::metadata(&dat_path).and_then(|metadata| metadata.modified()).ok();
+
+  if previous.is_some_and(|previous| previous.path == dat_path && modified.is_some() && previous.modified == modified) {
+    return Ok(previous.unwrap().clone());
+  }
+
+  let dat_str = fs::read_to_string(&dat_path).map_err(|e| e.to_string())?;
+  let dat: DatFile = quick_xml::de::from_str(&dat_str).map_err(|e| e.to_string())?;
+
+  let mut index = index_dat(dat);
+  index.path = dat_path;
+  index.modified = modified;
+
+  info!("Load DATs: Loaded \"{}\" with {} roms.", index.name, index.entries.len());
+
+  return Ok(index);
+}
+
+/// Loads the DATs referenced by the parsers, keyed by the parser's abbreviation. DATs that haven't changed are reused from `previous`.
+pub fn load_dats(parsers: &HashMap<String, Parser>, previous: &HashMap<String, DatIndex>, send_error: &ErrorSender) -> HashMap<String, DatIndex> {
+  let mut dats = HashMap::new();
+
+  for parser in parsers.values() {
+    if parser.datFile.is_empty() {
+      continue;
+    }
+
+    let dat_res = load_dat(&parser.datFile, previous.get(&parser.abbreviation));
+    if dat_res.is_err() {
+      send_error(
+        format!("Failed to load DAT \"{}\" for system \"{}\": {}", parser.datFile, parser.abbreviation, dat_res.err().unwrap()),
+        String::from("Please ensure the DAT is a Logiqx XML file in the \"dats\" folder of your config directory."),
+        crate::websocket::types::BackendErrorType::WARN
+      );
+      continue;
+    }
+
+    dats.insert(parser.abbreviation.clone(), dat_res.unwrap());
+  }
+
+  return dats;
+}
+
+/// Checks if an archive member has the same contents as a rom from a DAT.
+fn member_matches(member: &ArchiveMember, rom: &DatRom) -> bool {
+  if rom.sha1.is_some() {
+    return rom.sha1.as_ref().unwrap().to_lowercase() == member.sha1;
+  }
+
+  return rom.size == Some(member.size) && rom.crc.as_ref().is_some_and(|crc| crc.to_lowercase() == member.crc32);
+}
+
+/// Checks an archived ROM's members against its system's DAT.
+/// Sets are verified if every rom of the game is in the archive, every member from the DAT matches a rom from that game, and the archive is named after it.
+fn verify_archive(dat: &DatIndex, path: &Path, members: &[ArchiveMember]) -> ROMVerification {
+  let archive_name = path.file_stem().and_then(|name| name.to_str()).unwrap_or_default();
+  let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+
+  let mut matched = vec![];
+  let mut has_mismatched_member = false;
+
+  for member in members {
+    let position = dat.by_sha1.get(&member.sha1)
+      .or_else(|| dat.by_crc.get(&(member.crc32.clone(), member.size)));
+
+    if position.is_some() {
+      matched.push((member, &dat.entries[*position.unwrap()]));
+    } else if dat.by_name.contains_key(&member.name) {
+      // * A member with a known name but different contents is a different copy of that rom.
+      has_mismatched_member = true;
+    }
+  }
+
+  if matched.is_empty() {
+    let named_member = members.iter().find_map(|member| dat.by_name.get(&member.name));
+    if named_member.is_some() {
+      let entry = &dat.entries[*named_member.unwrap()];
+
+      return ROMVerification {
+        status: VerificationStatus::HASH_MISMATCH,
+        canonicalName: Some(entry.game.clone()),
+        canonicalFilename: Some(format!("{}.{}", entry.game, extension)),
+      };
+    }
+
+    return ROMVerification {
+      status: VerificationStatus::UNKNOWN,
+      canonicalName: None,
+      canonicalFilename: None,
+    };
+  }
+
+  let game = &matched[0].1.game;
+
+  // * Roms the DAT has no dump of can't be checked, so they aren't required.
+  let is_complete = dat.by_game.get(game).is_some_and(|positions| positions.iter()
+    .map(|position| &dat.entries[*position].rom)
+    .filter(|rom| rom.status.as_deref() != Some("nodump"))
+    .all(|rom| members.iter().any(|member| member_matches(member, rom)))
+  );
+
+  let status = if matched.iter().any(|(_, entry)| entry.rom.status.as_deref() == Some("baddump")) {
+    VerificationStatus::BAD_DUMP
+  } else if has_mismatched_member {
+    VerificationStatus::HASH_MISMATCH
+  } else if !is_complete {
+    VerificationStatus::INCOMPLETE
+  } else if archive_name != game || matched.iter().any(|(member, entry)| &entry.game != game || member.name != entry.rom.name) {
+    VerificationStatus::MISNAMED
+  } else {
+    VerificationStatus::VERIFIED
+  };
+
+  return ROMVerification {
+    status,
+    canonicalName: Some(game.clone()),
+    canonicalFilename: Some(format!("{}.{}", game, extension)),
+  };
+}
+
+/// Checks a ROM against its system's DAT.
+/// Archives are checked by their members, since DATs describe the files inside them.
+pub fn verify_rom(dat: &DatIndex, path: &Path, size: u64, hashes: &ROMHashes) -> ROMVerification {
+  let members = hashes.members.as_ref().filter(|members| !members.is_empty());
+  if members.is_some() {
+    return verify_archive(dat, path, members.unwrap());
+  }
+
+  let filename = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+
+  let position = dat.by_sha1.get(&hashes.sha1)
+    .or_else(|| dat.by_md5.get(&hashes.md5))
+    .or_else(|| dat.by_crc.get(&(hashes.crc32.clone(), size)));
+
+  if position.is_none() {
+    // * A file with a known name but different contents is a different copy of that rom.
+    let named_position = dat.by_name.get(filename);
+    if named_position.is_some() {
+      let entry = &dat.entries[*named_position.unwrap()];
+
+      return ROMVerification {
+        status: VerificationStatus::HASH_MISMATCH,
+        canonicalName: Some(entry.game.clone()),
+        canonicalFilename: Some(entry.rom.name.clone()),
+      };
+    }
+
+    return ROMVerification {
+      status: VerificationStatus::UNKNOWN,
+      canonicalName: None,
+      canonicalFilename: None,
+    };
+  }
+
+  let entry = &dat.entries[*position.unwrap()];
+
+  let status = if entry.rom.status.as_deref() == Some("baddump") {
+    VerificationStatus::BAD_DUMP
+  } else if entry.rom.name != filename {
+    VerificationStatus::MISNAMED
+  } else {
+    VerificationStatus::VERIFIED
+  };
+
+  return ROMVerification {
+    status,
+    canonicalName: Some(entry.game.clone()),
+    canonicalFilename: Some(entry.rom.name.clone()),
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::Path;
+
+  use crate::websocket::types::{dat::{DatFile, DatIndex, VerificationStatus}, library::{ArchiveMember, ROMHashes}};
+
+  use super::{index_dat, verify_rom};
+
+  const DAT: &str = r#"<?xml version="1.0"?>
+    <datafile>
+      <header><name>Test</name><version>1</version></header>
+      <game name="Single (USA)">
+        <rom name="Single (USA).gba" size="4" crc="aaaaaaaa" md5="11111111111111111111111111111111" sha1="1111111111111111111111111111111111111111"/>
+      </game>
+      <game name="Bad (USA)">
+        <rom name="Bad (USA).gba" size="4" crc="bbbbbbbb" sha1="2222222222222222222222222222222222222222" status="baddump"/>
+      </game>
+      <game name="Multi (USA)">
+        <rom name="Multi (USA) (Track 1).bin" size="8" crc="cccccccc" sha1="3333333333333333333333333333333333333333"/>
+        <rom name="Multi (USA) (Track 2).bin" size="8" crc="dddddddd"/>
+        <rom name="Multi (USA) (Track 3).bin" status="nodump"/>
+      </game>
+    </datafile>"#;
+
+  fn get_dat() -> DatIndex {
+    let dat: DatFile = quick_xml::de::from_str(DAT).unwrap();
+    return index_dat(dat);
+  }
+
+  fn file_hashes(crc32: &str, md5: &str, sha1: &str) -> ROMHashes {
+    return ROMHashes {
+      crc32: crc32.to_string(),
+      md5: md5.to_string(),
+      sha1: sha1.to_string(),
+      members: None,
+    };
+  }
+
+  fn member(name: &str, size: u64, crc32: &str, sha1: &str) -> ArchiveMember {
+    return ArchiveMember {
+      name: name.to_string(),
+      size,
+      crc32: crc32.to_string(),
+      sha1: sha1.to_string(),
+    };
+  }
+
+  fn archive_hashes(members: Vec<ArchiveMember>) -> ROMHashes {
+    return ROMHashes {
+      members: Some(members),
+      ..file_hashes("00000000", "", "")
+    };
+  }
+
+  fn track_1() -> ArchiveMember {
+    return member("Multi (USA) (Track 1).bin", 8, "cccccccc", "3333333333333333333333333333333333333333");
+  }
+
+  fn track_2() -> ArchiveMember {
+    return member("Multi (USA) (Track 2).bin", 8, "dddddddd", "4444444444444444444444444444444444444444");
+  }
+
+  #[test]
+  fn verifies_files_by_hash_and_name() {
+    let dat = get_dat();
+    let hashes = file_hashes("aaaaaaaa", "11111111111111111111111111111111", "1111111111111111111111111111111111111111");
+
+    let verified = verify_rom(&dat, Path::new("/roms/Single (USA).gba"), 4, &hashes);
+    assert_eq!(verified.status, VerificationStatus::VERIFIED);
+    assert_eq!(verified.canonicalName.as_deref(), Some("Single (USA)"));
+
+    let misnamed = verify_rom(&dat, Path::new("/roms/single.gba"), 4, &hashes);
+    assert_eq!(misnamed.status, VerificationStatus::MISNAMED);
+    assert_eq!(misnamed.canonicalFilename.as_deref(), Some("Single (USA).gba"));
+  }
+
+  #[test]
+  fn matches_files_by_crc_and_size() {
+    let dat = get_dat();
+    let hashes = file_hashes("aaaaaaaa", "", "");
+
+    assert_eq!(verify_rom(&dat, Path::new("/roms/Single (USA).gba"), 4, &hashes).status, VerificationStatus::VERIFIED);
+    assert_eq!(verify_rom(&dat, Path::new("/roms/Single (USA).gba"), 5, &hashes).status, VerificationStatus::HASH_MISMATCH);
+  }
+
+  #[test]
+  fn separates_bad_dumps_from_hash_mismatches() {
+    let dat = get_dat();
+
+    let bad_dump = verify_rom(&dat, Path::new("/roms/Bad (USA).gba"), 4, &file_hashes("bbbbbbbb", "", ""));
+    assert_eq!(bad_dump.status, VerificationStatus::BAD_DUMP);
+
+    let mismatch = verify_rom(&dat, Path::new("/roms/Single (USA).gba"), 4, &file_hashes("ffffffff", "", "ffff"));
+    assert_eq!(mismatch.status, VerificationStatus::HASH_MISMATCH);
+    assert_eq!(mismatch.canonicalName.as_deref(), Some("Single (USA)"));
+
+    let unknown = verify_rom(&dat, Path::new("/roms/Other.gba"), 4, &file_hashes("ffffffff", "", "ffff"));
+    assert_eq!(unknown.status, VerificationStatus::UNKNOWN);
+    assert_eq!(unknown.canonicalName, None);
+  }
+
+  #[test]
+  fn verifies_complete_archives() {
+    let dat = get_dat();
+    let readme = member("readme.txt", 10, "eeeeeeee", "5555");
+
+    let verified = verify_rom(&dat, Path::new("/roms/Multi (USA).zip"), 100, &archive_hashes(vec![track_1(), track_2(), readme]));
+    assert_eq!(verified.status, VerificationStatus::VERIFIED);
+    assert_eq!(verified.canonicalFilename.as_deref(), Some("Multi (USA).zip"));
+
+    let misnamed = verify_rom(&dat, Path::new("/roms/multi.zip"), 100, &archive_hashes(vec![track_1(), track_2()]));
+    assert_eq!(misnamed.status, VerificationStatus::MISNAMED);
+  }
+
+  #[test]
+  fn requires_every_rom_in_archives() {
+    let dat = get_dat();
+
+    let incomplete = verify_rom(&dat, Path::new("/roms/Multi (USA).zip"), 100, &archive_hashes(vec![track_1()]));
+    assert_eq!(incomplete.status, VerificationStatus::INCOMPLETE);
+    assert_eq!(incomplete.canonicalName.as_deref(), Some("Multi (USA)"));
+  }
+
+  #[test]
+  fn reports_mismatched_archive_members() {
+    let dat = get_dat();
+    let modified_track = member("Multi (USA) (Track 2).bin", 8, "ffffffff", "6666");
+
+    let mismatch = verify_rom(&dat, Path::new("/roms/Multi (USA).zip"), 100, &archive_hashes(vec![track_1(), modified_track.clone()]));
+    assert_eq!(mismatch.status, VerificationStatus::HASH_MISMATCH);
+
+    let name_only = verify_rom(&dat, Path::new("/roms/Multi (USA).zip"), 100, &archive_hashes(vec![modified_track]));
+    assert_eq!(name_only.status, VerificationStatus::HASH_MISMATCH);
+    assert_eq!(name_only.canonicalFilename.as_deref(), Some("Multi (USA).zip"));
+  }
+}
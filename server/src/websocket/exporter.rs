@@ -0,0 +1,394 @@
+use std::{collections::HashMap, env::var, fs, path::{Component, Path, PathBuf}, sync::{Arc, Mutex}, time::Duration};
+
+use chrono::DateTime;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::Notify;
+
+use crate::job_queue::{JobContext, JobHandle, JobQueue};
+
+use super::{
+  config_files::write_atomic,
+  types::{export::{ExportResult, Gamelist, GamelistGame, RetroArchPlaylist, RetroArchPlaylistItem}, library::{Parser, ROMMetadata, StateStore, ROM}, settings::Settings},
+  utils::{get_igdb_list, get_rom_base, hash64}
+};
+
+/// How long to wait for library changes to settle before exporting.
+const EXPORT_DEBOUNCE: Duration = Duration::from_secs(10);
+
+/// Gets the folder exports are written to. It has to be inside the library, so absolute paths and `..` are rejected.
+fn get_export_dir(settings: &Settings) -> Result<PathBuf, String> {
+  let export_dir = PathBuf::from(&settings.export.exportDir);
+
+  if export_dir.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir)) {
+    return Err(format!("The export folder \"{}\" must be a folder inside your library.", settings.export.exportDir));
+  }
+
+  return Ok(PathBuf::from(&settings.library.libraryPath).join(export_dir));
+}
+
+/// Gets a string from a ROM's IGDB game, ex: its summary.
+fn get_igdb_string(game: &Value, key: &str) -> Option<String> {
+  return game.get(key)
+    .and_then(|value| value.as_str())
+    .filter(|value| !value.is_empty())
+    .map(|value| value.to_string());
+}
+
+/// Converts a path to use forward slashes, which both EmulationStation and RetroArch accept on every platform.
+fn to_export_path(path: &Path) -> String {
+  return path.to_string_lossy().replace("\\", "/");
+}
+
+/// Checks if a copy of a file is missing or older than the file.
+fn is_copy_outdated(source: &Path, dest: &Path) -> bool {
+  let source_modified = fs::metadata(source).and_then(|metadata| metadata.modified());
+  let dest_modified = fs::metadata(dest).and_then(|metadata| metadata.modified());
+  if source_modified.is_err() || dest_modified.is_err() {
+    return true;
+  }
+
+  return source_modified.unwrap() > dest_modified.unwrap();
+}
+
+/// Copies a piece of cached artwork into the gamelist's media folder, and gets its path in the gamelist. URLs are kept as is.
+fn export_artwork(cache_dir: &Path, artwork_path: &str, media_dir: &Path, kind: &str, rom_id: &str, system_path: &str) -> Option<String> {
+  if artwork_path.is_empty() {
+    return None;
+  }
+
+  if artwork_path.contains("://") {
+    return Some(artwork_path.to_string());
+  }
+
+  // * Artwork paths come from saved metadata, so they can only point to files inside the grids cache.
+  let relative_path = Path::new(artwork_path.trim_start_matches("/"));
+  if relative_path.components().any(|component| !matches!(component, Component::Normal(_))) {
+    warn!("Export Library: Skipping \"{}\" since it isn't in the grids cache.", artwork_path);
+    return None;
+  }
+
+  let source = cache_dir.join(relative_path);
+  let is_in_cache = fs::canonicalize(&source).ok()
+    .zip(fs::canonicalize(cache_dir).ok())
+    .is_some_and(|(source, cache_dir)| source.starts_with(cache_dir) && source.is_file());
+  if !is_in_cache {
+    warn!("Export Library: Skipping \"{}\" since it isn't in the grids cache.", source.display());
+    return None;
+  }
+
+  let extension = source.extension().map(|extension| extension.to_string_lossy().to_string()).unwrap_or(String::from("png"));
+  let file_name = format!("{}.{}", rom_id, extension);
+  let dest = media_dir.join(kind).join(&file_name);
+
+  if is_copy_outdated(&source, &dest) {
+    let copy_res = fs::create_dir_all(dest.parent().unwrap()).and_then(|_| fs::copy(&source, &dest));
+    if copy_res.is_err() {
+      let err = copy_res.err().unwrap();
+      warn!("Export Library: Failed to copy \"{}\": {}", source.display(), err.to_string());
+      return None;
+    }
+  }
+
+  return Some(format!("{}/media/{}/{}", system_path, kind, file_name));
+}
+
+/// Gets the path a system's folder is written as in gamelists. It's `.` unless a ROM path prefix is set.
+fn get_system_path(folder: &str, rom_path_prefix: &str) -> String {
+  if rom_path_prefix.is_empty() {
+    return String::from(".");
+  }
+
+  return format!("{}/{}", rom_path_prefix.trim_end_matches("/"), to_export_path(Path::new(folder)));
+}
+
+/// Gets a ROM's gamelist entry. Paths are relative to its system's folder, like EmulationStation expects, and its artwork is copied into `media_dir`.
+fn get_gamelist_game(rom: &ROM, metadata: Option<&ROMMetadata>, system_dir: &Path, system_path: &str, media_dir: &Path, cache_dir: &Path) -> GamelistGame {
+  let rom_base = get_rom_base(rom);
+  let relative_path = rom_base.strip_prefix(system_dir).unwrap_or(&rom_base);
+
+  let mut game = GamelistGame {
+    path: format!("{}/{}", system_path, to_export_path(relative_path)),
+    name: rom.title.clone(),
+    ..Default::default()
+  };
+
+  if metadata.is_none() {
+    return game;
+  }
+  let metadata = metadata.unwrap();
+
+  if !metadata.title.is_empty() {
+    game.name = metadata.title.clone();
+  }
+
+  let rom_id = hash64(&rom.path);
+  game.image = export_artwork(cache_dir, &metadata.fullCapsulePath, media_dir, "images", &rom_id, system_path);
+  game.thumbnail = export_artwork(cache_dir, &metadata.thumbCapsulePath, media_dir, "thumbnails", &rom_id, system_path);
+  game.fanart = export_artwork(cache_dir, &metadata.heroPath, media_dir, "fanart", &rom_id, system_path);
+  game.favorite = Some(metadata.isFavorite).filter(|is_favorite| *is_favorite);
+
+  if metadata.metadata.is_none() {
+    return game;
+  }
+  let igdb_game = metadata.metadata.as_ref().unwrap();
+
+  game.desc = get_igdb_string(igdb_game, "summary");
+
+  // * IGDB release dates are unix timestamps, and EmulationStation wants them as ISO 8601 basic dates.
+  game.releasedate = igdb_game.get("metadata")
+    .and_then(|igdb_metadata| igdb_metadata.get("firstReleaseDate"))
+    .and_then(|timestamp| timestamp.as_i64())
+    .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0))
+    .map(|date| date.format("%Y%m%dT%H%M%S").to_string());
+
+  game.developer = get_igdb_list(igdb_game, "developers").first().cloned();
+  game.publisher = get_igdb_list(igdb_game, "publishers").first().cloned();
+
+  let genres = get_igdb_list(igdb_game, "genres");
+  game.genre = Some(genres.join(", ")).filter(|genre| !genre.is_empty());
+
+  // ? IGDB ratings are out of 100, while EmulationStation's are out of 1.
+  game.rating = igdb_game.get("metadata")
+    .and_then(|igdb_metadata| get_igdb_string(igdb_metadata, "totalRating"))
+    .and_then(|rating| rating.parse::<f64>().ok())
+    .map(|rating| format!("{:.2}", rating / 100.0));
+
+  return game;
+}
+
+/// Gets a ROM's RetroArch playlist entry.
+fn get_playlist_item(rom: &ROM, metadata: Option<&ROMMetadata>, parser: &Parser, roms_dir: &Path, rom_path_prefix: &str) -> RetroArchPlaylistItem {
+  let rom_base = get_rom_base(rom);
+
+  // * Devices usually mount the library somewhere else, so the ROM folder can be swapped for where they see it.
+  let path = if rom_path_prefix.is_empty() {
+    rom_base.to_string_lossy().to_string()
+  } else {
+    let relative_path = rom_base.strip_prefix(roms_dir).unwrap_or(&rom_base);
+    format!("{}/{}", rom_path_prefix.trim_end_matches("/"), to_export_path(relative_path))
+  };
+
+  let label = metadata.map(|metadata| metadata.title.clone())
+    .filter(|title| !title.is_empty())
+    .unwrap_or(rom.title.clone());
+
+  let crc32 = rom.hashes.as_ref()
+    .map(|hashes| format!("{}|crc", hashes.crc32.to_uppercase()))
+    .unwrap_or(String::from("DETECT"));
+
+  return RetroArchPlaylistItem {
+    path,
+    label,
+    core_path: get_core_field(&parser.retroarchCorePath),
+    core_name: get_core_field(&parser.retroarchCoreName),
+    crc32,
+    db_name: format!("{}.lpl", parser.name),
+  };
+}
+
+/// RetroArch asks which core to use for entries without one when they're launched.
+fn get_core_field(value: &str) -> String {
+  if value.is_empty() {
+    return String::from("DETECT");
+  }
+
+  return value.to_string();
+}
+
+/// Writes a gamelist.xml file.
+fn write_gamelist(path: &Path, gamelist: &Gamelist) -> Result<(), String> {
+  let mut xml = String::from("<?xml version=\"1.0\"?>\n");
+
+  let mut serializer = quick_xml::se::Serializer::new(&mut xml);
+  serializer.indent(' ', 2);
+  gamelist.serialize(serializer).map_err(|e| e.to_string())?;
+  xml.push('\n');
+
+  fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+  return write_atomic(path, &xml).map_err(|e| e.to_string());
+}
+
+/// Writes a RetroArch playlist file.
+fn write_playlist(path: &Path, playlist: &RetroArchPlaylist) -> Result<(), String> {
+  let playlist_str = serde_json::to_string_pretty(playlist).map_err(|e| e.to_string())?;
+
+  fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+  return write_atomic(path, &playlist_str).map_err(|e| e.to_string());
+}
+
+/// Exports a gamelist.xml and RetroArch playlist for every system with ROMs.
+pub fn export_library(settings: &Arc<Mutex<Settings>>, state_store: &Arc<Mutex<StateStore>>, job: &JobContext) -> Result<ExportResult, String> {
+  let state_settings = settings.lock().expect("Failed to lock Settings Mutex.").clone();
+  if state_settings.library.libraryPath.is_empty() {
+    return Err(String::from("No library has been set up yet."));
+  }
+
+  // ? The state is copied so the library isn't locked while the files are written.
+  let state = state_store.lock().expect("Failed to lock State Mutex.");
+  let parsers = state.parsers.clone();
+  let metadata = state.metadata.clone();
+  let mut roms_by_system: HashMap<String, Vec<ROM>> = HashMap::new();
+  for rom in state.roms.iter() {
+    roms_by_system.entry(rom.system.clone()).or_default().push(rom.clone());
+  }
+  drop(state);
+
+  let cache_dir = PathBuf::from(var("NRM_GRIDS_CACHE_DIR").unwrap());
+  let roms_dir = PathBuf::from(&state_settings.library.libraryPath).join(&state_settings.library.romDir);
+  let export_dir = get_export_dir(&state_settings)?;
+
+  let mut systems: Vec<&String> = roms_by_system.keys().collect();
+  systems.sort();
+
+  let mut result = ExportResult {
+    exportPath: export_dir.to_string_lossy().to_string(),
+    gamelists: 0,
+    playlists: 0,
+    romCount: 0,
+  };
+
+  for (i, system) in systems.iter().enumerate() {
+    if job.is_cancelled() {
+      return Err(String::from("The export was cancelled."));
+    }
+
+    let parser = parsers.get(*system);
+    if parser.is_none() {
+      warn!("Export Library: Skipping \"{}\" since it has no parser.", system);
+      continue;
+    }
+    let parser = parser.unwrap();
+
+    job.report(Some(i as f64 / systems.len() as f64), &format!("Exporting {}...", parser.name));
+
+    let mut roms = roms_by_system.get(*system).unwrap().clone();
+    roms.sort_by_key(|rom| rom.title.to_lowercase());
+
+    let system_dir = roms_dir.join(&parser.folder);
+    let system_path = get_system_path(&parser.folder, &state_settings.export.romPathPrefix);
+    let gamelist_dir = export_dir.join("es-de").join("gamelists").join(&parser.folder);
+    let media_dir = gamelist_dir.join("media");
+    let mut gamelist = Gamelist::default();
+    let mut items = vec![];
+
+    for rom in roms.iter() {
+      let rom_metadata = metadata.get(&hash64(&rom.path));
+
+      gamelist.games.push(get_gamelist_game(rom, rom_metadata, &system_dir, &system_path, &media_dir, &cache_dir));
+      items.push(get_playlist_item(rom, rom_metadata, parser, &roms_dir, &state_settings.export.romPathPrefix));
+    }
+
+    let gamelist_path = gamelist_dir.join("gamelist.xml");
+    write_gamelist(&gamelist_path, &gamelist).map_err(|err| format!("Failed to write \"{}\": {}", gamelist_path.display(), err))?;
+    result.gamelists += 1;
+
+    let playlist = RetroArchPlaylist {
+      version: String::from("1.5"),
+      default_core_path: parser.retroarchCorePath.clone(),
+      default_core_name: parser.retroarchCoreName.clone(),
+      label_display_mode: 0,
+      right_thumbnail_mode: 0,
+      left_thumbnail_mode: 0,
+      sort_mode: 0,
+      items,
+    };
+
+    let playlist_path = export_dir.join("retroarch").join("playlists").join(format!("{}.lpl", parser.name));
+    write_playlist(&playlist_path, &playlist).map_err(|err| format!("Failed to write \"{}\": {}", playlist_path.display(), err))?;
+    result.playlists += 1;
+
+    result.romCount += roms.len();
+  }
+
+  info!("Export Library: Exported {} ROMs to \"{}\".", result.romCount, result.exportPath);
+  return Ok(result);
+}
+
+/// Queues a job that exports the library.
+pub fn queue_export(job_queue: &JobQueue, settings: Arc<Mutex<Settings>>, state_store: Arc<Mutex<StateStore>>) -> JobHandle {
+  return job_queue.spawn_blocking("export_library", "", move |job| {
+    return export_library(&settings, &state_store, &job)
+      .map(|result| serde_json::to_value(result).expect("Export result was malformatted."));
+  });
+}
+
+/// Re-exports the library after it changes, if the settings ask for it.
+#[derive(Clone)]
+pub struct Exporter {
+  changed: Arc<Notify>,
+}
+
+impl Exporter {
+  /// Creates a new Exporter.
+  pub fn new() -> Exporter {
+    return Exporter {
+      changed: Arc::new(Notify::new()),
+    };
+  }
+
+  /// Initializes the task that exports the library after it changes.
+  pub fn init(&self, job_queue: JobQueue, settings: Arc<Mutex<Settings>>, state_store: Arc<Mutex<StateStore>>) {
+    let changed = self.changed.clone();
+
+    tokio::spawn(async move {
+      info!("Thread: Starting library exporter...");
+
+      loop {
+        changed.notified().await;
+
+        // * Changes tend to come in bursts, ex: copying a folder of ROMs, so wait for them to settle.
+        tokio::time::sleep(EXPORT_DEBOUNCE).await;
+
+        // * The settings lock is shared with blocking handlers, so it isn't waited on from the runtime.
+        let export_settings = settings.clone();
+        let export_on_change = tokio::task::spawn_blocking(move || {
+          return export_settings.lock().expect("Failed to lock Settings Mutex.").export.exportOnLibraryChange;
+        }).await.unwrap_or(false);
+        if !export_on_change {
+          continue;
+        }
+
+        let result = queue_export(&job_queue, settings.clone(), state_store.clone()).wait().await;
+        if result.is_err() {
+          warn!("Export Library: Failed to export after a library change: {}", result.err().unwrap());
+        }
+      }
+    });
+  }
+
+  /// Lets the exporter know the library or its metadata changed.
+  pub fn library_changed(&self) {
+    self.changed.notify_one();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env::temp_dir, fs, os::unix::fs::symlink};
+
+  use super::export_artwork;
+
+  #[test]
+  fn export_artwork_only_copies_cached_files() {
+    let test_dir = temp_dir().join(format!("nrm-export-test-{}", std::process::id()));
+    let cache_dir = test_dir.join("grids");
+    let media_dir = test_dir.join("media");
+    fs::create_dir_all(cache_dir.join("capsule")).unwrap();
+    fs::write(cache_dir.join("capsule").join("rom.png"), "capsule").unwrap();
+    fs::write(test_dir.join("users.json"), "secret").unwrap();
+    symlink(test_dir.join("users.json"), cache_dir.join("capsule").join("link.png")).unwrap();
+
+    assert_eq!(export_artwork(&cache_dir, "/capsule/rom.png", &media_dir, "images", "id", "."), Some(String::from("./media/images/id.png")));
+    assert_eq!(fs::read_to_string(media_dir.join("images").join("id.png")).unwrap(), "capsule");
+
+    assert_eq!(export_artwork(&cache_dir, "https://example.com/rom.png", &media_dir, "images", "id", "."), Some(String::from("https://example.com/rom.png")));
+    assert_eq!(export_artwork(&cache_dir, "../users.json", &media_dir, "fanart", "id", "."), None);
+    assert_eq!(export_artwork(&cache_dir, "/capsule/../../users.json", &media_dir, "fanart", "id", "."), None);
+    assert_eq!(export_artwork(&cache_dir, "/capsule/link.png", &media_dir, "fanart", "id", "."), None);
+    assert!(!media_dir.join("fanart").exists());
+
+    let _ = fs::remove_dir_all(test_dir);
+  }
+}
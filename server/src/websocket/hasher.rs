@@ -0,0 +1,365 @@
+use std::{collections::{HashMap, HashSet}, env::var, fs::{self, File, Metadata}, io::{self, BufReader, Read}, path::{Path, PathBuf}, sync::{mpsc::{Receiver, RecvTimeoutError, Sender}, Arc, Mutex}, time::{Duration, UNIX_EPOCH}};
+
+use async_zip::base::read::seek::ZipFileReader;
+use crypto::{digest::Digest, md5::Md5, sha1::Sha1};
+use futures::{io::AllowStdIo, AsyncReadExt};
+use log::{info, warn};
+use sevenz_rust::{Password, SevenZReader};
+use tokio::sync::broadcast;
+
+use super::{config_files::write_atomic, dats::verify_rom, types::{library::{ArchiveMember, CachedROMHashes, HashedROM, ROMHashes, StateStore}, protocol::Event}, utils::broadcast};
+
+/// How long the hasher waits for more work before saving its cache.
+const SAVE_DELAY: Duration = Duration::from_secs(5);
+
+/// Keeps the hasher thread and library loads from writing hashes.json at the same time.
+static CACHE_WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Gets the path of the hashes.json file.
+fn get_cache_path() -> PathBuf {
+  let config_path = PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap());
+  return config_path.join("hashes.json");
+}
+
+/// Loads the cached hashes from hashes.json.
+fn load_cache() -> HashMap<String, CachedROMHashes> {
+  let cache_path = get_cache_path();
+
+  if !fs::exists(&cache_path).unwrap_or(false) {
+    return HashMap::new();
+  }
+
+  let cache_file_res = File::open(&cache_path);
+  if cache_file_res.is_err() {
+    warn!("Hasher: Failed to read hashes.json: {}", cache_file_res.err().unwrap());
+    return HashMap::new();
+  }
+
+  let cache_res: Result<HashMap<String, CachedROMHashes>, serde_json::Error> = serde_json::from_reader(cache_file_res.unwrap());
+  if cache_res.is_err() {
+    warn!("Hasher: Failed to parse hashes.json, hashes will be recomputed: {}", cache_res.err().unwrap());
+    return HashMap::new();
+  }
+
+  let cache = cache_res.unwrap();
+  info!("Hasher: Loaded {} cached hashes.", cache.len());
+
+  return cache;
+}
+
+/// Writes the cached hashes to hashes.json. Pass a copy of the cache, so lookups aren't blocked while it's written.
+fn write_cache(cache: &HashMap<String, CachedROMHashes>) {
+  let cache_path = get_cache_path();
+  let _ = fs::create_dir_all(cache_path.parent().unwrap());
+
+  let cache_str = serde_json::to_string(cache).expect("Hashes were malformatted.");
+
+  let _write_lock = CACHE_WRITE_LOCK.lock().expect("Failed to lock Hash Cache Write Mutex.");
+  let write_res = write_atomic(&cache_path, &cache_str);
+  if write_res.is_err() {
+    warn!("Hasher: Failed to write hashes.json: {}", write_res.err().unwrap());
+  }
+}
+
+/// Gets a file's modified time in seconds.
+pub fn get_modified(metadata: &Metadata) -> i64 {
+  return metadata.modified().ok()
+    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+    .map(|duration| duration.as_secs() as i64)
+    .unwrap_or(0);
+}
+
+/// Checks if a file is an archive whose members can be listed and hashed.
+fn is_member_archive(path: &Path) -> bool {
+  let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+  return extension == "zip" || extension == "7z";
+}
+
+/// Lists the files in a ZIP and hashes them. CRC32s are read from the central directory, so only the SHA-1 is computed.
+fn hash_zip_members(path: &Path) -> Result<Vec<ArchiveMember>, io::Error> {
+  let file = AllowStdIo::new(BufReader::new(File::open(path)?));
+
+  // * The hasher runs on its own thread, so the async reader is driven to completion here.
+  return futures::executor::block_on(async {
+    let mut reader = ZipFileReader::new(file).await.map_err(io::Error::other)?;
+    let mut buffer = vec![0; 1024 * 1024];
+    let mut members = vec![];
+
+    for index in 0..reader.file().entries().len() {
+      let entry = reader.file().entries().get(index).unwrap();
+      if entry.dir().unwrap_or(false) {
+        continue;
+      }
+
+      let name = String::from_utf8_lossy(entry.filename().as_bytes()).to_string();
+      let size = entry.uncompressed_size();
+      let crc32 = format!("{:08x}", entry.crc32());
+
+      let mut entry_reader = reader.reader_without_entry(index).await.map_err(io::Error::other)?;
+      let mut sha1 = Sha1::new();
+
+      loop {
+        let read = entry_reader.read(&mut buffer).await?;
+        if read == 0 {
+          break;
+        }
+
+        sha1.input(&buffer[..read]);
+      }
+
+      members.push(ArchiveMember {
+        name,
+        size,
+        crc32,
+        sha1: sha1.result_str(),
+      });
+    }
+
+    return Ok(members);
+  });
+}
+
+/// Lists the files in a 7z archive and hashes them. CRC32s are read from the archive's header when it has them.
+fn hash_7z_members(path: &Path) -> Result<Vec<ArchiveMember>, io::Error> {
+  let mut reader = SevenZReader::open(path, Password::empty()).map_err(io::Error::other)?;
+  let mut buffer = vec![0; 1024 * 1024];
+  let mut members = vec![];
+
+  reader.for_each_entries(|entry, entry_reader| {
+    if entry.is_directory() || entry.is_anti_item() {
+      return Ok(true);
+    }
+
+    let mut crc32 = crc32fast::Hasher::new();
+    let mut sha1 = Sha1::new();
+
+    loop {
+      let read = entry_reader.read(&mut buffer)?;
+      if read == 0 {
+        break;
+      }
+
+      if !entry.has_crc {
+        crc32.update(&buffer[..read]);
+      }
+      sha1.input(&buffer[..read]);
+    }
+
+    members.push(ArchiveMember {
+      name: entry.name().to_string(),
+      size: entry.size(),
+      crc32: format!("{:08x}", if entry.has_crc { entry.crc as u32 } else { crc32.finalize() }),
+      sha1: sha1.result_str(),
+    });
+
+    return Ok(true);
+  }).map_err(io::Error::other)?;
+
+  return Ok(members);
+}
+
+/// Lists and hashes the files inside an archived ROM. Returns None if the file isn't an archive.
+fn hash_archive_members(path: &Path) -> Result<Option<Vec<ArchiveMember>>, io::Error> {
+  if !is_member_archive(path) {
+    return Ok(None);
+  }
+
+  let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+  let mut members = if extension == "7z" {
+    hash_7z_members(path)?
+  } else {
+    hash_zip_members(path)?
+  };
+
+  members.sort_by(|a, b| a.name.cmp(&b.name));
+
+  return Ok(Some(members));
+}
+
+/// Computes the CRC32, MD5 and SHA-1 of a file in a single pass.
+/// ZIP and 7z archives also have their members listed and hashed.
+pub fn hash_file(path: &Path) -> Result<ROMHashes, io::Error> {
+  let mut file = File::open(path)?;
+  let mut buffer = vec![0; 1024 * 1024];
+
+  let mut crc32 = crc32fast::Hasher::new();
+  let mut md5 = Md5::new();
+  let mut sha1 = Sha1::new();
+
+  loop {
+    let read = file.read(&mut buffer)?;
+    if read == 0 {
+      break;
+    }
+
+    crc32.update(&buffer[..read]);
+    md5.input(&buffer[..read]);
+    sha1.input(&buffer[..read]);
+  }
+
+  // * A corrupt archive is still a ROM, it just can't be matched by its contents.
+  // * It's given no members so it isn't hashed again until it changes.
+  let members_res = hash_archive_members(path);
+  if members_res.is_err() {
+    warn!("Hasher: Failed to read the members of \"{}\": {}", path.display(), members_res.as_ref().err().unwrap());
+  }
+
+  return Ok(ROMHashes {
+    crc32: format!("{:08x}", crc32.finalize()),
+    md5: md5.result_str(),
+    sha1: sha1.result_str(),
+    members: members_res.unwrap_or(Some(vec![])),
+  });
+}
+
+/// Hashes ROMs in the background, and caches the results by path, size and modified time.
+#[derive(Clone)]
+pub struct Hasher {
+  cache: Arc<Mutex<HashMap<String, CachedROMHashes>>>,
+  pending: Arc<Mutex<HashSet<String>>>,
+  receiver: Arc<Mutex<Receiver<PathBuf>>>,
+  sender: Sender<PathBuf>,
+}
+
+impl Hasher {
+  /// Creates a new Hasher.
+  pub fn new() -> Hasher {
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    return Hasher {
+      cache: Arc::new(Mutex::new(load_cache())),
+      pending: Arc::new(Mutex::new(HashSet::new())),
+      receiver: Arc::new(Mutex::new(receiver)),
+      sender,
+    };
+  }
+
+  /// Initializes the hashing thread.
+  pub fn init(&self, tx: broadcast::Sender<String>, state_store: Arc<Mutex<StateStore>>) {
+    let hasher = self.clone();
+
+    std::thread::spawn(move || {
+      info!("Thread: Starting ROM hasher...");
+
+      let receiver = hasher.receiver.lock().expect("Failed to lock Hasher Receiver Mutex.");
+      let mut unsaved = false;
+
+      loop {
+        let path = match receiver.recv_timeout(SAVE_DELAY) {
+          Ok(path) => path,
+          Err(RecvTimeoutError::Timeout) => {
+            if unsaved {
+              let cache = hasher.cache.lock().expect("Failed to lock Hash Cache Mutex.").clone();
+              write_cache(&cache);
+              unsaved = false;
+            }
+            continue;
+          },
+          Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        let path_str = path.to_str().unwrap_or_default().to_string();
+
+        // * Removed files are sent by forget, so the cache is saved without them.
+        if !path.exists() {
+          hasher.pending.lock().expect("Failed to lock Hasher Pending Mutex.").remove(&path_str);
+          unsaved |= hasher.cache.lock().expect("Failed to lock Hash Cache Mutex.").remove(&path_str).is_some();
+          continue;
+        }
+
+        let hashes_res = hasher.hash(&path);
+        hasher.pending.lock().expect("Failed to lock Hasher Pending Mutex.").remove(&path_str);
+
+        if hashes_res.is_err() {
+          warn!("Hasher: Failed to hash \"{}\": {}", path_str, hashes_res.err().unwrap());
+          continue;
+        }
+        let hashes = hashes_res.unwrap();
+        unsaved = true;
+
+        // * The ROM may have been loaded before its hashes were ready, so update it in place.
+        let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+        let mut verification = None;
+
+        let StateStore { roms, dats, .. } = &mut *state;
+        for rom in roms.iter_mut().filter(|rom| rom.path == path_str) {
+          rom.hashes = Some(hashes.clone());
+          rom.verification = dats.get(&rom.system).map(|dat| verify_rom(dat, &path, rom.size, &hashes));
+          verification = rom.verification.clone();
+        }
+        drop(state);
+
+        broadcast(tx.clone(), Event::RomHashed(HashedROM {
+          path: path_str,
+          hashes,
+          verification,
+        }));
+      }
+    });
+  }
+
+  /// Hashes a file, and caches the result.
+  fn hash(&self, path: &Path) -> Result<ROMHashes, io::Error> {
+    let metadata = fs::metadata(path)?;
+    let hashes = hash_file(path)?;
+
+    self.cache.lock().expect("Failed to lock Hash Cache Mutex.").insert(path.to_str().unwrap_or_default().to_string(), CachedROMHashes {
+      size: metadata.len(),
+      modified: get_modified(&metadata),
+      hashes: hashes.clone(),
+    });
+
+    return Ok(hashes);
+  }
+
+  /// Removes the cached hashes of files that aren't in the library anymore. Called after a full rescan, so files don't need to be checked one by one.
+  pub fn prune(&self, rom_paths: &HashSet<&str>) {
+    let mut cache = self.cache.lock().expect("Failed to lock Hash Cache Mutex.");
+    let cached_count = cache.len();
+    cache.retain(|path, _| rom_paths.contains(path.as_str()));
+
+    if cache.len() == cached_count {
+      return;
+    }
+
+    info!("Hasher: Pruned {} cached hashes of files that aren't in the library.", cached_count - cache.len());
+    let cache_copy = cache.clone();
+    drop(cache);
+
+    write_cache(&cache_copy);
+  }
+
+  /// Removes a deleted or renamed file's hashes from the cache.
+  pub fn forget(&self, path: &Path) {
+    let _ = self.sender.send(path.to_path_buf());
+  }
+
+  /// Gets a ROM's cached hashes. If they're missing or the file changed, the ROM is queued to be hashed.
+  pub fn get_hashes(&self, path: &Path, metadata: &Metadata) -> Option<ROMHashes> {
+    if !metadata.is_file() {
+      return None;
+    }
+
+    return self.get_hashes_for(path, metadata.len(), get_modified(metadata));
+  }
+
+  /// Gets a file's cached hashes using a previously read size and modified time.
+  pub fn get_hashes_for(&self, path: &Path, size: u64, modified: i64) -> Option<ROMHashes> {
+    let path_str = path.to_str().unwrap_or_default().to_string();
+
+    let cache = self.cache.lock().expect("Failed to lock Hash Cache Mutex.");
+    let cached = cache.get(&path_str);
+
+    if cached.is_some_and(|cached| cached.size == size && cached.modified == modified) {
+      return Some(cached.unwrap().hashes.clone());
+    }
+    drop(cache);
+
+    let mut pending = self.pending.lock().expect("Failed to lock Hasher Pending Mutex.");
+    if pending.insert(path_str) {
+      let _ = self.sender.send(path.to_path_buf());
+    }
+
+    return None;
+  }
+}
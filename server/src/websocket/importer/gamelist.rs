@@ -0,0 +1,58 @@
+use std::{fs, path::Path};
+
+use chrono::NaiveDateTime;
+
+use crate::websocket::types::export::Gamelist;
+
+use super::{resolve_path, split_list, ImportSource, ImportedGame};
+
+/// Parses an EmulationStation release date, ex: "19980101T000000".
+fn parse_release_date(date: &str) -> Option<i64> {
+  return NaiveDateTime::parse_from_str(date.trim(), "%Y%m%dT%H%M%S").ok().map(|date| date.and_utc().timestamp());
+}
+
+/// Reads the games in an EmulationStation gamelist.xml. Paths are relative to the folder it's in.
+pub fn read(path: &Path) -> Result<ImportSource, String> {
+  let gamelist_str = fs::read_to_string(path).map_err(|e| e.to_string())?;
+  let gamelist: Gamelist = quick_xml::de::from_str(&gamelist_str).map_err(|e| e.to_string())?;
+
+  let base_dir = path.parent().unwrap_or(Path::new("/"));
+
+  let games = gamelist.games.into_iter().map(|game| {
+    return ImportedGame {
+      title: game.name.trim().to_string(),
+      paths: resolve_path(base_dir, &game.path).into_iter().collect(),
+      description: game.desc.filter(|desc| !desc.trim().is_empty()),
+      release_date: game.releasedate.as_deref().and_then(parse_release_date),
+      developers: split_list(game.developer.as_deref(), &[',']),
+      publishers: split_list(game.publisher.as_deref(), &[',']),
+      genres: split_list(game.genre.as_deref(), &[',', '/']),
+      rating: game.rating.and_then(|rating| rating.trim().parse::<f64>().ok()).map(|rating| rating * 100.0),
+      favorite: game.favorite,
+      md5: game.md5,
+      crc32: game.hash,
+      cover: game.image.and_then(|image| resolve_path(base_dir, &image)),
+      thumb: game.thumbnail.and_then(|thumbnail| resolve_path(base_dir, &thumbnail)),
+      hero: game.fanart.and_then(|fanart| resolve_path(base_dir, &fanart)),
+    };
+  }).collect();
+
+  // * Gamelists are kept in their system's ROM folder, or a folder named after the system.
+  return Ok(ImportSource {
+    games,
+    system_hint: base_dir.file_name().unwrap_or_default().to_string_lossy().to_string(),
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::parse_release_date;
+
+  #[test]
+  fn parse_release_date_reads_iso_basic_dates() {
+    assert_eq!(parse_release_date("19980101T000000"), Some(883612800));
+    assert_eq!(parse_release_date(" 20010321T000000 "), Some(985132800));
+    assert_eq!(parse_release_date("1998-01-01"), None);
+    assert_eq!(parse_release_date(""), None);
+  }
+}
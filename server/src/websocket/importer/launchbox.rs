@@ -0,0 +1,91 @@
+use std::{fs::{self, read_dir}, path::{Path, PathBuf}};
+
+use chrono::{DateTime, NaiveDate};
+
+use crate::websocket::types::import::LaunchBoxPlatform;
+
+use super::{resolve_path, split_list, ImportSource, ImportedGame};
+
+/// Parses a LaunchBox release date, ex: "2001-03-21T00:00:00-08:00".
+fn parse_release_date(date: &str) -> Option<i64> {
+  let date = date.trim();
+
+  return DateTime::parse_from_rfc3339(date).ok()
+    .map(|date| date.timestamp())
+    .or_else(|| NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok().map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp()));
+}
+
+/// Finds a game's image. LaunchBox names images after the game's title, ex: "Title-01.png", and can sort them into region folders.
+fn find_image(launchbox_dir: &Path, platform: &str, kind: &str, title: &str) -> Option<PathBuf> {
+  // * Characters that aren't allowed in file names are replaced with underscores.
+  let file_title: String = title.chars().map(|c| if ":/\\?*\"<>|'".contains(c) { '_' } else { c }).collect();
+  let prefix = format!("{}-0", file_title).to_lowercase();
+
+  let mut images = vec![];
+  let mut pending = vec![launchbox_dir.join("Images").join(platform).join(kind)];
+
+  while let Some(dir) = pending.pop() {
+    let entries_res = read_dir(&dir);
+    if entries_res.is_err() {
+      continue;
+    }
+
+    for entry in entries_res.unwrap().flatten() {
+      if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+        pending.push(entry.path());
+      } else if entry.file_name().to_string_lossy().to_lowercase().starts_with(&prefix) {
+        images.push(entry.path());
+      }
+    }
+  }
+
+  images.sort();
+  return images.into_iter().next();
+}
+
+/// Reads the games in a LaunchBox platform XML. Paths are relative to the LaunchBox folder.
+pub fn read(path: &Path) -> Result<ImportSource, String> {
+  let platform_str = fs::read_to_string(path).map_err(|e| e.to_string())?;
+  let platform_file: LaunchBoxPlatform = quick_xml::de::from_str(&platform_str).map_err(|e| e.to_string())?;
+
+  // * Platform files are saved in "Data/Platforms" in the LaunchBox folder.
+  let launchbox_dir = path.ancestors().nth(3).unwrap_or(Path::new("/"));
+  let platform = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+
+  let games = platform_file.games.into_iter().map(|game| {
+    return ImportedGame {
+      paths: resolve_path(launchbox_dir, &game.ApplicationPath).into_iter().collect(),
+      description: game.Notes.filter(|notes| !notes.trim().is_empty()),
+      release_date: game.ReleaseDate.as_deref().and_then(parse_release_date),
+      developers: split_list(game.Developer.as_deref(), &[';']),
+      publishers: split_list(game.Publisher.as_deref(), &[';']),
+      genres: split_list(game.Genre.as_deref(), &[';']),
+      // ? LaunchBox ratings are out of 5 stars.
+      rating: game.CommunityStarRating.and_then(|rating| rating.trim().parse::<f64>().ok()).map(|rating| rating * 20.0),
+      favorite: game.Favorite.map(|favorite| favorite.trim().eq_ignore_ascii_case("true")),
+      cover: find_image(launchbox_dir, &platform, "Box - Front", &game.Title),
+      hero: find_image(launchbox_dir, &platform, "Fanart - Background", &game.Title),
+      title: game.Title.trim().to_string(),
+      ..Default::default()
+    };
+  }).collect();
+
+  return Ok(ImportSource {
+    games,
+    system_hint: platform,
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::parse_release_date;
+
+  #[test]
+  fn parse_release_date_reads_timestamps_and_dates() {
+    assert_eq!(parse_release_date("2001-03-21T00:00:00-08:00"), Some(985161600));
+    assert_eq!(parse_release_date("2001-03-21T00:00:00Z"), Some(985132800));
+    // * Timestamps without an offset fall back to their date.
+    assert_eq!(parse_release_date("2001-03-21T00:00:00"), Some(985132800));
+    assert_eq!(parse_release_date("2001"), None);
+  }
+}
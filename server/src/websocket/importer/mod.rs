@@ -0,0 +1,742 @@
+use std::{collections::{HashMap, HashSet}, env::var, ffi::OsStr, fs::{self, read_dir}, path::{Component, Path, PathBuf}, sync::{Arc, Mutex}};
+
+use log::{info, warn};
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+
+use crate::job_queue::JobContext;
+
+use super::{
+  collections::refresh_collections,
+  metadata::{MetadataStore, SIDECAR_DIR},
+  types::{import::ImportResult, library::{Parser, ROMMetadata, StateStore, ROM}, protocol::Event, settings::Settings, ErrorSender},
+  utils::{broadcast, get_rom_base, hash64}
+};
+
+mod gamelist;
+mod launchbox;
+mod pegasus;
+
+/// A game read from another frontend's metadata, before it's matched to a ROM.
+#[derive(Clone, Debug, Default)]
+struct ImportedGame {
+  title: String,
+  /// The paths the game's ROM could be at. Only their file names need to match if the library has moved.
+  paths: Vec<PathBuf>,
+  description: Option<String>,
+  /// The release date, as a unix timestamp.
+  release_date: Option<i64>,
+  developers: Vec<String>,
+  publishers: Vec<String>,
+  genres: Vec<String>,
+  /// The rating, from 0 to 100.
+  rating: Option<f64>,
+  favorite: Option<bool>,
+  md5: Option<String>,
+  crc32: Option<String>,
+  cover: Option<PathBuf>,
+  thumb: Option<PathBuf>,
+  hero: Option<PathBuf>,
+}
+
+/// The games in a metadata file, along with the system they're for, if the file says.
+struct ImportSource {
+  games: Vec<ImportedGame>,
+  system_hint: String,
+}
+
+/// The metadata formats of other frontends that can be imported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+enum ImportFormat {
+  /// An EmulationStation gamelist.xml.
+  GAMELIST,
+  /// A LaunchBox platform XML, ex: "Data/Platforms/Nintendo Game Boy Advance.xml".
+  LAUNCHBOX,
+  /// A Pegasus metadata.pegasus.txt.
+  PEGASUS,
+}
+
+impl ImportFormat {
+  /// Gets the format of a metadata file, if it's one that can be imported.
+  fn detect(path: &Path) -> Option<ImportFormat> {
+    let file_name = path.file_name()?.to_str()?.to_lowercase();
+
+    if file_name == "gamelist.xml" {
+      return Some(ImportFormat::GAMELIST);
+    }
+
+    if file_name == "metadata.pegasus.txt" || file_name == "metadata.txt" {
+      return Some(ImportFormat::PEGASUS);
+    }
+
+    // * LaunchBox names its platform files after the platform, so they're recognized by their root element.
+    if file_name.ends_with(".xml") && fs::read_to_string(path).is_ok_and(|contents| contents.contains("<LaunchBox>")) {
+      return Some(ImportFormat::LAUNCHBOX);
+    }
+
+    return None;
+  }
+
+  /// Reads the games in a metadata file of this format.
+  fn read(&self, path: &Path) -> Result<ImportSource, String> {
+    return match self {
+      ImportFormat::GAMELIST => gamelist::read(path),
+      ImportFormat::LAUNCHBOX => launchbox::read(path),
+      ImportFormat::PEGASUS => pegasus::read(path),
+    };
+  }
+}
+
+/// Resolves a path from a metadata file. Relative paths are resolved from the provided folder.
+fn resolve_path(base_dir: &Path, path: &str) -> Option<PathBuf> {
+  let path = path.trim();
+  if path.is_empty() {
+    return None;
+  }
+
+  // ? LaunchBox saves Windows paths, which would otherwise be read as a single file name.
+  let path = PathBuf::from(path.replace("\\", "/"));
+  let joined = if path.is_absolute() { path } else { base_dir.join(path) };
+
+  let mut resolved = PathBuf::new();
+  for component in joined.components() {
+    match component {
+      Component::CurDir => {},
+      Component::ParentDir => {
+        resolved.pop();
+      },
+      component => resolved.push(component),
+    }
+  }
+
+  return Some(resolved);
+}
+
+/// Splits a list saved as text, ex: "Action, Platform".
+fn split_list(list: Option<&str>, separators: &[char]) -> Vec<String> {
+  return list.unwrap_or_default()
+    .split(separators)
+    .map(|entry| entry.trim().to_string())
+    .filter(|entry| !entry.is_empty())
+    .collect();
+}
+
+/// Finds the metadata files to import. Folders are searched for every file that can be imported.
+fn find_import_files(path: &str) -> Result<Vec<(PathBuf, ImportFormat)>, String> {
+  let root = PathBuf::from(path);
+
+  if root.is_file() {
+    let format = ImportFormat::detect(&root);
+    if format.is_none() {
+      return Err(format!("\"{}\" isn't a gamelist.xml, LaunchBox platform XML or Pegasus metadata file.", path));
+    }
+
+    return Ok(vec![(root, format.unwrap())]);
+  }
+
+  if !root.is_dir() {
+    return Err(format!("\"{}\" doesn't exist.", path));
+  }
+
+  let mut files = vec![];
+  let mut pending = vec![root];
+
+  while let Some(dir) = pending.pop() {
+    let entries_res = read_dir(&dir);
+    if entries_res.is_err() {
+      warn!("Import Metadata: Failed to read directory \"{}\": {}", dir.display(), entries_res.err().unwrap());
+      continue;
+    }
+
+    for entry in entries_res.unwrap().flatten() {
+      let entry_path = entry.path();
+
+      if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+        if entry.file_name() != SIDECAR_DIR {
+          pending.push(entry_path);
+        }
+        continue;
+      }
+
+      let format = ImportFormat::detect(&entry_path);
+      if format.is_some() {
+        files.push((entry_path, format.unwrap()));
+      }
+    }
+  }
+
+  files.sort_by(|a, b| a.0.cmp(&b.0));
+  return Ok(files);
+}
+
+/// Looks up ROMs by the ways imported games can be matched to them.
+struct ROMLookup {
+  by_path: HashMap<PathBuf, usize>,
+  by_name: HashMap<String, Vec<usize>>,
+  by_hash: HashMap<String, usize>,
+}
+
+impl ROMLookup {
+  /// Indexes the library's ROMs.
+  fn new(roms: &[ROM]) -> ROMLookup {
+    let mut lookup = ROMLookup {
+      by_path: HashMap::new(),
+      by_name: HashMap::new(),
+      by_hash: HashMap::new(),
+    };
+
+    for (i, rom) in roms.iter().enumerate() {
+      // * Folder ROMs can be referenced by their folder or the file inside it.
+      let rom_base = get_rom_base(rom);
+      lookup.by_path.insert(PathBuf::from(&rom.path), i);
+      lookup.by_path.insert(rom_base.clone(), i);
+
+      let name = rom_base.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+      lookup.by_name.entry(name).or_default().push(i);
+
+      if rom.hashes.is_some() {
+        let hashes = rom.hashes.as_ref().unwrap();
+        lookup.by_hash.insert(format!("md5:{}", hashes.md5.to_lowercase()), i);
+        lookup.by_hash.insert(format!("crc32:{}", hashes.crc32.to_lowercase()), i);
+      }
+    }
+
+    return lookup;
+  }
+
+  /// Finds the ROM a game is for, by its path, then its hashes, then its file name.
+  fn find(&self, game: &ImportedGame, roms: &[ROM], parsers: &HashMap<String, Parser>, system_hint: &str) -> Option<usize> {
+    for path in game.paths.iter() {
+      if let Some(index) = self.by_path.get(path) {
+        return Some(*index);
+      }
+    }
+
+    let hashes = [("md5", &game.md5), ("crc32", &game.crc32)];
+    for (kind, hash) in hashes {
+      let index = hash.as_ref().and_then(|hash| self.by_hash.get(&format!("{}:{}", kind, hash.trim().to_lowercase())));
+      if index.is_some() {
+        return index.copied();
+      }
+    }
+
+    for path in game.paths.iter() {
+      let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+      let candidates = self.by_name.get(&name);
+      if candidates.is_none() {
+        continue;
+      }
+      let candidates = candidates.unwrap();
+
+      if candidates.len() == 1 {
+        return Some(candidates[0]);
+      }
+
+      // * The same file can be in more than one system, so the system the metadata is for decides.
+      let in_system: Vec<usize> = candidates.iter()
+        .copied()
+        .filter(|index| is_in_system(parsers.get(&roms[*index].system), system_hint))
+        .collect();
+
+      if in_system.len() == 1 {
+        return Some(in_system[0]);
+      }
+    }
+
+    return None;
+  }
+}
+
+/// Checks if a system is the one a metadata file is for, ex: the folder a gamelist.xml is in.
+fn is_in_system(parser: Option<&Parser>, system_hint: &str) -> bool {
+  if parser.is_none() || system_hint.is_empty() {
+    return false;
+  }
+  let parser = parser.unwrap();
+
+  return [&parser.folder, &parser.abbreviation, &parser.name].iter().any(|name| name.eq_ignore_ascii_case(system_hint));
+}
+
+/// Gets the metadata a ROM has before anything is saved for it.
+fn get_default_metadata(rom: &ROM) -> ROMMetadata {
+  return ROMMetadata {
+    title: rom.title.clone(),
+    fullCapsulePath: String::new(),
+    thumbCapsulePath: String::new(),
+    heroPath: String::new(),
+    sgdbId: String::new(),
+    igdbId: String::new(),
+    metadata: None,
+    isFavorite: false,
+  };
+}
+
+/// Gets empty IGDB metadata. Imported metadata is saved in the same shape, so it shows up like metadata from IGDB.
+fn get_empty_igdb_metadata() -> Value {
+  return json!({
+    "totalRating": "",
+    "aggregatedRating": "",
+    "firstReleaseDate": 0,
+    "keywords": [],
+    "genres": [],
+    "franchises": [],
+    "alternativeNames": [],
+    "collections": [],
+    "developers": [],
+    "publishers": [],
+    "gameModes": [],
+    "languages": [],
+    "ageRatings": [],
+    "platforms": [],
+    "dlcs": [],
+    "expansions": [],
+    "websites": [],
+  });
+}
+
+/// Checks if a metadata field is unset.
+fn is_unset(value: Option<&Value>) -> bool {
+  return match value {
+    None | Some(Value::Null) => true,
+    // ? IGDB saves missing ratings as "0.00".
+    Some(Value::String(text)) => text.is_empty() || text.parse::<f64>().is_ok_and(|number| number == 0.0),
+    Some(Value::Array(list)) => list.is_empty(),
+    Some(Value::Number(number)) => number.as_f64() == Some(0.0),
+    _ => false,
+  };
+}
+
+/// Sets a metadata field to an imported value. Fields that are already set are only replaced when overwriting.
+fn set_field(object: &mut Value, key: &str, value: Value, overwrite: bool) {
+  if is_unset(Some(&value)) || (!overwrite && !is_unset(object.get(key))) {
+    return;
+  }
+
+  object[key] = value;
+}
+
+/// Checks if a ROM is missing a piece of artwork.
+fn is_artwork_unset(artwork_path: &str) -> bool {
+  return artwork_path.is_empty() || artwork_path == "No Grids";
+}
+
+/// The file extensions images can be imported from.
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "webp", "gif", "bmp"];
+
+/// Checks if an image can be imported. It has to be an image inside one of the provided folders, since the grids cache is served to every user.
+fn is_importable_image(source: &Path, image_roots: &[PathBuf]) -> bool {
+  let is_image = source.extension().is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str()));
+  if !is_image {
+    return false;
+  }
+
+  let canonical_res = fs::canonicalize(source);
+  if canonical_res.is_err() {
+    return false;
+  }
+  let canonical = canonical_res.unwrap();
+
+  return canonical.is_file() && image_roots.iter().any(|root| canonical.starts_with(root));
+}
+
+/// Copies an image to the grids cache, named the same way as uploaded capsules and heroes, and returns its path in the cache.
+fn copy_to_cache(cache_dir: &Path, kind: &str, rom_id: &str, source: &Path, image_roots: &[PathBuf]) -> Option<String> {
+  if !source.is_file() {
+    warn!("Import Metadata: Missing image \"{}\"", source.display());
+    return None;
+  }
+
+  if !is_importable_image(source, image_roots) {
+    warn!("Import Metadata: Skipping \"{}\" since it isn't an image in the import folder or library.", source.display());
+    return None;
+  }
+
+  let kind_dir = cache_dir.join(kind);
+  let extension = source.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or(String::from("png"));
+  let file_name = format!("{}.{}", rom_id, extension);
+  let dest = kind_dir.join(&file_name);
+
+  // * Re-importing from the cache itself would otherwise remove the image before it's copied.
+  let is_same_file = fs::canonicalize(source).ok().is_some_and(|source| fs::canonicalize(&dest).ok() == Some(source));
+  if is_same_file {
+    return Some(format!("/{}/{}", kind, file_name));
+  }
+
+  // ? The image is copied next to the old one first, so the ROM keeps its artwork if the copy fails.
+  let temp_path = kind_dir.join(format!("{}.tmp", file_name));
+  let copy_res = fs::create_dir_all(&kind_dir)
+    .and_then(|_| fs::copy(source, &temp_path))
+    .and_then(|_| fs::rename(&temp_path, &dest));
+  if copy_res.is_err() {
+    let _ = fs::remove_file(&temp_path);
+    warn!("Import Metadata: Failed to cache image \"{}\": {}", source.display(), copy_res.err().unwrap());
+    return None;
+  }
+
+  // * The ROM's existing artwork could have a different extension, so it's removed rather than left behind.
+  if let Ok(entries) = read_dir(&kind_dir) {
+    for entry in entries.flatten() {
+      if entry.path().file_stem() == Some(OsStr::new(rom_id)) && entry.path() != dest {
+        let _ = fs::remove_file(entry.path());
+      }
+    }
+  }
+
+  return Some(format!("/{}/{}", kind, file_name));
+}
+
+/// The paths of a game's images in the grids cache, once they've been copied there.
+#[derive(Clone, Debug, Default)]
+struct ImportedArtwork {
+  full: Option<String>,
+  thumb: Option<String>,
+  hero: Option<String>,
+}
+
+/// Copies the images a ROM will use from an imported game to the grids cache. Returns their paths, and how many were cached.
+fn cache_artwork(game: &ImportedGame, rom_id: &str, existing: &ROMMetadata, overwrite: bool, cache_dir: &Path, image_roots: &[PathBuf]) -> (ImportedArtwork, usize) {
+  let mut artwork = ImportedArtwork::default();
+
+  if game.cover.is_some() && (overwrite || is_artwork_unset(&existing.fullCapsulePath)) {
+    artwork.full = copy_to_cache(cache_dir, "full", rom_id, game.cover.as_ref().unwrap(), image_roots);
+  }
+
+  // ? Most frontends only have one cover, so it doubles as the thumbnail.
+  let thumb = game.thumb.as_ref().or(game.cover.as_ref());
+  if thumb.is_some() && (overwrite || is_artwork_unset(&existing.thumbCapsulePath)) {
+    artwork.thumb = copy_to_cache(cache_dir, "thumb", rom_id, thumb.unwrap(), image_roots);
+  }
+
+  if game.hero.is_some() && (overwrite || is_artwork_unset(&existing.heroPath)) {
+    artwork.hero = copy_to_cache(cache_dir, "hero", rom_id, game.hero.as_ref().unwrap(), image_roots);
+  }
+
+  let count = [&artwork.full, &artwork.thumb, &artwork.hero].iter().filter(|path| path.is_some()).count();
+  return (artwork, count);
+}
+
+/// Sets a piece of a ROM's artwork to an imported image.
+fn set_artwork(artwork_path: &mut String, cache_path: &Option<String>, overwrite: bool) {
+  if cache_path.is_some() && (overwrite || is_artwork_unset(artwork_path)) {
+    *artwork_path = cache_path.clone().unwrap();
+  }
+}
+
+/// Applies an imported game and its cached artwork to a ROM's metadata.
+fn apply_game(game: &ImportedGame, artwork: &ImportedArtwork, existing: ROMMetadata, rom: &ROM, overwrite: bool) -> ROMMetadata {
+  let mut metadata = existing;
+
+  // * ROMs are titled after their file until the user changes it.
+  if !game.title.is_empty() && (overwrite || metadata.title.is_empty() || metadata.title == rom.title) {
+    metadata.title = game.title.clone();
+  }
+
+  if game.favorite == Some(true) || (overwrite && game.favorite.is_some()) {
+    metadata.isFavorite = game.favorite.unwrap();
+  }
+
+  let mut igdb_game = metadata.metadata.take().unwrap_or(json!({
+    "igdbId": "",
+    "slug": "",
+    "name": metadata.title,
+    "summary": null,
+    "coverUrl": null,
+    "thumbUrl": null,
+    "metadata": null,
+  }));
+
+  if is_unset(igdb_game.get("metadata")) {
+    igdb_game["metadata"] = get_empty_igdb_metadata();
+  }
+
+  set_field(&mut igdb_game, "summary", json!(game.description), overwrite);
+
+  let igdb_metadata = &mut igdb_game["metadata"];
+  set_field(igdb_metadata, "firstReleaseDate", json!(game.release_date), overwrite);
+  set_field(igdb_metadata, "totalRating", json!(game.rating.map(|rating| format!("{:.2}", rating))), overwrite);
+  set_field(igdb_metadata, "genres", json!(game.genres), overwrite);
+  set_field(igdb_metadata, "developers", json!(game.developers), overwrite);
+  set_field(igdb_metadata, "publishers", json!(game.publishers), overwrite);
+
+  metadata.metadata = Some(igdb_game);
+
+  set_artwork(&mut metadata.fullCapsulePath, &artwork.full, overwrite);
+  set_artwork(&mut metadata.thumbCapsulePath, &artwork.thumb, overwrite);
+  set_artwork(&mut metadata.heroPath, &artwork.hero, overwrite);
+
+  return metadata;
+}
+
+/// Gets the folders images can be imported from: the folder being imported and the library.
+fn get_image_roots(path: &str, settings: &Settings) -> Vec<PathBuf> {
+  let import_path = PathBuf::from(path);
+  let import_dir = if import_path.is_file() { import_path.parent().map(|parent| parent.to_path_buf()).unwrap_or(import_path) } else { import_path };
+
+  let mut roots = vec![import_dir];
+  if !settings.library.libraryPath.is_empty() {
+    roots.push(PathBuf::from(&settings.library.libraryPath));
+  }
+
+  return roots.iter().filter_map(|root| fs::canonicalize(root).ok()).collect();
+}
+
+/// Imports the metadata in a file, or every file in a folder, into the library's metadata, and lets every connection know.
+pub fn import_metadata(
+  path: &str,
+  overwrite: bool,
+  settings: &Arc<Mutex<Settings>>,
+  state_store: &Arc<Mutex<StateStore>>,
+  tx: &broadcast::Sender<String>,
+  send_error: &ErrorSender,
+  job: &JobContext
+) -> Result<ImportResult, String> {
+  let files = find_import_files(path)?;
+
+  let state_settings = settings.lock().expect("Failed to lock Settings Mutex.").clone();
+  let metadata_store = MetadataStore::from_settings(&state_settings);
+
+  let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+  if state.roms.is_empty() {
+    return Err(String::from("The library hasn't been loaded yet."));
+  }
+
+  // * Metadata is only loaded once a user views the library, and importing shouldn't replace what's saved.
+  if state.metadata.is_empty() {
+    (*state).metadata = metadata_store.load(&state_settings, send_error).map_err(|_| String::from("Failed to load the existing metadata."))?;
+  }
+
+  // ? The state is copied so the library isn't locked while the files are read.
+  let roms = state.roms.clone();
+  let parsers = state.parsers.clone();
+  let metadata = state.metadata.clone();
+  drop(state);
+
+  let lookup = ROMLookup::new(&roms);
+  let cache_dir = PathBuf::from(var("NRM_GRIDS_CACHE_DIR").unwrap());
+  let image_roots = get_image_roots(path, &state_settings);
+  let mut matches: Vec<(usize, ImportedGame, ImportedArtwork)> = vec![];
+
+  let mut result = ImportResult {
+    files: files.len(),
+    games: 0,
+    matched: 0,
+    artwork: 0,
+    unmatched: vec![],
+  };
+
+  for (i, (file, format)) in files.iter().enumerate() {
+    if job.is_cancelled() {
+      return Err(String::from("The import was cancelled."));
+    }
+
+    job.report(Some(i as f64 / files.len() as f64), &format!("Importing \"{}\"...", file.display()));
+
+    let source_res = format.read(file);
+    if source_res.is_err() {
+      warn!("Import Metadata: Failed to read \"{}\": {}", file.display(), source_res.err().unwrap());
+      continue;
+    }
+    let source = source_res.unwrap();
+
+    for game in source.games.iter() {
+      result.games += 1;
+
+      let rom_index = lookup.find(game, &roms, &parsers, &source.system_hint);
+      if rom_index.is_none() {
+        let name = game.paths.first().map(|path| path.display().to_string()).unwrap_or(game.title.clone());
+        result.unmatched.push(name);
+        continue;
+      }
+
+      let rom = &roms[rom_index.unwrap()];
+      let rom_id = hash64(&rom.path);
+      let existing = metadata.get(&rom_id).cloned().unwrap_or(get_default_metadata(rom));
+
+      let (artwork, artwork_count) = cache_artwork(game, &rom_id, &existing, overwrite, &cache_dir, &image_roots);
+      matches.push((rom_index.unwrap(), game.clone(), artwork));
+
+      result.matched += 1;
+      result.artwork += artwork_count;
+    }
+  }
+
+  if matches.is_empty() {
+    info!("Import Metadata: No games in \"{}\" matched a ROM.", path);
+    return Ok(result);
+  }
+
+  // * The imported fields are applied to the latest metadata while the state is locked, so changes made while importing aren't lost.
+  let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+  let mut new_metadata = state.metadata.clone();
+  let loaded_ids: HashSet<String> = state.roms.iter().map(|rom| hash64(&rom.path)).collect();
+
+  for (rom_index, game, artwork) in matches.iter() {
+    let rom = &roms[*rom_index];
+    let rom_id = hash64(&rom.path);
+
+    // ? ROMs removed while importing don't get their metadata back.
+    if !loaded_ids.contains(&rom_id) {
+      continue;
+    }
+
+    let existing = new_metadata.get(&rom_id).cloned().unwrap_or(get_default_metadata(rom));
+    new_metadata.insert(rom_id, apply_game(game, artwork, existing, rom, overwrite));
+  }
+
+  let success = metadata_store.write(&new_metadata, &state.metadata, &state.roms, send_error);
+  if !success {
+    return Err(String::from("Failed to save the imported metadata."));
+  }
+
+  (*state).metadata = new_metadata.clone();
+  refresh_collections(&mut state, tx);
+  drop(state);
+
+  broadcast(tx.clone(), Event::MetadataRefreshed(new_metadata));
+
+  info!("Import Metadata: Imported metadata for {} of {} games from \"{}\".", result.matched, result.games, path);
+  return Ok(result);
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{collections::HashMap, env::temp_dir, fs, os::unix::fs::symlink, path::{Path, PathBuf}};
+
+  use serde_json::json;
+
+  use crate::websocket::{test_utils::{self, parser}, types::library::{ROMHashes, ROM}};
+
+  use super::{apply_game, copy_to_cache, get_default_metadata, resolve_path, ImportedArtwork, ImportedGame, ROMLookup};
+
+  fn rom(path: &str, system: &str, crc32: Option<&str>) -> ROM {
+    let hashes = crc32.map(|crc32| ROMHashes { crc32: crc32.to_string(), md5: String::new(), sha1: String::new(), members: None });
+    return ROM { hashes, ..test_utils::rom(path, system) };
+  }
+
+  fn game(paths: Vec<&str>, crc32: Option<&str>) -> ImportedGame {
+    return ImportedGame {
+      paths: paths.into_iter().map(PathBuf::from).collect(),
+      crc32: crc32.map(|crc32| crc32.to_string()),
+      ..Default::default()
+    };
+  }
+
+  #[test]
+  fn resolve_path_resolves_relative_and_windows_paths() {
+    // * ROMs can be anywhere, so paths are resolved as is. Images are checked when they're copied.
+    let base_dir = Path::new("/frontend/gba");
+
+    assert_eq!(resolve_path(base_dir, "./Metroid Fusion.gba"), Some(PathBuf::from("/frontend/gba/Metroid Fusion.gba")));
+    assert_eq!(resolve_path(base_dir, "../roms/gba/a.gba"), Some(PathBuf::from("/frontend/roms/gba/a.gba")));
+    assert_eq!(resolve_path(base_dir, "/roms/gba/a.gba"), Some(PathBuf::from("/roms/gba/a.gba")));
+    assert_eq!(resolve_path(base_dir, "..\\Images\\a.png"), Some(PathBuf::from("/frontend/Images/a.png")));
+    assert_eq!(resolve_path(base_dir, "  "), None);
+  }
+
+  #[test]
+  fn find_prefers_paths_then_hashes() {
+    let roms = vec![rom("/roms/gba/a.gba", "GBA", Some("AAAA1111")), rom("/roms/gba/b.gba", "GBA", Some("BBBB2222"))];
+    let lookup = ROMLookup::new(&roms);
+    let parsers = HashMap::new();
+
+    assert_eq!(lookup.find(&game(vec!["/roms/gba/b.gba"], Some("aaaa1111")), &roms, &parsers, ""), Some(1));
+    assert_eq!(lookup.find(&game(vec!["/old/gba/renamed.gba"], Some(" aaaa1111 ")), &roms, &parsers, ""), Some(0));
+    assert_eq!(lookup.find(&game(vec!["/old/gba/c.gba"], Some("cccc3333")), &roms, &parsers, ""), None);
+  }
+
+  #[test]
+  fn find_matches_file_names_by_system() {
+    let roms = vec![rom("/roms/gba/game.zip", "GBA", None), rom("/roms/snes/game.zip", "SNES", None), rom("/roms/gba/only.gba", "GBA", None)];
+    let lookup = ROMLookup::new(&roms);
+    let parsers = HashMap::from([
+      (String::from("GBA"), parser("Game Boy Advance", "GBA", "gba")),
+      (String::from("SNES"), parser("Super Nintendo", "SNES", "snes")),
+    ]);
+
+    assert_eq!(lookup.find(&game(vec!["/old/ONLY.gba"], None), &roms, &parsers, ""), Some(2));
+    assert_eq!(lookup.find(&game(vec!["/old/game.zip"], None), &roms, &parsers, "snes"), Some(1));
+    assert_eq!(lookup.find(&game(vec!["/old/game.zip"], None), &roms, &parsers, "Game Boy Advance"), Some(0));
+    // * Ambiguous file names aren't guessed.
+    assert_eq!(lookup.find(&game(vec!["/old/game.zip"], None), &roms, &parsers, ""), None);
+  }
+
+  #[test]
+  fn copy_to_cache_replaces_artwork() {
+    let test_dir = temp_dir().join(format!("nrm-import-test-{}", std::process::id()));
+    let cache_dir = test_dir.join("cache");
+    fs::create_dir_all(cache_dir.join("capsule")).unwrap();
+    fs::write(cache_dir.join("capsule").join("rom.png"), "old").unwrap();
+
+    let source = test_dir.join("cover.JPG");
+    fs::write(&source, "new").unwrap();
+    let image_roots = vec![fs::canonicalize(&test_dir).unwrap()];
+
+    assert_eq!(copy_to_cache(&cache_dir, "capsule", "rom", &source, &image_roots), Some(String::from("/capsule/rom.jpg")));
+    assert_eq!(fs::read_to_string(cache_dir.join("capsule").join("rom.jpg")).unwrap(), "new");
+    assert!(!cache_dir.join("capsule").join("rom.png").exists());
+    assert!(!cache_dir.join("capsule").join("rom.jpg.tmp").exists());
+
+    // * Importing the cached image again leaves it as is.
+    assert_eq!(copy_to_cache(&cache_dir, "capsule", "rom", &cache_dir.join("capsule").join("rom.jpg"), &image_roots), Some(String::from("/capsule/rom.jpg")));
+    assert_eq!(fs::read_to_string(cache_dir.join("capsule").join("rom.jpg")).unwrap(), "new");
+
+    assert_eq!(copy_to_cache(&cache_dir, "capsule", "rom", &test_dir.join("missing.png"), &image_roots), None);
+
+    let _ = fs::remove_dir_all(test_dir);
+  }
+
+  #[test]
+  fn copy_to_cache_only_copies_images_in_the_import_folder() {
+    let test_dir = temp_dir().join(format!("nrm-import-roots-test-{}", std::process::id()));
+    let import_dir = test_dir.join("import");
+    let cache_dir = test_dir.join("cache");
+    fs::create_dir_all(import_dir.join("media")).unwrap();
+    fs::write(test_dir.join("users.json"), "secret").unwrap();
+    fs::write(test_dir.join("outside.png"), "outside").unwrap();
+    fs::write(import_dir.join("media").join("notes.txt"), "notes").unwrap();
+    symlink(test_dir.join("outside.png"), import_dir.join("media").join("link.png")).unwrap();
+
+    let image_roots = vec![fs::canonicalize(&import_dir).unwrap()];
+    let copy = |source: &str| copy_to_cache(&cache_dir, "full", "rom", &resolve_path(&import_dir, source).unwrap(), &image_roots);
+
+    assert_eq!(copy("../users.json"), None);
+    assert_eq!(copy("../outside.png"), None);
+    assert_eq!(copy(&test_dir.join("outside.png").to_string_lossy()), None);
+    assert_eq!(copy("./media/notes.txt"), None);
+    assert_eq!(copy("./media/link.png"), None);
+    assert!(!cache_dir.exists());
+
+    let _ = fs::remove_dir_all(test_dir);
+  }
+
+  #[test]
+  fn apply_game_keeps_edits_unless_overwriting() {
+    let rom = rom("/roms/gba/fusion.gba", "GBA", None);
+    let game = ImportedGame {
+      title: String::from("Metroid Fusion"),
+      description: Some(String::from("Imported summary")),
+      genres: vec![String::from("Action")],
+      favorite: Some(false),
+      ..Default::default()
+    };
+    let artwork = ImportedArtwork { full: Some(String::from("/full/rom.png")), ..Default::default() };
+
+    let mut existing = get_default_metadata(&rom);
+    existing.title = String::from("My Title");
+    existing.isFavorite = true;
+    existing.fullCapsulePath = String::from("/full/mine.png");
+
+    let kept = apply_game(&game, &artwork, existing.clone(), &rom, false);
+    assert_eq!(kept.title, "My Title");
+    assert!(kept.isFavorite);
+    assert_eq!(kept.fullCapsulePath, "/full/mine.png");
+    assert_eq!(kept.metadata.as_ref().unwrap()["summary"], json!("Imported summary"));
+    assert_eq!(kept.metadata.as_ref().unwrap()["metadata"]["genres"], json!(["Action"]));
+
+    let overwritten = apply_game(&game, &artwork, existing, &rom, true);
+    assert_eq!(overwritten.title, "Metroid Fusion");
+    assert!(!overwritten.isFavorite);
+    assert_eq!(overwritten.fullCapsulePath, "/full/rom.png");
+  }
+}
@@ -0,0 +1,227 @@
+use std::{fs::{self, read_dir}, path::{Path, PathBuf}};
+
+use chrono::NaiveDate;
+
+use super::{resolve_path, split_list, ImportSource, ImportedGame};
+
+/// An entry in a Pegasus metadata file, with its values in the order they were listed. Each value can span multiple lines.
+type PegasusEntry = Vec<(String, Vec<String>)>;
+
+/// Parses Pegasus' format into its entries. Entries start with a "collection" or "game" key, values continue on indented lines, and "#" starts a comment.
+fn parse_entries(metadata_str: &str) -> Vec<PegasusEntry> {
+  let mut entries: Vec<PegasusEntry> = vec![];
+
+  for line in metadata_str.lines() {
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with("#") {
+      continue;
+    }
+
+    let is_continuation = line.starts_with([' ', '\t']);
+    if is_continuation {
+      if let Some((_, lines)) = entries.last_mut().and_then(|entry| entry.last_mut()) {
+        lines.push(trimmed.to_string());
+      }
+      continue;
+    }
+
+    let split = trimmed.split_once(":");
+    if split.is_none() {
+      continue;
+    }
+    let (key, value) = split.unwrap();
+
+    // * Keys ignore case, and asset keys can be written like "boxFront", "box_front" or "box-front".
+    let key = key.trim().to_lowercase().replace(['_', '-'], "");
+    if key == "collection" || key == "game" || entries.is_empty() {
+      entries.push(vec![]);
+    }
+
+    let lines = if value.trim().is_empty() { vec![] } else { vec![value.trim().to_string()] };
+    entries.last_mut().unwrap().push((key, lines));
+  }
+
+  return entries;
+}
+
+/// Gets the first value of a key in an entry.
+fn get_value<'a>(entry: &'a PegasusEntry, keys: &[&str]) -> Option<&'a Vec<String>> {
+  return entry.iter().find(|(key, _)| keys.contains(&key.as_str())).map(|(_, lines)| lines);
+}
+
+/// Gets a value as text. A line with just "." is a paragraph break.
+fn get_text(entry: &PegasusEntry, keys: &[&str]) -> Option<String> {
+  let lines = get_value(entry, keys)?;
+
+  let text = lines.iter()
+    .map(|line| if line == "." { "\n\n" } else { line.as_str() })
+    .collect::<Vec<&str>>()
+    .join(" ")
+    .replace(" \n\n ", "\n\n");
+
+  return Some(text).filter(|text| !text.is_empty());
+}
+
+/// Gets a value as a list. Lists can be on one line separated by commas, or one entry per line.
+fn get_list(entry: &PegasusEntry, keys: &[&str]) -> Vec<String> {
+  return get_value(entry, keys)
+    .map(|lines| lines.iter().flat_map(|line| split_list(Some(line), &[','])).collect())
+    .unwrap_or_default();
+}
+
+/// Parses a Pegasus release date, ex: "2001-03-21", "2001-03" or "2001".
+fn parse_release_date(date: &str) -> Option<i64> {
+  let date = date.trim();
+  let padded = match date.len() {
+    4 => format!("{}-01-01", date),
+    7 => format!("{}-01", date),
+    _ => date.to_string(),
+  };
+
+  return NaiveDate::parse_from_str(&padded, "%Y-%m-%d").ok().map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp());
+}
+
+/// Finds a game's image in Pegasus' media folder, ex: "media/Game/boxFront.png".
+fn find_media(base_dir: &Path, file: &Path, asset: &str) -> Option<PathBuf> {
+  let stem = file.file_stem()?;
+  let media_dir = base_dir.join("media").join(stem);
+
+  let mut images: Vec<PathBuf> = read_dir(media_dir).ok()?
+    .flatten()
+    .map(|entry| entry.path())
+    .filter(|image| image.file_stem().is_some_and(|image_stem| image_stem.to_string_lossy().eq_ignore_ascii_case(asset)))
+    .collect();
+
+  images.sort();
+  return images.into_iter().next();
+}
+
+/// Reads the games in a Pegasus metadata file. Paths are relative to the folder it's in.
+pub fn read(path: &Path) -> Result<ImportSource, String> {
+  let metadata_str = fs::read_to_string(path).map_err(|e| e.to_string())?;
+  let entries = parse_entries(&metadata_str);
+
+  let base_dir = path.parent().unwrap_or(Path::new("/"));
+
+  // * A collection's short name is usually its system, ex: "gba".
+  let system_hint = entries.iter()
+    .find(|entry| entry.first().is_some_and(|(key, _)| key == "collection"))
+    .and_then(|entry| get_value(entry, &["shortname"]).or(get_value(entry, &["collection"])))
+    .and_then(|lines| lines.first().cloned())
+    .unwrap_or_default();
+
+  let mut games = vec![];
+
+  for entry in entries.iter() {
+    let title = get_text(entry, &["game"]);
+    if title.is_none() || entry.first().is_none_or(|(key, _)| key != "game") {
+      continue;
+    }
+
+    let paths: Vec<PathBuf> = get_value(entry, &["file", "files"])
+      .map(|files| files.iter().filter_map(|file| resolve_path(base_dir, file)).collect())
+      .unwrap_or_default();
+
+    let get_asset = |keys: &[&str], media_name: &str| -> Option<PathBuf> {
+      return get_text(entry, keys)
+        .and_then(|asset| resolve_path(base_dir, &asset))
+        .or_else(|| paths.first().and_then(|file| find_media(base_dir, file, media_name)));
+    };
+
+    games.push(ImportedGame {
+      title: title.unwrap(),
+      description: get_text(entry, &["description"]).or(get_text(entry, &["summary"])),
+      release_date: get_text(entry, &["release"]).as_deref().and_then(parse_release_date),
+      developers: get_list(entry, &["developer", "developers"]),
+      publishers: get_list(entry, &["publisher", "publishers"]),
+      genres: get_list(entry, &["genre", "genres"]),
+      rating: get_text(entry, &["rating"]).and_then(|rating| rating.trim_end_matches("%").trim().parse::<f64>().ok()),
+      cover: get_asset(&["assets.boxfront", "assets.box"], "boxFront"),
+      hero: get_asset(&["assets.background"], "background"),
+      paths,
+      ..Default::default()
+    });
+  }
+
+  return Ok(ImportSource {
+    games,
+    system_hint,
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{get_list, get_text, parse_entries, parse_release_date};
+
+  const METADATA: &str = "collection: Game Boy Advance
+shortname: gba
+# Games are listed after the collection.
+
+game: Metroid Fusion
+file: Metroid Fusion.gba
+Release_Date: 2002-11-17
+genre: Action, Platform
+description: Samus is infected
+  by an X parasite.
+  .
+  The station is overrun.
+assets.box-front: media/fusion.png
+
+game: Golden Sun
+files:
+  Golden Sun.gba
+genres:
+  RPG
+  Fantasy
+";
+
+  #[test]
+  fn parse_entries_splits_collections_and_games() {
+    let entries = parse_entries(METADATA);
+    assert_eq!(entries.len(), 3);
+
+    assert_eq!(entries[0][0], (String::from("collection"), vec![String::from("Game Boy Advance")]));
+    assert_eq!(entries[1][0], (String::from("game"), vec![String::from("Metroid Fusion")]));
+    assert_eq!(entries[2][0], (String::from("game"), vec![String::from("Golden Sun")]));
+  }
+
+  #[test]
+  fn parse_entries_normalizes_keys_and_continues_lines() {
+    let entries = parse_entries(METADATA);
+    let fusion = &entries[1];
+
+    assert!(fusion.iter().any(|(key, _)| key == "releasedate"));
+    assert!(fusion.iter().any(|(key, _)| key == "assets.boxfront"));
+
+    let description = fusion.iter().find(|(key, _)| key == "description").map(|(_, lines)| lines.len());
+    assert_eq!(description, Some(4));
+
+    let files = entries[2].iter().find(|(key, _)| key == "files").map(|(_, lines)| lines.clone());
+    assert_eq!(files, Some(vec![String::from("Golden Sun.gba")]));
+  }
+
+  #[test]
+  fn get_text_joins_lines_and_keeps_paragraphs() {
+    let entries = parse_entries(METADATA);
+
+    assert_eq!(get_text(&entries[1], &["description"]).as_deref(), Some("Samus is infected by an X parasite.\n\nThe station is overrun."));
+    assert_eq!(get_text(&entries[1], &["summary", "game"]).as_deref(), Some("Metroid Fusion"));
+    assert_eq!(get_text(&entries[2], &["description"]), None);
+  }
+
+  #[test]
+  fn get_list_reads_single_and_multi_line_lists() {
+    let entries = parse_entries(METADATA);
+
+    assert_eq!(get_list(&entries[1], &["genre", "genres"]), vec!["Action", "Platform"]);
+    assert_eq!(get_list(&entries[2], &["genre", "genres"]), vec!["RPG", "Fantasy"]);
+  }
+
+  #[test]
+  fn parse_release_date_pads_partial_dates() {
+    assert_eq!(parse_release_date("2001-03-21"), Some(985132800));
+    assert_eq!(parse_release_date(" 2001-03 "), Some(983404800));
+    assert_eq!(parse_release_date("2001"), Some(978307200));
+    assert_eq!(parse_release_date("March 2001"), None);
+  }
+}
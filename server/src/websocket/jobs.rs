@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex};
+
+use serde_json::json;
+
+use crate::{job_queue::JobContext, scheduler::{get_job_schedule, Scheduler}};
+
+use super::{
+  collections::refresh_collections,
+  context::ServerContext,
+  library_manager::parse_library,
+  metadata::load_metadata,
+  settings::load_settings,
+  types::protocol::Event,
+  utils::{broadcast, get_job_error_sender}
+};
+
+/// Rescans the library from the saved settings, and sends the result to every connection.
+fn rescan_library(context: &ServerContext, job: &JobContext) -> Result<String, String> {
+  let errors = Arc::new(Mutex::new(vec![]));
+
+  let mut state_settings = context.settings.lock().expect("Failed to lock Settings Mutex.");
+
+  // * Settings are only loaded once a user connects, so load them if nobody has yet.
+  if state_settings.library.libraryPath.is_empty() {
+    let saved_settings = load_settings(get_job_error_sender(errors.clone()));
+    if saved_settings.is_err() {
+      return Err(errors.lock().expect("Failed to lock Job Errors Mutex.").join(" "));
+    }
+
+    *state_settings = saved_settings.unwrap();
+  }
+
+  let library = state_settings.library.clone();
+  drop(state_settings);
+
+  if library.libraryPath.is_empty() {
+    return Ok(String::from("No library has been set up yet."));
+  }
+
+  let library_res = parse_library(
+    &library,
+    &context.get_watcher(),
+    &context.get_hasher(),
+    &context.state_store,
+    &context.broadcast_tx,
+    get_job_error_sender(errors.clone()),
+    job
+  );
+
+  if library_res.is_err() {
+    if job.is_cancelled() {
+      return Err(String::from("The rescan was cancelled."));
+    }
+
+    return Err(errors.lock().expect("Failed to lock Job Errors Mutex.").join(" "));
+  }
+
+  let load_res = library_res.unwrap();
+  let rom_count = load_res.roms.len();
+  broadcast(context.broadcast_tx.clone(), Event::LibraryRescanned(load_res));
+
+  return Ok(format!("Loaded {} ROMs.", rom_count));
+}
+
+/// Reloads the saved metadata, and sends the result to every connection.
+fn refresh_metadata(context: &ServerContext) -> Result<String, String> {
+  let errors = Arc::new(Mutex::new(vec![]));
+
+  let state_settings = context.settings.lock().expect("Failed to lock Settings Mutex.").clone();
+  let metadata_res = load_metadata(&state_settings, get_job_error_sender(errors.clone()));
+
+  if metadata_res.is_err() {
+    return Err(errors.lock().expect("Failed to lock Job Errors Mutex.").join(" "));
+  }
+
+  let metadata = metadata_res.unwrap();
+  let mut state = context.state_store.lock().expect("Failed to lock State Mutex.");
+  (*state).metadata = metadata.clone();
+  refresh_collections(&mut state, &context.broadcast_tx);
+  drop(state);
+
+  let rom_count = metadata.len();
+  broadcast(context.broadcast_tx.clone(), Event::MetadataRefreshed(metadata));
+
+  return Ok(format!("Loaded metadata for {} ROMs.", rom_count));
+}
+
+/// Schedules the jobs that keep the library and its metadata in sync with the disk.
+pub fn schedule_library_jobs(scheduler: &Scheduler, context: ServerContext) {
+  let rescan_context = context.clone();
+  scheduler.add_job("rescan_library", &get_job_schedule("NRM_LIBRARY_RESCAN_SCHEDULE", ""), move || {
+    let context = rescan_context.clone();
+
+    // * Rescans share the manual load's job kind, so a rescan is skipped while a load is still queued or running.
+    async move {
+      if context.job_queue.find_active("parse_library", "").is_some() {
+        return Ok(String::from("The library is already being loaded."));
+      }
+
+      let job_context = context.clone();
+      let handle = context.job_queue.spawn_blocking("parse_library", "", move |job| {
+        return rescan_library(&job_context, &job)
+          .map(|message| json!({ "message": message }));
+      });
+
+      let result = handle.wait().await?;
+      context.exporter.library_changed();
+
+      return Ok(result["message"].as_str().unwrap_or_default().to_string());
+    }
+  });
+
+  scheduler.add_job("refresh_metadata", &get_job_schedule("NRM_METADATA_REFRESH_SCHEDULE", ""), move || {
+    let context = context.clone();
+
+    async move {
+      let job_context = context.clone();
+      let message = tokio::task::spawn_blocking(move || refresh_metadata(&job_context)).await
+        .map_err(|err| err.to_string())??;
+      context.exporter.library_changed();
+
+      return Ok(message);
+    }
+  });
+}
@@ -0,0 +1,246 @@
+use std::{collections::HashMap, env::var, fs::{self, read_dir, File, Metadata}, path::{Path, PathBuf}, sync::Mutex, time::{SystemTime, UNIX_EPOCH}};
+
+use log::{info, warn};
+
+use super::{config_files::write_atomic, hasher::get_modified, metadata::SIDECAR_DIR, types::library::{IndexedDirectory, IndexedROM, Library, LibraryIndex, Parser}};
+
+/// The version of the index format. Bump this whenever IndexedDirectory, IndexedROM or ROM change shape.
+const INDEX_VERSION: u32 = 2;
+
+/// Directories modified this recently may still be getting written to, so their listings are re-read on the next load.
+const SETTLE_TIME_MILLIS: i64 = 2000;
+
+/// Keeps the watcher and library loads from writing the index at the same time, since they write it outside of the state lock.
+static INDEX_WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Gets the path of the library_index.json file.
+fn get_index_path() -> PathBuf {
+  let config_path = PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap());
+  return config_path.join("library_index.json");
+}
+
+/// Loads the library index from library_index.json.
+pub fn load_index() -> LibraryIndex {
+  let index_path = get_index_path();
+
+  if !fs::exists(&index_path).unwrap_or(false) {
+    return LibraryIndex::default();
+  }
+
+  let index_file_res = File::open(&index_path);
+  if index_file_res.is_err() {
+    warn!("Library Index: Failed to read library_index.json: {}", index_file_res.err().unwrap());
+    return LibraryIndex::default();
+  }
+
+  let index_res: Result<LibraryIndex, serde_json::Error> = serde_json::from_reader(std::io::BufReader::new(index_file_res.unwrap()));
+  if index_res.is_err() {
+    warn!("Library Index: Failed to parse library_index.json, the library will be rescanned: {}", index_res.err().unwrap());
+    return LibraryIndex::default();
+  }
+
+  let index = index_res.unwrap();
+  if index.version != INDEX_VERSION {
+    info!("Library Index: Index is from version {}, the library will be rescanned.", index.version);
+    return LibraryIndex::default();
+  }
+
+  info!("Library Index: Loaded {} indexed ROMs.", index.roms.len());
+
+  return index;
+}
+
+/// Writes the library index to library_index.json. Pass a copy of the index, so the state isn't locked while it's written.
+pub fn write_index(index: &LibraryIndex) {
+  let index_path = get_index_path();
+  let _ = fs::create_dir_all(index_path.parent().unwrap());
+
+  let index_str = serde_json::to_string(index).expect("Library index was malformatted.");
+
+  let _write_lock = INDEX_WRITE_LOCK.lock().expect("Failed to lock Index Write Mutex.");
+  let write_res = write_atomic(&index_path, &index_str);
+  if write_res.is_err() {
+    warn!("Library Index: Failed to write library_index.json: {}", write_res.err().unwrap());
+  }
+}
+
+/// Gets a file's modified time in milliseconds.
+fn get_modified_millis(metadata: &Metadata) -> i64 {
+  return metadata.modified().ok()
+    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+    .map(|duration| duration.as_millis() as i64)
+    .unwrap_or(0);
+}
+
+/// A file or directory found while walking a folder.
+pub struct IndexedEntry {
+  pub path: PathBuf,
+  pub relative_path: PathBuf,
+  /// Whether the directory containing this entry changed since the last load.
+  pub changed: bool,
+}
+
+/// Reconciles the previous index against the filesystem, reusing the listings and ROMs of directories that haven't changed.
+/// Files modified in place don't change their directory's modified time, so those are picked up by the watcher instead.
+pub struct IndexReconciler<'a> {
+  previous: Option<&'a LibraryIndex>,
+  next: LibraryIndex,
+  read_directories: usize,
+  reused_roms: usize,
+}
+
+impl<'a> IndexReconciler<'a> {
+  /// Creates a new IndexReconciler.
+  pub fn new(previous: &'a LibraryIndex, library: &Library, parsers: &HashMap<String, Parser>) -> IndexReconciler<'a> {
+    // * Paths are indexed absolutely, so nothing can be reused once the library moves.
+    let is_same_library = previous.library.as_ref() == Some(library);
+
+    return IndexReconciler {
+      previous: if is_same_library { Some(previous) } else { None },
+      next: LibraryIndex {
+        version: INDEX_VERSION,
+        library: Some(library.clone()),
+        parsers: parsers.clone(),
+        directories: HashMap::new(),
+        roms: HashMap::new(),
+      },
+      read_directories: 0,
+      reused_roms: 0,
+    };
+  }
+
+  /// Lists a directory's entries, only reading it if its modified time changed since the last load.
+  /// Returns the entries, and whether they were re-read.
+  pub fn list_dir(&mut self, path: &Path) -> Result<(IndexedDirectory, bool), std::io::Error> {
+    let path_str = path.to_str().unwrap_or_default().to_string();
+    let metadata = fs::metadata(path)?;
+    let modified = get_modified_millis(&metadata);
+
+    let cached = self.previous.and_then(|previous| previous.directories.get(&path_str));
+    if cached.is_some_and(|cached| cached.modified != 0 && cached.modified == modified) {
+      let directory = cached.unwrap().clone();
+      self.next.directories.insert(path_str, directory.clone());
+
+      return Ok((directory, false));
+    }
+
+    let mut directory = IndexedDirectory {
+      modified,
+      files: vec![],
+      directories: vec![],
+    };
+
+    for entry_res in read_dir(path)? {
+      if entry_res.is_err() {
+        continue;
+      }
+      let entry = entry_res.unwrap();
+
+      let name_res = entry.file_name().into_string();
+      let file_type_res = entry.file_type();
+      if name_res.is_err() || file_type_res.is_err() {
+        continue;
+      }
+
+      if file_type_res.unwrap().is_dir() {
+        directory.directories.push(name_res.unwrap());
+      } else {
+        directory.files.push(name_res.unwrap());
+      }
+    }
+
+    directory.files.sort();
+    directory.directories.sort();
+    self.read_directories += 1;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_millis() as i64).unwrap_or(0);
+    let mut indexed = directory.clone();
+    if now - modified < SETTLE_TIME_MILLIS {
+      indexed.modified = 0;
+    }
+    self.next.directories.insert(path_str, indexed);
+
+    return Ok((directory, true));
+  }
+
+  /// Recursively lists a folder's files and directories.
+  pub fn walk(&mut self, root: &Path) -> Vec<IndexedEntry> {
+    let mut entries = vec![];
+    let mut pending = vec![PathBuf::new()];
+
+    while let Some(relative_dir) = pending.pop() {
+      let dir = root.join(&relative_dir);
+
+      let listing_res = self.list_dir(&dir);
+      if listing_res.is_err() {
+        warn!("Library Index: Failed to read directory \"{}\": {}", dir.display(), listing_res.err().unwrap());
+        continue;
+      }
+      let (directory, changed) = listing_res.unwrap();
+
+      // * Sidecar folders hold metadata, not ROMs.
+      for name in directory.directories.into_iter().filter(|name| name != SIDECAR_DIR) {
+        let relative_path = relative_dir.join(&name);
+
+        entries.push(IndexedEntry {
+          path: root.join(&relative_path),
+          relative_path: relative_path.clone(),
+          changed,
+        });
+        pending.push(relative_path);
+      }
+
+      for name in directory.files {
+        let relative_path = relative_dir.join(&name);
+
+        entries.push(IndexedEntry {
+          path: root.join(&relative_path),
+          relative_path,
+          changed,
+        });
+      }
+    }
+
+    return entries;
+  }
+
+  /// Gets the indexed ROM for an entry, if its parser and file haven't changed since the last load.
+  pub fn get_rom(&mut self, entry: &IndexedEntry, parser: &Parser) -> Option<IndexedROM> {
+    let previous = self.previous?;
+    if previous.parsers.get(&parser.abbreviation) != Some(parser) {
+      return None;
+    }
+
+    let cached = previous.roms.get(entry.path.to_str()?)?;
+
+    // * The directory changed, so only reuse the ROM if its own file didn't.
+    if entry.changed {
+      let metadata = fs::metadata(&entry.path).ok()?;
+      if cached.rom.size != metadata.len() || cached.modified != get_modified(&metadata) {
+        return None;
+      }
+    }
+
+    self.reused_roms += 1;
+
+    return Some(cached.clone());
+  }
+
+  /// Adds a ROM to the new index.
+  pub fn insert_rom(&mut self, rom: IndexedROM) {
+    self.next.roms.insert(rom.rom.path.clone(), rom);
+  }
+
+  /// Gets the reconciled index.
+  pub fn finish(self) -> LibraryIndex {
+    info!(
+      "Library Index: Reconciled {} ROMs, reused {} and read {} of {} directories.",
+      self.next.roms.len(),
+      self.reused_roms,
+      self.read_directories,
+      self.next.directories.len()
+    );
+
+    return self.next;
+  }
+}
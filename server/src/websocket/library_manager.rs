@@ -1,79 +1,143 @@
-use std::{collections::HashMap, fs::{self, read_dir}, path::PathBuf};
+use std::{collections::{HashMap, HashSet}, fs::{self, read_dir}, path::{Path, PathBuf}, sync::{Arc, Mutex}, time::SystemTime};
 use chrono::{DateTime, Local};
 use log::warn;
 use regex::RegexBuilder;
+use tokio::sync::broadcast;
 use wax::{Glob, Pattern};
 
+use crate::job_queue::JobContext;
+
 use super::{parsers::load_parsers, types::{
   library::{
     Library, LoadResult, Parser, ParserPattern, StateStore, ROM
   },
   ErrorSender
 }};
-use super::watcher::Watcher;
+use super::{
+  collections::refresh_collections,
+  dats::{load_dats, verify_rom},
+  hasher::{get_modified, Hasher},
+  library_index::{write_index, IndexReconciler, IndexedEntry},
+  metadata::SIDECAR_DIR,
+  types::{dat::DatIndex, library::{BiosFiles, ExtraFiles, IndexedROM, LibraryIndex, RemovedROM, RenamedROM}, protocol::Event},
+  watcher::Watcher
+};
+
+/// Loads a ROM from its path. Returns None if it can't be read, ex: it was removed while it was being loaded.
+fn load_rom(parser: &Parser, pattern: &ParserPattern, hasher: &Hasher, dat: Option<&DatIndex>, path: PathBuf) -> Option<IndexedROM> {
+  let path_str_res = path.to_str();
+  if path_str_res.is_none() {
+    warn!("Load ROM: Skipping \"{}\", its path isn't valid UTF-8.", path.display());
+    return None;
+  }
+  let path_str = path_str_res.unwrap().to_string();
 
-fn load_rom(parser: &Parser, pattern: &ParserPattern, path: PathBuf) -> ROM {
-  let path_str = path.to_str().unwrap().to_string();
-  let metadata = fs::metadata(&path).expect("Failed to read ROM metadata");
+  let metadata_res = fs::metadata(&path);
+  if metadata_res.is_err() {
+    warn!("Load ROM: Skipping \"{}\", its metadata couldn't be read: {}", path_str, metadata_res.err().unwrap());
+    return None;
+  }
+  let metadata = metadata_res.unwrap();
 
-  let create_date: DateTime<Local> = DateTime::<Local>::from(metadata.created().unwrap());
-  let extension = path.extension().unwrap().to_str().unwrap();
+  // * Not every filesystem records when a file was created.
+  let created = metadata.created().or_else(|_| metadata.modified()).unwrap_or(SystemTime::now());
+  let create_date: DateTime<Local> = DateTime::<Local>::from(created);
+  let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
 
   let mut title = path_str.clone();
 
   let mut regex_builder = RegexBuilder::new(&pattern.regex);
   regex_builder.case_insensitive(true);
 
-  let regex = regex_builder.build().unwrap();
+  let regex_res = regex_builder.build();
+  if regex_res.is_err() {
+    warn!("Load ROM: Parser \"{}\" has an invalid regex: {}", parser.abbreviation, regex_res.as_ref().err().unwrap());
+  }
 
   let clean_title = title.replace("\\", "/");
-  let captures = regex.captures(&clean_title);
+  let captures = regex_res.as_ref().ok().and_then(|regex| regex.captures(&clean_title));
 
   if captures.is_some() {
     let results = captures.unwrap();
-    if results.len() > 0 {
+    if results.name("title").is_some() {
       title = results.name("title").unwrap().as_str().to_owned();
     }
   }
 
-  return ROM {
-    title,
-    path: path_str,
-    size: metadata.len(),
-    addDate: format!("{}", create_date.format("%b %e, %Y")),
-    format: extension.to_owned(),
-    system: parser.abbreviation.clone(),
-    systemFullName: parser.name.clone(),
-    downloadStrategy: pattern.downloadStrategy.clone(),
-  };
+  let hashes = hasher.get_hashes(&path, &metadata);
+  let verification = dat.zip(hashes.as_ref()).map(|(dat, hashes)| verify_rom(dat, &path, metadata.len(), hashes));
+
+  return Some(IndexedROM {
+    modified: get_modified(&metadata),
+    isDirectory: metadata.is_dir(),
+    rom: ROM {
+      title,
+      path: path_str,
+      size: metadata.len(),
+      addDate: format!("{}", create_date.format("%b %e, %Y")),
+      addedAt: create_date.timestamp(),
+      format: extension.to_owned(),
+      system: parser.abbreviation.clone(),
+      systemFullName: parser.name.clone(),
+      downloadStrategy: pattern.downloadStrategy.clone(),
+      hashes,
+      verification,
+    },
+  });
 }
 
-fn load_platform(parser: &Parser, path: PathBuf) -> Vec<ROM> {
+/// Refreshes the hashes and verification of a ROM loaded from the index, since they may have changed after it was indexed.
+fn refresh_rom(mut indexed: IndexedROM, hasher: &Hasher, dat: Option<&DatIndex>) -> IndexedROM {
+  if indexed.isDirectory {
+    return indexed;
+  }
+
+  let path = PathBuf::from(&indexed.rom.path);
+
+  let hashes = hasher.get_hashes_for(&path, indexed.rom.size, indexed.modified);
+  indexed.rom.verification = dat.zip(hashes.as_ref()).map(|(dat, hashes)| verify_rom(dat, &path, indexed.rom.size, hashes));
+  indexed.rom.hashes = hashes;
+
+  return indexed;
+}
+
+fn load_platform(parser: &Parser, hasher: &Hasher, dat: Option<&DatIndex>, reconciler: &mut IndexReconciler, path: PathBuf) -> Vec<ROM> {
   let mut roms: Vec<ROM> = Vec::new();
 
+  let entries: Vec<IndexedEntry> = reconciler.walk(&path);
+
   for pattern in &parser.patterns {
     let glob = Glob::new(&pattern.glob).unwrap();
-    for entry in glob.walk(&path) {
-      if entry.is_err() {
-        let err = entry.err().unwrap();
-        warn!("Library: Parser: \"{}\"; Failed to walk entry \"{}\"", &parser.abbreviation, err.path().unwrap().display());
-        continue;
-      }
 
-      let path = entry.unwrap().into_path();
+    for entry in entries.iter().filter(|entry| glob.is_match(entry.relative_path.as_path())) {
+      let cached = reconciler.get_rom(entry, parser);
+
+      let indexed = if cached.is_some() {
+        refresh_rom(cached.unwrap(), hasher, dat)
+      } else {
+        let loaded = load_rom(
+          parser,
+          pattern,
+          hasher,
+          dat,
+          entry.path.clone()
+        );
+
+        if loaded.is_none() {
+          continue;
+        }
+        loaded.unwrap()
+      };
 
-      roms.push(load_rom(
-        parser,
-        pattern,
-        path
-      ));
+      roms.push(indexed.rom.clone());
+      reconciler.insert_rom(indexed);
     }
   }
 
   return roms;
 }
 
-fn load_roms(library: &Library, watcher: &Watcher, parsers: &HashMap<String, Parser>, send_error: &ErrorSender) -> Result<Vec<ROM>, ()> {
+fn load_roms(library: &Library, hasher: &Hasher, parsers: &HashMap<String, Parser>, dats: &HashMap<String, DatIndex>, reconciler: &mut IndexReconciler, send_error: &ErrorSender, job: &JobContext) -> Result<Vec<ROM>, ()> {
   let mut roms: Vec<ROM> = vec![];
 
   let mut parsers_folder_map: HashMap<String, Parser> = HashMap::new();
@@ -84,9 +148,9 @@ fn load_roms(library: &Library, watcher: &Watcher, parsers: &HashMap<String, Par
 
   let roms_path = PathBuf::from(&library.libraryPath).join(&library.romDir);
 
-  let entries_res = read_dir(&roms_path);
-  if entries_res.is_err() {
-    let err = entries_res.err().unwrap();
+  let listing_res = reconciler.list_dir(&roms_path);
+  if listing_res.is_err() {
+    let err = listing_res.err().unwrap();
     
     send_error(
       format!("Failed to load library: {}", err.to_string()),
@@ -96,31 +160,28 @@ fn load_roms(library: &Library, watcher: &Watcher, parsers: &HashMap<String, Par
 
     return Err(());
   }
+  let (roms_dir, _) = listing_res.unwrap();
 
-  for dir_entry_res in entries_res.unwrap() {
-    if dir_entry_res.is_err() {
-      continue;
-    }
-    let dir_entry = dir_entry_res.unwrap();
-    let dir_name_os = dir_entry.file_name();
-    let dir_name = dir_name_os.to_str().unwrap().to_owned();
-
-    let dir_metadata_res = dir_entry.metadata();
-    if dir_metadata_res.is_err() {
-      let err = dir_metadata_res.err().unwrap();
-      warn!("Library: Can't read metadata of directory \"{}\": {}", dir_name, err.to_string());
-      continue;
+  let platform_dirs: Vec<String> = roms_dir.directories.into_iter()
+    .filter(|dir_name| parsers_folder_map.contains_key(dir_name))
+    .collect();
+  let platform_count = platform_dirs.len();
+
+  for (index, dir_name) in platform_dirs.into_iter().enumerate() {
+    // * Nothing is saved until every system is loaded, so stopping here leaves the library as it was.
+    if job.is_cancelled() {
+      return Err(());
     }
-    let dir_metadata = dir_metadata_res.unwrap();
 
-    if dir_metadata.is_dir() && parsers_folder_map.contains_key(&dir_name) {
+    {
       let parser = parsers_folder_map.get(&dir_name).unwrap();
+      let platform_path = roms_path.join(&dir_name);
 
-      let mut platform_roms = load_platform(parser, dir_entry.path());
+      job.report(Some(index as f64 / platform_count as f64), &format!("Loading {}...", parser.name));
+
+      let mut platform_roms = load_platform(parser, hasher, dats.get(&parser.abbreviation), reconciler, platform_path.clone());
 
       roms.append(&mut platform_roms);
-      
-      watcher.watch_path(dir_entry.path());
     }
   }
 
@@ -128,56 +189,29 @@ fn load_roms(library: &Library, watcher: &Watcher, parsers: &HashMap<String, Par
 }
 
 
-fn load_platform_extras(dict: &mut HashMap<String, Vec<String>>, path: PathBuf) {
-  let entries_res = read_dir(&path);
-  for dir_entry_res in entries_res.unwrap() {
-    if dir_entry_res.is_err() {
-      continue;
-    }
-    let dir_entry = dir_entry_res.unwrap();
-    let rom_id_os = dir_entry.file_name();
-    let rom_id = rom_id_os.to_str().unwrap().to_owned();
-
-    let dir_metadata_res = dir_entry.metadata();
-    if dir_metadata_res.is_err() {
-      let err = dir_metadata_res.err().unwrap();
-      warn!("Load Platform Extras: Can't read metadata of directory \"{}\": {}", rom_id, err.to_string());
+fn load_platform_extras(dict: &mut HashMap<String, Vec<String>>, reconciler: &mut IndexReconciler, path: PathBuf) {
+  let listing_res = reconciler.list_dir(&path);
+  if listing_res.is_err() {
+    warn!("Load Platform Extras: Can't read directory \"{}\": {}", path.display(), listing_res.err().unwrap());
+    return;
+  }
+  let (platform_dir, _) = listing_res.unwrap();
+
+  for rom_id in platform_dir.directories {
+    let extras_res = reconciler.list_dir(&path.join(&rom_id));
+    if extras_res.is_err() {
+      warn!("Load Platform Extras: Can't read directory \"{}\": {}", rom_id, extras_res.err().unwrap());
       continue;
     }
-    let dir_metadata = dir_metadata_res.unwrap();
-
-    if dir_metadata.is_dir() {
-      let extra_entries_res = read_dir(&dir_entry.path());
-      for extra_entry_res in extra_entries_res.unwrap() {
-        if extra_entry_res.is_err() {
-          continue;
-        }
-
-        let extra_entry = extra_entry_res.unwrap();
-        let extra_filename_os = extra_entry.file_name();
-        let extra_filename = extra_filename_os.to_str().unwrap();
-
-        let extra_metadata_res = extra_entry.metadata();
-        if extra_metadata_res.is_err() {
-          continue;
-        }
-        let extra_metadata = extra_metadata_res.unwrap();
+    let (extras_dir, _) = extras_res.unwrap();
 
-        if extra_metadata.is_file() {
-          if dict.contains_key(&rom_id) {
-            dict.get_mut(&rom_id).unwrap().push(extra_filename.to_string());
-          } else {
-            let extras = vec![extra_filename.to_string()];
-
-            dict.insert(rom_id.clone(), extras);
-          }
-        }
-      }
+    if !extras_dir.files.is_empty() {
+      dict.entry(rom_id).or_default().extend(extras_dir.files);
     }
   }
 }
 
-fn load_extra(path: PathBuf, send_error: &ErrorSender) -> Result<HashMap<String, Vec<String>>, ()> {
+fn load_extra(path: PathBuf, reconciler: &mut IndexReconciler, send_error: &ErrorSender) -> Result<HashMap<String, Vec<String>>, ()> {
   let mut dict: HashMap<String, Vec<String>> = HashMap::new();
 
   let exists_res = fs::exists(&path);
@@ -197,42 +231,37 @@ fn load_extra(path: PathBuf, send_error: &ErrorSender) -> Result<HashMap<String,
     return Ok(dict);
   }
 
-  let entries_res = read_dir(&path);
-  for dir_entry_res in entries_res.unwrap() {
-    if dir_entry_res.is_err() {
-      continue;
-    }
-    let dir_entry = dir_entry_res.unwrap();
-    let dir_name_os = dir_entry.file_name();
-    let dir_name = dir_name_os.to_str().unwrap().to_owned();
-
-    let dir_metadata_res = dir_entry.metadata();
-    if dir_metadata_res.is_err() {
-      let err = dir_metadata_res.err().unwrap();
-      warn!("Load Extras: Can't read metadata of directory \"{}\": {}", dir_name, err.to_string());
-      continue;
-    }
-    let dir_metadata = dir_metadata_res.unwrap();
+  let listing_res = reconciler.list_dir(&path);
+  if listing_res.is_err() {
+    let err = listing_res.err().unwrap();
+    
+    send_error(
+      format!("Failed to load extras: {}", err.to_string()),
+      String::from("Please double check that there your extras directory is readable."),
+      crate::websocket::types::BackendErrorType::WARN
+    );
 
-    if dir_metadata.is_dir() {
-      let rom_extras_path = dir_entry.path();
-      load_platform_extras(&mut dict, rom_extras_path);
-    }
+    return Err(());
+  }
+  let (extras_dir, _) = listing_res.unwrap();
+
+  for dir_name in extras_dir.directories {
+    load_platform_extras(&mut dict, reconciler, path.join(dir_name));
   }
 
   return Ok(dict);
 }
 
-fn load_extras(library: &Library, send_error: &ErrorSender) -> Result<(HashMap<String, Vec<String>>, HashMap<String, Vec<String>>), ()> {
+fn load_extras(library: &Library, reconciler: &mut IndexReconciler, send_error: &ErrorSender) -> Result<(HashMap<String, Vec<String>>, HashMap<String, Vec<String>>), ()> {
   let dlcs_path = PathBuf::from(&library.libraryPath).join(&library.dlcDir);
-  let dlcs_res = load_extra(dlcs_path, send_error);
+  let dlcs_res = load_extra(dlcs_path, reconciler, send_error);
   if dlcs_res.is_err() {
     return Err(());
   }
   let dlcs = dlcs_res.unwrap();
   
   let updates_path = PathBuf::from(&library.libraryPath).join(&library.updateDir);
-  let updates_res = load_extra(updates_path, send_error);
+  let updates_res = load_extra(updates_path, reconciler, send_error);
   if updates_res.is_err() {
     return Err(());
   }
@@ -241,7 +270,35 @@ fn load_extras(library: &Library, send_error: &ErrorSender) -> Result<(HashMap<S
   return Ok((dlcs, updates));
 }
 
-fn load_library(library: &Library, watcher: &Watcher, send_error: &ErrorSender) -> Result<(LoadResult, HashMap<String, Parser>), ()> {
+/// Loads the BIOS files present for each system, keyed by the system's abbreviation.
+fn load_bios_files(library: &Library, parsers: &HashMap<String, Parser>, reconciler: &mut IndexReconciler) -> HashMap<String, Vec<String>> {
+  let mut bios: HashMap<String, Vec<String>> = HashMap::new();
+
+  let bios_path = PathBuf::from(&library.libraryPath).join(&library.biosDir);
+
+  for parser in parsers.values() {
+    let system_bios_path = bios_path.join(&parser.folder);
+    if !system_bios_path.is_dir() {
+      continue;
+    }
+
+    let listing_res = reconciler.list_dir(&system_bios_path);
+    if listing_res.is_err() {
+      warn!("Load BIOS Files: Can't read directory \"{}\": {}", system_bios_path.display(), listing_res.err().unwrap());
+      continue;
+    }
+    let (bios_dir, _) = listing_res.unwrap();
+
+    bios.insert(parser.abbreviation.clone(), bios_dir.files);
+  }
+
+  return bios;
+}
+
+/// A loaded library, along with the parsers, DATs and index it was loaded with.
+type LoadedLibrary = (LoadResult, HashMap<String, Parser>, HashMap<String, DatIndex>, LibraryIndex);
+
+fn load_library(library: &Library, watcher: &Watcher, hasher: &Hasher, index: &LibraryIndex, previous_dats: &HashMap<String, DatIndex>, send_error: &ErrorSender, job: &JobContext) -> Result<LoadedLibrary, ()> {
   let parsers_res = load_parsers(library, send_error);
   if parsers_res.is_err() {
     return Err(());
@@ -250,52 +307,93 @@ fn load_library(library: &Library, watcher: &Watcher, send_error: &ErrorSender)
 
   let systems: Vec<Parser> = parsers.clone().into_values().collect();
 
-  let roms_res = load_roms(library, watcher, &parsers, &send_error);
-  if roms_res.is_err() {
-    return Err(());
-  }
-  
-  let roms_res = load_roms(library, watcher, &parsers, &send_error);
+  let dats = load_dats(&parsers, previous_dats, send_error);
+
+  let mut reconciler = IndexReconciler::new(index, library, &parsers);
+
+  let roms_res = load_roms(library, hasher, &parsers, &dats, &mut reconciler, &send_error, job);
   if roms_res.is_err() {
     return Err(());
   }
   
-  let extras_res = load_extras(library, send_error);
+  let extras_res = load_extras(library, &mut reconciler, send_error);
   if extras_res.is_err() {
     return Err(());
   }
   let extras = extras_res.unwrap();
 
+  let bios = load_bios_files(library, &parsers, &mut reconciler);
+
+  let library_path = PathBuf::from(&library.libraryPath);
+  let platform_paths = parsers.values().map(|parser| library_path.join(&library.romDir).join(&parser.folder));
+  let extras_paths = [&library.dlcDir, &library.updateDir, &library.biosDir].map(|extras_dir| library_path.join(extras_dir));
+
+  for watched_path in platform_paths.chain(extras_paths) {
+    if watched_path.is_dir() {
+      watcher.watch_path(watched_path);
+    }
+  }
+
   return Ok((
     LoadResult {
       library: library.to_owned(),
       roms: roms_res.unwrap(),
       systems,
       dlcs: extras.0,
-      updates: extras.1
+      updates: extras.1,
+      bios
     },
-    parsers
+    parsers,
+    dats,
+    reconciler.finish()
   ));
 }
 
-/// Parses the app's library
-pub fn parse_library(library: &Library, watcher: &Watcher, sate_store: &mut StateStore, send_error: ErrorSender) -> Result<LoadResult, ()> {
-  let load_res = load_library(library, watcher, &send_error);
+/// Parses the app's library, and stores the result in the state.
+/// The state is only locked to copy the previous index and to store the result, so the library stays usable while it's parsed.
+pub fn parse_library(
+  library: &Library,
+  watcher: &Watcher,
+  hasher: &Hasher,
+  state_store: &Arc<Mutex<StateStore>>,
+  tx: &broadcast::Sender<String>,
+  send_error: ErrorSender,
+  job: &JobContext
+) -> Result<LoadResult, ()> {
+  let state = state_store.lock().expect("Failed to lock State Mutex.");
+  let previous_index = state.index.clone();
+  let previous_dats = state.dats.clone();
+  drop(state);
+
+  let load_res = load_library(library, watcher, hasher, &previous_index, &previous_dats, &send_error, job);
 
   if load_res.is_err() {
     return Err(());
   }
 
-  let (loaded_library, parsers) = load_res.unwrap();
-  (*sate_store).parsers = parsers.to_owned();
+  let (loaded_library, parsers, dats, index) = load_res.unwrap();
+  write_index(&index);
+
+  // * Every ROM was just found, so the hashes of anything else can go.
+  let rom_paths: HashSet<&str> = loaded_library.roms.iter().map(|rom| rom.path.as_str()).collect();
+  hasher.prune(&rom_paths);
+
+  let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+  (*state).library = library.clone();
+  (*state).parsers = parsers.to_owned();
+  (*state).dats = dats;
+  (*state).roms = loaded_library.roms.clone();
+  (*state).dlcs = loaded_library.dlcs.clone();
+  (*state).updates = loaded_library.updates.clone();
+  (*state).bios = loaded_library.bios.clone();
+  (*state).index = index;
+  refresh_collections(&mut state, tx);
 
   return Ok(loaded_library);
 }
 
-/// Parses a ROM's data from its path.
-pub fn parse_added_rom(parser_name: String, rom_path: &str, state_store: &StateStore, send_error: ErrorSender) -> Result<ROM, ()> {
-  let parser = state_store.parsers.get(&parser_name).expect("System abbreviation was missing from parser map");
-
+/// Parses a ROM's data from its path. The parser and DAT are copied from the state, so it isn't locked while the ROM is read.
+pub fn parse_added_rom(parser: &Parser, dat: Option<&DatIndex>, rom_path: &str, hasher: &Hasher, send_error: ErrorSender) -> Result<ROM, ()> {
   let path = PathBuf::from(rom_path);
 
   if path.is_dir() {
@@ -311,34 +409,356 @@ pub fn parse_added_rom(parser_name: String, rom_path: &str, state_store: &StateS
   
         let path = entry.unwrap().into_path();
   
-        return Ok(load_rom(
+        return load_rom(
           parser,
           pattern,
+          hasher,
+          dat,
           path
-        ));
+        ).map(|indexed| indexed.rom).ok_or(());
       }
     }
   } else {
-    let filename = path.file_name().unwrap().to_str().unwrap();
+    let filename = path.file_name().and_then(|filename| filename.to_str()).unwrap_or_default();
 
     for pattern in &parser.patterns {
       let glob = Glob::new(&pattern.glob).unwrap();
   
       if glob.is_match(filename) {
-        return Ok(load_rom(
+        return load_rom(
           parser,
           pattern,
+          hasher,
+          dat,
           path
-        ));
+        ).map(|indexed| indexed.rom).ok_or(());
       }
     }
   }
 
   send_error(
-    format!("Adding ROM: ROM should have matched on of the parsers for system \"{}\".", parser_name),
-    format!("Please double check that there is a parser for system \"{}\".", parser_name),
+    format!("Adding ROM: ROM should have matched on of the parsers for system \"{}\".", parser.abbreviation),
+    format!("Please double check that there is a parser for system \"{}\".", parser.abbreviation),
     crate::websocket::types::BackendErrorType::WARN
   );
 
   return Err(());
-}
\ No newline at end of file
+}
+
+/// Finds the system and pattern a path in the ROMs folder belongs to, along with the path of the ROM it's part of.
+fn match_rom_path(path: &Path, state_store: &StateStore) -> Option<(Parser, ParserPattern, PathBuf)> {
+  let roms_path = PathBuf::from(&state_store.library.libraryPath).join(&state_store.library.romDir);
+  let relative_path = path.strip_prefix(&roms_path).ok()?;
+
+  let folder = relative_path.components().next()?.as_os_str().to_str()?;
+  let parser = state_store.parsers.values().find(|parser| parser.folder == folder)?;
+
+  let platform_path = roms_path.join(folder);
+  let platform_relative_path = path.strip_prefix(&platform_path).ok()?;
+  if platform_relative_path.components().any(|component| component.as_os_str() == SIDECAR_DIR) {
+    return None;
+  }
+
+  // * Changes inside a folder ROM belong to the folder, so check the outermost parents first.
+  let mut candidates: Vec<&Path> = platform_relative_path.ancestors()
+    .filter(|candidate| !candidate.as_os_str().is_empty())
+    .collect();
+  candidates.reverse();
+
+  for candidate in candidates {
+    for pattern in &parser.patterns {
+      let glob_res = Glob::new(&pattern.glob);
+
+      if glob_res.is_ok_and(|glob| glob.is_match(candidate)) {
+        return Some((parser.clone(), pattern.clone(), platform_path.join(candidate)));
+      }
+    }
+  }
+
+  return None;
+}
+
+/// Adds a ROM to the state, replacing it if it was already loaded. Returns nothing if the ROM didn't change.
+fn upsert_rom(indexed: IndexedROM, state_store: &mut StateStore) -> Option<Event> {
+  let rom = indexed.rom.clone();
+  state_store.index.roms.insert(rom.path.clone(), indexed);
+
+  let existing = state_store.roms.iter().position(|loaded| loaded.path == rom.path);
+  if existing.is_some() {
+    if state_store.roms[existing.unwrap()] == rom {
+      return None;
+    }
+
+    state_store.roms[existing.unwrap()] = rom.clone();
+    return Some(Event::RomModified(rom));
+  }
+
+  state_store.roms.push(rom.clone());
+  return Some(Event::RomAdded(rom));
+}
+
+/// Updates the state after a path in the library was modified.
+pub fn update_rom_path(path: &Path, hasher: &Hasher, state_store: &mut StateStore) -> Vec<Event> {
+  let roms_path = PathBuf::from(&state_store.library.libraryPath).join(&state_store.library.romDir);
+  if state_store.library.libraryPath.is_empty() || !path.starts_with(&roms_path) || !path.exists() {
+    return vec![];
+  }
+
+  let matched = match_rom_path(path, state_store);
+  if matched.is_none() {
+    return vec![];
+  }
+
+  let (parser, pattern, rom_path) = matched.unwrap();
+  let indexed = load_rom(&parser, &pattern, hasher, state_store.dats.get(&parser.abbreviation), rom_path);
+  if indexed.is_none() {
+    return vec![];
+  }
+
+  return upsert_rom(indexed.unwrap(), state_store).into_iter().collect();
+}
+
+/// Updates the state after a path in the library was created or moved in.
+pub fn add_rom_path(path: &Path, hasher: &Hasher, state_store: &mut StateStore) -> Vec<Event> {
+  if match_rom_path(path, state_store).is_some() || !path.is_dir() {
+    return update_rom_path(path, hasher, state_store);
+  }
+
+  // * A folder that was created or moved in may already have ROMs in it.
+  let mut events = vec![];
+
+  let entries_res = read_dir(path);
+  if entries_res.is_ok() {
+    for entry in entries_res.unwrap().flatten() {
+      events.append(&mut add_rom_path(&entry.path(), hasher, state_store));
+    }
+  }
+
+  return events;
+}
+
+/// Updates the state after a path in the library was removed.
+pub fn remove_rom_path(path: &Path, hasher: &Hasher, state_store: &mut StateStore) -> Vec<Event> {
+  let removed_paths: Vec<String> = state_store.roms.iter()
+    .filter(|rom| Path::new(&rom.path).starts_with(path))
+    .map(|rom| rom.path.clone())
+    .collect();
+
+  if removed_paths.is_empty() {
+    // * Removing a file from a folder ROM changes the folder.
+    let matched = match_rom_path(path, state_store);
+    if matched.as_ref().is_some_and(|(_, _, rom_path)| rom_path != path) {
+      return update_rom_path(&matched.unwrap().2, hasher, state_store);
+    }
+
+    return vec![];
+  }
+
+  state_store.roms.retain(|rom| !removed_paths.contains(&rom.path));
+  for removed_path in removed_paths.iter() {
+    state_store.index.roms.remove(removed_path);
+    hasher.forget(Path::new(removed_path));
+  }
+
+  return removed_paths.into_iter()
+    .map(|path| Event::RomRemoved(RemovedROM { path }))
+    .collect();
+}
+
+/// Updates the state after a path in the library was renamed or moved.
+pub fn rename_rom_path(from: &Path, to: &Path, hasher: &Hasher, state_store: &mut StateStore) -> Vec<Event> {
+  let renamed_paths: Vec<String> = state_store.roms.iter()
+    .filter(|rom| Path::new(&rom.path).starts_with(from))
+    .map(|rom| rom.path.clone())
+    .collect();
+
+  // * Nothing was loaded from the old path, so this is the same as the new path being created (ex: a finished download being renamed).
+  if renamed_paths.is_empty() {
+    let events = add_rom_path(to, hasher, state_store);
+    if events.is_empty() {
+      return remove_rom_path(from, hasher, state_store);
+    }
+
+    return events;
+  }
+
+  let mut events = vec![];
+
+  for old_path in renamed_paths {
+    state_store.roms.retain(|rom| rom.path != old_path);
+    state_store.index.roms.remove(&old_path);
+    hasher.forget(Path::new(&old_path));
+
+    let relative_path = Path::new(&old_path).strip_prefix(from).unwrap();
+    let new_path = if relative_path.as_os_str().is_empty() { to.to_path_buf() } else { to.join(relative_path) };
+
+    // * The ROM may have been moved somewhere its system's patterns don't match.
+    let matched = match_rom_path(&new_path, state_store);
+    if matched.as_ref().is_none_or(|(_, _, rom_path)| rom_path != &new_path) || !new_path.exists() {
+      events.push(Event::RomRemoved(RemovedROM { path: old_path }));
+      continue;
+    }
+
+    let (parser, pattern, rom_path) = matched.unwrap();
+    let indexed_res = load_rom(&parser, &pattern, hasher, state_store.dats.get(&parser.abbreviation), rom_path);
+    if indexed_res.is_none() {
+      events.push(Event::RomRemoved(RemovedROM { path: old_path }));
+      continue;
+    }
+
+    let indexed = indexed_res.unwrap();
+    let rom = indexed.rom.clone();
+
+    state_store.index.roms.insert(rom.path.clone(), indexed);
+    state_store.roms.push(rom.clone());
+
+    events.push(Event::RomRenamed(RenamedROM {
+      oldPath: old_path,
+      rom,
+    }));
+  }
+
+  return events;
+}
+
+/// Lists the files in a directory, or nothing if it doesn't exist.
+fn list_files(path: &Path) -> Vec<String> {
+  let entries_res = read_dir(path);
+  if entries_res.is_err() {
+    return vec![];
+  }
+
+  let mut files: Vec<String> = entries_res.unwrap()
+    .flatten()
+    .filter(|entry| entry.file_type().is_ok_and(|file_type| !file_type.is_dir()))
+    .filter_map(|entry| entry.file_name().into_string().ok())
+    .collect();
+  files.sort();
+
+  return files;
+}
+
+/// Lists every ROM's extras in the DLC or update folder.
+fn list_extra_files(extras_path: &Path) -> HashMap<String, Vec<String>> {
+  let mut extras: HashMap<String, Vec<String>> = HashMap::new();
+
+  let systems_res = read_dir(extras_path);
+  if systems_res.is_err() {
+    return extras;
+  }
+
+  for system_entry in systems_res.unwrap().flatten() {
+    let roms_res = read_dir(system_entry.path());
+    if roms_res.is_err() {
+      continue;
+    }
+
+    for rom_entry in roms_res.unwrap().flatten() {
+      let rom_id_res = rom_entry.file_name().into_string();
+      let files = list_files(&rom_entry.path());
+
+      if rom_id_res.is_ok() && !files.is_empty() {
+        extras.entry(rom_id_res.unwrap()).or_default().extend(files);
+      }
+    }
+  }
+
+  return extras;
+}
+
+/// Updates the ROM extras affected by a path in the DLC or update folder changing.
+fn update_extra_files(extras_path: &Path, path: &Path, extras: &mut HashMap<String, Vec<String>>) -> Vec<ExtraFiles> {
+  let relative_path_res = path.strip_prefix(extras_path);
+  if relative_path_res.is_err() {
+    return vec![];
+  }
+
+  // * Extras are stored as <system>/<rom id>/<file>.
+  let components: Vec<&str> = relative_path_res.unwrap().components()
+    .filter_map(|component| component.as_os_str().to_str())
+    .collect();
+
+  if components.len() >= 2 {
+    let rom_id = components[1].to_string();
+
+    let files = list_files(&extras_path.join(components[0]).join(&rom_id));
+    if extras.get(&rom_id).map_or(files.is_empty(), |existing| existing == &files) {
+      return vec![];
+    }
+
+    if files.is_empty() {
+      extras.remove(&rom_id);
+    } else {
+      extras.insert(rom_id.clone(), files.clone());
+    }
+
+    return vec![ExtraFiles {
+      romId: rom_id,
+      files,
+    }];
+  }
+
+  // * A whole system folder changed, and we don't know which ROMs were in it, so compare everything.
+  let reloaded = list_extra_files(extras_path);
+  let mut changed: Vec<ExtraFiles> = reloaded.iter()
+    .filter(|(rom_id, files)| extras.get(*rom_id) != Some(*files))
+    .map(|(rom_id, files)| ExtraFiles { romId: rom_id.clone(), files: files.clone() })
+    .collect();
+
+  for rom_id in extras.keys().filter(|rom_id| !reloaded.contains_key(*rom_id)) {
+    changed.push(ExtraFiles {
+      romId: rom_id.clone(),
+      files: vec![],
+    });
+  }
+
+  *extras = reloaded;
+
+  return changed;
+}
+
+/// Updates the state after a path in the DLC, update or BIOS folders changed.
+pub fn update_extra_path(path: &Path, state_store: &mut StateStore) -> Vec<Event> {
+  if state_store.library.libraryPath.is_empty() {
+    return vec![];
+  }
+
+  let library_path = PathBuf::from(&state_store.library.libraryPath);
+
+  let dlcs_path = library_path.join(&state_store.library.dlcDir);
+  if path.starts_with(&dlcs_path) {
+    return update_extra_files(&dlcs_path, path, &mut state_store.dlcs).into_iter()
+      .map(Event::DlcsChanged)
+      .collect();
+  }
+
+  let updates_path = library_path.join(&state_store.library.updateDir);
+  if path.starts_with(&updates_path) {
+    return update_extra_files(&updates_path, path, &mut state_store.updates).into_iter()
+      .map(Event::UpdatesChanged)
+      .collect();
+  }
+
+  let bios_path = library_path.join(&state_store.library.biosDir);
+  let folder = path.strip_prefix(&bios_path).ok()
+    .and_then(|relative_path| relative_path.components().next())
+    .and_then(|component| component.as_os_str().to_str());
+
+  let parser = folder.and_then(|folder| state_store.parsers.values().find(|parser| parser.folder == folder));
+  if parser.is_none() {
+    return vec![];
+  }
+
+  let system = parser.unwrap().abbreviation.clone();
+  let files = list_files(&bios_path.join(folder.unwrap()));
+  if state_store.bios.get(&system) == Some(&files) {
+    return vec![];
+  }
+
+  state_store.bios.insert(system.clone(), files.clone());
+
+  return vec![Event::BiosFilesChanged(BiosFiles {
+    system,
+    files,
+  })];
+}
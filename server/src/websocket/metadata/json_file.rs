@@ -1,8 +1,8 @@
-use std::{collections::HashMap, env::var, fs::{self, File}, path::PathBuf};
+use std::{collections::HashMap, env::var, fs, path::PathBuf};
 
-use crate::websocket::types::ErrorSender;
+use crate::websocket::{config_files::{get_backup_dir, read_with_recovery, write_atomic, write_with_backup}, types::ErrorSender};
 
-use super::types::library::ROMMetadata;
+use crate::websocket::types::library::ROMMetadata;
 
 /// Checks if the app's metadata.json file exist and writes the default if they don't.
 fn write_default_if_missing(config_path: &PathBuf, metadata_path: &PathBuf, default_metadata: &HashMap<String, ROMMetadata>, send_error: &ErrorSender) -> bool {
@@ -24,7 +24,7 @@ fn write_default_if_missing(config_path: &PathBuf, metadata_path: &PathBuf, defa
   if !metadata_exists.ok().unwrap() {
     let metadata_str = serde_json::to_string_pretty(default_metadata).expect("Default metadata was malformatted.");
     
-    let write_res = fs::write(metadata_path, &metadata_str);
+    let write_res = write_atomic(metadata_path, &metadata_str);
 
     if write_res.is_err() {
       let err = write_res.err().unwrap();
@@ -42,61 +42,56 @@ fn write_default_if_missing(config_path: &PathBuf, metadata_path: &PathBuf, defa
   return true;
 }
 
-/// Loads the app's metadata from the file system.
-pub fn load_metadata(send_error: ErrorSender) -> Result<HashMap<String, ROMMetadata>, ()> {
+/// Loads the app's metadata from metadata.json.
+pub fn load(send_error: &ErrorSender) -> Result<HashMap<String, ROMMetadata>, ()> {
   let default_metadata = HashMap::new();
   
   let config_path = PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap());
   let metadata_path = config_path.join("metadata.json");
 
-  let metadata_exist = write_default_if_missing(&config_path, &metadata_path, &default_metadata, &send_error);
+  let metadata_exist = write_default_if_missing(&config_path, &metadata_path, &default_metadata, send_error);
   if !metadata_exist {
     return Ok(default_metadata);
   }
 
-  let metadata_file_res = File::open(&metadata_path);
-  if metadata_file_res.is_err() {
-    let err = metadata_file_res.err().unwrap();
+  let saved_metadata_res = read_with_recovery(&metadata_path, &get_backup_dir(""));
+  if saved_metadata_res.is_err() {
+    let err = saved_metadata_res.err().unwrap();
     
     send_error(
-      format!("Failed to read metadata.json: {}", err.to_string()),
-      "Please ensure NRM has write access to the mounted \"/config\" directory.".to_string(),
+      format!("Failed to load metadata.json: {}", err),
+      "Please ensure your metadata.json follows the proper JSON format listed in the docs, or restore it from the \"backups\" folder in \"/config\".".to_string(),
       crate::websocket::types::BackendErrorType::PANIC
     );
     return Err(());
   }
 
-  let saved_metadata_res = serde_json::from_reader(metadata_file_res.ok().unwrap());
-  if saved_metadata_res.is_err() {
-    let err = saved_metadata_res.err().unwrap();
-    
+  let (saved_metadata, backup) = saved_metadata_res.unwrap();
+  if backup.is_some() {
     send_error(
-      format!("Failed to parse metadata.json: {}", err.to_string()),
-      "Please ensure your metadata.json follows the proper JSON format listed in the docs.".to_string(),
-      crate::websocket::types::BackendErrorType::PANIC
+      format!("metadata.json was corrupted, so it was restored from the backup \"{}\".", backup.unwrap().display()),
+      "Please double check your ROMs' metadata, since recent changes may have been lost.".to_string(),
+      crate::websocket::types::BackendErrorType::WARN
     );
-    return Err(());
   }
 
-  let saved_metadata = saved_metadata_res.unwrap();
-
   // check_metadata(&mut saved_metadata, &default_metadata);
 
   return Ok(saved_metadata);
 }
 
-/// Writes all the provided metadata to the file system.
-pub fn write_metadata(metadata: &HashMap<String, ROMMetadata>, send_error: ErrorSender) -> bool {
+/// Writes all the provided metadata to metadata.json.
+pub fn write(metadata: &HashMap<String, ROMMetadata>, send_error: &ErrorSender) -> bool {
   let default_metadata = HashMap::new();
   
   let config_path = PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap());
   let metadata_path = config_path.join("metadata.json");
 
-  let _ = write_default_if_missing(&config_path, &metadata_path, &default_metadata, &send_error);
+  let _ = write_default_if_missing(&config_path, &metadata_path, &default_metadata, send_error);
 
   let metadata_str = serde_json::to_string_pretty(&metadata).expect("metadata were malformatted.");
     
-  let write_res = fs::write(metadata_path, &metadata_str);
+  let write_res = write_with_backup(&metadata_path, &get_backup_dir(""), &metadata_str);
   if write_res.is_err() {
     let err = write_res.err().unwrap();
     
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use super::types::{library::{ROMMetadata, ROM}, settings::Settings, BackendErrorType, ErrorSender};
+
+mod json_file;
+mod sidecars;
+
+pub use sidecars::SIDECAR_DIR;
+
+/// Where ROM metadata is saved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum MetadataStore {
+  /// A single metadata.json in the config directory.
+  JSON_FILE,
+  /// A sidecar file per ROM, with its artwork, next to the ROM in the library.
+  SIDECARS,
+}
+
+impl MetadataStore {
+  /// Gets the store the settings save metadata to.
+  pub fn from_settings(settings: &Settings) -> MetadataStore {
+    if settings.metadata.saveAlongsideROMs {
+      return MetadataStore::SIDECARS;
+    }
+
+    return MetadataStore::JSON_FILE;
+  }
+
+  /// Loads all the metadata saved in this store.
+  pub fn load(&self, settings: &Settings, send_error: &ErrorSender) -> Result<HashMap<String, ROMMetadata>, ()> {
+    return match self {
+      MetadataStore::JSON_FILE => json_file::load(send_error),
+      MetadataStore::SIDECARS => sidecars::load(&settings.library, send_error),
+    };
+  }
+
+  /// Saves the metadata to this store. The previously saved metadata is used to only update what changed, where possible.
+  pub fn write(&self, metadata: &HashMap<String, ROMMetadata>, previous: &HashMap<String, ROMMetadata>, roms: &[ROM], send_error: &ErrorSender) -> bool {
+    return match self {
+      MetadataStore::JSON_FILE => json_file::write(metadata, send_error),
+      MetadataStore::SIDECARS => sidecars::write(metadata, previous, roms, send_error),
+    };
+  }
+}
+
+/// Loads the app's metadata from the store the settings use.
+pub fn load_metadata(settings: &Settings, send_error: ErrorSender) -> Result<HashMap<String, ROMMetadata>, ()> {
+  return MetadataStore::from_settings(settings).load(settings, &send_error);
+}
+
+/// Writes the provided metadata to the store the settings use.
+pub fn write_metadata(settings: &Settings, metadata: &HashMap<String, ROMMetadata>, previous: &HashMap<String, ROMMetadata>, roms: &[ROM], send_error: ErrorSender) -> bool {
+  return MetadataStore::from_settings(settings).write(metadata, previous, roms, &send_error);
+}
+
+/// Moves the metadata from the store the old settings use to the one the new settings use, and returns the migrated metadata.
+/// metadata.json is left in place when moving to sidecars, but sidecars are removed when moving back to metadata.json.
+pub fn migrate_metadata(from: &Settings, to: &Settings, roms: &[ROM], send_error: &ErrorSender) -> Result<HashMap<String, ROMMetadata>, ()> {
+  let from_store = MetadataStore::from_settings(from);
+  let to_store = MetadataStore::from_settings(to);
+  let metadata = from_store.load(from, send_error)?;
+
+  // * Sidecars are written next to the ROMs, so they need to be loaded to know where each one goes.
+  if to_store == MetadataStore::SIDECARS && !metadata.is_empty() && roms.is_empty() {
+    send_error(
+      "Can't save metadata alongside ROMs before the library has been loaded.".to_string(),
+      "Please wait for your library to finish loading, then try again.".to_string(),
+      BackendErrorType::WARN
+    );
+    return Err(());
+  }
+
+  let success = to_store.write(&metadata, &HashMap::new(), roms, send_error);
+  if !success {
+    return Err(());
+  }
+
+  if from_store == MetadataStore::SIDECARS {
+    // ? The metadata was already moved, so leftover sidecars are reported but don't fail the migration.
+    let _ = sidecars::clear(&from.library, send_error);
+  }
+
+  return to_store.load(to, send_error);
+}
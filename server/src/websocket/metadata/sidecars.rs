@@ -0,0 +1,333 @@
+use std::{collections::HashMap, env::var, ffi::OsStr, fs::{self, read_dir, File}, io, path::{Path, PathBuf}};
+
+use log::warn;
+
+use crate::websocket::{config_files::write_atomic, types::{library::{Library, ROMMetadata, ROMSidecar, ROM}, BackendErrorType, ErrorSender}, utils::{get_rom_base, hash64}};
+
+/// The name of the hidden folder that sidecars are saved in, next to the ROMs they belong to.
+pub const SIDECAR_DIR: &str = ".nrm";
+
+/// Gets the paths of a ROM's sidecar file and artwork folder.
+fn get_sidecar_paths(rom: &ROM) -> Option<(PathBuf, PathBuf)> {
+  let base = get_rom_base(rom);
+  let name = base.file_name()?.to_str()?;
+  let sidecar_dir = base.parent()?.join(SIDECAR_DIR);
+
+  return Some((sidecar_dir.join(format!("{}.json", name)), sidecar_dir.join(name)));
+}
+
+/// Copies a piece of artwork from the grids cache to a ROM's artwork folder, and returns its path relative to that folder.
+/// Paths that don't point to the cache are kept as is.
+fn copy_artwork_to_sidecar(cache_dir: &Path, cache_path: &str, artwork_dir: &Path) -> Result<String, io::Error> {
+  let relative_path = cache_path.trim_start_matches("/");
+  let source = cache_dir.join(relative_path);
+  if cache_path.is_empty() || !source.is_file() {
+    return Ok(cache_path.to_string());
+  }
+
+  // * The first folder in the cache is the type of the artwork, ex: full, thumb or hero.
+  let kind = Path::new(relative_path).components().next().unwrap().as_os_str().to_string_lossy().to_string();
+  let artwork_name = match source.extension() {
+    Some(extension) => format!("{}.{}", kind, extension.to_string_lossy()),
+    None => kind,
+  };
+
+  fs::create_dir_all(artwork_dir)?;
+  fs::copy(&source, artwork_dir.join(&artwork_name))?;
+
+  return Ok(artwork_name);
+}
+
+/// Copies a piece of artwork from a ROM's artwork folder to the grids cache, and returns its path in the cache.
+/// Artwork that's already cached isn't copied again, and paths outside the artwork folder are kept as is.
+fn copy_artwork_to_cache(cache_dir: &Path, sidecar_path: &str, artwork_dir: &Path, rom_id: &str) -> String {
+  if sidecar_path.is_empty() || sidecar_path.contains("/") {
+    return sidecar_path.to_string();
+  }
+
+  let source = artwork_dir.join(sidecar_path);
+  let artwork_name = Path::new(sidecar_path);
+  let kind = artwork_name.file_stem().unwrap_or_default().to_string_lossy().to_string();
+  let cache_name = match artwork_name.extension() {
+    Some(extension) => format!("{}.{}", rom_id, extension.to_string_lossy()),
+    None => rom_id.to_string(),
+  };
+
+  let destination = cache_dir.join(&kind).join(&cache_name);
+
+  let source_len_res = fs::metadata(&source).map(|metadata| metadata.len());
+  if source_len_res.is_err() {
+    warn!("Metadata Sidecars: Missing artwork \"{}\"", source.display());
+    return String::new();
+  }
+  let source_len = source_len_res.unwrap();
+
+  let destination_len = fs::metadata(&destination).map(|metadata| metadata.len()).ok();
+  if destination_len != Some(source_len) {
+    let copy_res = fs::create_dir_all(cache_dir.join(&kind)).and_then(|_| fs::copy(&source, &destination));
+    if copy_res.is_err() {
+      warn!("Metadata Sidecars: Failed to cache artwork \"{}\": {}", source.display(), copy_res.err().unwrap());
+      return String::new();
+    }
+  }
+
+  return format!("/{}/{}", kind, cache_name);
+}
+
+/// Writes a ROM's sidecar file, along with its artwork.
+fn write_sidecar(cache_dir: &Path, rom: &ROM, rom_metadata: &ROMMetadata) -> Result<(), io::Error> {
+  let (sidecar_path, artwork_dir) = get_sidecar_paths(rom).ok_or(io::Error::other("The ROM's path has no parent folder."))?;
+  let sidecar_dir = sidecar_path.parent().unwrap();
+  let rom_path = Path::new(&rom.path).strip_prefix(sidecar_dir.parent().unwrap()).unwrap_or(Path::new(&rom.path));
+
+  // ? Artwork can change extension when it's replaced, so start fresh rather than leave the old files behind.
+  let remove_res = fs::remove_dir_all(&artwork_dir);
+  if remove_res.as_ref().is_err_and(|e| e.kind() != io::ErrorKind::NotFound) {
+    return remove_res;
+  }
+
+  let mut metadata = rom_metadata.clone();
+  metadata.fullCapsulePath = copy_artwork_to_sidecar(cache_dir, &rom_metadata.fullCapsulePath, &artwork_dir)?;
+  metadata.thumbCapsulePath = copy_artwork_to_sidecar(cache_dir, &rom_metadata.thumbCapsulePath, &artwork_dir)?;
+  metadata.heroPath = copy_artwork_to_sidecar(cache_dir, &rom_metadata.heroPath, &artwork_dir)?;
+
+  let sidecar = ROMSidecar {
+    romPath: rom_path.to_string_lossy().to_string(),
+    metadata,
+  };
+
+  fs::create_dir_all(sidecar_dir)?;
+  let sidecar_str = serde_json::to_string_pretty(&sidecar).expect("Sidecar was malformatted.");
+
+  return write_atomic(&sidecar_path, &sidecar_str);
+}
+
+/// Removes a ROM's sidecar file and artwork, as well as the sidecar folder if it's now empty.
+fn remove_sidecar(rom: &ROM) -> Result<(), io::Error> {
+  let paths = get_sidecar_paths(rom);
+  if paths.is_none() {
+    return Ok(());
+  }
+  let (sidecar_path, artwork_dir) = paths.unwrap();
+
+  for remove_res in [fs::remove_file(&sidecar_path), fs::remove_dir_all(&artwork_dir)] {
+    if remove_res.as_ref().is_err_and(|e| e.kind() != io::ErrorKind::NotFound) {
+      return remove_res;
+    }
+  }
+
+  // * Only removes the folder if it's empty.
+  let _ = fs::remove_dir(sidecar_path.parent().unwrap());
+
+  return Ok(());
+}
+
+/// Reads a sidecar file, and caches its artwork. Returns the ROM's id and its metadata.
+fn read_sidecar(cache_dir: &Path, sidecar_path: &Path) -> Result<(String, ROMMetadata), String> {
+  let sidecar_file = File::open(sidecar_path).map_err(|e| e.to_string())?;
+  let sidecar: ROMSidecar = serde_json::from_reader(sidecar_file).map_err(|e| e.to_string())?;
+
+  let rom_path = sidecar_path.parent().and_then(|parent| parent.parent()).unwrap().join(&sidecar.romPath);
+  let rom_id = hash64(rom_path.to_str().unwrap_or_default());
+
+  // * ex: "game.iso.json" has its artwork in "game.iso".
+  let artwork_dir = sidecar_path.with_extension("");
+
+  let mut metadata = sidecar.metadata;
+  metadata.fullCapsulePath = copy_artwork_to_cache(cache_dir, &metadata.fullCapsulePath, &artwork_dir, &rom_id);
+  metadata.thumbCapsulePath = copy_artwork_to_cache(cache_dir, &metadata.thumbCapsulePath, &artwork_dir, &rom_id);
+  metadata.heroPath = copy_artwork_to_cache(cache_dir, &metadata.heroPath, &artwork_dir, &rom_id);
+
+  return Ok((rom_id, metadata));
+}
+
+/// Finds all the sidecar folders in the library's ROM folder.
+fn find_sidecar_dirs(roms_path: &Path) -> Result<Vec<PathBuf>, io::Error> {
+  let mut sidecar_dirs = vec![];
+  let mut pending = vec![roms_path.to_path_buf()];
+
+  // * The root has to be readable, but subfolders that aren't are skipped.
+  read_dir(roms_path)?;
+
+  while let Some(dir) = pending.pop() {
+    let entries_res = read_dir(&dir);
+    if entries_res.is_err() {
+      warn!("Metadata Sidecars: Failed to read directory \"{}\": {}", dir.display(), entries_res.err().unwrap());
+      continue;
+    }
+
+    for entry in entries_res.unwrap().flatten() {
+      if !entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+        continue;
+      }
+
+      if entry.file_name() == SIDECAR_DIR {
+        sidecar_dirs.push(entry.path());
+      } else {
+        pending.push(entry.path());
+      }
+    }
+  }
+
+  return Ok(sidecar_dirs);
+}
+
+/// Gets the library's ROM folder, if the library has been set up.
+fn get_roms_path(library: &Library) -> Option<PathBuf> {
+  if library.libraryPath.is_empty() {
+    return None;
+  }
+
+  return Some(PathBuf::from(&library.libraryPath).join(&library.romDir));
+}
+
+/// Loads the metadata saved in sidecars throughout the library.
+pub fn load(library: &Library, send_error: &ErrorSender) -> Result<HashMap<String, ROMMetadata>, ()> {
+  let mut metadata = HashMap::new();
+
+  let roms_path = get_roms_path(library);
+  if roms_path.is_none() {
+    return Ok(metadata);
+  }
+
+  let sidecar_dirs_res = find_sidecar_dirs(&roms_path.unwrap());
+  if sidecar_dirs_res.is_err() {
+    let err = sidecar_dirs_res.err().unwrap();
+
+    send_error(
+      format!("Failed to read ROM metadata sidecars: {}", err.to_string()),
+      "Please ensure NRM has read access to your library's ROM folders.".to_string(),
+      BackendErrorType::PANIC
+    );
+    return Err(());
+  }
+
+  let cache_dir = PathBuf::from(var("NRM_GRIDS_CACHE_DIR").unwrap());
+
+  for sidecar_dir in sidecar_dirs_res.unwrap() {
+    let entries_res = read_dir(&sidecar_dir);
+    if entries_res.is_err() {
+      warn!("Metadata Sidecars: Failed to read directory \"{}\": {}", sidecar_dir.display(), entries_res.err().unwrap());
+      continue;
+    }
+
+    for entry in entries_res.unwrap().flatten() {
+      let sidecar_path = entry.path();
+      if !sidecar_path.is_file() || sidecar_path.extension() != Some(OsStr::new("json")) {
+        continue;
+      }
+
+      let sidecar_res = read_sidecar(&cache_dir, &sidecar_path);
+      if sidecar_res.is_err() {
+        send_error(
+          format!("Failed to parse \"{}\": {}", sidecar_path.display(), sidecar_res.err().unwrap()),
+          "Please ensure your sidecar files follow the proper JSON format listed in the docs.".to_string(),
+          BackendErrorType::WARN
+        );
+        continue;
+      }
+
+      let (rom_id, rom_metadata) = sidecar_res.unwrap();
+      metadata.insert(rom_id, rom_metadata);
+    }
+  }
+
+  return Ok(metadata);
+}
+
+/// Writes the sidecars of every ROM whose metadata changed since the previous save, and removes the sidecars of ROMs whose metadata was removed.
+/// Metadata for ROMs that aren't loaded can't be placed, so it's reported instead.
+pub fn write(metadata: &HashMap<String, ROMMetadata>, previous: &HashMap<String, ROMMetadata>, roms: &[ROM], send_error: &ErrorSender) -> bool {
+  let cache_dir = PathBuf::from(var("NRM_GRIDS_CACHE_DIR").unwrap());
+  let roms_by_id: HashMap<String, &ROM> = roms.iter().map(|rom| (hash64(&rom.path), rom)).collect();
+  let mut unplaced: Vec<&str> = vec![];
+  let mut success = true;
+
+  for (rom_id, rom_metadata) in metadata {
+    if previous.get(rom_id) == Some(rom_metadata) {
+      continue;
+    }
+
+    let rom = roms_by_id.get(rom_id);
+    if rom.is_none() {
+      warn!("Metadata Sidecars: No ROM with id \"{}\" is loaded, so its metadata can't be saved.", rom_id);
+      unplaced.push(&rom_metadata.title);
+      continue;
+    }
+
+    let write_res = write_sidecar(&cache_dir, rom.unwrap(), rom_metadata);
+    if write_res.is_err() {
+      send_error(
+        format!("Failed to write the metadata of \"{}\": {}", rom.unwrap().path, write_res.err().unwrap()),
+        "Please ensure NRM has write access to your library's ROM folders.".to_string(),
+        BackendErrorType::WARN
+      );
+      success = false;
+    }
+  }
+
+  if !unplaced.is_empty() {
+    unplaced.sort();
+
+    send_error(
+      format!("Failed to save the metadata of {} ROMs that aren't in the library: {}", unplaced.len(), unplaced.join(", ")),
+      "Please make sure these ROMs are in your library and reload it, then try again.".to_string(),
+      BackendErrorType::WARN
+    );
+  }
+
+  for rom_id in previous.keys().filter(|rom_id| !metadata.contains_key(*rom_id)) {
+    let rom = roms_by_id.get(rom_id);
+    if rom.is_none() {
+      continue;
+    }
+
+    let remove_res = remove_sidecar(rom.unwrap());
+    if remove_res.is_err() {
+      send_error(
+        format!("Failed to remove the metadata of \"{}\": {}", rom.unwrap().path, remove_res.err().unwrap()),
+        "Please ensure NRM has write access to your library's ROM folders.".to_string(),
+        BackendErrorType::WARN
+      );
+      success = false;
+    }
+  }
+
+  return success;
+}
+
+/// Removes every sidecar folder in the library.
+pub fn clear(library: &Library, send_error: &ErrorSender) -> bool {
+  let roms_path = get_roms_path(library);
+  if roms_path.is_none() {
+    return true;
+  }
+
+  let sidecar_dirs_res = find_sidecar_dirs(&roms_path.unwrap());
+  if sidecar_dirs_res.is_err() {
+    let err = sidecar_dirs_res.err().unwrap();
+
+    send_error(
+      format!("Failed to read ROM metadata sidecars: {}", err.to_string()),
+      "Please ensure NRM has read access to your library's ROM folders.".to_string(),
+      BackendErrorType::WARN
+    );
+    return false;
+  }
+
+  let mut success = true;
+
+  for sidecar_dir in sidecar_dirs_res.unwrap() {
+    let remove_res = fs::remove_dir_all(&sidecar_dir);
+    if remove_res.is_err() {
+      send_error(
+        format!("Failed to remove \"{}\": {}", sidecar_dir.display(), remove_res.err().unwrap()),
+        "Please ensure NRM has write access to your library's ROM folders.".to_string(),
+        BackendErrorType::WARN
+      );
+      success = false;
+    }
+  }
+
+  return success;
+}
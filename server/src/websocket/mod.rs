@@ -1,29 +1,49 @@
-mod auth;
+pub mod auth;
 mod library_manager;
-mod settings;
-mod types;
-mod utils;
+pub mod settings;
+pub mod types;
+pub mod utils;
 mod watcher;
 mod ws_handler;
 mod file_picker;
 mod metadata;
-mod parsers;
+pub mod parsers;
+mod users;
+mod hasher;
+mod dats;
+mod library_index;
+pub mod config_files;
+mod collections;
+mod exporter;
+mod importer;
+mod jobs;
+mod context;
+#[cfg(test)]
+mod test_utils;
 
 use sysinfo::Disks;
+use auth::sync_user_roles;
+use users::load_users;
 use types::{
-  settings::get_default_settings,
+  auth::SessionStore,
+  settings::{get_default_settings, Settings},
   library::StateStore
 };
 use warp::Filter;
 use watcher::Watcher;
+use hasher::Hasher;
+use library_index::load_index;
+use collections::load_collections;
+use exporter::Exporter;
+use jobs::schedule_library_jobs;
+use context::ServerContext;
 use std::{collections::HashMap, sync::{Arc, Mutex}};
 use tokio::sync::broadcast;
 
+use crate::{job_queue::JobQueue, scheduler::Scheduler};
+
 /// Initializes the websocket api.
-pub fn initialize_websocket_api() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
-  let tx = Arc::new(Mutex::new(broadcast::channel(100).0));
-  let settings = Arc::new(Mutex::new(get_default_settings()));
-  
+pub fn initialize_websocket_api(tx: broadcast::Sender<String>, job_queue: JobQueue, scheduler: Scheduler, sessions: Arc<Mutex<SessionStore>>, settings: Arc<Mutex<Settings>>) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
   sysinfo::set_open_files_limit(0);
   let disks = Arc::new(Mutex::new(Disks::new()));
 
@@ -33,40 +53,46 @@ pub fn initialize_websocket_api() -> impl Filter<Extract = (impl warp::Reply,),
     parsers: HashMap::new(),
     metadata: HashMap::new(),
     dlcs: HashMap::new(),
-    updates: HashMap::new()
+    updates: HashMap::new(),
+    bios: HashMap::new(),
+    dats: HashMap::new(),
+    index: load_index(),
+    collections: load_collections(),
   }));
 
+  let users_store = load_users();
+  sync_user_roles(&mut sessions.lock().expect("Failed to lock Sessions Mutex."), &users_store);
 
-  let tx_ws = tx.clone();
-  let settings_ws = settings.clone();
-  let disks_ws = disks.clone();
-  let state_store_ws = state_store.clone();
+  let hasher_core = Hasher::new();
+  hasher_core.init(tx.clone(), state_store.clone());
 
+  let exporter = Exporter::new();
+  exporter.init(job_queue.clone(), settings.clone(), state_store.clone());
 
   let watcher_core = Watcher::new();
-  watcher_core.init(tx.lock().unwrap().to_owned());
+  watcher_core.init(tx.clone(), state_store.clone(), hasher_core.clone(), exporter.clone());
+
+  let context = ServerContext {
+    settings,
+    watcher: Arc::new(Mutex::new(watcher_core)),
+    hasher: Arc::new(Mutex::new(hasher_core)),
+    state_store,
+    users: Arc::new(Mutex::new(users_store)),
+    sessions,
+    disks,
+    job_queue,
+    broadcast_tx: tx,
+    exporter,
+  };
 
-  let watcher_arc = Arc::new(Mutex::new(watcher_core));
-  let watcher_ws = watcher_arc.clone();
+  schedule_library_jobs(&scheduler, context.clone());
 
-  
   let ws_route = warp::path("ws")
     .and(warp::ws())
     .map(move |ws: warp::ws::Ws| {
-      let tx = tx_ws.clone();
-      let settings = settings_ws.clone();
-      let watcher = watcher_ws.clone();
-      let disks = disks_ws.clone();
-      let state_store = state_store_ws.clone();
+      let context = context.clone();
 
-      ws.on_upgrade(move |websocket| ws_handler::handle_connection(
-        websocket,
-        tx,
-        disks,
-        settings,
-        watcher,
-        state_store
-      ))
+      ws.on_upgrade(move |websocket| ws_handler::handle_connection(websocket, context))
     });
 
   return ws_route;
@@ -1,9 +1,9 @@
-use std::{collections::HashMap, env::var, fs::{self, read_dir, File}, path::PathBuf};
+use std::{collections::HashMap, env::var, ffi::OsStr, fs::{self, read_dir, File}, path::PathBuf};
 use log::{info, warn};
 use regex::RegexBuilder;
 use wax::Glob;
 
-use super::{types::{
+use super::{config_files::{get_backup_dir, read_with_recovery, write_with_backup}, types::{
   library::{Library, Parser},
   ErrorSender
 }, watcher::Watcher};
@@ -162,27 +162,30 @@ pub fn load_parsers(library: &Library, send_error: &ErrorSender) -> Result<HashM
     }
     let parser_metadata = parser_metadata_res.unwrap();
 
-    if parser_metadata.is_file() {
-      let parser_file_res = File::open(&parser_entry.path());
-      if parser_file_res.is_err() {
-        let err = parser_file_res.err().unwrap();
-        warn!("Load Parsers: Can't read parser \"{}\": {}", parser_filename, err.to_string());
-        continue;
-      }
-
-      let parser_res = serde_json::from_reader(parser_file_res.ok().unwrap());
+    // * Only parsers are loaded, not leftover temporary or corrupt files.
+    let parser_path = parser_entry.path();
+    if parser_metadata.is_file() && parser_path.extension() == Some(OsStr::new("json")) {
+      let parser_res = read_with_recovery(&parser_path, &get_backup_dir("parsers"));
       if parser_res.is_err() {
         let err = parser_res.err().unwrap();
         
         send_error(
-          format!("Failed to parse parser \"{}\": {}", parser_filename, err.to_string()),
-          "Please ensure your settings.json follows the proper JSON format listed in the docs.".to_string(),
+          format!("Failed to parse parser \"{}\": {}", parser_filename, err),
+          "Please ensure your parser follows the proper JSON format listed in the docs, or restore it from the \"backups/parsers\" folder in \"/config\".".to_string(),
           crate::websocket::types::BackendErrorType::PANIC
         );
         return Err(());
       }
-      
-      let mut parser: Parser = parser_res.unwrap();
+
+      let (mut parser, backup): (Parser, Option<PathBuf>) = parser_res.unwrap();
+      if backup.is_some() {
+        send_error(
+          format!("Parser \"{}\" was corrupted, so it was restored from the backup \"{}\".", parser_filename, backup.unwrap().display()),
+          "Please double check the parser's settings, since recent changes may have been lost.".to_string(),
+          crate::websocket::types::BackendErrorType::WARN
+        );
+      }
+
       parser.biosFiles = load_bios_files(library, &parser);
 
       if validate_parser(&parser) {
@@ -196,6 +199,29 @@ pub fn load_parsers(library: &Library, send_error: &ErrorSender) -> Result<HashM
   return Ok(parsers);
 }
 
+/// Loads a single parser by its abbreviation, without validating it.
+pub fn load_parser(abbreviation: &str) -> Option<Parser> {
+  let parsers_path = var("NRM_PARSERS_DIR").expect("Load Parser: Failed to get parsers directory env variable");
+
+  for parser_entry in read_dir(&parsers_path).ok()?.flatten() {
+    if parser_entry.path().extension() != Some(OsStr::new("json")) {
+      continue;
+    }
+
+    let parser_file_res = File::open(parser_entry.path());
+    if parser_file_res.is_err() {
+      continue;
+    }
+
+    let parser_res: Result<Parser, serde_json::Error> = serde_json::from_reader(parser_file_res.unwrap());
+    if parser_res.as_ref().is_ok_and(|parser| parser.abbreviation == abbreviation) {
+      return parser_res.ok();
+    }
+  }
+
+  return None;
+}
+
 /// Writes the parsers to the file system.
 pub fn write_parsers(parsers: &HashMap<String, Parser>, library: &Library, watcher: &Watcher, send_error: ErrorSender) -> bool {
   let parsers_path = var("NRM_PARSERS_DIR").expect("Write Parsers: Failed to get parsers directory env variable");
@@ -208,7 +234,7 @@ pub fn write_parsers(parsers: &HashMap<String, Parser>, library: &Library, watch
 
     let should_watch_path = !fs::exists(&file_path).unwrap_or(false);
 
-    let write_res = fs::write(file_path, &parser_str);
+    let write_res = write_with_backup(&file_path, &get_backup_dir("parsers"), &parser_str);
     if write_res.is_err() {
       let err = write_res.err().unwrap();
       
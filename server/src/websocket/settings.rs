@@ -1,10 +1,10 @@
-use std::{env::var, fs::{self, File}, path::PathBuf, sync::MutexGuard};
+use std::{env::var, fs, path::PathBuf, sync::MutexGuard};
 use serde_json::{Map, Value};
 
-use crate::websocket::types::{
+use crate::websocket::{config_files::{get_backup_dir, read_with_recovery, write_atomic, write_with_backup}, types::{
   settings::{get_default_settings, Settings},
   ErrorSender
-};
+}};
 
 fn check_settings(settings: &mut Settings, defaults: &Settings) {
   settings.version = defaults.version.clone();
@@ -33,7 +33,7 @@ fn write_default_if_missing(config_path: &PathBuf, settings_path: &PathBuf, defa
   if !settings_exists.ok().unwrap() {
     let settings_str = serde_json::to_string_pretty(default_settings).expect("Settings were malformatted.");
     
-    let write_res = fs::write(settings_path, &settings_str);
+    let write_res = write_atomic(settings_path, &settings_str);
 
     if write_res.is_err() {
       let err = write_res.err().unwrap();
@@ -63,32 +63,27 @@ pub fn load_settings(send_error: ErrorSender) -> Result<Settings, ()> {
     return Ok(default_settings);
   }
 
-  let settings_file_res = File::open(&settings_path);
-  if settings_file_res.is_err() {
-    let err = settings_file_res.err().unwrap();
+  let saved_settings_res = read_with_recovery::<Settings>(&settings_path, &get_backup_dir(""));
+  if saved_settings_res.is_err() {
+    let err = saved_settings_res.err().unwrap();
     
     send_error(
-      format!("Failed to read settings.json: {}", err.to_string()),
-      "Please ensure NRM has write access to the mounted \"/config\" directory.".to_string(),
+      format!("Failed to load settings.json: {}", err),
+      "Please ensure your settings.json follows the proper JSON format listed in the docs, or restore it from the \"backups\" folder in \"/config\".".to_string(),
       crate::websocket::types::BackendErrorType::PANIC
     );
     return Err(());
   }
 
-  let saved_settings_res = serde_json::from_reader(settings_file_res.ok().unwrap());
-  if saved_settings_res.is_err() {
-    let err = saved_settings_res.err().unwrap();
-    
+  let (mut saved_settings, backup) = saved_settings_res.unwrap();
+  if backup.is_some() {
     send_error(
-      format!("Failed to parse settings.json: {}", err.to_string()),
-      "Please ensure your settings.json follows the proper JSON format listed in the docs.".to_string(),
-      crate::websocket::types::BackendErrorType::PANIC
+      format!("settings.json was corrupted, so it was restored from the backup \"{}\".", backup.unwrap().display()),
+      "Please double check your settings, since recent changes may have been lost.".to_string(),
+      crate::websocket::types::BackendErrorType::WARN
     );
-    return Err(());
   }
 
-  let mut saved_settings: Settings = saved_settings_res.unwrap();
-
   check_settings(&mut saved_settings, &default_settings);
 
   return Ok(saved_settings);
@@ -106,7 +101,7 @@ pub fn write_settings(state_settings: MutexGuard<'_, Settings>, send_error: Erro
   let settings = state_settings.clone();
   let settings_str = serde_json::to_string_pretty(&settings).expect("Settings were malformatted.");
     
-  let write_res = fs::write(settings_path, &settings_str);
+  let write_res = write_with_backup(&settings_path, &get_backup_dir(""), &settings_str);
   if write_res.is_err() {
     let err = write_res.err().unwrap();
     
@@ -122,28 +117,33 @@ pub fn write_settings(state_settings: MutexGuard<'_, Settings>, send_error: Erro
   return true;
 }
 
-fn set_property_recursive(object: &mut Map<String, Value>, properties: &[&str], index: usize, value: &Value) -> Value {
+fn set_property_recursive(object: &mut Map<String, Value>, properties: &[&str], index: usize, value: &Value) -> Result<Value, String> {
   let mut new_value = value.to_owned();
 
   if index != properties.len() - 1 {
-    let child = object.get_mut(properties[index]).unwrap().as_object_mut().unwrap();
-    new_value = set_property_recursive(child, properties, index + 1, value);
+    let child = object.get_mut(properties[index])
+      .and_then(|child| child.as_object_mut())
+      .ok_or(format!("\"{}\" is not a group of settings", properties[..=index].join(".")))?;
+
+    new_value = set_property_recursive(child, properties, index + 1, value)?;
   }
 
   object.insert(properties[index].to_string(), new_value);
   
-  return Value::Object(object.to_owned());
+  return Ok(Value::Object(object.to_owned()));
 }
 
 /// Sets the provided setting.
-pub fn set_setting(state_settings: &mut MutexGuard<'_, Settings>, key: &str, value: Value) {
-  let mut map_value = serde_json::to_value(state_settings.clone()).unwrap();
-  let map = map_value.as_object_mut().unwrap();
+pub fn set_setting(state_settings: &mut MutexGuard<'_, Settings>, key: &str, value: Value) -> Result<(), String> {
+  let mut map_value = serde_json::to_value(state_settings.clone()).map_err(|e| e.to_string())?;
+  let map = map_value.as_object_mut().ok_or("Settings were malformatted.")?;
 
   let keys: Vec<&str> = key.split(".").collect();
   
-  let updated_value = set_property_recursive(map, &keys, 0, &value);
-  let updated_settings: Settings = serde_json::from_value(updated_value).unwrap();
+  let updated_value = set_property_recursive(map, &keys, 0, &value)?;
+  let updated_settings: Settings = serde_json::from_value(updated_value).map_err(|e| e.to_string())?;
 
   **state_settings = updated_settings.clone();
+
+  return Ok(());
 }
\ No newline at end of file
@@ -0,0 +1,29 @@
+use serde_json::{json, Map};
+
+use crate::websocket::types::library::{Parser, ROM};
+
+/// Builds a ROM with the given path and system and empty defaults for everything else.
+pub fn rom(path: &str, system: &str) -> ROM {
+  return ROM {
+    title: path.to_string(),
+    path: path.to_string(),
+    size: 0,
+    addDate: String::new(),
+    addedAt: 0,
+    format: String::from("gba"),
+    system: system.to_string(),
+    systemFullName: system.to_string(),
+    downloadStrategy: Map::new(),
+    hashes: None,
+    verification: None,
+  };
+}
+
+/// Builds a parser with the given name, abbreviation and folder and no patterns.
+pub fn parser(name: &str, abbreviation: &str, folder: &str) -> Parser {
+  return serde_json::from_value(json!({
+    "name": name, "abbreviation": abbreviation, "igdbPlatformId": "", "folder": folder, "sgdbId": "",
+    "fullCapsulePath": "", "thumbCapsulePath": "", "heroPath": "",
+    "tagConfig": { "backgroundColor": "", "borderColor": "" }, "patterns": [], "biosFiles": []
+  })).unwrap();
+}
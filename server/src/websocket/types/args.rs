@@ -3,13 +3,19 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use super::{file_picker::FilePickerConfig, library::{Library, Parser, ROMMetadata}};
+use super::{auth::UserRole, file_picker::FilePickerConfig, library::{CollectionRules, Library, Parser, ROMMetadata}};
 
 // * Interop types
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct HelloArgs {
+  pub version: u32,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct SimpleArgs {
-  pub passwordHash: String,
+  pub token: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -22,7 +28,7 @@ pub struct AuthArgs {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct SetSettingArgs {
-  pub passwordHash: String,
+  pub token: String,
   pub key: String,
   pub value: Value,
 }
@@ -30,14 +36,14 @@ pub struct SetSettingArgs {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct ModifyLibraryArgs {
-  pub passwordHash: String,
+  pub token: String,
   pub library: Library,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct ParseRomArgs {
-  pub passwordHash: String,
+  pub token: String,
   pub parser: String,
   pub romPath: String,
 }
@@ -45,7 +51,7 @@ pub struct ParseRomArgs {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct FilePickerArgs {
-  pub passwordHash: String,
+  pub token: String,
   pub path: String,
   pub config: FilePickerConfig,
 }
@@ -53,36 +59,110 @@ pub struct FilePickerArgs {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct MetadataArgs {
-  pub passwordHash: String,
+  pub token: String,
   pub data: HashMap<String, ROMMetadata>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct ParsersArgs {
-  pub passwordHash: String,
+  pub token: String,
   pub data: HashMap<String, Parser>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct DeleteParserArgs {
-  pub passwordHash: String,
+  pub token: String,
   pub abbreviation: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct GlobArgs {
-  pub passwordHash: String,
+  pub token: String,
   pub glob: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct ModifyExtraFileArgs {
-  pub passwordHash: String,
+  pub token: String,
   pub fileType: String,
   pub romId: String,
   pub filename: String,
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct SaveUserArgs {
+  pub token: String,
+  pub username: String,
+  pub passwordHash: String,
+  pub role: UserRole,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct DeleteUserArgs {
+  pub token: String,
+  pub username: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct CancelJobArgs {
+  pub token: String,
+  pub jobId: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct CreateCollectionArgs {
+  pub token: String,
+  pub name: String,
+  #[serde(default)]
+  pub rules: Option<CollectionRules>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct CollectionArgs {
+  pub token: String,
+  pub collectionId: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct RenameCollectionArgs {
+  pub token: String,
+  pub collectionId: String,
+  pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct ModifyCollectionArgs {
+  pub token: String,
+  pub collectionId: String,
+  pub romIds: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct SetCollectionRulesArgs {
+  pub token: String,
+  pub collectionId: String,
+  pub rules: Option<CollectionRules>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct ImportMetadataArgs {
+  pub token: String,
+  /// A metadata file, or a folder to search for them.
+  pub path: String,
+  /// Whether imported values replace ones the ROMs already have.
+  #[serde(default)]
+  pub overwrite: bool,
+}
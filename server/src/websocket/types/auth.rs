@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The roles a user can have. Each role can do everything the roles before it can.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(non_snake_case)]
+pub enum UserRole {
+  READONLY,
+  UPLOADER,
+  ADMIN,
+}
+
+/// A user account saved in users.json.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct User {
+  pub username: String,
+  pub passwordHash: String,
+  pub salt: String,
+  pub role: UserRole,
+}
+
+/// The user info that is safe to send to the frontend.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct UserInfo {
+  pub username: String,
+  pub role: UserRole,
+}
+
+/// Holds the user accounts loaded from users.json.
+#[derive(Clone, Debug)]
+pub struct UserStore {
+  pub users: HashMap<String, User>,
+}
+
+/// The claims encoded in a session token.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct SessionClaims {
+  pub user: String,
+  pub role: UserRole,
+  pub sessionId: String,
+  pub issued: i64,
+  pub expires: i64,
+}
+
+/// The session token returned to the frontend.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct SessionToken {
+  pub token: String,
+  pub user: String,
+  pub role: UserRole,
+  pub expires: i64,
+}
+
+/// The sessions that were ended before they expired, saved to sessions.json so they stay ended after a restart.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[allow(non_snake_case)]
+pub struct RevokedSessions {
+  /// The ids of ended sessions, and when they expire.
+  pub sessions: HashMap<String, i64>,
+  /// The users whose sessions were all ended, and when, in milliseconds.
+  pub users: HashMap<String, i64>,
+}
+
+/// Holds the session signing key and the sessions that were ended before they expired.
+#[derive(Clone, Debug)]
+pub struct SessionStore {
+  pub secret: Vec<u8>,
+  pub length: i64,
+  /// The current role of each user in users.json.
+  pub user_roles: HashMap<String, UserRole>,
+  pub revoked: HashMap<String, i64>,
+  pub revoked_users: HashMap<String, i64>,
+}
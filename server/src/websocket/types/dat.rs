@@ -0,0 +1,92 @@
+use std::{collections::HashMap, path::PathBuf, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+// * Logiqx XML types
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct DatFile {
+  #[serde(default)]
+  pub header: Option<DatHeader>,
+  #[serde(rename = "game", alias = "machine", default)]
+  pub games: Vec<DatGame>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct DatHeader {
+  #[serde(default)]
+  pub name: String,
+  #[serde(default)]
+  pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct DatGame {
+  #[serde(rename = "@name")]
+  pub name: String,
+  #[serde(rename = "rom", default)]
+  pub roms: Vec<DatRom>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct DatRom {
+  #[serde(rename = "@name")]
+  pub name: String,
+  #[serde(rename = "@size", default)]
+  pub size: Option<u64>,
+  #[serde(rename = "@crc", default)]
+  pub crc: Option<String>,
+  #[serde(rename = "@md5", default)]
+  pub md5: Option<String>,
+  #[serde(rename = "@sha1", default)]
+  pub sha1: Option<String>,
+  #[serde(rename = "@status", default)]
+  pub status: Option<String>,
+}
+
+/// A rom entry from a DAT, along with the game it belongs to.
+#[derive(Clone, Debug)]
+pub struct DatEntry {
+  pub game: String,
+  pub rom: DatRom,
+}
+
+/// A DAT's entries, indexed by their hashes and names.
+#[derive(Clone, Debug)]
+pub struct DatIndex {
+  pub name: String,
+  /// The file the DAT was loaded from, and its modified time, so it's only parsed again when it changes.
+  pub path: PathBuf,
+  pub modified: Option<SystemTime>,
+  pub entries: Vec<DatEntry>,
+  pub by_game: HashMap<String, Vec<usize>>,
+  pub by_sha1: HashMap<String, usize>,
+  pub by_md5: HashMap<String, usize>,
+  pub by_crc: HashMap<(String, u64), usize>,
+  pub by_name: HashMap<String, usize>,
+}
+
+/// The result of checking a ROM against its system's DAT.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum VerificationStatus {
+  VERIFIED,
+  BAD_DUMP,
+  UNKNOWN,
+  MISNAMED,
+  /// An archive that matches a game, but is missing some of its roms.
+  INCOMPLETE,
+  /// A file named after a rom in the DAT, whose hashes don't match it. Usually a different revision or a modified copy.
+  HASH_MISMATCH,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[allow(non_snake_case)]
+pub struct ROMVerification {
+  pub status: VerificationStatus,
+  pub canonicalName: Option<String>,
+  pub canonicalFilename: Option<String>,
+}
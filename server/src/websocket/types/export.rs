@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+// * EmulationStation gamelist.xml types. These are also read when importing metadata.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[allow(non_snake_case)]
+#[serde(rename = "gameList")]
+pub struct Gamelist {
+  #[serde(rename = "game", default)]
+  pub games: Vec<GamelistGame>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[allow(non_snake_case)]
+pub struct GamelistGame {
+  pub path: String,
+  #[serde(default)]
+  pub name: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub desc: Option<String>,
+  /// The release date, ex: "19980101T000000".
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub releasedate: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub developer: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub publisher: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub genre: Option<String>,
+  /// The rating, from 0 to 1.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub rating: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub image: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub thumbnail: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub fanart: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub favorite: Option<bool>,
+  /// Some frontends save the ROM's hashes, ex: Batocera saves its MD5, and Recalbox saves its CRC32 as its hash.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub md5: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hash: Option<String>,
+}
+
+// * RetroArch playlist types
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct RetroArchPlaylist {
+  pub version: String,
+  pub default_core_path: String,
+  pub default_core_name: String,
+  pub label_display_mode: u32,
+  pub right_thumbnail_mode: u32,
+  pub left_thumbnail_mode: u32,
+  pub sort_mode: u32,
+  pub items: Vec<RetroArchPlaylistItem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct RetroArchPlaylistItem {
+  pub path: String,
+  pub label: String,
+  pub core_path: String,
+  pub core_name: String,
+  /// The ROM's CRC32, ex: "1A2B3C4D|crc", or "DETECT" if it hasn't been hashed yet.
+  pub crc32: String,
+  pub db_name: String,
+}
+
+/// What an export wrote.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct ExportResult {
+  pub exportPath: String,
+  pub gamelists: usize,
+  pub playlists: usize,
+  pub romCount: usize,
+}
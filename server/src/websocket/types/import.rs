@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+// * LaunchBox platform XML types
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[allow(non_snake_case)]
+pub struct LaunchBoxPlatform {
+  #[serde(rename = "Game", default)]
+  pub games: Vec<LaunchBoxGame>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[allow(non_snake_case)]
+pub struct LaunchBoxGame {
+  #[serde(default)]
+  pub Title: String,
+  #[serde(default)]
+  pub ApplicationPath: String,
+  #[serde(default)]
+  pub Platform: String,
+  #[serde(default)]
+  pub Notes: Option<String>,
+  /// The release date, ex: "2001-03-21T00:00:00-08:00".
+  #[serde(default)]
+  pub ReleaseDate: Option<String>,
+  #[serde(default)]
+  pub Developer: Option<String>,
+  #[serde(default)]
+  pub Publisher: Option<String>,
+  /// The genres, separated by semicolons.
+  #[serde(default)]
+  pub Genre: Option<String>,
+  /// The community rating, from 0 to 5.
+  #[serde(default)]
+  pub CommunityStarRating: Option<String>,
+  #[serde(default)]
+  pub Favorite: Option<String>,
+}
+
+/// What an import changed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct ImportResult {
+  pub files: usize,
+  pub games: usize,
+  pub matched: usize,
+  pub artwork: usize,
+  /// The titles of the games that didn't match a ROM.
+  pub unmatched: Vec<String>,
+}
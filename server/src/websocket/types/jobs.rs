@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Where a background job is in its lifecycle.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum JobStatus {
+  QUEUED,
+  RUNNING,
+  DONE,
+  FAILED,
+  CANCELLED,
+}
+
+impl JobStatus {
+  /// Checks if the job is still waiting or running.
+  pub fn is_active(&self) -> bool {
+    return *self == JobStatus::QUEUED || *self == JobStatus::RUNNING;
+  }
+}
+
+/// A background job, and how far along it is.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct JobInfo {
+  pub id: String,
+  pub kind: String,
+  // ? What the job is working on, like the folder being zipped.
+  pub target: String,
+  pub status: JobStatus,
+  // ? From 0 to 1, or None if the amount of work isn't known.
+  pub progress: Option<f64>,
+  pub message: String,
+  pub createdAt: i64,
+  pub finishedAt: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct JobDone {
+  pub jobId: String,
+  pub kind: String,
+  pub result: Value,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct JobFailed {
+  pub jobId: String,
+  pub kind: String,
+  pub message: String,
+  pub cancelled: bool,
+}
@@ -2,6 +2,8 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 
+use super::dat::{DatIndex, ROMVerification};
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct LoadResult {
@@ -10,6 +12,7 @@ pub struct LoadResult {
   pub systems: Vec<Parser>,
   pub dlcs: HashMap<String, Vec<String>>,
   pub updates: HashMap<String, Vec<String>>,
+  pub bios: HashMap<String, Vec<String>>,
 }
 
 #[derive(Clone, Debug)]
@@ -20,16 +23,20 @@ pub struct StateStore {
   pub metadata: HashMap<String, ROMMetadata>,
   pub updates: HashMap<String, Vec<String>>,
   pub dlcs: HashMap<String, Vec<String>>,
+  pub bios: HashMap<String, Vec<String>>,
+  pub dats: HashMap<String, DatIndex>,
+  pub index: LibraryIndex,
+  pub collections: HashMap<String, Collection>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[allow(non_snake_case)]
 pub struct SystemTagConfig {
   pub backgroundColor: String,
   pub borderColor: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[allow(non_snake_case)]
 pub struct ParserPattern {
   pub glob: String,
@@ -37,7 +44,18 @@ pub struct ParserPattern {
   pub downloadStrategy: Map<String, Value>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// How a system's folder roms are compressed when they're downloaded.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum ZipCompression {
+  /// Streamed as-is, which is fastest and best for already compressed formats like ISOs.
+  #[default]
+  STORED,
+  /// Packed ahead of time to a temporary archive, so the download is smaller.
+  DEFLATE,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[allow(non_snake_case)]
 pub struct Parser {
   pub name: String,
@@ -51,23 +69,136 @@ pub struct Parser {
   pub tagConfig: SystemTagConfig,
   pub patterns: Vec<ParserPattern>,
   pub biosFiles: Vec<String>,
+  #[serde(default)]
+  pub datFile: String,
+  #[serde(default)]
+  pub zipCompression: ZipCompression,
+  /// The RetroArch core this system's playlist launches with, ex: "/cores/mgba_libretro.so".
+  #[serde(default)]
+  pub retroarchCorePath: String,
+  #[serde(default)]
+  pub retroarchCoreName: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[allow(non_snake_case)]
 pub struct ROM {
   pub title: String,
   pub path: String,
   pub size: u64,
   pub addDate: String,
+  /// When the ROM was added, as a unix timestamp in seconds.
+  pub addedAt: i64,
   pub format: String,
   pub system: String,
   pub systemFullName: String,
   pub downloadStrategy: Map<String, Value>,
+  pub hashes: Option<ROMHashes>,
+  pub verification: Option<ROMVerification>,
+}
+
+/// The content hashes of a ROM file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[allow(non_snake_case)]
+pub struct ROMHashes {
+  pub crc32: String,
+  pub md5: String,
+  pub sha1: String,
+  /// The files inside the ROM, if it's a ZIP or 7z archive.
+  #[serde(default)]
+  pub members: Option<Vec<ArchiveMember>>,
 }
 
+/// A file inside an archived ROM, along with its hashes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[allow(non_snake_case)]
+pub struct ArchiveMember {
+  pub name: String,
+  pub size: u64,
+  pub crc32: String,
+  pub sha1: String,
+}
+
+/// A ROM's hashes, along with the size and modified time of the file they were computed from.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
+pub struct CachedROMHashes {
+  pub size: u64,
+  pub modified: i64,
+  pub hashes: ROMHashes,
+}
+
+/// Sent when a ROM's hashes finish computing in the background.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct HashedROM {
+  pub path: String,
+  pub hashes: ROMHashes,
+  pub verification: Option<ROMVerification>,
+}
+
+/// A directory's entries, along with its modified time when they were read.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[allow(non_snake_case)]
+pub struct IndexedDirectory {
+  pub modified: i64,
+  pub files: Vec<String>,
+  pub directories: Vec<String>,
+}
+
+/// A parsed ROM, along with the modified time of the file it was parsed from.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct IndexedROM {
+  pub modified: i64,
+  pub isDirectory: bool,
+  pub rom: ROM,
+}
+
+/// The on-disk index of the library, used to skip directories that haven't changed since the last load.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[allow(non_snake_case)]
+pub struct LibraryIndex {
+  pub version: u32,
+  pub library: Option<Library>,
+  pub parsers: HashMap<String, Parser>,
+  pub directories: HashMap<String, IndexedDirectory>,
+  pub roms: HashMap<String, IndexedROM>,
+}
+
+/// Sent when a ROM is removed from the library.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct RemovedROM {
+  pub path: String,
+}
+
+/// Sent when a ROM is renamed or moved within its system's folder.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct RenamedROM {
+  pub oldPath: String,
+  pub rom: ROM,
+}
+
+/// Sent when a ROM's DLCs or updates change.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct ExtraFiles {
+  pub romId: String,
+  pub files: Vec<String>,
+}
+
+/// Sent when a system's BIOS files change.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct BiosFiles {
+  pub system: String,
+  pub files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[allow(non_snake_case)]
 pub struct ROMMetadata {
   pub title: String,
   pub fullCapsulePath: String,
@@ -79,8 +210,17 @@ pub struct ROMMetadata {
   pub isFavorite: bool,
 }
 
+/// The metadata of a ROM, saved in a sidecar file next to it.
+/// The ROM's path is relative to the folder the sidecar folder is in, and artwork paths are relative to the ROM's artwork folder.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
+pub struct ROMSidecar {
+  pub romPath: String,
+  pub metadata: ROMMetadata,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[allow(non_snake_case)]
 pub struct Library {
   pub libraryPath: String,
   pub romDir: String,
@@ -90,9 +230,64 @@ pub struct Library {
   pub updateDir: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// A group of ROMs. Smart collections have rules, and their ROMs are kept up to date as the library changes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[allow(non_snake_case)]
 pub struct Collection {
+  pub id: String,
   pub name: String,
   pub romIds: Vec<String>,
+  #[serde(default)]
+  pub rules: Option<CollectionRules>,
+}
+
+/// The rules a ROM has to match to be in a smart collection.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[allow(non_snake_case)]
+pub struct CollectionRules {
+  pub combinator: RuleCombinator,
+  pub rules: Vec<CollectionRule>,
+}
+
+/// Whether a ROM has to match all of a smart collection's rules, or just one.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum RuleCombinator {
+  ALL,
+  ANY,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[allow(non_snake_case)]
+pub struct CollectionRule {
+  pub field: RuleField,
+  pub operator: RuleOperator,
+  pub value: Value,
+}
+
+/// The ROM and metadata fields rules can check.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum RuleField {
+  TITLE,
+  SYSTEM,
+  FORMAT,
+  SIZE,
+  ADDED,
+  FAVORITE,
+  GENRE,
+  DEVELOPER,
+  PUBLISHER,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum RuleOperator {
+  EQUALS,
+  NOT_EQUALS,
+  CONTAINS,
+  NOT_CONTAINS,
+  GREATER_THAN,
+  LESS_THAN,
+  WITHIN_DAYS,
 }
\ No newline at end of file
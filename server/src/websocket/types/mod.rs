@@ -2,8 +2,16 @@ pub mod settings;
 pub mod args;
 pub mod library;
 pub mod file_picker;
+pub mod auth;
+pub mod dat;
+pub mod protocol;
+pub mod jobs;
+pub mod export;
+pub mod import;
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
@@ -18,8 +26,24 @@ impl Into<usize> for BackendErrorType {
   }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct BackendError {
+  pub message: String,
+  pub fix: String,
+  pub r#type: BackendErrorType,
+}
+
 pub type ErrorSender = Box<dyn Fn(String, String, BackendErrorType)>;
 
+/// Sends replies back to the connection that made a request, tagged with the request's id.
+#[derive(Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct Responder {
+  pub sender: mpsc::UnboundedSender<String>,
+  pub requestId: Option<Value>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 pub struct AvailableStorage {
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{args::{
+  AuthArgs, CancelJobArgs, CollectionArgs, CreateCollectionArgs, DeleteParserArgs, DeleteUserArgs, FilePickerArgs, GlobArgs, ImportMetadataArgs, MetadataArgs, ModifyExtraFileArgs,
+  ModifyCollectionArgs, ModifyLibraryArgs, ParseRomArgs, ParsersArgs, RenameCollectionArgs, SaveUserArgs, SetCollectionRulesArgs, SetSettingArgs, SimpleArgs
+}, auth::UserRole, jobs::{JobDone, JobFailed, JobInfo}, library::{BiosFiles, Collection, ExtraFiles, HashedROM, LoadResult, RemovedROM, RenamedROM, ROMMetadata, ROM}, BackendError};
+
+/// The version of the websocket protocol. Bump this whenever a command or event changes shape.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// The envelope every frame from the frontend is wrapped in.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct RequestEnvelope {
+  pub r#type: String,
+  #[serde(default)]
+  pub requestId: Option<Value>,
+  #[serde(default)]
+  pub data: Value,
+}
+
+/// The commands the frontend can send after the handshake. The "hello" handshake is handled by the connection itself.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Command {
+  UserAuth(AuthArgs),
+  RefreshSession(SimpleArgs),
+  Logout(SimpleArgs),
+  ListUsers(SimpleArgs),
+  SaveUser(SaveUserArgs),
+  DeleteUser(DeleteUserArgs),
+  LoadSettings(SimpleArgs),
+  WriteSettings(SimpleArgs),
+  SetSetting(SetSettingArgs),
+  LoadLibrary(SimpleArgs),
+  UpdateLibrary(ModifyLibraryArgs),
+  LoadMetadata(SimpleArgs),
+  RefreshMetadata(SimpleArgs),
+  SaveMetadata(MetadataArgs),
+  LoadCollections(SimpleArgs),
+  CreateCollection(CreateCollectionArgs),
+  RenameCollection(RenameCollectionArgs),
+  DeleteCollection(CollectionArgs),
+  AddToCollection(ModifyCollectionArgs),
+  RemoveFromCollection(ModifyCollectionArgs),
+  SetCollectionRules(SetCollectionRulesArgs),
+  ExportLibrary(SimpleArgs),
+  ImportMetadata(ImportMetadataArgs),
+  SaveParsers(ParsersArgs),
+  DeleteParser(DeleteParserArgs),
+  AddExtraFile(ModifyExtraFileArgs),
+  DeleteExtraFile(ModifyExtraFileArgs),
+  ParseRom(ParseRomArgs),
+  FilePicker(FilePickerArgs),
+  AvailableStorage(SimpleArgs),
+  IsValidGlob(GlobArgs),
+  ListJobs(SimpleArgs),
+  CancelJob(CancelJobArgs),
+  Demo(SimpleArgs),
+}
+
+/// The `type` of every command, so unknown commands can be told apart from commands with invalid args. Keep this in sync with `Command`.
+pub const COMMAND_TYPES: [&str; 34] = [
+  "user_auth", "refresh_session", "logout", "list_users", "save_user", "delete_user",
+  "load_settings", "write_settings", "set_setting", "load_library", "update_library", "load_metadata",
+  "refresh_metadata", "save_metadata", "load_collections", "create_collection", "rename_collection",
+  "delete_collection", "add_to_collection", "remove_from_collection", "set_collection_rules",
+  "export_library", "import_metadata", "save_parsers", "delete_parser", "add_extra_file",
+  "delete_extra_file", "parse_rom", "file_picker", "available_storage", "is_valid_glob", "list_jobs",
+  "cancel_job", "demo"
+];
+
+/// The reasons a frame from the frontend can be rejected.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum ProtocolErrorCode {
+  MALFORMED_MESSAGE,
+  UNKNOWN_COMMAND,
+  INVALID_ARGS,
+  HANDSHAKE_REQUIRED,
+  UNSUPPORTED_VERSION,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct ProtocolError {
+  pub code: ProtocolErrorCode,
+  pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct HelloResponse {
+  pub version: u32,
+}
+
+/// The events the backend sends that aren't the direct result of a command.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum Event {
+  Hello(HelloResponse),
+  ProtocolError(ProtocolError),
+  BackendError(BackendError),
+  SessionInvalid,
+  PermissionDenied(UserRole),
+  MissingEnvVariable(String),
+  RomHashed(HashedROM),
+  RomAdded(ROM),
+  RomRemoved(RemovedROM),
+  RomModified(ROM),
+  RomRenamed(RenamedROM),
+  DlcsChanged(ExtraFiles),
+  UpdatesChanged(ExtraFiles),
+  BiosFilesChanged(BiosFiles),
+  LibraryRescanned(LoadResult),
+  MetadataRefreshed(HashMap<String, ROMMetadata>),
+  CollectionsChanged(HashMap<String, Collection>),
+  JobQueued(JobInfo),
+  JobProgress(JobInfo),
+  JobDone(JobDone),
+  JobFailed(JobFailed),
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use super::{Command, COMMAND_TYPES};
+
+  #[test]
+  fn command_types_are_all_commands() {
+    for command_type in COMMAND_TYPES {
+      let err = serde_json::from_value::<Command>(json!({ "type": command_type, "data": null })).err();
+
+      assert!(err.is_none_or(|err| !err.to_string().starts_with("unknown variant")), "\"{}\" is not a command.", command_type);
+    }
+  }
+}
@@ -26,6 +26,11 @@ pub fn get_default_settings() -> Settings {
     accessibility: AccessibilitySettings {
       reducedMotion: false,
     },
+    export: ExportSettings {
+      exportDir: "exports".to_string(),
+      romPathPrefix: "".to_string(),
+      exportOnLibraryChange: false,
+    },
     library: Library {
       libraryPath: "".to_string(),
       romDir: "roms".to_string(),
@@ -65,6 +70,17 @@ pub struct AccessibilitySettings {
   pub reducedMotion: bool,
 }
 
+/// Where the library's gamelists and playlists are exported to.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[allow(non_snake_case)]
+pub struct ExportSettings {
+  /// The folder exports are written to, relative to the library.
+  pub exportDir: String,
+  /// The path the ROM folder is mounted at on the devices reading the exports. Empty uses the server's paths.
+  pub romPathPrefix: String,
+  pub exportOnLibraryChange: bool,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[allow(non_snake_case)]
 #[serde(default = "get_default_settings")]
@@ -75,5 +91,6 @@ pub struct Settings {
   pub navigation: NavigationSettings,
   pub metadata: MetadataSettings,
   pub accessibility: AccessibilitySettings,
+  pub export: ExportSettings,
   pub library: Library
 }
\ No newline at end of file
@@ -0,0 +1,127 @@
+use std::{collections::HashMap, env::var, fs, path::PathBuf};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use log::{info, warn};
+
+use super::{auth::hash_password, config_files::{get_backup_dir, read_with_recovery, write_with_backup}, types::{
+  auth::{User, UserInfo, UserRole, UserStore},
+  ErrorSender
+}};
+
+/// Gets the path of the users.json file.
+fn get_users_path() -> PathBuf {
+  let config_path = PathBuf::from(var("NRM_CONFIG_DIR").ok().unwrap());
+  return config_path.join("users.json");
+}
+
+/// Loads the user accounts from users.json. The account from `NRM_USERNAME`/`NRM_PASSWORD` is always an admin and isn't stored here.
+pub fn load_users() -> UserStore {
+  let users_path = get_users_path();
+  let mut store = UserStore {
+    users: HashMap::new(),
+  };
+
+  if !fs::exists(&users_path).unwrap_or(false) {
+    info!("Load Users: No users.json was found, only the environment account can sign in.");
+    return store;
+  }
+
+  let users_res = read_with_recovery::<Vec<User>>(&users_path, &get_backup_dir(""));
+  if users_res.is_err() {
+    let err = users_res.err().unwrap();
+    warn!("Load Users: Failed to read users.json: {}", err);
+    return store;
+  }
+
+  for user in users_res.unwrap().0 {
+    store.users.insert(user.username.clone(), user);
+  }
+
+  info!("Load Users: Loaded {} users.", store.users.len());
+
+  return store;
+}
+
+/// Writes the user accounts to users.json.
+pub fn write_users(store: &UserStore, send_error: ErrorSender) -> bool {
+  let users_path = get_users_path();
+  let _ = fs::create_dir_all(users_path.parent().unwrap());
+
+  let mut users: Vec<&User> = store.users.values().collect();
+  users.sort_by(|a, b| a.username.cmp(&b.username));
+
+  let users_str = serde_json::to_string_pretty(&users).expect("Users were malformatted.");
+
+  let write_res = write_with_backup(&users_path, &get_backup_dir(""), &users_str);
+  if write_res.is_err() {
+    let err = write_res.err().unwrap();
+
+    send_error(
+      format!("Failed to write users: {}", err.to_string()),
+      "Please ensure NRM has write access to the mounted \"/config\" directory.".to_string(),
+      crate::websocket::types::BackendErrorType::PANIC
+    );
+
+    return false;
+  }
+
+  return true;
+}
+
+/// Gets the username of the environment account, if it has been configured.
+pub fn get_env_username() -> Option<String> {
+  return var("NRM_USERNAME").ok().filter(|username| !username.is_empty());
+}
+
+/// Gets the list of users for the frontend.
+pub fn list_users(store: &UserStore) -> Vec<UserInfo> {
+  let mut users: Vec<UserInfo> = store.users.values().map(|user| UserInfo {
+    username: user.username.clone(),
+    role: user.role,
+  }).collect();
+
+  users.sort_by(|a, b| a.username.cmp(&b.username));
+
+  return users;
+}
+
+/// Creates or updates a user. An empty password hash keeps the user's current password.
+pub fn save_user(store: &mut UserStore, username: String, password_hash: String, role: UserRole) -> Result<(), String> {
+  if username.is_empty() {
+    return Err("Usernames can't be empty.".to_string());
+  }
+
+  if get_env_username().as_deref() == Some(username.as_str()) {
+    return Err(format!("\"{}\" is the environment account, and can only be changed through NRM_USERNAME/NRM_PASSWORD.", username));
+  }
+
+  let existing = store.users.get(&username);
+  if password_hash.is_empty() && existing.is_none() {
+    return Err(format!("A password is required to create user \"{}\".", username));
+  }
+
+  let user = if password_hash.is_empty() {
+    User {
+      role,
+      ..existing.unwrap().clone()
+    }
+  } else {
+    let salt = URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>());
+
+    User {
+      username: username.clone(),
+      passwordHash: hash_password(&salt, &password_hash),
+      salt,
+      role,
+    }
+  };
+
+  store.users.insert(username, user);
+
+  return Ok(());
+}
+
+/// Deletes a user.
+pub fn delete_user(store: &mut UserStore, username: &str) -> bool {
+  return store.users.remove(username).is_some();
+}
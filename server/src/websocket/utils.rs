@@ -1,47 +1,168 @@
+use std::{ffi::OsStr, path::PathBuf, sync::{Arc, Mutex}};
+
 use log::warn;
 use serde::Serialize;
 use serde_json::{Map, Value};
 use tokio::sync::broadcast;
 
-use crate::websocket::auth::validate_hash;
+use crate::websocket::auth::validate_token;
+
+use super::types::{auth::{SessionStore, UserRole}, protocol::{Event, ProtocolError, ProtocolErrorCode}, library::ROM, BackendError, BackendErrorType, ErrorSender, Responder};
+
+/// Serializes a frame for the frontend, tagging it with the id of the request it answers.
+fn to_frame(mut frame: Map<String, Value>, request_id: Option<Value>) -> String {
+  if request_id.is_some() {
+    frame.insert(String::from("requestId"), request_id.unwrap());
+  }
+
+  return Value::Object(frame).to_string();
+}
+
+/// Wraps a Responder to reply to the connection that sent the request with json data.
+pub fn send<T: Serialize>(tx: Responder, message: &str, data: T) {
+  let data_res = serde_json::to_value(data);
+  if data_res.is_err() {
+    warn!("Failed to serialize the reply to \"{}\": {}", message, data_res.err().unwrap());
+    return;
+  }
+
+  let mut frame = Map::new();
+  frame.insert(String::from("type"), Value::String(message.to_string()));
+  frame.insert(String::from("data"), data_res.unwrap());
+
+  // * The connection may have closed while the request was handled, so there's no one left to reply to.
+  let _ = tx.sender.send(to_frame(frame, tx.requestId));
+}
+
+/// Wraps a Responder to send an event to the connection that sent the request.
+pub fn send_event(tx: Responder, event: Event) {
+  let frame = serde_json::to_value(event).ok().and_then(|value| value.as_object().cloned()).unwrap_or_default();
 
-use super::types::{BackendErrorType, ErrorSender};
+  let _ = tx.sender.send(to_frame(frame, tx.requestId));
+}
 
-/// Wraps a Broadcast Sender to handle json data.
-pub fn send<T: Serialize>(tx: broadcast::Sender<String>, message: &str, data: T) {
-  let mut map = Map::new();
-  map.insert(String::from("data"), serde_json::to_value(data).unwrap());
+/// Wraps a Broadcast Sender to send an event to every connection.
+pub fn broadcast(tx: broadcast::Sender<String>, event: Event) {
+  let frame = serde_json::to_value(event).ok().and_then(|value| value.as_object().cloned()).unwrap_or_default();
 
-  tx.send(format!("{} {}", message, serde_json::to_string(&map).unwrap())).expect("Failed to broadcast message");
+  // * Sending only fails when no connections are listening.
+  let _ = tx.send(to_frame(frame, None));
 }
 
 /// Gets the sender to send errors to the frontend.
-pub fn get_error_sender(tx: broadcast::Sender<String>) -> ErrorSender {
+pub fn get_error_sender(tx: Responder) -> ErrorSender {
   let error_sender = tx.clone();
 
   return Box::new(move | message: String, fix: String, error_type: BackendErrorType | {
     warn!("{}", &message);
 
-    let mut data = Map::new();
-    data.insert(String::from("message"), Value::String(message));
-    data.insert(String::from("fix"), Value::String(fix));
-    data.insert(String::from("type"), serde_json::to_value(error_type).unwrap());
+    send_event(error_sender.clone(), Event::BackendError(BackendError {
+      message,
+      fix,
+      r#type: error_type,
+    }));
+  });
+}
 
-    let mut map = Map::new();
-    map.insert(String::from("data"), serde_json::to_value(data).unwrap());
+/// Gets the sender for errors from background jobs. Errors are collected so they can be reported as the job's result.
+pub fn get_job_error_sender(errors: Arc<Mutex<Vec<String>>>) -> ErrorSender {
+  return Box::new(move | message: String, _fix: String, _error_type: BackendErrorType | {
+    warn!("{}", &message);
 
-    error_sender.send(format!("backend_error {}", serde_json::to_string(&map).unwrap())).expect("Failed to send error message");
+    errors.lock().expect("Failed to lock Job Errors Mutex.").push(message);
   });
 }
 
-/// Checks the User's hash.
-pub fn check_hash(hash: String, tx: broadcast::Sender<String>) -> bool {
-  let is_valid = validate_hash(hash, tx.clone());
+/// Sends a protocol error for a frame that couldn't be handled.
+pub fn send_protocol_error(tx: Responder, code: ProtocolErrorCode, message: String) {
+  warn!("Rejected websocket message ({:?}): {}", code, &message);
 
-  if !is_valid {
-    warn!("Password hashes do not match!");
-    send(tx, "hash_mismatch", String::from(""));
+  send_event(tx, Event::ProtocolError(ProtocolError { code, message }));
+}
+
+/// Checks the User's session token, and that their role is allowed to perform the action.
+pub fn check_token(token: String, role: UserRole, sessions: Arc<Mutex<SessionStore>>, tx: Responder) -> bool {
+  let store = sessions.lock().expect("Failed to lock Sessions Mutex.");
+  let claims_res = validate_token(&store, &token);
+
+  if claims_res.is_none() {
+    warn!("Session token was invalid or expired!");
+    send_event(tx, Event::SessionInvalid);
+    return false;
   }
 
-  return is_valid;
-}
\ No newline at end of file
+  let claims = claims_res.unwrap();
+  if claims.role < role {
+    warn!("User \"{}\" does not have permission to perform this action!", claims.user);
+    send_event(tx, Event::PermissionDenied(role));
+    return false;
+  }
+
+  return true;
+}
+
+/// Formats a number in base 36, like JavaScript's `toString(36)`.
+fn to_base36(mut value: u32) -> String {
+  let digits = b"0123456789abcdefghijklmnopqrstuvwxyz";
+  let mut result = vec![];
+
+  loop {
+    result.push(digits[(value % 36) as usize]);
+    value /= 36;
+
+    if value == 0 {
+      break;
+    }
+  }
+
+  result.reverse();
+  return String::from_utf8(result).unwrap();
+}
+
+/// Hashes a string into the ids the frontend uses for ROMs. This is a port of the frontend's hash64, so the two always agree.
+pub fn hash64(value: &str) -> String {
+  let mut h1: u32 = 0xdeadbeef;
+  let mut h2: u32 = 0x41c6ce57;
+
+  // * JavaScript strings are UTF-16, so hash the same code units it does.
+  for ch in value.encode_utf16() {
+    h1 = (h1 ^ ch as u32).wrapping_mul(2654435761);
+    h2 = (h2 ^ ch as u32).wrapping_mul(1597334677);
+  }
+
+  h1 = (h1 ^ (h1 >> 16)).wrapping_mul(2246822507);
+  h1 ^= (h2 ^ (h2 >> 13)).wrapping_mul(3266489909);
+  h2 = (h2 ^ (h2 >> 16)).wrapping_mul(2246822507);
+  h2 ^= (h1 ^ (h1 >> 13)).wrapping_mul(3266489909);
+
+  return format!("{:0>7}{:0>7}", to_base36(h2), to_base36(h1));
+}
+
+/// Gets the path a ROM is known by on disk. Folder ROMs use the folder, since their path points to a file inside it.
+pub fn get_rom_base(rom: &ROM) -> PathBuf {
+  let rom_path = PathBuf::from(&rom.path);
+
+  let is_folder = rom.downloadStrategy.get("type").and_then(|value| value.as_str()) == Some("folder");
+  let parent = rom.downloadStrategy.get("parent").and_then(|value| value.as_str()).unwrap_or_default();
+  if !is_folder || parent.is_empty() {
+    return rom_path;
+  }
+
+  let os_parent_dir = OsStr::new(parent);
+  for ancestor in rom_path.ancestors() {
+    if ancestor.parent().and_then(|parent| parent.file_name()) == Some(os_parent_dir) {
+      return ancestor.to_path_buf();
+    }
+  }
+
+  return rom_path;
+}
+
+/// Gets the strings in a list from a ROM's IGDB game, ex: its genres.
+pub fn get_igdb_list(game: &Value, key: &str) -> Vec<String> {
+  return game.get("metadata")
+    .and_then(|igdb_metadata| igdb_metadata.get(key))
+    .and_then(|list| list.as_array())
+    .map(|list| list.iter().filter_map(|entry| entry.as_str().map(|entry| entry.to_string())).collect())
+    .unwrap_or_default();
+}
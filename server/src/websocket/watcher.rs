@@ -1,16 +1,153 @@
-use std::{path::PathBuf, sync::{mpsc::{Receiver, Sender}, Arc, Mutex}};
+use std::{collections::HashMap, env::var, fs, path::{Path, PathBuf}, sync::{mpsc::{Receiver, Sender}, Arc, Mutex}, time::Duration};
 
 use log::{info, warn};
-use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use notify::{event::{MetadataKind, ModifyKind, RenameMode}, Config, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode};
+use notify_debouncer_full::{new_debouncer_opt, DebounceEventResult, Debouncer, FileIdMap};
 use tokio::sync::broadcast;
 
-use crate::websocket::utils::send;
+use crate::websocket::{types::{library::StateStore, protocol::Event}, utils::broadcast};
+
+use super::{collections::refresh_collections, exporter::Exporter, hasher::Hasher, library_index::write_index, library_manager::{add_rom_path, remove_rom_path, rename_rom_path, update_extra_path, update_rom_path}};
+
+/// How long a path has to stop changing before its events are handled.
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often paths are polled when the env variable isn't set.
+const DEFAULT_POLL_INTERVAL: u64 = 30;
+
+/// Filesystems that don't report changes made by other machines, so they have to be polled.
+const NETWORK_FILESYSTEMS: [&str; 12] = [
+  "nfs", "nfs4", "cifs", "smb3", "smbfs", "9p", "afs", "ceph", "glusterfs", "fuse.glusterfs", "fuse.sshfs", "fuse.rclone"
+];
 
 pub enum WatcherEvent {
   Add(PathBuf),
   Remove(PathBuf),
 }
 
+/// How the watcher detects changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum WatcherMode {
+  /// Uses polling for network filesystems, and native notifications for everything else.
+  AUTO,
+  NATIVE,
+  POLL,
+}
+
+/// Gets the watcher mode from the NRM_WATCHER_MODE env variable.
+fn get_watcher_mode() -> WatcherMode {
+  let mode = var("NRM_WATCHER_MODE").unwrap_or_default().to_lowercase();
+
+  return match mode.as_str() {
+    "" | "auto" => WatcherMode::AUTO,
+    "native" => WatcherMode::NATIVE,
+    "poll" => WatcherMode::POLL,
+    _ => {
+      warn!("Watcher: Unknown watcher mode \"{}\", defaulting to auto.", mode);
+      WatcherMode::AUTO
+    }
+  };
+}
+
+/// Gets the polling interval from the NRM_WATCHER_POLL_INTERVAL env variable.
+fn get_poll_interval() -> Duration {
+  let interval = var("NRM_WATCHER_POLL_INTERVAL").ok()
+    .and_then(|interval| interval.parse::<u64>().ok())
+    .filter(|interval| *interval > 0)
+    .unwrap_or(DEFAULT_POLL_INTERVAL);
+
+  return Duration::from_secs(interval);
+}
+
+/// Checks if a path is on a network filesystem, using the closest mount point in /proc/mounts.
+fn is_network_filesystem(path: &Path) -> bool {
+  let mounts_res = fs::read_to_string("/proc/mounts");
+  if mounts_res.is_err() {
+    return false;
+  }
+
+  let path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+  let mut closest_mount: Option<(PathBuf, String)> = None;
+
+  for line in mounts_res.unwrap().lines() {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 3 {
+      continue;
+    }
+
+    // * Spaces in mount points are escaped as octal.
+    let mount_point = PathBuf::from(fields[1].replace("\\040", " "));
+
+    let is_closer = closest_mount.as_ref().is_none_or(|(closest, _)| mount_point.starts_with(closest));
+    if path.starts_with(&mount_point) && is_closer {
+      closest_mount = Some((mount_point, fields[2].to_string()));
+    }
+  }
+
+  return closest_mount.is_some_and(|(_, filesystem)| NETWORK_FILESYSTEMS.contains(&filesystem.as_str()));
+}
+
+/// Adds a path to a debouncer, and to its file id cache so renames can be matched.
+fn watch_with<T: notify::Watcher>(debouncer: &mut Debouncer<T, FileIdMap>, path: &Path) -> notify::Result<()> {
+  debouncer.watcher().watch(path, RecursiveMode::Recursive)?;
+  debouncer.cache().add_root(path, RecursiveMode::Recursive);
+
+  return Ok(());
+}
+
+/// Removes a path from a debouncer.
+fn unwatch_with<T: notify::Watcher>(debouncer: &mut Debouncer<T, FileIdMap>, path: &Path) {
+  let _ = debouncer.watcher().unwatch(path);
+  debouncer.cache().remove_root(path);
+}
+
+/// Gets the events for a debounced filesystem event.
+fn handle_event(event: &notify::Event, hasher: &Hasher, state: &mut StateStore) -> Vec<Event> {
+  let mut events = vec![];
+
+  match event.kind {
+    EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+      for path in event.paths.iter() {
+        events.append(&mut add_rom_path(path, hasher, state));
+      }
+    },
+    EventKind::Modify(ModifyKind::Data(_))
+    | EventKind::Modify(ModifyKind::Metadata(MetadataKind::WriteTime))
+    | EventKind::Modify(ModifyKind::Any) => {
+      for path in event.paths.iter() {
+        events.append(&mut update_rom_path(path, hasher, state));
+      }
+    },
+    EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+      for path in event.paths.iter() {
+        events.append(&mut remove_rom_path(path, hasher, state));
+      }
+    },
+    EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+      events.append(&mut rename_rom_path(&event.paths[0], &event.paths[1], hasher, state));
+    },
+    EventKind::Modify(ModifyKind::Name(_)) => {
+      for path in event.paths.iter() {
+        if path.exists() {
+          events.append(&mut add_rom_path(path, hasher, state));
+        } else {
+          events.append(&mut remove_rom_path(path, hasher, state));
+        }
+      }
+    },
+    _ => {
+      return events;
+    }
+  }
+
+  for path in event.paths.iter() {
+    events.append(&mut update_extra_path(path, state));
+  }
+
+  return events;
+}
+
 #[derive(Clone)]
 pub struct Watcher {
   receiver: Arc<Mutex<Receiver<WatcherEvent>>>,
@@ -21,7 +158,7 @@ impl Watcher {
   /// Creates a new Watcher.
   pub fn new() -> Watcher {
     let (sender, receiver) = std::sync::mpsc::channel();
-    
+
     return Watcher {
       receiver: Arc::new(Mutex::new(receiver)),
       sender
@@ -29,34 +166,76 @@ impl Watcher {
   }
 
   /// Initializes the watcher thread.
-  pub fn init(&self, tx: broadcast::Sender<String>) {
+  pub fn init(&self, tx: broadcast::Sender<String>, state_store: Arc<Mutex<StateStore>>, hasher: Hasher, exporter: Exporter) {
     let event_receiver = self.receiver.clone();
 
-    let (sender, receiver) = std::sync::mpsc::channel();
+    let (sender, receiver) = std::sync::mpsc::channel::<DebounceEventResult>();
+
+    let mode = get_watcher_mode();
+    let poll_interval = get_poll_interval();
 
-    let reciever_mutex = Arc::new(Mutex::new(receiver));
-    
     // Create a thread for handling the folder watching.
     std::thread::spawn(move || {
-      info!("Thread: Starting watcher file listener...");
+      info!("Thread: Starting watcher file listener in {:?} mode...", mode);
 
-      // Select recommended watcher for debouncer.
-      // Using a callback here, could also be a channel.
-      let mut watcher = RecommendedWatcher::new(sender, Config::default()).unwrap();
+      // * Both watchers are created when they're first needed, since a library can mix local and network folders.
+      let mut native_watcher: Option<Debouncer<RecommendedWatcher, FileIdMap>> = None;
+      let mut poll_watcher: Option<Debouncer<PollWatcher, FileIdMap>> = None;
+
+      // * Whether each watched path is being polled.
+      let mut watched_paths: HashMap<PathBuf, bool> = HashMap::new();
+
+      let event_receiver = event_receiver.lock().expect("Failed to lock Watcher Receiver Mutex.");
 
       // * Listen for watcher events from the frontend.
       loop {
-        let event = event_receiver.try_lock().unwrap().recv();
-
-        if let Ok(result) = event {
-          match result {
-            WatcherEvent::Add(path) => {
-              // * Add watcher to the path.
-              let _ = watcher.watch(path.as_path(), RecursiveMode::Recursive);
-            },
-            WatcherEvent::Remove(path) => {
-              // * Remove path from watcher.
-              let _ = watcher.unwatch(path.as_path());
+        let event = event_receiver.recv();
+        if event.is_err() {
+          break;
+        }
+
+        match event.unwrap() {
+          WatcherEvent::Add(path) => {
+            if watched_paths.contains_key(&path) {
+              continue;
+            }
+
+            let use_polling = match mode {
+              WatcherMode::AUTO => is_network_filesystem(&path),
+              WatcherMode::NATIVE => false,
+              WatcherMode::POLL => true,
+            };
+
+            let watch_res = if use_polling {
+              if poll_watcher.is_none() {
+                let config = Config::default().with_poll_interval(poll_interval);
+                poll_watcher = new_debouncer_opt(DEBOUNCE_TIMEOUT, None, sender.clone(), FileIdMap::new(), config).ok();
+              }
+
+              poll_watcher.as_mut().map(|debouncer| watch_with(debouncer, &path))
+            } else {
+              if native_watcher.is_none() {
+                native_watcher = new_debouncer_opt(DEBOUNCE_TIMEOUT, None, sender.clone(), FileIdMap::new(), Config::default()).ok();
+              }
+
+              native_watcher.as_mut().map(|debouncer| watch_with(debouncer, &path))
+            };
+
+            if watch_res.as_ref().is_none_or(|res| res.is_err()) {
+              warn!("Watcher: Failed to watch \"{}\": {:?}", path.display(), watch_res);
+              continue;
+            }
+
+            info!("Watcher: Watching \"{}\"{}.", path.display(), if use_polling { " by polling" } else { "" });
+            watched_paths.insert(path, use_polling);
+          },
+          WatcherEvent::Remove(path) => {
+            let polled = watched_paths.remove(&path);
+
+            if polled == Some(true) && poll_watcher.is_some() {
+              unwatch_with(poll_watcher.as_mut().unwrap(), &path);
+            } else if polled == Some(false) && native_watcher.is_some() {
+              unwatch_with(native_watcher.as_mut().unwrap(), &path);
             }
           }
         }
@@ -67,30 +246,35 @@ impl Watcher {
     std::thread::spawn(move || {
       info!("Thread: Starting watcher event handler...");
 
-      loop {
-        let event = reciever_mutex.try_lock().unwrap().recv();
-
-        if let Ok(res) = event {
-          match res {
-            Ok(event) => match event.kind {
-                EventKind::Create(_create_kind) => {
-                  let path = event.paths[0].clone();
-
-                  send(tx.clone(), "reload_library", path.to_str().to_owned());
-                },
-                EventKind::Remove(_create_kind) => {
-                  let path = event.paths[0].clone();
-                  
-                  send(tx.clone(), "reload_library", path.to_str().to_owned());
-                },
-                _ => {
-                  
-                }
-            },
-            Err(e) => {
-              warn!("Error {:?}", e)
-            },
+      for result in receiver {
+        if result.is_err() {
+          for error in result.err().unwrap() {
+            warn!("Watcher: {:?}", error);
           }
+          continue;
+        }
+
+        let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+        let mut events: Vec<Event> = vec![];
+
+        for event in result.unwrap() {
+          events.append(&mut handle_event(&event, &hasher, &mut state));
+        }
+
+        // ? The index is copied so it's written after the state is unlocked.
+        let index = if events.is_empty() { None } else { Some(state.index.clone()) };
+        if index.is_some() {
+          refresh_collections(&mut state, &tx);
+        }
+        drop(state);
+
+        if index.is_some() {
+          write_index(index.as_ref().unwrap());
+          exporter.library_changed();
+        }
+
+        for event in events {
+          broadcast(tx.clone(), event);
         }
       }
     });
@@ -105,4 +289,4 @@ impl Watcher {
   pub fn unwatch_path(&self, path: PathBuf) {
     let _ = self.sender.send(WatcherEvent::Remove(path));
   }
-}
\ No newline at end of file
+}
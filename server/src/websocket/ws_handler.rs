@@ -1,45 +1,165 @@
 use futures_util::{SinkExt, StreamExt};
+use log::warn;
 use warp::filters::ws::{Message, WebSocket};
 use wax::Glob;
-use std::{sync::{Arc, Mutex}};
-use tokio::sync::broadcast;
-use sysinfo::{DiskRefreshKind, Disks};
+use serde_json::json;
+use tokio::sync::{broadcast, mpsc};
+use sysinfo::DiskRefreshKind;
+
+use crate::{job_queue::JobQueue, websocket::{
+  auth::{authenticate_user, create_session, refresh_session, revoke_session, revoke_user_sessions, sync_user_roles, validate_token}, file_picker::get_entries, library_manager::{parse_added_rom, parse_library}, settings::{load_settings, set_setting, write_settings}, types::{
+    auth::{SessionToken, UserRole}, library::StateStore, AvailableStorage, BackendErrorType, ErrorSender, Responder
+  }, users::{delete_user, list_users, save_user, write_users}, utils::{check_token, get_error_sender, send}
+}};
+
+use super::{collections::{
+  add_to_collection, create_collection, delete_collection, refresh_collections, remove_from_collection, rename_collection, set_collection_rules, write_collections
+}, context::ServerContext, exporter::export_library, importer::import_metadata, metadata::{load_metadata, migrate_metadata, write_metadata}, parsers::{delete_parser, write_parsers}, types::{library::{Collection, LoadResult}, args::HelloArgs, protocol::{Command, Event, HelloResponse, ProtocolErrorCode, RequestEnvelope, COMMAND_TYPES, PROTOCOL_VERSION}}, utils::{broadcast as broadcast_event, send_event, send_protocol_error}};
+
+
+/// Lets the connection that started a job know its id, so it can follow its progress or cancel it.
+fn send_job_queued(tx: Responder, job_queue: &JobQueue, job_id: &str) {
+  let info = job_queue.get_job(job_id);
+  if info.is_some() {
+    send_event(tx, Event::JobQueued(info.unwrap()));
+  }
+}
 
-use crate::websocket::{
-  auth::authenticate_user, file_picker::get_entries, library_manager::{parse_added_rom, parse_library}, settings::{load_settings, set_setting, write_settings}, types::{
-    args::{
-      AuthArgs, FilePickerArgs, MetadataArgs, ModifyExtraFileArgs, ModifyLibraryArgs, ParseRomArgs, SetSettingArgs, SimpleArgs
-    }, library::StateStore, settings::Settings, AvailableStorage, ErrorSender
-  }, utils::{check_hash, get_error_sender, send}, watcher::Watcher
-};
+/// Saves the collections after a command changed one, and lets every connection know.
+fn finish_collection_change(
+  change_res: Result<Collection, String>,
+  message: &str,
+  state: &StateStore,
+  tx: Responder,
+  broadcast_tx: &broadcast::Sender<String>,
+  send_error: ErrorSender
+) {
+  if change_res.is_err() {
+    send_error(
+      format!("Failed to update collection: {}", change_res.err().unwrap()),
+      String::from("Please refresh your collections and try again."),
+      BackendErrorType::WARN
+    );
+    return;
+  }
 
-use super::{metadata::{load_metadata, write_metadata}, parsers::{delete_parser, write_parsers}, types::{args::{DeleteParserArgs, GlobArgs, ParsersArgs}, library::LoadResult}};
+  let success = write_collections(&state.collections, &send_error);
 
+  if success {
+    broadcast_event(broadcast_tx.clone(), Event::CollectionsChanged(state.collections.clone()));
+    send(tx, message, change_res.unwrap());
+  }
+}
 
-fn handle_message(
-  message: &str,
-  data: &str,
-  tx: broadcast::Sender<String>,
-  disks: Arc<Mutex<Disks>>,
-  settings: Arc<Mutex<Settings>>,
-  watcher: Arc<Mutex<Watcher>>,
-  state_store: Arc<Mutex<StateStore>>
-) {
+fn handle_message(command: Command, tx: Responder, context: ServerContext) {
   let send_error: ErrorSender = get_error_sender(tx.clone());
+  let job_context = context.clone();
+  let ServerContext { settings, watcher, hasher, state_store, users, sessions, disks, job_queue, broadcast_tx, exporter } = context;
 
-  match message {
-    "user_auth" => {
-      let args: AuthArgs = serde_json::from_str(data).unwrap();
+  match command {
+    Command::UserAuth(args) => {
       let username = args.user;
       let password_hash = args.passwordHash;
 
-      let result = authenticate_user(username.clone(), password_hash, tx.clone());
+      let users_store = users.lock().expect("Failed to lock Users Mutex.");
+      let role_res = authenticate_user(username.clone(), password_hash, &users_store, tx.clone());
+
+      if role_res.is_none() {
+        send(tx, "user_auth", None::<SessionToken>);
+        return;
+      }
+
+      let store = sessions.lock().expect("Failed to lock Sessions Mutex.");
+      let session = create_session(&store, username, role_res.unwrap());
+
+      send(tx, "user_auth", Some(session));
+    }
+    Command::RefreshSession(args) => {
+      let mut store = sessions.lock().expect("Failed to lock Sessions Mutex.");
+      let session = refresh_session(&mut store, &args.token);
+
+      if session.is_none() {
+        warn!("Failed to refresh session: token was invalid or expired.");
+      }
+
+      send(tx, "refresh_session", session);
+    }
+    Command::Logout(args) => {
+      let mut store = sessions.lock().expect("Failed to lock Sessions Mutex.");
+      let claims = validate_token(&store, &args.token);
+
+      if claims.is_some() {
+        revoke_session(&mut store, &claims.unwrap());
+      }
+
+      send(tx, "logout", true);
+    }
+    Command::ListUsers(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      let users_store = users.lock().expect("Failed to lock Users Mutex.");
+
+      send(tx, "list_users", list_users(&users_store));
+    }
+    Command::SaveUser(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      let mut users_store = users.lock().expect("Failed to lock Users Mutex.");
+      let save_res = save_user(&mut users_store, args.username.clone(), args.passwordHash, args.role);
+
+      if save_res.is_err() {
+        send_error(
+          format!("Failed to save user: {}", save_res.err().unwrap()),
+          String::from("Please double check the user's info and try again."),
+          BackendErrorType::WARN
+        );
+        return;
+      }
+
+      let success = write_users(&users_store, send_error);
+
+      if success {
+        let mut store = sessions.lock().expect("Failed to lock Sessions Mutex.");
+        revoke_user_sessions(&mut store, &args.username);
+        sync_user_roles(&mut store, &users_store);
+
+        send(tx, "save_user", success);
+      }
+    }
+    Command::DeleteUser(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      let mut users_store = users.lock().expect("Failed to lock Users Mutex.");
+      if !delete_user(&mut users_store, &args.username) {
+        send_error(
+          format!("Failed to delete user: \"{}\" does not exist.", args.username),
+          String::from("Please refresh the users list and try again."),
+          BackendErrorType::WARN
+        );
+        return;
+      }
+
+      let success = write_users(&users_store, send_error);
 
-      send(tx, "user_auth", result);
+      if success {
+        let mut store = sessions.lock().expect("Failed to lock Sessions Mutex.");
+        revoke_user_sessions(&mut store, &args.username);
+        sync_user_roles(&mut store, &users_store);
+
+        send(tx, "delete_user", success);
+      }
     }
-    "load_settings" => {
-      let args: SimpleArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::LoadSettings(args) => {
+      let valid = check_token(args.token, UserRole::READONLY, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
@@ -65,9 +185,8 @@ fn handle_message(
         send(tx, "load_settings", &settings);
       }
     }
-    "write_settings" => {
-      let args: SimpleArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::WriteSettings(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
@@ -80,16 +199,74 @@ fn handle_message(
         send(tx, "write_settings", success);
       }
     }
-    "set_setting" => {
-      let args: SetSettingArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::SetSetting(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
 
       let mut state_settings = settings.lock().expect("Failed to lock Settings Mutex.");
-      set_setting(&mut state_settings, &args.key, args.value);
-      
+      let previous_settings = state_settings.clone();
+      let set_res = set_setting(&mut state_settings, &args.key, args.value.clone());
+      if set_res.is_err() {
+        send_error(
+          format!("Failed to set setting \"{}\": {}", args.key, set_res.err().unwrap()),
+          String::from("Please double check the setting's key and value."),
+          BackendErrorType::WARN
+        );
+        return;
+      }
+
+      // * Changing where metadata is saved moves the existing metadata over in a job, and keeps the old setting until that's done.
+      if previous_settings.metadata.saveAlongsideROMs != state_settings.metadata.saveAlongsideROMs {
+        let new_settings = state_settings.clone();
+        *state_settings = previous_settings.clone();
+        drop(state_settings);
+
+        if job_queue.find_active("migrate_metadata", "").is_some() {
+          send_error(
+            String::from("Failed to set setting: Metadata is already being moved."),
+            String::from("Please wait for the current move to finish, then try again."),
+            BackendErrorType::WARN
+          );
+          return;
+        }
+
+        let job_tx = tx.clone();
+        let job = job_queue.spawn_blocking("migrate_metadata", "", move |_| {
+          let send_error = get_error_sender(job_tx.clone());
+
+          let roms = job_context.state_store.lock().expect("Failed to lock State Mutex.").roms.clone();
+          let migrate_res = migrate_metadata(&previous_settings, &new_settings, &roms, &send_error);
+          if migrate_res.is_err() {
+            return Err(String::from("Failed to move the metadata."));
+          }
+          let metadata = migrate_res.unwrap();
+
+          // * The setting is set again, so settings changed while the metadata was moving aren't lost.
+          let mut state_settings = job_context.settings.lock().expect("Failed to lock Settings Mutex.");
+          let _ = set_setting(&mut state_settings, &args.key, args.value);
+
+          let mut state = job_context.state_store.lock().expect("Failed to lock State Mutex.");
+          (*state).metadata = metadata.clone();
+          refresh_collections(&mut state, &job_context.broadcast_tx);
+          drop(state);
+
+          let success = write_settings(state_settings, send_error);
+          broadcast_event(job_context.broadcast_tx.clone(), Event::MetadataRefreshed(metadata.clone()));
+          job_context.exporter.library_changed();
+
+          if success {
+            send(job_tx, "set_setting", success);
+          }
+
+          return Ok(json!({ "romCount": metadata.len() }));
+        });
+
+        send_job_queued(tx, &job_queue, &job.id);
+        return;
+      }
+
       let success = write_settings(state_settings, send_error);
 
       // If write failed, we've already notfied the frontend of that, so we don't need to here.
@@ -97,16 +274,13 @@ fn handle_message(
         send(tx, "set_setting", success);
       }
     }
-    "load_library" => {
-      let args: SimpleArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::LoadLibrary(args) => {
+      let valid = check_token(args.token, UserRole::READONLY, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
 
-      let state_settings = settings.lock().expect("Failed to lock Settings Mutex.");
-      let state_watcher = watcher.lock().expect("Failed to lock Watcher Mutex.");
-      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+      let state = state_store.lock().expect("Failed to lock State Mutex.");
 
       // If we've already cached the roms and systems, return them.
       if state.roms.len() > 0 && state.parsers.len() > 0 {
@@ -116,58 +290,89 @@ fn handle_message(
           systems: state.parsers.clone().into_values().collect(),
           dlcs: state.dlcs.clone(),
           updates: state.updates.clone(),
+          bios: state.bios.clone(),
         });
 
         return;
       }
+      drop(state);
+
+      // * Parsing can take a while for big libraries, so it runs as a job and replies once it's done.
+      let job_tx = tx.clone();
+      let job = job_queue.spawn_blocking("parse_library", "", move |job| {
+        let send_error = get_error_sender(job_tx.clone());
+
+        let library = job_context.settings.lock().expect("Failed to lock Settings Mutex.").library.clone();
+        let library_res = parse_library(
+          &library,
+          &job_context.get_watcher(),
+          &job_context.get_hasher(),
+          &job_context.state_store,
+          &job_context.broadcast_tx,
+          send_error,
+          &job
+        );
+
+        // If loading failed, we've already notfied the frontend of that, so we don't need to here.
+        if library_res.is_err() {
+          return Err(String::from("Failed to load the library."));
+        }
 
-      let library_res = parse_library(
-        &state_settings.library,
-        &state_watcher,
-        &mut state,
-        send_error
-      );
-
-      // If loading failed, we've already notfied the frontend of that, so we don't need to here.
-      if library_res.is_ok() {
         let load_res = library_res.unwrap();
-        (*state).roms = load_res.roms.clone();
-        send(tx, "load_library", load_res);
-      }
+        job_context.exporter.library_changed();
+
+        let rom_count = load_res.roms.len();
+        send(job_tx, "load_library", load_res);
+
+        return Ok(json!({ "romCount": rom_count }));
+      });
+
+      send_job_queued(tx, &job_queue, &job.id);
     }
-    "update_library" => {
-      let args: ModifyLibraryArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::UpdateLibrary(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
-      
-      let state_watcher = watcher.lock().expect("Failed to lock Watcher Mutex.");
-      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
-      let library_res = parse_library(
-        &args.library,
-        &state_watcher,
-        &mut state,
-        send_error
-      );
 
-      // If loading failed, we've already notfied the frontend of that, so we don't need to here.
-      if library_res.is_ok() {
-        (*state).library = args.library.clone();
-        
+      let job_tx = tx.clone();
+      let library_path = args.library.libraryPath.clone();
+      let job = job_queue.spawn_blocking("parse_library", &library_path, move |job| {
+        let send_error = get_error_sender(job_tx.clone());
+
+        let library_res = parse_library(
+          &args.library,
+          &job_context.get_watcher(),
+          &job_context.get_hasher(),
+          &job_context.state_store,
+          &job_context.broadcast_tx,
+          send_error,
+          &job
+        );
+
+        // If loading failed, we've already notfied the frontend of that, so we don't need to here.
+        if library_res.is_err() {
+          return Err(String::from("Failed to load the library."));
+        }
+
         let load_res = library_res.unwrap();
-        (*state).roms = load_res.roms.clone();
+        job_context.exporter.library_changed();
 
-        send(tx, "update_library", load_res);
-      }
+        let rom_count = load_res.roms.len();
+        send(job_tx, "update_library", load_res);
+
+        return Ok(json!({ "romCount": rom_count }));
+      });
+
+      send_job_queued(tx, &job_queue, &job.id);
     }
-    "load_metadata" => {
-      let args: SimpleArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::LoadMetadata(args) => {
+      let valid = check_token(args.token, UserRole::READONLY, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
       
+      let state_settings = settings.lock().expect("Failed to lock Settings Mutex.");
       let mut state = state_store.lock().expect("Failed to lock State Mutex.");
 
       // If we've already cached the metadata, return it.
@@ -177,75 +382,223 @@ fn handle_message(
         return;
       }
 
-      let metadata_res = load_metadata(send_error);
+      let metadata_res = load_metadata(&state_settings, send_error);
 
       // If loading failed, we've already notfied the frontend of that, so we don't need to here.
       if metadata_res.is_ok() {
         let metadata = metadata_res.unwrap();
         (*state).metadata = metadata.clone();
+        refresh_collections(&mut state, &broadcast_tx);
 
         send(tx, "load_metadata", &metadata);
       }
     }
-    "refresh_metadata" => {
-      let args: SimpleArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::RefreshMetadata(args) => {
+      let valid = check_token(args.token, UserRole::READONLY, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
       
+      let state_settings = settings.lock().expect("Failed to lock Settings Mutex.");
       let mut state = state_store.lock().expect("Failed to lock State Mutex.");
-      let metadata_res = load_metadata(send_error);
+      let metadata_res = load_metadata(&state_settings, send_error);
 
       // If loading failed, we've already notfied the frontend of that, so we don't need to here.
       if metadata_res.is_ok() {
         let metadata = metadata_res.unwrap();
         (*state).metadata = metadata.clone();
+        refresh_collections(&mut state, &broadcast_tx);
 
         send(tx, "refresh_metadata", &metadata);
       }
     },
-    "save_metadata" => {
-      let args: MetadataArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::SaveMetadata(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
       
+      let state_settings = settings.lock().expect("Failed to lock Settings Mutex.");
       let mut state = state_store.lock().expect("Failed to lock State Mutex.");
-      let success = write_metadata(&args.data, send_error);
+      let success = write_metadata(&state_settings, &args.data, &state.metadata, &state.roms, send_error);
 
       if success {
         (*state).metadata = args.data;
+        refresh_collections(&mut state, &broadcast_tx);
+        exporter.library_changed();
         send(tx, "save_metadata", success);
       }
     }
-    "save_parsers" => {
-      let args: ParsersArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::LoadCollections(args) => {
+      let valid = check_token(args.token, UserRole::READONLY, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
-      
+
+      let state = state_store.lock().expect("Failed to lock State Mutex.");
+      send(tx, "load_collections", state.collections.clone());
+    }
+    Command::CreateCollection(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+      let create_res = create_collection(&mut state, &args.name, args.rules);
+      finish_collection_change(create_res, "create_collection", &state, tx, &broadcast_tx, send_error);
+    }
+    Command::RenameCollection(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+      let rename_res = rename_collection(&mut state, &args.collectionId, &args.name);
+      finish_collection_change(rename_res, "rename_collection", &state, tx, &broadcast_tx, send_error);
+    }
+    Command::DeleteCollection(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+      let delete_res = delete_collection(&mut state, &args.collectionId);
+      finish_collection_change(delete_res, "delete_collection", &state, tx, &broadcast_tx, send_error);
+    }
+    Command::AddToCollection(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
       let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+      let add_res = add_to_collection(&mut state, &args.collectionId, args.romIds);
+      finish_collection_change(add_res, "add_to_collection", &state, tx, &broadcast_tx, send_error);
+    }
+    Command::RemoveFromCollection(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+      let remove_res = remove_from_collection(&mut state, &args.collectionId, args.romIds);
+      finish_collection_change(remove_res, "remove_from_collection", &state, tx, &broadcast_tx, send_error);
+    }
+    Command::SetCollectionRules(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+      let set_res = set_collection_rules(&mut state, &args.collectionId, args.rules);
+      finish_collection_change(set_res, "set_collection_rules", &state, tx, &broadcast_tx, send_error);
+    }
+    Command::ExportLibrary(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      let job_tx = tx.clone();
+      let job = job_queue.spawn_blocking("export_library", "", move |job| {
+        let send_error = get_error_sender(job_tx.clone());
+
+        let export_res = export_library(&settings, &state_store, &job);
+        if export_res.is_err() {
+          let err = export_res.err().unwrap();
+
+          send_error(
+            format!("Failed to export library: {}", err),
+            String::from("Please ensure NRM has write access to your library's export folder."),
+            BackendErrorType::WARN
+          );
+
+          return Err(err);
+        }
+
+        let result = export_res.unwrap();
+        send(job_tx, "export_library", &result);
+
+        return Ok(serde_json::to_value(result).expect("Export result was malformatted."));
+      });
+
+      send_job_queued(tx, &job_queue, &job.id);
+    }
+    Command::ImportMetadata(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      let job_tx = tx.clone();
+      let import_path = args.path.clone();
+      let job = job_queue.spawn_blocking("import_metadata", &import_path, move |job| {
+        let send_error = get_error_sender(job_tx.clone());
+
+        let import_res = import_metadata(&args.path, args.overwrite, &settings, &state_store, &broadcast_tx, &send_error, &job);
+        if import_res.is_err() {
+          let err = import_res.err().unwrap();
+
+          send_error(
+            format!("Failed to import metadata: {}", err),
+            String::from("Please double check the path, and ensure NRM has read access to it."),
+            BackendErrorType::WARN
+          );
+
+          return Err(err);
+        }
+
+        let result = import_res.unwrap();
+        if result.matched > 0 {
+          exporter.library_changed();
+        }
+
+        send(job_tx, "import_metadata", &result);
+
+        return Ok(serde_json::to_value(result).expect("Import result was malformatted."));
+      });
+
+      send_job_queued(tx, &job_queue, &job.id);
+    }
+    Command::SaveParsers(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+      
       let state_watcher = watcher.lock().expect("Failed to lock Watcher Mutex.");
+      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
       let success = write_parsers(&args.data, &state.library, &state_watcher, send_error);
 
       if success {
         (*state).parsers = args.data;
+        exporter.library_changed();
         send(tx, "save_parsers", success);
       }
     }
-    "delete_parser" => {
-      let args: DeleteParserArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::DeleteParser(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
       
-      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
       let state_watcher = watcher.lock().expect("Failed to lock Watcher Mutex.");
-      let parser = state.parsers.get(&args.abbreviation).expect("Failed to get parser with provided abbreviation.");
+      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+      let parser_res = state.parsers.get(&args.abbreviation);
+      if parser_res.is_none() {
+        send_error(
+          format!("Failed to delete parser: \"{}\" does not exist.", args.abbreviation),
+          String::from("Please refresh the parsers list and try again."),
+          BackendErrorType::WARN
+        );
+        return;
+      }
+      let parser = parser_res.unwrap();
       let success = delete_parser(parser, &state.library, &state_watcher, send_error);
 
       if success {
@@ -253,9 +606,8 @@ fn handle_message(
         send(tx, "delete_parser", success);
       }
     }
-    "add_extra_file" => {
-      let args: ModifyExtraFileArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::AddExtraFile(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
@@ -286,9 +638,8 @@ fn handle_message(
 
       send(tx, "add_extra_file", true);
     }
-    "delete_extra_file" => {
-      let args: ModifyExtraFileArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::DeleteExtraFile(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
@@ -318,29 +669,56 @@ fn handle_message(
 
       send(tx, "delete_extra_file", true);
     }
-    "parse_rom" => {
-      let args: ParseRomArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::ParseRom(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
 
-      let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+      // ? The parser and DAT are copied so the library isn't locked while the ROM is read.
+      let state_hasher = hasher.lock().expect("Failed to lock Hasher Mutex.").clone();
+      let state = state_store.lock().expect("Failed to lock State Mutex.");
+      let parser = state.parsers.get(&args.parser).cloned();
+      let dat = state.dats.get(&args.parser).cloned();
+      drop(state);
+
+      if parser.is_none() {
+        send_error(
+          format!("Adding ROM: There is no parser for system \"{}\".", args.parser),
+          "Please double check that the system still exists.".to_string(),
+          BackendErrorType::WARN
+        );
+        return;
+      }
+
       let rom_res = parse_added_rom(
-        args.parser,
+        parser.as_ref().unwrap(),
+        dat.as_ref(),
         &args.romPath,
-        &state,
+        &state_hasher,
         send_error
       );
 
       if rom_res.is_ok() {
-        (*state).roms.push(rom_res.clone().unwrap());
-        send(tx, "parse_rom", rom_res.unwrap());
+        let rom = rom_res.unwrap();
+
+        // * The watcher could have added the ROM while it was being read.
+        let mut state = state_store.lock().expect("Failed to lock State Mutex.");
+        let existing = state.roms.iter().position(|state_rom| state_rom.path == rom.path);
+        if existing.is_some() {
+          (*state).roms[existing.unwrap()] = rom.clone();
+        } else {
+          (*state).roms.push(rom.clone());
+        }
+        refresh_collections(&mut state, &broadcast_tx);
+        drop(state);
+
+        exporter.library_changed();
+        send(tx, "parse_rom", rom);
       }
     }
-    "file_picker" => {
-      let args: FilePickerArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::FilePicker(args) => {
+      let valid = check_token(args.token, UserRole::ADMIN, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
@@ -356,9 +734,8 @@ fn handle_message(
         send(tx, "file_picker", file_entries_res.unwrap());
       }
     }
-    "available_storage" => {
-      let args: SimpleArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::AvailableStorage(args) => {
+      let valid = check_token(args.token, UserRole::READONLY, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
@@ -383,9 +760,8 @@ fn handle_message(
 
       send(tx, "available_storage", info);
     }
-    "is_valid_glob" => {
-      let args: GlobArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::IsValidGlob(args) => {
+      let valid = check_token(args.token, UserRole::READONLY, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
@@ -394,56 +770,121 @@ fn handle_message(
 
       send(tx, "is_valid_glob", glob_res.is_ok());
     }
-    "demo" => {
-      let args: SimpleArgs = serde_json::from_str(data).unwrap();
-      let valid = check_hash(args.passwordHash, tx.clone());
+    Command::ListJobs(args) => {
+      let valid = check_token(args.token, UserRole::READONLY, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      send(tx, "list_jobs", job_queue.list());
+    }
+    Command::CancelJob(args) => {
+      let valid = check_token(args.token, UserRole::UPLOADER, sessions.clone(), tx.clone());
+      if !valid {
+        return;
+      }
+
+      send(tx, "cancel_job", job_queue.cancel(&args.jobId));
+    }
+    Command::Demo(args) => {
+      let valid = check_token(args.token, UserRole::READONLY, sessions.clone(), tx.clone());
       if !valid {
         return;
       }
     }
-    _ => {}
   }
 }
 
 /// Handles WebSocket Connections
-pub async fn handle_connection(
-  ws: WebSocket,
-  tx: Arc<Mutex<broadcast::Sender<String>>>,
-  disks: Arc<Mutex<Disks>>,
-  settings: Arc<Mutex<Settings>>,
-  watcher: Arc<Mutex<Watcher>>,
-  state_store: Arc<Mutex<StateStore>>
-) {
+pub async fn handle_connection(ws: WebSocket, context: ServerContext) {
   let (mut ws_sender, mut ws_receiver) = ws.split();
-  let mut rx = tx.lock().unwrap().subscribe();
+  let mut rx = context.broadcast_tx.subscribe();
+  let (response_tx, mut response_rx) = mpsc::unbounded_channel::<String>();
 
-  // * Spawn the Message Propegation Thread.
+  // * Spawn the Message Propegation Thread. Replies only go to this connection, while library events go to everyone.
   tokio::spawn(async move {
-    while let Ok(msg) = rx.recv().await {
+    loop {
+      let msg = tokio::select! {
+        Some(msg) = response_rx.recv() => msg,
+        Ok(msg) = rx.recv() => msg,
+        else => break,
+      };
+
       if ws_sender.send(Message::text(msg)).await.is_err() {
         break;
       }
     }
   });
 
+  // * Commands are rejected until the frontend has agreed on a protocol version.
+  let mut handshake_done = false;
+
   while let Some(result) = ws_receiver.next().await {
     match result {
       Ok(message) => {
         if let Ok(text) = message.to_str() {
-          let (message, data) = text.split_once(" ").unwrap();
-
-          handle_message(
-            message,
-            data,
-            tx.lock().unwrap().to_owned(),
-            disks.clone(),
-            settings.clone(),
-            watcher.clone(),
-            state_store.clone()
-          );
+          let envelope_res = serde_json::from_str::<RequestEnvelope>(text);
+          if envelope_res.is_err() {
+            let responder = Responder { sender: response_tx.clone(), requestId: None };
+            send_protocol_error(responder, ProtocolErrorCode::MALFORMED_MESSAGE, envelope_res.err().unwrap().to_string());
+            continue;
+          }
+          let envelope = envelope_res.unwrap();
+
+          let responder = Responder {
+            sender: response_tx.clone(),
+            requestId: envelope.requestId.clone(),
+          };
+
+          if envelope.r#type == "hello" {
+            let args_res = serde_json::from_value::<HelloArgs>(envelope.data);
+            if args_res.is_err() {
+              send_protocol_error(responder, ProtocolErrorCode::INVALID_ARGS, format!("hello: {}", args_res.err().unwrap()));
+              continue;
+            }
+
+            let args = args_res.unwrap();
+            if args.version != PROTOCOL_VERSION {
+              send_protocol_error(
+                responder,
+                ProtocolErrorCode::UNSUPPORTED_VERSION,
+                format!("The frontend uses protocol version {}, but the backend uses version {}.", args.version, PROTOCOL_VERSION)
+              );
+              continue;
+            }
+
+            handshake_done = true;
+            send_event(responder, Event::Hello(HelloResponse { version: PROTOCOL_VERSION }));
+            continue;
+          }
+
+          if !COMMAND_TYPES.contains(&envelope.r#type.as_str()) {
+            send_protocol_error(responder, ProtocolErrorCode::UNKNOWN_COMMAND, format!("{}: Unknown command.", envelope.r#type));
+            continue;
+          }
+
+          if !handshake_done {
+            send_protocol_error(responder, ProtocolErrorCode::HANDSHAKE_REQUIRED, format!("{}: Send \"hello\" before any other command.", envelope.r#type));
+            continue;
+          }
+
+          let command_res = serde_json::from_value::<Command>(json!({ "type": envelope.r#type, "data": envelope.data }));
+          if command_res.is_err() {
+            send_protocol_error(responder, ProtocolErrorCode::INVALID_ARGS, format!("{}: {}", envelope.r#type, command_res.err().unwrap()));
+            continue;
+          }
+
+          // * Handlers wait on the shared locks, so they run on the blocking pool instead of stalling the runtime.
+          // * They're still awaited one at a time, so a connection's commands are handled in order.
+          let command = command_res.unwrap();
+          let handler_context = context.clone();
+          let handle_res = tokio::task::spawn_blocking(move || handle_message(command, responder, handler_context)).await;
+          if handle_res.is_err() {
+            warn!("Websocket: Command handler panicked: {}", handle_res.err().unwrap());
+          }
         }
       },
       Err(_e) => break,
     }
   }
-}
\ No newline at end of file
+}
@@ -1,6 +1,7 @@
 export { default as ContextMenu } from "./ContextMenu.svelte";
 export { default as GameTitleEntry } from "./GameTitleEntry.svelte";
 export { default as Icon } from "./Icon.svelte";
+export { default as JobProgress } from "./JobProgress.svelte";
 export { default as MediaQuery } from "./MediaQuery.svelte";
 export { default as ModalBody } from "./ModalBody.svelte";
 
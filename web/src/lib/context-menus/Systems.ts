@@ -19,6 +19,11 @@ export function getSystemMenuItems(abbreviation: string, sgdbId: string): Contex
     });
   }
 
+  items.push({
+    text: "Refresh Metadata",
+    action: () => SystemService.refreshMetadata(abbreviation),
+  });
+
   items.push({
     text: "Delete",
     action: () => SystemService.delete(abbreviation),
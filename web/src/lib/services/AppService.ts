@@ -1,6 +1,6 @@
 import { UpdateService } from "@services/UpdateService";
-import { isFirstSetup, loadingModalMessage, showEditLibraryModal, showLoadingModal } from "@stores/Modals";
-import { library, loadedLibrary, romDLCs, romMetadata, roms, romsBySystem, romUpdates, systems, systemTagConfigs } from "@stores/State";
+import { isFirstSetup, loadingModalJobId, loadingModalMessage, showEditLibraryModal, showLoadingModal } from "@stores/Modals";
+import { collections, library, loadedLibrary, romDLCs, romMetadata, roms, romsBySystem, romUpdates, systems, systemTagConfigs } from "@stores/State";
 import type { Library, LoadResult, ROMMetadata } from "@types";
 import { hash64 } from "@utils";
 import { get } from "svelte/store";
@@ -31,6 +31,8 @@ export class AppService {
       await this.loadLibrary();
     }
 
+    collections.set(await WebsocketService.loadCollections());
+
     SettingsService.registerSubs();
   }
 
@@ -129,12 +131,17 @@ export class AppService {
     showLoadingModal.set(true);
     loadedLibrary.set(false);
 
-    WebsocketService.updateLibrary(get(library)).then(async (loadRes) => {
+    WebsocketService.updateLibrary(get(library), (jobId) => loadingModalJobId.set(jobId)).then(async (loadRes) => {
       const metadata = await WebsocketService.refreshMetadata();
 
       await AppService.setStateFromLoadRes(loadRes, metadata);
       loadedLibrary.set(true);
       showLoadingModal.set(false);
+    }).catch((e: Error) => {
+      // ? The library that was loaded before is still in the state, so it's left as is.
+      LogService.warn(`Library refresh stopped: ${e.message}`);
+      loadedLibrary.set(true);
+      showLoadingModal.set(false);
     });
   }
 
@@ -1,6 +1,7 @@
 import { goto } from "$app/navigation";
 import { isSignedIn, username } from "@stores/Auth";
 import { landingPage } from "@stores/State";
+import type { SessionToken } from "@types";
 import { get } from "svelte/store";
 import { AppService } from "./AppService";
 import { WebsocketService } from "./utils/WebsocketService";
@@ -9,19 +10,20 @@ import { WebsocketService } from "./utils/WebsocketService";
  * The user authentication Service.
  */
 export class AuthService {
+  private static refreshTimeout: ReturnType<typeof setTimeout> | null = null;
+
   /**
-   * Authenticates the user.
-   * @param user The username to authenticate with.
-   * @param passwordHash The hash of the user's password.
-   * @returns The backend's response.
+   * Stores the session and schedules its refresh.
+   * @param session The session to start.
    */
-  static async authenticate(user: string, passwordHash: string): Promise<boolean> {
-    const success = await WebsocketService.authenticate(user, passwordHash);
+  private static async startSession(session: SessionToken) {
+    sessionStorage.setItem("token", session.token);
+    sessionStorage.setItem("user", session.user);
+    username.set(session.user);
+
+    AuthService.scheduleRefresh(session);
 
-    if (success) {
-      sessionStorage.setItem("hash", passwordHash);
-      sessionStorage.setItem("user", user);
-      username.set(user);
+    if (!get(isSignedIn)) {
       isSignedIn.set(true);
       await AppService.load();
 
@@ -29,18 +31,74 @@ export class AuthService {
         goto(`/${get(landingPage)}`);
       }
     }
+  }
+
+  /**
+   * Refreshes the session a minute before it expires.
+   * @param session The current session.
+   */
+  private static scheduleRefresh(session: SessionToken) {
+    if (AuthService.refreshTimeout) clearTimeout(AuthService.refreshTimeout);
+
+    const refreshIn = Math.max(session.expires * 1000 - Date.now() - 60000, 0);
+
+    AuthService.refreshTimeout = setTimeout(async () => {
+      const refreshed = await WebsocketService.refreshSession(session.token);
+
+      if (refreshed) {
+        await AuthService.startSession(refreshed);
+      } else {
+        AuthService.logout();
+      }
+    }, refreshIn);
+  }
+
+  /**
+   * Authenticates the user.
+   * @param user The username to authenticate with.
+   * @param passwordHash The hash of the user's password.
+   * @returns The backend's response.
+   */
+  static async authenticate(user: string, passwordHash: string): Promise<boolean> {
+    const session = await WebsocketService.authenticate(user, passwordHash);
+
+    if (session) {
+      await AuthService.startSession(session);
+    }
+
+    return !!session;
+  }
+
+  /**
+   * Resumes a previous session.
+   * @param token The token of the previous session.
+   * @returns True if the session was resumed.
+   */
+  static async resume(token: string): Promise<boolean> {
+    const session = await WebsocketService.refreshSession(token);
+
+    if (session) {
+      await AuthService.startSession(session);
+    } else {
+      sessionStorage.removeItem("token");
+    }
 
-    return success;
+    return !!session;
   }
-  
+
   /**
    * Logs the current user out and resets the relevant state.
    */
   static logout() {
-    sessionStorage.removeItem("hash");
+    if (AuthService.refreshTimeout) clearTimeout(AuthService.refreshTimeout);
+    AuthService.refreshTimeout = null;
+
+    if (get(isSignedIn)) WebsocketService.logout();
+
+    sessionStorage.removeItem("token");
     sessionStorage.removeItem("user");
     username.set("");
     isSignedIn.set(false);
     AppService.unload();
   }
-}
\ No newline at end of file
+}
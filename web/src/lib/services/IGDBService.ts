@@ -28,6 +28,22 @@ export class IGDBService {
     }
   }
   
+  /**
+   * Gets the IGDB metadata for several games at once.
+   * @param igdbIds The IGDB ids of the games.
+   * @param onJob Function to run with the id of the job fetching the metadata.
+   * @returns A promise resolving to the metadata for each id, or an empty object if the job failed.
+   */
+  static async getMetadataBulk(igdbIds: string[], onJob: (jobId: string) => void = () => {}): Promise<Record<string, IGDBGame | null>> {
+    try {
+      return await RestService.getIGDBMetadataBulk(igdbIds, onJob);
+    } catch (e: any) {
+      LogService.warn(`IGDB Bulk Metadata Request for ${igdbIds.length} games stopped.`);
+      LogService.warn(e.message);
+      return {};
+    }
+  }
+  
   /**
    * Searches IGDB for the provided query.
    * @param query The search query to use.
@@ -1,9 +1,10 @@
 import { WebsocketService } from "@services/utils/WebsocketService";
-import { changeGridsId, changeGridsOnSelect, changeGridsSearchId, changeGridsType, loadingModalMessage, showChangeGridsModal, showEditSystemModal, showLoadingModal, systemEditingId } from "@stores/Modals";
-import { roms, romsBySystem, systems } from "@stores/State";
+import { changeGridsId, changeGridsOnSelect, changeGridsSearchId, changeGridsType, loadingModalJobId, loadingModalMessage, showChangeGridsModal, showEditSystemModal, showLoadingModal, systemEditingId } from "@stores/Modals";
+import { romMetadata, roms, romsBySystem, showInfoSnackbar, systems } from "@stores/State";
 import type { ParserPattern } from "@types";
 import { isValidRegex } from "@utils";
 import { get } from "svelte/store";
+import { IGDBService } from "./IGDBService";
 import { DialogService } from "./utils/DialogService";
 import { RestService } from "./utils/RestService";
 
@@ -70,6 +71,42 @@ export class SystemService {
     showChangeGridsModal.set(true);
   }
 
+  /**
+   * Refreshes the IGDB metadata of every rom in a system that has an IGDB id.
+   * @param abbreviation The abbreviation of the system.
+   */
+  static async refreshMetadata(abbreviation: string) {
+    const metadataMap = get(romMetadata);
+    const romIds = (get(romsBySystem)[abbreviation] ?? []).filter((id) => {
+      const igdbId = metadataMap[id]?.igdbId;
+      return !!igdbId && igdbId !== "None";
+    });
+
+    if (!romIds.length) {
+      get(showInfoSnackbar)({ message: "No ROMs in this system have an IGDB id" });
+      return;
+    }
+
+    loadingModalMessage.set("Refreshing System Metadata...");
+    showLoadingModal.set(true);
+
+    const igdbIds = [...new Set(romIds.map((id) => metadataMap[id].igdbId))];
+    const results = await IGDBService.getMetadataBulk(igdbIds, (jobId) => loadingModalJobId.set(jobId));
+
+    // ? Roms may have been edited while the job ran, so the latest metadata is updated.
+    const latestMetadata = get(romMetadata);
+    for (const id of romIds) {
+      if (!latestMetadata[id]) continue;
+
+      const igdbMetadata = results[latestMetadata[id].igdbId];
+      if (igdbMetadata) latestMetadata[id].metadata = igdbMetadata;
+    }
+    romMetadata.set({ ...latestMetadata });
+
+    loadingModalMessage.set("");
+    showLoadingModal.set(false);
+  }
+
   /**
    * Prompts the user to delete a rom.
    * @param abbreviation The abbreviation of the system.
@@ -4,10 +4,12 @@ import { showError } from "@utils";
 import streamSaver from "streamsaver";
 import { get } from "svelte/store";
 import { LogService } from "./LogService";
+import { WebsocketService } from "./WebsocketService";
 
 type ROMDownload = {
   path: string,
   parent: string,
+  system: string,
 }
 
 /**
@@ -16,7 +18,11 @@ type ROMDownload = {
 export class RestService {
   private static readonly BASE_URL = `http://${import.meta.env.NRM_SERVER_URL}/rest`;
 
+  private static readonly MAX_DOWNLOAD_RETRIES = 5;
+  private static readonly DOWNLOAD_RETRY_DELAY = 2000;
+
   private static currentDownload: ReadableStreamDefaultReader<Uint8Array<ArrayBufferLike>> | null = null;
+  private static currentJobId: string | null = null;
 
   /**
    * Deletes the capsule for a title.
@@ -30,6 +36,7 @@ export class RestService {
       method: "DELETE",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
         "Content-Type": "application/json",
         "Full-Capsule-Extension": fullCapsuleUrl.substring(fullCapsuleUrl.lastIndexOf(".") + 1),
@@ -57,6 +64,7 @@ export class RestService {
       method: "POST",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
         "Content-Type": "application/json"
       },
@@ -95,6 +103,7 @@ export class RestService {
       method: "DELETE",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
         "Content-Type": "application/json",
         "Hero-Extension": heroUrl.substring(heroUrl.lastIndexOf(".") + 1),
@@ -120,6 +129,7 @@ export class RestService {
       method: "POST",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
         "Content-Type": "application/json"
       },
@@ -142,13 +152,24 @@ export class RestService {
 
 
   private static async getROMMetadata(data: ROMDownload): Promise<{ size: number, path: string }> {
-    const res = await fetch(RestService.BASE_URL + `/roms/download/metadata?romPath=${encodeURIComponent(data.path)}&romParent=${encodeURIComponent(data.parent)}`, {
-      method: "GET",
-      mode: "cors",
-      headers: {
-        "Accept": "application/json, text/plain, */*",
+    let res = await RestService.fetchROMMetadata(data);
+
+    // ? Compressed folders are packed by a job first, after which the metadata is ready.
+    if (res.status === 202) {
+      const { jobId } = await res.json() as { jobId: string };
+      RestService.currentJobId = jobId;
+
+      try {
+        await WebsocketService.waitForJob(jobId);
+      } catch (e) {
+        LogService.warn(`Failed to pack ${data.path}:`, (e as Error).message);
+        return { size: 0, path: "" };
+      } finally {
+        RestService.currentJobId = null;
       }
-    });
+
+      res = await RestService.fetchROMMetadata(data);
+    }
 
     if (res.ok) {
       return await res.json();
@@ -158,9 +179,88 @@ export class RestService {
     }
   }
 
-  private static async downloadNative(url: string, filename: string, onProgress: (progress: number) => void) {
+  private static async fetchROMMetadata(data: ROMDownload): Promise<Response> {
+    return await fetch(RestService.BASE_URL + `/roms/download/metadata?romPath=${encodeURIComponent(data.path)}&romParent=${encodeURIComponent(data.parent)}&romSystem=${encodeURIComponent(data.system)}`, {
+      method: "GET",
+      mode: "cors",
+      headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
+        "Accept": "application/json, text/plain, */*",
+      }
+    });
+  }
+
+  /**
+   * Streams a download, resuming it with a Range request if the connection drops.
+   * @param url The url to download.
+   * @param write Function to write a chunk of the file.
+   * @param abort Function to abort the write if the download is canceled.
+   * @param onProgress Function to run with the number of bytes downloaded.
+   */
+  private static async streamDownload(url: string, write: (chunk: Uint8Array) => Promise<void>, abort: (reason: string) => void, onProgress: (progress: number) => void) {
     let downloaded = 0;
+    let validator: string | null = null;
+    let resumable = false;
+    let failedAttempts = 0;
+
+    while (true) {
+      const headers: Record<string, string> = {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
+      };
+
+      // ? If-Range makes the server send the whole file if it changed, which is caught below.
+      if (downloaded > 0) {
+        headers["Range"] = `bytes=${downloaded}-`;
+        if (validator) headers["If-Range"] = validator;
+      }
+
+      const response = await fetch(url, { headers }).catch((e) => e as Error);
+
+      if (response instanceof Response) {
+        if (downloaded > 0 && response.status !== 206) {
+          abort("File Changed");
+          throw new Error("The file changed on the server, so the download can't be resumed.");
+        }
+
+        validator = response.headers.get("ETag") ?? response.headers.get("Last-Modified");
+        resumable = response.headers.get("Accept-Ranges") === "bytes";
+
+        const reader = response.body?.getReader();
+        if (!reader) return;
 
+        RestService.currentDownload = reader;
+
+        try {
+          while (true) {
+            const { done, value } = await reader.read();
+
+            if (done) return;
+
+            if (!RestService.currentDownload) {
+              abort("User Canceled");
+              return;
+            }
+
+            await write(value);
+
+            downloaded += value.length;
+            failedAttempts = 0;
+            onProgress(downloaded);
+          }
+        } catch (e: any) {
+          if (!resumable || ++failedAttempts > RestService.MAX_DOWNLOAD_RETRIES) throw e;
+          LogService.warn(`Download interrupted at ${downloaded} bytes, retrying (${failedAttempts}/${RestService.MAX_DOWNLOAD_RETRIES}):`, e?.message);
+        }
+      } else {
+        if (++failedAttempts > RestService.MAX_DOWNLOAD_RETRIES) throw response;
+        LogService.warn(`Download request failed, retrying (${failedAttempts}/${RestService.MAX_DOWNLOAD_RETRIES}):`, response.message);
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, RestService.DOWNLOAD_RETRY_DELAY * failedAttempts));
+    }
+  }
+
+  private static async downloadNative(url: string, filename: string, onProgress: (progress: number) => void) {
     const newHandle = await window.showSaveFilePicker({ suggestedName: filename });
     const writableStream = await newHandle.createWritable();
 
@@ -172,31 +272,12 @@ export class RestService {
     }
     window.addEventListener("pagehide", onPageHideChange, pageHideOptions);
 
-    await fetch(url).then(async (response) => {
-      const reader = response.body?.getReader();
-
-      if (!reader) return;
-
-      RestService.currentDownload = reader;
-      
-      while(true) {
-        const {done, value} = await reader.read();
-      
-        if (done) {
-          break;
-        }
-
-        if (!RestService.currentDownload) {
-          writableStream.abort("User Canceled");
-          break;
-        }
-        
-        await writableStream.write(value);
-      
-        downloaded += value.length;
-        onProgress(downloaded);
-      }
-    });
+    await RestService.streamDownload(
+      url,
+      (chunk) => writableStream.write(chunk),
+      (reason) => writableStream.abort(reason),
+      onProgress
+    );
     
     await writableStream.close();
     
@@ -205,7 +286,6 @@ export class RestService {
   }
 
   private static async downloadPolyfill(url: string, filename: string, fileSize: number, onProgress: (progress: number) => void) {
-    let downloaded = 0;
     const fileStream = streamSaver.createWriteStream(filename, { size: fileSize });
     const writer = fileStream.getWriter();
     
@@ -217,31 +297,12 @@ export class RestService {
     }
     window.addEventListener("pagehide", onPageHideChange, pageHideOptions);
 
-    await fetch(url).then(async (response) => {
-      const reader = response.body?.getReader();
-
-      if (!reader) return;
-
-      RestService.currentDownload = reader;
-      
-      while(true) {
-        const { done, value } = await reader.read();
-      
-        if (done) {
-          break;
-        }
-
-        if (!RestService.currentDownload) {
-          writer.abort("User Canceled");
-          break;
-        }
-        
-        await writer.write(value);
-      
-        downloaded += value.length;
-        onProgress(downloaded);
-      }
-    });
+    await RestService.streamDownload(
+      url,
+      (chunk) => writer.write(chunk),
+      (reason) => writer.abort(reason),
+      onProgress
+    );
 
     writer.close();
     
@@ -249,13 +310,15 @@ export class RestService {
     window.removeEventListener("pagehide", onPageHideChange, pageHideOptions);
   }
 
-  private static async streamROMDownload(path: string, fileSize: number, onProgress: (progress: number) => void) {
+  private static async streamROMDownload(path: string, system: string, fileSize: number, isFolder: boolean, onProgress: (progress: number) => void) {
     const backslashIndex = path.lastIndexOf("\\");
     const slashIndex = path.lastIndexOf("/");
     const startIndex = backslashIndex > slashIndex ? backslashIndex : slashIndex;
-    const filename = path.substring(startIndex + 1);
+    // ? Folder roms are streamed as a ZIP.
+    const filename = path.substring(startIndex + 1) + (isFolder ? ".zip" : "");
     
-    const romURL = RestService.BASE_URL + `/roms/download?filePath=${encodeURIComponent(path)}`;
+    // ? The system decides whether folder roms are compressed.
+    const romURL = RestService.BASE_URL + `/roms/download?filePath=${encodeURIComponent(path)}&romSystem=${encodeURIComponent(system)}`;
 
     // @ts-expect-error This error is because we have a type package installed. The File System API is still not supported in all browsers.
     // ? See https://developer.mozilla.org/en-US/docs/Web/API/FileSystemWritableFileStream#browser_compatibility
@@ -266,25 +329,6 @@ export class RestService {
     }
   }
 
-  private static async notifyROMDownloadComplete(data: ROMDownload) {
-    const res = await fetch(RestService.BASE_URL + "/roms/download/complete", {
-      method: "POST",
-      mode: "cors",
-      headers: {
-        "Accept": "application/json, text/plain, */*",
-        "Content-Type": "application/json"
-      },
-      body: JSON.stringify(data)
-    });
-
-    if (res.ok) {
-      return await res.text();
-    } else {
-      LogService.error(`Failed to notify the backend of the completed download for ${data.path}:`, res.statusText);
-      return "";
-    }
-  }
-
   /**
    * Downloads the requested rom.
    * @param rom The rom to download.
@@ -301,17 +345,20 @@ export class RestService {
     const romDownloadConfig = {
       path: rom.path,
       parent: rom.downloadStrategy.type === "folder" ? rom.downloadStrategy.parent : "",
+      system: rom.system,
     }
 
     const { size, path } = await RestService.getROMMetadata(romDownloadConfig);
-    romDownloadConfig.path = path;
+    if (!path) {
+      onEnd(false);
+      return;
+    }
+
     onStart(size);
 
 
-    await RestService.streamROMDownload(path, size, onProgress);
+    await RestService.streamROMDownload(path, romDownloadConfig.system, size, romDownloadConfig.parent !== "", onProgress);
     
-
-    await RestService.notifyROMDownloadComplete(romDownloadConfig);
     onEnd(!!RestService.currentDownload);
 
     RestService.currentDownload = null;
@@ -321,26 +368,54 @@ export class RestService {
    * Cancels the current download if it exists.
    */
   static async cancelDownload() {
-    if (RestService.currentDownload) {
+    if (RestService.currentJobId) {
+      await WebsocketService.cancelJob(RestService.currentJobId);
+    } else if (RestService.currentDownload) {
       RestService.currentDownload.cancel("User Canceled");
     } else {
       get(showWarningSnackbar)({ message: "There is no download currently" });
     }
   }
 
-  static async uploadROMComplete(data: CompletedUploadData) {
+  /**
+   * Notifies the backend that a rom finished uploading.
+   * @param data The completed upload's data.
+   * @param onJob Function to run with the id of the job extracting the upload, if it's an archive.
+   * @returns The path of the rom, or an empty string if it failed.
+   */
+  static async uploadROMComplete(data: CompletedUploadData, onJob: (jobId: string) => void = () => {}) {
     const res = await fetch(RestService.BASE_URL + "/roms/upload/complete", {
       method: "POST",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
         "Content-Type": "application/json"
       },
       body: JSON.stringify(data)
     });
 
+    // ? Archives are extracted by a job, which has the extracted path as its result.
+    if (res.status === 202) {
+      const { jobId } = await res.json() as { jobId: string };
+      onJob(jobId);
+
+      try {
+        const result = await WebsocketService.waitForJob<{ path: string }>(jobId);
+        return result.path;
+      } catch (e) {
+        LogService.error(`Failed to extract ${data.path}:`, (e as Error).message);
+        get(showWarningSnackbar)({ message: "Failed to extract the upload" });
+        return "";
+      }
+    }
+
     if (res.ok) {
       return await res.text();
+    } else if (res.status === 422) {
+      LogService.error(`The upload of ${data.path} failed verification:`, await res.text());
+      get(showWarningSnackbar)({ message: "Upload was corrupted, please try again" });
+      return "";
     } else {
       LogService.error(`Failed to notify the backend of the completed download for ${data.path}:`, res.statusText);
       return "";
@@ -357,6 +432,7 @@ export class RestService {
       method: "DELETE",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "text/plain, */*"
       },
     });
@@ -378,6 +454,9 @@ export class RestService {
     const res = await fetch(RestService.BASE_URL + "/proxy/sgdb/init", {
       method: "POST",
       mode: "cors",
+      headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
+      },
     });
 
     if (res.ok) {
@@ -403,6 +482,7 @@ export class RestService {
   static async getSGDBGridsById(id: string, page: number, gridType: "grids" | "heroes"): Promise<GridResults> {
     const res = await fetch(RestService.BASE_URL + "/proxy/sgdb/grids", {
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "SGDB-Game-Id": id,
         "SGDB-Results-Page": page.toString(),
         "SGDB-Grid-Type": gridType
@@ -428,7 +508,11 @@ export class RestService {
    * @returns The search results.
    */
   static async searchSGDBForTitle(query: string): Promise<SGDBGame[]> {
-    const res = await fetch(RestService.BASE_URL + `/proxy/sgdb/search?query=${encodeURIComponent(query)}`);
+    const res = await fetch(RestService.BASE_URL + `/proxy/sgdb/search?query=${encodeURIComponent(query)}`, {
+      headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
+      },
+    });
 
     if (res.ok) {
       return await res.json();
@@ -448,6 +532,9 @@ export class RestService {
     const res = await fetch(RestService.BASE_URL + "/proxy/igdb/init", {
       method: "POST",
       mode: "cors",
+      headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
+      },
     });
 
     if (res.ok) {
@@ -471,6 +558,7 @@ export class RestService {
   static async getIGDBMetadataById(id: string): Promise<IGDBGame | null> {
     const res = await fetch(RestService.BASE_URL + "/proxy/igdb/metadata", {
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "IGDB-Game-Id": id,
       }
     });
@@ -484,13 +572,45 @@ export class RestService {
     }
   }
   
+  /**
+   * Gets the IGDB metadata for several games in a background job.
+   * @param ids The ids of the games to get metadata for.
+   * @param onJob Function to run with the id of the job fetching the metadata.
+   * @returns The metadata for each id, which is null if it couldn't be fetched.
+   */
+  static async getIGDBMetadataBulk(ids: string[], onJob: (jobId: string) => void = () => {}): Promise<Record<string, IGDBGame | null>> {
+    const res = await fetch(RestService.BASE_URL + "/proxy/igdb/metadata/bulk", {
+      method: "POST",
+      mode: "cors",
+      headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
+        "Content-Type": "application/json",
+      },
+      body: JSON.stringify({ igdbIds: ids })
+    });
+
+    if (!res.ok) {
+      get(showWarningSnackbar)({ message: "Error getting metadata from IGDB."})
+      return {};
+    }
+
+    const { jobId } = await res.json() as { jobId: string };
+    onJob(jobId);
+
+    return await WebsocketService.waitForJob<Record<string, IGDBGame | null>>(jobId);
+  }
+  
   /**
    * Searches IGDB for games matching the query.
    * @param query The query to search for.
    * @returns The best match for the search.
    */
   static async searchIGDBForTitle(query: string, igdbPlatformId: string): Promise<IGDBSearchResult[]> {
-    const res = await fetch(RestService.BASE_URL + `/proxy/igdb/search/games?query=${encodeURIComponent(query)}&platform-id=${igdbPlatformId}`);
+    const res = await fetch(RestService.BASE_URL + `/proxy/igdb/search/games?query=${encodeURIComponent(query)}&platform-id=${igdbPlatformId}`, {
+      headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
+      },
+    });
 
     if (res.ok) {
       return await res.json();
@@ -507,7 +627,11 @@ export class RestService {
    * @returns The best match for the search.
    */
   static async searchIGDBForPlatform(query: string): Promise<IGDBMetadataPlatform[]> {
-    const res = await fetch(RestService.BASE_URL + `/proxy/igdb/search/platforms?query=${encodeURIComponent(query)}`);
+    const res = await fetch(RestService.BASE_URL + `/proxy/igdb/search/platforms?query=${encodeURIComponent(query)}`, {
+      headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
+      },
+    });
 
     if (res.ok) {
       return await res.json();
@@ -523,6 +647,7 @@ export class RestService {
       method: "GET",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
       }
     });
@@ -584,15 +709,21 @@ export class RestService {
       method: "POST",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
         "Content-Type": "application/json",
-        "Upload-Id": data.uploadId
+        "Upload-Id": data.uploadId,
+        "Upload-Crc32": data.crc32
       },
       body: JSON.stringify({})
     });
 
     if (res.ok) {
       return await res.text();
+    } else if (res.status === 422) {
+      LogService.error(`The upload for ${data.uploadId} failed verification:`, await res.text());
+      get(showWarningSnackbar)({ message: "Upload was corrupted, please try again" });
+      return "";
     } else {
       LogService.error(`Failed to notify the backend of the completed download for ${data.uploadId}:`, res.statusText);
       return "";
@@ -609,6 +740,7 @@ export class RestService {
       method: "DELETE",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "text/plain, */*"
       },
     });
@@ -626,6 +758,7 @@ export class RestService {
       method: "GET",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
       }
     });
@@ -687,15 +820,21 @@ export class RestService {
       method: "POST",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
         "Content-Type": "application/json",
-        "Upload-Id": data.uploadId
+        "Upload-Id": data.uploadId,
+        "Upload-Crc32": data.crc32
       },
       body: JSON.stringify({})
     });
 
     if (res.ok) {
       return await res.text();
+    } else if (res.status === 422) {
+      LogService.error(`The upload for ${data.uploadId} failed verification:`, await res.text());
+      get(showWarningSnackbar)({ message: "Upload was corrupted, please try again" });
+      return "";
     } else {
       LogService.error(`Failed to notify the backend of the completed download for ${data.uploadId}:`, res.statusText);
       return "";
@@ -712,6 +851,7 @@ export class RestService {
       method: "DELETE",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "text/plain, */*"
       },
     });
@@ -15,7 +15,7 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>
  */
 
-import { landingPage, landscapeViews, library, loadedSettings, palette, portraitViews, reducedMotion, romMetadata, saveMetadataAlongside, systems, themePrimaryColor, useOledPalette } from "@stores/State";
+import { exportOnLibraryChange, landingPage, landscapeViews, library, loadedSettings, palette, portraitViews, reducedMotion, romMetadata, saveMetadataAlongside, systems, themePrimaryColor, useOledPalette } from "@stores/State";
 import type { Settings } from "@types";
 import type { Unsubscriber } from "svelte/store";
 import { LogService } from "./LogService";
@@ -91,6 +91,8 @@ export class SettingsService {
     
     const metadata = this.settings.metadata;
     saveMetadataAlongside.set(metadata.saveAlongsideROMs);
+
+    exportOnLibraryChange.set(this.settings.export.exportOnLibraryChange);
     
     const accessibility = this.settings.accessibility;
     reducedMotion.set(accessibility.reducedMotion);
@@ -112,6 +114,7 @@ export class SettingsService {
       portraitViews.subscribe(this.setOnChange("navigation.portraitViews")),
 
       saveMetadataAlongside.subscribe(this.setOnChange("metadata.saveAlongsideROMs")),
+      exportOnLibraryChange.subscribe(this.setOnChange("export.exportOnLibraryChange")),
 
       reducedMotion.subscribe(this.setOnChange("accessibility.reducedMotion")),
 
@@ -1,22 +1,26 @@
 import { DialogService } from "@services/utils/DialogService";
 import { library, showInfoSnackbar, showWarningSnackbar, systems } from "@stores/State";
 import type { CompletedUploadData, UploadConfig } from "@types";
-import { hash64 } from "@utils";
+import { formatCrc32, hash64, updateCrc32 } from "@utils";
 import { get } from "svelte/store";
 import { LogService } from "./LogService";
+import { WebsocketService } from "./WebsocketService";
 
 export class UploadService {
   private static readonly STREAM_CHUNK_SIZE = 10 * 1024 * 1024;
+  private static readonly MAX_CHUNK_RETRIES = 3;
 
   private static readonly BASE_URL = `http://${import.meta.env.NRM_SERVER_URL}/rest`;
 
   static currentUploadId: string | null = null;
+  private static currentJobId: string | null = null;
   
   static async prepareReplace(filePath: string): Promise<string> {
     const res = await fetch(UploadService.BASE_URL + `/upload/replace/prepare?filePath=${encodeURIComponent(filePath)}`, {
       method: "POST",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
       }
     });
@@ -36,6 +40,7 @@ export class UploadService {
       method: "POST",
       mode: "cors",
       headers: {
+        "Authorization": `Bearer ${WebsocketService.getToken()}`,
         "Accept": "application/json, text/plain, */*",
       }
     });
@@ -62,26 +67,28 @@ export class UploadService {
     }
   }
   
-  private static async streamUpload(uploadId: string, path: string, file: File, onProgress: (progress: number) => void) {
-    let sent = 0;
-
-    const fileSize = file.size;
-
-    while (sent < fileSize) {
-      if (!UploadService.currentUploadId) break;
-
-      const end = Math.min(sent + this.STREAM_CHUNK_SIZE - 1, fileSize - 1);
-      const range = `bytes=${sent}-${end}`;
-      const length = end - sent + 1;
+  /**
+   * Sends a chunk of the file. Chunks the server didn't fully receive are sent again.
+   * @param uploadId The id of the upload.
+   * @param path The path of the file on the server.
+   * @param data The chunk to send.
+   * @param start The offset of the chunk in the file.
+   * @param fileSize The size of the file.
+   * @returns True if the chunk was sent, false if the upload was canceled.
+   */
+  private static async sendChunk(uploadId: string, path: string, data: ArrayBuffer, start: number, fileSize: number): Promise<boolean> {
+    const end = start + data.byteLength - 1;
 
-      const data = file.slice(sent, end + 1);
+    for (let attempt = 0; attempt <= this.MAX_CHUNK_RETRIES; attempt++) {
+      if (!UploadService.currentUploadId) return false;
 
       const response = await fetch(UploadService.BASE_URL + `/upload?filePath=${encodeURIComponent(path)}`, {
         method: "POST",
         mode: "cors",
         headers: {
-          "Range": range,
-          "Content-Length": length.toString(),
+          "Authorization": `Bearer ${WebsocketService.getToken()}`,
+          "Range": `bytes=${start}-${end}`,
+          "Content-Length": data.byteLength.toString(),
           "Upload-Id": uploadId,
           "File-Size": fileSize.toString(),
           "Content-Type": "application/octet-stream"
@@ -89,13 +96,44 @@ export class UploadService {
         body: data
       });
 
-      if (!response.ok && UploadService.currentUploadId) {
-        throw new Error("Failed to send the chunk");
-      }
+      if (response.ok) return true;
+
+      // * The server discards partial chunks, so the chunk can be sent again if it's still expecting it.
+      const offset = response.headers.get("Upload-Offset");
+      if (response.status !== 409 || offset !== start.toString()) break;
+    }
+
+    if (!UploadService.currentUploadId) return false;
 
-      sent += length;
+    throw new Error("Failed to send the chunk");
+  }
+  
+  /**
+   * Streams a file to the server in chunks.
+   * @returns The CRC32 of the file, which the server uses to verify the upload.
+   */
+  private static async streamUpload(uploadId: string, path: string, file: File, onProgress: (progress: number) => void): Promise<string> {
+    let sent = 0;
+    let crc32 = 0;
+
+    const fileSize = file.size;
+
+    while (sent < fileSize) {
+      if (!UploadService.currentUploadId) break;
+
+      const end = Math.min(sent + this.STREAM_CHUNK_SIZE - 1, fileSize - 1);
+      const data = await file.slice(sent, end + 1).arrayBuffer();
+
+      const wasSent = await UploadService.sendChunk(uploadId, path, data, sent, fileSize);
+      if (!wasSent) break;
+
+      crc32 = updateCrc32(crc32, new Uint8Array(data));
+
+      sent += data.byteLength;
       onProgress(sent);
     }
+
+    return formatCrc32(crc32);
   }
 
   /**
@@ -106,17 +144,19 @@ export class UploadService {
    * @param onComplete Function to run on upload complete.
    * @param onEnd Function to run on after the upload has fully finished.
    * @param isReplace If true, a file is being replaced.
+   * @param onJob Function to run if the backend processes the upload in a background job.
    * @returns True if the upload was canceled by a duplicate filename, false if not.
    */
   static async upload(
     uploadConfig: UploadConfig,
     onStart: () => void = () => {},
     onProgress: (progress: number) => void = () => {},
-    onComplete: (data: CompletedUploadData) => Promise<string>,
+    onComplete: (data: CompletedUploadData, onJob?: (jobId: string) => void) => Promise<string>,
     onEnd: (success: boolean, filePath: string) => void = () => {},
-    isReplace = false
+    isReplace = false,
+    onJob: (jobId: string) => void = () => {}
   ) {
-    const { uploadFolder, romId, file, system, needsUnzip } = uploadConfig;
+    const { uploadFolder, romId, file, system, needsExtract } = uploadConfig;
     const lib = get(library);
     
     const systemFolder = get(systems)[system].folder;
@@ -139,7 +179,7 @@ export class UploadService {
     UploadService.currentUploadId = uploadId;
 
   
-    await UploadService.streamUpload(
+    const crc32 = await UploadService.streamUpload(
       uploadId,
       filePath,
       file,
@@ -155,9 +195,14 @@ export class UploadService {
         path: filePath,
         libraryPath: lib.libraryPath,
         system: systemFolder,
-        unzip: needsUnzip,
+        extract: needsExtract,
+        crc32: crc32,
+      }, (jobId) => {
+        UploadService.currentJobId = jobId;
+        onJob(jobId);
       });
 
+      UploadService.currentJobId = null;
       onEnd(finalPath !== "", finalPath);
     }
 
@@ -169,11 +214,15 @@ export class UploadService {
    * @returns True if successful, false if not.
    */
   static async cancelUpload(): Promise<boolean> {
-    if (UploadService.currentUploadId) {
+    // ? Once the file is uploaded, the only thing left to cancel is its processing.
+    if (UploadService.currentJobId) {
+      return await WebsocketService.cancelJob(UploadService.currentJobId);
+    } else if (UploadService.currentUploadId) {
       const res = await fetch(UploadService.BASE_URL + "/upload/cancel", {
         method: "POST",
         mode: "cors",
         headers: {
+          "Authorization": `Bearer ${WebsocketService.getToken()}`,
           "Upload-Id": UploadService.currentUploadId,
         },
       });
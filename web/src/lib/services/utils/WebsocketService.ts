@@ -15,20 +15,33 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>
  */
 
-import { library, roms, romsBySystem, showWarningSnackbar, systems } from "@stores/State";
-import { BackendErrorType, type AvailableStorage, type BackendError, type FilePickerConfig, type FilePickerEntry, type Library, type LoadResult, type ROM, type ROMMetadata, type Settings, type System } from "@types";
+import { collections, jobs, loadedLibrary, romDLCs, romMetadata, romUpdates, roms, romsBySystem, showWarningSnackbar } from "@stores/State";
+import { BackendErrorType, type AvailableStorage, type BackendError, type Collection, type CollectionRules, type SessionToken, type FilePickerConfig, type ExportResult, type ExtraFiles, type ImportResult, type FilePickerEntry, type HashedROM, type JobDone, type JobFailed, type JobInfo, type Library, type RemovedROM, type RenamedROM, type LoadResult, type ROM, type ROMMetadata, type Settings, type System } from "@types";
 import { hash64, showError } from "@utils";
 import { get } from "svelte/store";
 import { LogService } from "./LogService";
 
-type Response<T> = { data: T }
+type Response<T> = { type: string, data: T, requestId?: string }
+
+type ProtocolError = { code: string, message: string }
+
+type JobWaiter = { resolve: (result: any) => void, reject: (error: Error) => void }
+
+/**
+ * The version of the websocket protocol this frontend speaks.
+ */
+const PROTOCOL_VERSION = 2;
 
 /**
  * Handles wrapping websocket communication into an easy to use JS bindings.
  */
 export class WebsocketService {
   private static ws: WebSocket;
-  private static hash: string;
+  private static token: string;
+  private static requestCount = 0;
+
+  private static jobWaiters: Record<string, JobWaiter[]> = {};
+  private static finishedJobs: Record<string, { result?: any, error?: Error }> = {};
 
   /**
    * Initializes the Rust <-> Svelte communication.
@@ -44,75 +57,315 @@ export class WebsocketService {
       showError(message, fix, BackendErrorType.PANIC);
     });
 
-    WebsocketService.ws.addEventListener("open", () => {
-      WebsocketService.ws.send("Hello World!");
+    WebsocketService.ws.addEventListener("open", async () => {
+      await WebsocketService.invoke<{ version: number }>("hello", { version: PROTOCOL_VERSION });
       onOpen();
     });
 
     // * Handles generic messages such as token expiration.
     WebsocketService.ws.addEventListener("message", async (event) => {
-      const firstSpace = event.data.indexOf(" ");
-      const message = event.data.substring(0, firstSpace);
-      const data = event.data.substring(firstSpace + 1);
+      const { type, data } = JSON.parse(event.data) as Response<any>;
 
-      switch(message) {
-        case "hash_mismatch": {
-          WebsocketService.hash = "";
+      switch(type) {
+        case "session_invalid": {
+          WebsocketService.token = "";
           onLogout();
           get(showWarningSnackbar)({ message: "Something went wrong verifying your request"});
           break;
         }
+        case "permission_denied": {
+          get(showWarningSnackbar)({ message: "You don't have permission to do that"});
+          break;
+        }
         case "missing_env_variable": {
-          const variable = JSON.parse(data).data;
+          const variable = data as string;
           const message = `No environment variable ${variable} was found`;
           const fix = `Please check your container to ensure ${variable} is set`;
           showError(message, fix, BackendErrorType.PANIC);
           break;
         }
         case "backend_error": {
-          const { message, fix, type } = JSON.parse(data).data as BackendError;
+          const { message, fix, type } = data as BackendError;
           showError(message, fix, type);
           break;
         }
-        case "reload_library": {
-          // if (get(showUploadProgressModal) || RestService.currentUploadId) break;
+        case "protocol_error": {
+          const { code, message } = data as ProtocolError;
+          LogService.error(`Websocket request was rejected (${code}): ${message}`);
+          break;
+        }
+        case "rom_hashed": {
+          const { path, hashes, verification } = data as HashedROM;
+          const id = hash64(path);
+          const romMap = get(roms);
+
+          if (romMap[id]) {
+            romMap[id].hashes = hashes;
+            romMap[id].verification = verification;
+            roms.set({ ...romMap });
+          }
+          break;
+        }
+        case "rom_added":
+        case "rom_modified": {
+          WebsocketService.setRom(data as ROM);
+          break;
+        }
+        case "rom_removed": {
+          const { path } = data as RemovedROM;
+          WebsocketService.removeRom(hash64(path));
+          break;
+        }
+        case "rom_renamed": {
+          const { oldPath, rom } = data as RenamedROM;
+          const oldId = hash64(oldPath);
+          const metadataMap = get(romMetadata);
+
+          // * Keep the user's edits when the file is renamed.
+          if (metadataMap[oldId]) {
+            metadataMap[hash64(rom.path)] = metadataMap[oldId];
+            delete metadataMap[oldId];
+            romMetadata.set({ ...metadataMap });
+          }
+
+          WebsocketService.removeRom(oldId);
+          WebsocketService.setRom(rom);
+          break;
+        }
+        case "dlcs_changed": {
+          const { romId, files } = data as ExtraFiles;
+          const dlcs = get(romDLCs);
+
+          if (files.length) {
+            dlcs[romId] = files;
+          } else {
+            delete dlcs[romId];
+          }
+
+          romDLCs.set({ ...dlcs });
+          break;
+        }
+        case "updates_changed": {
+          const { romId, files } = data as ExtraFiles;
+          const updates = get(romUpdates);
+
+          if (files.length) {
+            updates[romId] = files;
+          } else {
+            delete updates[romId];
+          }
+
+          romUpdates.set({ ...updates });
+          break;
+        }
+        case "library_rescanned": {
+          if (!get(loadedLibrary)) break;
+
+          const loadRes = data as LoadResult;
+          const rescannedIds = new Set(loadRes.roms.map((rom) => hash64(rom.path)));
 
-          // const path: string = JSON.parse(data).data;
+          for (const id of Object.keys(get(roms))) {
+            if (!rescannedIds.has(id)) WebsocketService.removeRom(id);
+          }
+
+          for (const rom of loadRes.roms) {
+            WebsocketService.setRom(rom);
+          }
+
+          romDLCs.set(loadRes.dlcs);
+          romUpdates.set(loadRes.updates);
+          break;
+        }
+        case "job_progress": {
+          const info = data as JobInfo;
+          jobs.set({ ...get(jobs), [info.id]: info });
+          break;
+        }
+        case "job_done": {
+          const { jobId, result } = data as JobDone;
+          WebsocketService.updateJob(jobId, { status: "DONE", progress: 1, message: "Done." });
+          WebsocketService.finishJob(jobId, { result });
+          break;
+        }
+        case "job_failed": {
+          const { jobId, message, cancelled } = data as JobFailed;
+          WebsocketService.updateJob(jobId, { status: cancelled ? "CANCELLED" : "FAILED", message });
+          WebsocketService.finishJob(jobId, { error: new Error(message) });
+          break;
+        }
+        case "metadata_refreshed": {
+          if (!get(loadedLibrary)) break;
 
-          // this.addRomFromPath(path);
+          // * Keep the defaults for roms that haven't been saved yet.
+          romMetadata.set({ ...get(romMetadata), ...(data as Record<string, ROMMetadata>) });
+          break;
+        }
+        case "collections_changed": {
+          collections.set(data as Record<string, Collection>);
           break;
         }
       }
     });
   }
 
+  /**
+   * Adds or updates a rom that changed on the server.
+   * @param rom The rom to set.
+   */
+  private static setRom(rom: ROM) {
+    const id = hash64(rom.path);
+    const romMap = get(roms);
+    const romSystemMap = get(romsBySystem);
+    const metadataMap = get(romMetadata);
+
+    romMap[id] = rom;
+
+    if (romSystemMap[rom.system] && !romSystemMap[rom.system].includes(id)) {
+      romSystemMap[rom.system].push(id);
+    }
+
+    if (!metadataMap[id]) {
+      metadataMap[id] = {
+        title: rom.title,
+        fullCapsulePath: "",
+        thumbCapsulePath: "",
+        sgdbId: "",
+        igdbId: "",
+        heroPath: "",
+        metadata: null,
+        isFavorite: false,
+      }
+      romMetadata.set({ ...metadataMap });
+    }
+
+    roms.set({ ...romMap });
+    romsBySystem.set({ ...romSystemMap });
+  }
+
+  /**
+   * Removes a rom that was removed on the server.
+   * @param id The id of the rom to remove.
+   */
+  private static removeRom(id: string) {
+    const romMap = get(roms);
+    const rom = romMap[id];
+    if (!rom) return;
+
+    delete romMap[id];
+    roms.set({ ...romMap });
+
+    const romSystemMap = get(romsBySystem);
+    const systemRoms = romSystemMap[rom.system] ?? [];
+    const index = systemRoms.indexOf(id);
+    if (index !== -1) systemRoms.splice(index, 1);
+    romsBySystem.set({ ...romSystemMap });
+  }
+
+  /**
+   * Updates a job in the jobs store, if it's there.
+   * @param jobId The id of the job.
+   * @param changes The changes to the job's info.
+   */
+  private static updateJob(jobId: string, changes: Partial<JobInfo>) {
+    const jobMap = get(jobs);
+    if (!jobMap[jobId]) return;
+
+    jobs.set({ ...jobMap, [jobId]: { ...jobMap[jobId], ...changes, finishedAt: Math.floor(Date.now() / 1000) } });
+  }
+
+  /**
+   * Settles anything waiting on a job that finished.
+   * @param jobId The id of the job.
+   * @param outcome The job's result, or the error it failed with.
+   */
+  private static finishJob(jobId: string, outcome: { result?: any, error?: Error }) {
+    // * Jobs can finish before anyone waits on them, so the outcome is kept around.
+    WebsocketService.finishedJobs[jobId] = outcome;
+
+    for (const waiter of WebsocketService.jobWaiters[jobId] ?? []) {
+      if (outcome.error) {
+        waiter.reject(outcome.error);
+      } else {
+        waiter.resolve(outcome.result);
+      }
+    }
+
+    delete WebsocketService.jobWaiters[jobId];
+  }
+
+  /**
+   * Waits for a background job to finish.
+   * @param jobId The id of the job.
+   * @returns The job's result. Rejects if the job failed or was cancelled.
+   */
+  static waitForJob<T>(jobId: string): Promise<T> {
+    const finished = WebsocketService.finishedJobs[jobId];
+    if (finished) {
+      return finished.error ? Promise.reject(finished.error) : Promise.resolve(finished.result);
+    }
+
+    return new Promise<T>((resolve, reject) => {
+      WebsocketService.jobWaiters[jobId] ??= [];
+      WebsocketService.jobWaiters[jobId].push({ resolve, reject });
+    });
+  }
+
+  /**
+   * Gets the current session token.
+   * @returns The session token.
+   */
+  static getToken(): string {
+    return WebsocketService.token;
+  }
+
   /**
    * Sends a message to the backend.
    * @param message The message name.
    * @param data The data to send. **Always use an object literal**
+   * @param onJob Function to run if the backend handles the request in a background job.
    * @returns The backend's response.
    */
-  private static async invoke<T>(message: string, data: Record<string, any>): Promise<Response<T>> {
+  private static async invoke<T>(message: string, data: Record<string, any>, onJob: (jobId: string) => void = () => {}): Promise<Response<T>> {
+    const requestId = `${++WebsocketService.requestCount}`;
+    let jobId: string | null = null;
+
     const result = new Promise<Response<T>>((resolve, reject) => {
       const handler = (event: MessageEvent<string>) => {
-        if (event.data.startsWith(message)) {
+        const response = JSON.parse(event.data) as Response<T>;
+
+        // * Job events aren't tied to a request, so failures are matched by the job's id.
+        if (jobId && response.type === "job_failed" && (response.data as JobFailed).jobId === jobId) {
           WebsocketService.ws.removeEventListener("message", handler);
+          reject(new Error((response.data as JobFailed).message));
+          return;
+        }
 
-          const jsonStart = event.data.indexOf(" ") + 1;
-          const data = JSON.parse(event.data.substring(jsonStart)) as Response<T>;
+        // * Replies to other requests aren't ours.
+        if (response.requestId !== requestId) return;
 
-          resolve(data);
+        if (response.type === "job_queued") {
+          jobId = (response.data as JobInfo).id;
+          onJob(jobId);
+          return;
+        }
+
+        if (response.type === message) {
+          WebsocketService.ws.removeEventListener("message", handler);
+          resolve(response);
+        } else if (response.type === "protocol_error") {
+          WebsocketService.ws.removeEventListener("message", handler);
+          reject(new Error((response.data as ProtocolError).message));
         }
       }
 
       WebsocketService.ws.addEventListener("message", handler);
     });
 
-    if (message !== "user_auth") {
-      data.passwordHash = WebsocketService.hash;
+    if (message !== "user_auth" && message !== "hello" && data.token === undefined) {
+      data.token = WebsocketService.token;
     }
 
-    WebsocketService.ws.send(`${message} ${JSON.stringify(data)}`);
+    WebsocketService.ws.send(JSON.stringify({ type: message, requestId, data }));
 
     return await result;
   }
@@ -122,17 +375,44 @@ export class WebsocketService {
    * Authenticates the user.
    * @param user The username to authenticate with.
    * @param passwordHash The hash of the user's password.
-   * @returns The backend's response.
+   * @returns The session token, or null if authentication failed.
+   */
+  static async authenticate(user: string, passwordHash: string): Promise<SessionToken | null> {
+    const res = await WebsocketService.invoke<SessionToken | null>("user_auth", { user, passwordHash });
+    const session = res.data;
+
+    if (session) {
+      WebsocketService.token = session.token;
+    }
+
+    return session;
+  }
+
+  /**
+   * Exchanges a valid session token for a new one.
+   * @param token The session token to refresh.
+   * @returns The new session token, or null if the provided one was invalid.
    */
-  static async authenticate(user: string, passwordHash: string): Promise<boolean> {
-    const res = await WebsocketService.invoke<boolean>("user_auth", { user, passwordHash});
-    const success = res.data;
+  static async refreshSession(token: string): Promise<SessionToken | null> {
+    const res = await WebsocketService.invoke<SessionToken | null>("refresh_session", { token });
+    const session = res.data;
 
-    if (success) {
-      WebsocketService.hash = passwordHash;
+    if (session) {
+      WebsocketService.token = session.token;
     }
 
-    return success;
+    return session;
+  }
+
+  /**
+   * Ends the current session.
+   * @returns True if the session was ended.
+   */
+  static async logout(): Promise<boolean> {
+    const res = await WebsocketService.invoke<boolean>("logout", {});
+    WebsocketService.token = "";
+
+    return res.data;
   }
 
 
@@ -166,53 +446,6 @@ export class WebsocketService {
   }
 
 
-  /**
-   * ! Useful if live reloading is added in the future.
-   * Adds a rom based on its path.
-   * @param path The path of the Rom to add.
-   */
-  private static async addRomFromPath(path: string) {
-    const lib = get(library)
-    const systemMap = get(systems);
-    const systemList = Object.values(systemMap);
-
-    const romMap = get(roms);
-    const romSystemMap = get(romsBySystem);
-
-    let libraryName = null;
-
-    if (!libraryName) {
-      LogService.log(`\"${path}\" did not start with a library path. Skipping...`);
-      return;
-    }
-
-    let pathNoLibrary = path.substring(lib.libraryPath.length + lib.romDir.length + 1);
-
-    let systemName = null;
-
-    for (const system of systemList) {
-      if (pathNoLibrary.startsWith(system.folder)) {
-        systemName = system.abbreviation;
-        break;
-      }
-    }
-
-    if (!systemName) {
-      LogService.log(`\"${path}\" did not contain a system. Skipping...`);
-      return;
-    }
-
-    const rom = await this.parseAddedRom(systemName, path);
-    const id = hash64(rom.path);
-
-    romMap[id] = rom;
-    romSystemMap[systemName].push(id);
-    
-    roms.set({ ...romMap });
-    romsBySystem.set({ ...romSystemMap });
-  }
-
-  
   /**
    * Gets the rom metadata from the server.
    * @returns The rom metadata.
@@ -242,6 +475,103 @@ export class WebsocketService {
   }
   
 
+  /**
+   * Gets the collections from the server.
+   * @returns The collections, keyed by id.
+   */
+  static async loadCollections(): Promise<Record<string, Collection>> {
+    const res = await WebsocketService.invoke<Record<string, Collection>>("load_collections", {});
+    return res.data;
+  }
+
+  /**
+   * Creates a collection.
+   * @param name The name of the collection.
+   * @param rules The rules of the collection, if it's a smart collection.
+   * @returns The created collection.
+   */
+  static async createCollection(name: string, rules: CollectionRules | null = null): Promise<Collection> {
+    const res = await WebsocketService.invoke<Collection>("create_collection", { name, rules });
+    return res.data;
+  }
+
+  /**
+   * Renames a collection.
+   * @param collectionId The id of the collection.
+   * @param name The new name.
+   * @returns The renamed collection.
+   */
+  static async renameCollection(collectionId: string, name: string): Promise<Collection> {
+    const res = await WebsocketService.invoke<Collection>("rename_collection", { collectionId, name });
+    return res.data;
+  }
+
+  /**
+   * Deletes a collection.
+   * @param collectionId The id of the collection.
+   * @returns The deleted collection.
+   */
+  static async deleteCollection(collectionId: string): Promise<Collection> {
+    const res = await WebsocketService.invoke<Collection>("delete_collection", { collectionId });
+    return res.data;
+  }
+
+  /**
+   * Adds roms to a collection.
+   * @param collectionId The id of the collection.
+   * @param romIds The ids of the roms to add.
+   * @returns The updated collection.
+   */
+  static async addToCollection(collectionId: string, romIds: string[]): Promise<Collection> {
+    const res = await WebsocketService.invoke<Collection>("add_to_collection", { collectionId, romIds });
+    return res.data;
+  }
+
+  /**
+   * Removes roms from a collection.
+   * @param collectionId The id of the collection.
+   * @param romIds The ids of the roms to remove.
+   * @returns The updated collection.
+   */
+  static async removeFromCollection(collectionId: string, romIds: string[]): Promise<Collection> {
+    const res = await WebsocketService.invoke<Collection>("remove_from_collection", { collectionId, romIds });
+    return res.data;
+  }
+
+  /**
+   * Sets the rules of a collection. Passing null turns it into a regular collection.
+   * @param collectionId The id of the collection.
+   * @param rules The new rules.
+   * @returns The updated collection.
+   */
+  static async setCollectionRules(collectionId: string, rules: CollectionRules | null): Promise<Collection> {
+    const res = await WebsocketService.invoke<Collection>("set_collection_rules", { collectionId, rules });
+    return res.data;
+  }
+
+  /**
+   * Exports the library as EmulationStation gamelists and RetroArch playlists.
+   * @param onJob Function to run with the id of the job exporting the library.
+   * @returns What the export wrote.
+   */
+  static async exportLibrary(onJob: (jobId: string) => void = () => {}): Promise<ExportResult> {
+    const res = await WebsocketService.invoke<ExportResult>("export_library", {}, onJob);
+    return res.data;
+  }
+
+  /**
+   * Imports metadata from EmulationStation, LaunchBox or Pegasus.
+   * @param path The metadata file, or a folder to search for them.
+   * @param overwrite Whether imported metadata replaces what ROMs already have.
+   * @param onJob Function to run with the id of the job importing the metadata.
+   * @returns What the import changed.
+   */
+  static async importMetadata(path: string, overwrite: boolean, onJob: (jobId: string) => void = () => {}): Promise<ImportResult> {
+    const res = await WebsocketService.invoke<ImportResult>("import_metadata", { path, overwrite }, onJob);
+    return res.data;
+  }
+  
+
   /**
    * Saves the parsers to the server.
    * @param data The parsers to save.
@@ -265,20 +595,42 @@ export class WebsocketService {
 
   /**
    * Loads the app's library.
+   * @param onJob Function to run with the id of the job loading the library.
    * @returns The loaded library data.
    */
-  static async loadLibrary(): Promise<LoadResult> {
-    const res = await WebsocketService.invoke<LoadResult>("load_library", {});
+  static async loadLibrary(onJob: (jobId: string) => void = () => {}): Promise<LoadResult> {
+    const res = await WebsocketService.invoke<LoadResult>("load_library", {}, onJob);
     return res.data;
   }
 
   /**
    * Updates the app's library.
    * @param library The updated library.
+   * @param onJob Function to run with the id of the job loading the library.
    * @returns The loaded library data.
    */
-  static async updateLibrary(library: Library): Promise<LoadResult> {
-    const res = await WebsocketService.invoke<LoadResult>("update_library", { library });
+  static async updateLibrary(library: Library, onJob: (jobId: string) => void = () => {}): Promise<LoadResult> {
+    const res = await WebsocketService.invoke<LoadResult>("update_library", { library }, onJob);
+    return res.data;
+  }
+
+  /**
+   * Gets the jobs that are queued, running, or recently finished.
+   * @returns The list of jobs.
+   */
+  static async listJobs(): Promise<JobInfo[]> {
+    const res = await WebsocketService.invoke<JobInfo[]>("list_jobs", {});
+    jobs.set(Object.fromEntries(res.data.map((info) => [info.id, info])));
+    return res.data;
+  }
+
+  /**
+   * Cancels a background job.
+   * @param jobId The id of the job to cancel.
+   * @returns True if the job was cancelled, false if it had already finished.
+   */
+  static async cancelJob(jobId: string): Promise<boolean> {
+    const res = await WebsocketService.invoke<boolean>("cancel_job", { jobId });
     return res.data;
   }
 
@@ -0,0 +1,28 @@
+export type JobStatus = "QUEUED" | "RUNNING" | "DONE" | "FAILED" | "CANCELLED";
+
+export type JobInfo = {
+  id: string;
+  kind: string;
+  target: string;
+  status: JobStatus;
+  /**
+   * From 0 to 1, or null if the job doesn't know how much work is left.
+   */
+  progress: number | null;
+  message: string;
+  createdAt: number;
+  finishedAt: number | null;
+}
+
+export type JobDone = {
+  jobId: string;
+  kind: string;
+  result: any;
+}
+
+export type JobFailed = {
+  jobId: string;
+  kind: string;
+  message: string;
+  cancelled: boolean;
+}
@@ -25,8 +25,17 @@ export type System = {
   tagConfig: SystemTagConfig;
   patterns: ParserPattern[];
   biosFiles: string[];
+  // ? A Logiqx XML DAT to verify ROMs against. Relative paths are resolved from the config directory's "dats" folder.
+  datFile: string;
+  // ? How folder roms are compressed when they're downloaded.
+  zipCompression: ZipCompression;
+  // ? The RetroArch core this system's exported playlist launches with.
+  retroarchCorePath: string;
+  retroarchCoreName: string;
 }
 
+export type ZipCompression = "STORED" | "DEFLATE";
+
 export type ROMMetadata = {
   title: string;
   fullCapsulePath: string;
@@ -46,7 +55,58 @@ export type ROM = {
   system: string;
   systemFullName: string;
   addDate: string;
+  /** When the ROM was added, as a unix timestamp in seconds. */
+  addedAt: number;
   downloadStrategy: DownloadStrategy;
+  hashes: ROMHashes | null;
+  verification: ROMVerification | null;
+}
+
+export type ROMHashes = {
+  crc32: string;
+  md5: string;
+  sha1: string;
+  members: ArchiveMember[] | null;
+}
+
+export type ArchiveMember = {
+  name: string;
+  size: number;
+  crc32: string;
+  sha1: string;
+}
+
+export type VerificationStatus = "VERIFIED" | "BAD_DUMP" | "UNKNOWN" | "MISNAMED" | "INCOMPLETE" | "HASH_MISMATCH";
+
+export type ROMVerification = {
+  status: VerificationStatus;
+  canonicalName: string | null;
+  canonicalFilename: string | null;
+}
+
+export type HashedROM = {
+  path: string;
+  hashes: ROMHashes;
+  verification: ROMVerification | null;
+}
+
+export type RemovedROM = {
+  path: string;
+}
+
+export type RenamedROM = {
+  oldPath: string;
+  rom: ROM;
+}
+
+export type ExtraFiles = {
+  romId: string;
+  files: string[];
+}
+
+export type BiosFiles = {
+  system: string;
+  files: string[];
 }
 
 export type LoadResult = {
@@ -55,6 +115,8 @@ export type LoadResult = {
   systems: System[];
   dlcs: Record<string, string[]>;
   updates: Record<string, string[]>;
+  // ? The BIOS files present on disk, keyed by system abbreviation.
+  bios: Record<string, string[]>;
 }
 
 export type Library = {
@@ -67,6 +129,42 @@ export type Library = {
 }
 
 export type Collection = {
+  id: string;
   name: string;
-  romsIds: string[];
-}
\ No newline at end of file
+  romIds: string[];
+  // ? Smart collections have rules, and the server keeps their ROMs up to date.
+  rules: CollectionRules | null;
+}
+
+export type RuleCombinator = "ALL" | "ANY";
+
+export type RuleField = "TITLE" | "SYSTEM" | "FORMAT" | "SIZE" | "ADDED" | "FAVORITE" | "GENRE" | "DEVELOPER" | "PUBLISHER";
+
+export type RuleOperator = "EQUALS" | "NOT_EQUALS" | "CONTAINS" | "NOT_CONTAINS" | "GREATER_THAN" | "LESS_THAN" | "WITHIN_DAYS";
+
+export type CollectionRule = {
+  field: RuleField;
+  operator: RuleOperator;
+  value: string | number | boolean;
+}
+
+export type CollectionRules = {
+  combinator: RuleCombinator;
+  rules: CollectionRule[];
+}
+
+export type ExportResult = {
+  exportPath: string;
+  gamelists: number;
+  playlists: number;
+  romCount: number;
+}
+
+export type ImportResult = {
+  files: number;
+  games: number;
+  matched: number;
+  artwork: number;
+  // ? The games that didn't match a ROM.
+  unmatched: string[];
+}
@@ -22,6 +22,12 @@ export type AccessibilitySettings = {
   reducedMotion: boolean;
 }
 
+export type ExportSettings = {
+  exportDir: string;
+  romPathPrefix: string;
+  exportOnLibraryChange: boolean;
+}
+
 export type Settings = {
   FILE_SIG_DO_NOT_EDIT: "dev.travislane.nas-rom-manager";
   version: string;
@@ -29,5 +35,6 @@ export type Settings = {
   navigation: NavigationSettings;
   metadata: MetadataSettings;
   accessibility: AccessibilitySettings;
+  export: ExportSettings;
   library: Library;
 }
\ No newline at end of file
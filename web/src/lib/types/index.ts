@@ -2,6 +2,7 @@ import type { Snippet } from "svelte";
 
 export * from "./DownloadStrategy";
 export * from "./IGDB";
+export * from "./Jobs";
 export * from "./Library";
 export * from "./Settings";
 export * from "./SGDB";
@@ -16,7 +17,7 @@ export type UploadConfig = {
   system: string;
   file: File;
   path?: string;
-  needsUnzip: boolean;
+  needsExtract: boolean;
   romId?: string;
 }
 
@@ -25,12 +26,13 @@ export type CompletedUploadData = {
   path: string;
   libraryPath: string;
   system: string;
-  unzip: boolean;
+  extract: boolean;
+  crc32: string;
 }
 
 export type UploadSettings = {
   config: UploadConfig;
-  complete: (data: CompletedUploadData) => Promise<string>;
+  complete: (data: CompletedUploadData, onJob?: (jobId: string) => void) => Promise<string>;
   process: (
     filePath: string,
     closeModal: () => void
@@ -38,6 +40,15 @@ export type UploadSettings = {
   isReplace?: boolean;
 }
 
+export type UserRole = "READONLY" | "UPLOADER" | "ADMIN";
+
+export type SessionToken = {
+  token: string;
+  user: string;
+  role: UserRole;
+  expires: number;
+}
+
 export type AvailableStorage = {
   usedSpace: number;
   totalSpace: number;
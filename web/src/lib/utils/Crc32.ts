@@ -0,0 +1,37 @@
+const CRC32_TABLE = (() => {
+  const table = new Uint32Array(256);
+
+  for (let i = 0; i < 256; i++) {
+    let crc = i;
+    for (let bit = 0; bit < 8; bit++) {
+      crc = crc & 1 ? 0xEDB88320 ^ (crc >>> 1) : crc >>> 1;
+    }
+    table[i] = crc >>> 0;
+  }
+
+  return table;
+})();
+
+/**
+ * Updates a running CRC32 with more data.
+ * @param crc The CRC32 of the data so far. Start with 0.
+ * @param data The next block of data.
+ * @returns The CRC32 of all of the data.
+ */
+export function updateCrc32(crc: number, data: Uint8Array): number {
+  crc = ~crc >>> 0;
+
+  for (let i = 0; i < data.length; i++) {
+    crc = CRC32_TABLE[(crc ^ data[i]) & 0xFF] ^ (crc >>> 8);
+  }
+
+  return ~crc >>> 0;
+}
+
+/**
+ * Formats a CRC32 as 8 hex characters.
+ * @param crc The CRC32 to format.
+ */
+export function formatCrc32(crc: number): string {
+  return crc.toString(16).padStart(8, "0");
+}
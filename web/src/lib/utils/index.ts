@@ -1,5 +1,6 @@
 export * from "./animations/animations";
 export * from "./animations/easing";
+export * from "./Crc32";
 export * from "./custom-stores/LocalStorageWritable";
 export * from "./Date";
 export * from "./Format";
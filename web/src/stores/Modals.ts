@@ -46,6 +46,7 @@ export const systemEditingId = writable<string | null>(null);
 
 export const showLoadingModal = writable(false);
 export const loadingModalMessage = writable("");
+export const loadingModalJobId = writable<string | null>(null);
 
 export const showChangeGridsModal = writable(false);
 export const changeGridsId = writable<string | null>(null);
@@ -1,5 +1,5 @@
 import { DEFAULT_FILTERS } from "@models";
-import { type DBFilters, type Library, type Palette, type ROM, type ROMMetadata, type SearchQuery, type SGDBGame, type System, type SystemTagConfig } from "@types";
+import { type Collection, type DBFilters, type JobInfo, type Library, type Palette, type ROM, type ROMMetadata, type SearchQuery, type SGDBGame, type System, type SystemTagConfig } from "@types";
 import { localStorageWritable } from "@utils";
 import { derived, writable } from "svelte/store";
 
@@ -28,6 +28,7 @@ export const portraitViews = writable(["Dashboard", "Library", "Search", "System
 export const libraryGridType = writable("portrait");
 
 export const saveMetadataAlongside = writable(false);
+export const exportOnLibraryChange = writable(false);
 
 export const reducedMotion = writable(false);
 
@@ -45,6 +46,7 @@ export const roms = writable<Record<string, ROM>>({});
 export const emulators = writable<Record<string, string>>({});
 export const romDLCs = writable<Record<string, string[]>>({});
 export const romUpdates = writable<Record<string, string[]>>({});
+export const collections = writable<Record<string, Collection>>({});
 
 export const fileFormatsBySystem = derived([ roms ], ([$roms]: [Record<string, ROM>]) => {
   const formats = Object.values($roms).reduce((formats: Record<string, Set<string>>, rom: ROM) => {
@@ -95,4 +97,6 @@ export const metadataSearchFilters = derived([ romMetadata ], ([$romMetadata]: [
   return filters;
 });
 
-export const searchFilters = writable<SearchQuery>({});
\ No newline at end of file
+export const searchFilters = writable<SearchQuery>({});
+
+export const jobs = writable<Record<string, JobInfo>>({});
\ No newline at end of file